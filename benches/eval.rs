@@ -0,0 +1,106 @@
+//! Throughput of `Assignment::eval` and `Assignment::eval_batch`.
+//!
+//! Run with `cargo bench --bench eval`.
+//! Reports latency and throughput in evaluations per second for every scenario.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use st_test::assignment::{arithmetic_rule::SubstitutionToken, Assignment, InputSet};
+
+/// Number of inputs evaluated by `eval_batch` at once.
+const BATCH_SIZE: usize = 1000;
+/// Number of non-matching logical rules in the large rule set.
+const LARGE_RULE_COUNT: usize = 500;
+
+/// Builds `Assignment` with base and custom rules.
+fn base_custom_rules() -> Assignment {
+    Assignment::new().with_rules(true, true)
+}
+
+/// Builds `Assignment` with a large number of string rules,
+/// where the matching logical rule is the last one.
+fn large_str_rules() -> Assignment {
+    let mut assignment = Assignment::new();
+    for _ in 0..LARGE_RULE_COUNT {
+        assignment
+            .add_logical_rule_from_str(SubstitutionToken::P, "!A && !B && !C".to_string())
+            .unwrap();
+    }
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A && B && !C".to_string())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D + (D * E / 10)".to_string())
+        .unwrap();
+    assignment
+}
+
+/// Builds `Assignment` with function rules equivalent to base rules.
+fn fn_rules() -> Assignment {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, b, c| a && b && !c))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|a, b, c| a && b && c))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|a, b, c| !a && b && c))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(
+        SubstitutionToken::M,
+        Box::new(|d, e, _| d + (d * e as f64 / 10.0)),
+    );
+    assignment.add_arithmetic_rule_from_fn(
+        SubstitutionToken::P,
+        Box::new(|d, e, f| d + (d * (e - f) as f64 / 25.5)),
+    );
+    assignment.add_arithmetic_rule_from_fn(
+        SubstitutionToken::T,
+        Box::new(|d, _, f| d - (d * f as f64 / 30.0)),
+    );
+    assignment
+}
+
+/// Measures single evaluation of `eval` with different rule sets.
+fn bench_eval(c: &mut Criterion) {
+    let input = InputSet::new(true, true, false, 2.0, 3, 4);
+    let mut group = c.benchmark_group("eval");
+    group.throughput(Throughput::Elements(1));
+
+    let assignment = base_custom_rules();
+    group.bench_function("base+custom", |b| {
+        b.iter(|| assignment.eval(black_box(&input)).unwrap())
+    });
+
+    let assignment = large_str_rules();
+    group.bench_function("large str rules", |b| {
+        b.iter(|| assignment.eval(black_box(&input)).unwrap())
+    });
+
+    let assignment = fn_rules();
+    group.bench_function("fn rules", |b| {
+        b.iter(|| assignment.eval(black_box(&input)).unwrap())
+    });
+
+    group.finish();
+}
+
+/// Measures `eval_batch` of `BATCH_SIZE` inputs.
+fn bench_eval_batch(c: &mut Criterion) {
+    let batch: Vec<InputSet> = (0..BATCH_SIZE as i32)
+        .map(|i| InputSet::new(true, true, i % 2 == 0, 2.0, i, 4))
+        .collect();
+    let mut group = c.benchmark_group("eval_batch");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+
+    let assignment = base_custom_rules();
+    group.bench_function("base+custom", |b| {
+        b.iter(|| assignment.eval_batch(black_box(&batch)))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_eval, bench_eval_batch);
+criterion_main!(benches);
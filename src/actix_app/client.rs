@@ -0,0 +1,265 @@
+//! Typed client for `actix_app` HTTP API.
+//!
+//! Requests and responses use the same types as endpoints, so client and server can't diverge.
+//! Client is built on `reqwest` and has to be used within tokio runtime, e.g. actix one.
+
+use reqwest::{Client, Response, StatusCode};
+
+use std::{error::Error, fmt};
+
+use crate::{
+    actix_app::{AddRuleReq, EvalResponse, RuleErrorResp},
+    assignment::{arithmetic_rule::SubstitutionToken, InputSet},
+};
+
+/// Error of `AssignmentClient` request.
+#[derive(Debug)]
+pub enum ClientError {
+    /// Request can't be sent or response can't be read.
+    Transport(reqwest::Error),
+    /// Server rejected rule.
+    InvalidRule {
+        /// Description of rule error.
+        error: String,
+        /// Detailed `evalexpr` error, if expression can't be evaluated by `evalexpr`.
+        evalexpr_error: Option<String>,
+    },
+    /// Server responded with unsuccessful status.
+    Status {
+        status: StatusCode,
+        /// Error message from response body, if there is one.
+        message: Option<String>,
+    },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "Request failed: {}.", e),
+            Self::InvalidRule { error, .. } => write!(f, "{}", error),
+            Self::Status {
+                message: Some(message),
+                ..
+            } => write!(f, "{}", message),
+            Self::Status {
+                status,
+                message: None,
+            } => write!(f, "Request failed with status {}.", status),
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e)
+    }
+}
+
+/// Client of `actix_app` server.
+///
+/// # Examples
+///
+/// ```no_run
+/// use st_test::actix_app::client::{AssignmentClient, ClientError};
+/// use st_test::assignment::{arithmetic_rule::SubstitutionToken, InputSet};
+///
+/// # async fn run() -> Result<(), ClientError> {
+/// let client = AssignmentClient::new("http://127.0.0.1:8080");
+/// client.add_logical_rule(SubstitutionToken::M, "A && B").await?;
+/// client.add_arithmetic_rule(SubstitutionToken::M, "D * E").await?;
+/// let (token, value) = client.eval(&InputSet::new(true, true, false, 1.5, 2, 0)).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AssignmentClient {
+    client: Client,
+    base_url: String,
+}
+
+impl AssignmentClient {
+    /// Builds `AssignmentClient` for server with given base URL, e.g. `http://127.0.0.1:8080`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+        }
+    }
+
+    /// Adds new logical rule with `/add_logical_rule`.
+    /// Returns `ClientError::InvalidRule` with server description if rule is rejected.
+    pub async fn add_logical_rule(
+        &self,
+        token: SubstitutionToken,
+        rule_str: impl Into<String>,
+    ) -> Result<(), ClientError> {
+        self.add_rule("/add_logical_rule", token, rule_str.into())
+            .await
+    }
+
+    /// Adds new arithmetic rule with `/add_arithmetic_rule`.
+    /// Returns `ClientError::InvalidRule` with server description if rule is rejected.
+    pub async fn add_arithmetic_rule(
+        &self,
+        token: SubstitutionToken,
+        rule_str: impl Into<String>,
+    ) -> Result<(), ClientError> {
+        self.add_rule("/add_arithmetic_rule", token, rule_str.into())
+            .await
+    }
+
+    /// Calculates result for given input with `/eval`.
+    /// Returns `ClientError::Status` with server description if calculation fails.
+    pub async fn eval(&self, input: &InputSet) -> Result<(SubstitutionToken, f64), ClientError> {
+        let resp = self
+            .client
+            .post(&self.url("/eval"))
+            .json(input)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(response_error(resp).await);
+        }
+
+        let res: EvalResponse = resp.json().await?;
+        Ok(res.into())
+    }
+
+    /// Sends `AddRuleReq` to given endpoint.
+    async fn add_rule(
+        &self,
+        path: &str,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<(), ClientError> {
+        let resp = self
+            .client
+            .post(&self.url(path))
+            .json(&AddRuleReq { token, rule_str })
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(response_error(resp).await);
+        }
+
+        Ok(())
+    }
+
+    /// Returns full URL of given endpoint.
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+/// Returns error described by failed response.
+/// Body is expected to be `RuleErrorResp` or error message in JSON,
+/// otherwise error contains only response status.
+async fn response_error(resp: Response) -> ClientError {
+    let status = resp.status();
+    let body = match resp.bytes().await {
+        Ok(body) => body,
+        Err(e) => return e.into(),
+    };
+
+    if let Ok(e) = serde_json::from_slice::<RuleErrorResp>(&body) {
+        return ClientError::InvalidRule {
+            error: e.error,
+            evalexpr_error: e.evalexpr_error,
+        };
+    }
+    ClientError::Status {
+        status,
+        message: serde_json::from_slice::<String>(&body).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actix_app::{add_arithmetic_rule, add_logical_rule, eval},
+        assignment::Assignment,
+    };
+    use actix_web::{test, web, App};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[actix_rt::test]
+    async fn test_client() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let server_data = data.clone();
+        let server = test::start(move || {
+            App::new()
+                .app_data(server_data.clone())
+                .service(add_logical_rule)
+                .service(add_arithmetic_rule)
+                .service(eval)
+        });
+        let client = AssignmentClient::new(server.url("/"));
+        let input = InputSet::new(true, true, false, 1.5, 2, 0);
+
+        match client.eval(&input).await.unwrap_err() {
+            ClientError::Status { status, message } => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(message.as_deref(), Some("Failed to apply logical rule."));
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        client
+            .add_logical_rule(SubstitutionToken::M, "A && B")
+            .await
+            .unwrap();
+        client
+            .add_arithmetic_rule(SubstitutionToken::M, "D * E")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.eval(&input).await.unwrap(),
+            (SubstitutionToken::M, 3.0)
+        );
+        assert!(data.read().await.has_rules());
+
+        let err = client
+            .add_arithmetic_rule(SubstitutionToken::M, "D && E")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::InvalidRule { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Expression contains invalid variables or operators."
+        );
+
+        // Unknown endpoint has no error message in body.
+        let err = AssignmentClient::new(server.url("/unknown"))
+            .eval(&input)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::Status {
+                status: StatusCode::NOT_FOUND,
+                message: None
+            }
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_client_transport_error() {
+        // Nothing listens on port 1.
+        let err = AssignmentClient::new("http://127.0.0.1:1")
+            .eval(&InputSet::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Transport(_)));
+        assert!(err.source().is_some());
+    }
+}
@@ -0,0 +1,150 @@
+//! Structured logging of `tracing` spans and events as JSON lines by `tracing-subscriber`.
+//!
+//! Each event is written as a single JSON object with its timestamp, level, target, fields
+//! and fields of all spans entered when it was recorded, from outermost to innermost.
+
+use tracing::{Level, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Name of environment variable selecting log format, JSON lines if it's `json`.
+pub const LOG_FORMAT_VAR: &str = "LOG_FORMAT";
+
+/// Builds `Subscriber` writing events of given level and above as JSON lines.
+///
+/// # Arguments
+/// * `max_level` - Most verbose level of written events.
+/// * `writer` - Destination of JSON lines.
+pub fn json_subscriber<W>(max_level: Level, writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .json()
+        .with_max_level(max_level)
+        .with_current_span(false)
+        .with_span_list(true)
+        .with_writer(writer)
+        .finish()
+}
+
+/// Initializes logging of the server.
+///
+/// Access log of actix is written by `env_logger` as before.
+/// If `LOG_FORMAT` environment variable is `json`, `tracing` events of `INFO` level and above
+/// are written as JSON lines to stdout, otherwise they are forwarded to `env_logger`.
+pub fn init() {
+    let _ = env_logger::try_init();
+
+    if std::env::var(LOG_FORMAT_VAR).is_ok_and(|v| v.eq_ignore_ascii_case("json")) {
+        let _ =
+            tracing::subscriber::set_global_default(json_subscriber(Level::INFO, std::io::stdout));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actix_app::eval,
+        assignment::{approx_eq, Assignment, InputSet},
+    };
+    use actix_web::{test, web, App};
+    use serde_json::Value;
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+    use tokio::sync::RwLock;
+
+    /// Writer appending to shared buffer.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        /// Returns `MakeWriter` appending to the buffer.
+        fn writer(&self) -> impl Fn() -> SharedBuf + Send + Sync + 'static {
+            let buf = self.clone();
+            move || buf.clone()
+        }
+
+        /// Returns written JSON lines.
+        fn lines(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_json_subscriber() {
+        let buf = SharedBuf::default();
+        let subscriber = json_subscriber(Level::INFO, buf.writer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("outer", id = 7);
+            let _enter = span.enter();
+            tracing::info!(value = 1.5, ok = true, "inside");
+            tracing::debug!("filtered");
+        });
+        tracing::subscriber::with_default(json_subscriber(Level::INFO, buf.writer()), || {
+            tracing::warn!(token = "M", "outside")
+        });
+
+        let lines = buf.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["fields"]["message"], "inside");
+        assert_eq!(lines[0]["fields"]["value"], 1.5);
+        assert_eq!(lines[0]["fields"]["ok"], true);
+        assert_eq!(
+            lines[0]["spans"],
+            serde_json::json!([{ "name": "outer", "id": 7 }])
+        );
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["fields"]["token"], "M");
+        // Events outside of spans have no span list.
+        assert!(lines[1].get("spans").is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_span() {
+        let buf = SharedBuf::default();
+        let _guard = tracing::subscriber::set_default(json_subscriber(Level::INFO, buf.writer()));
+
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 4))
+            .to_request();
+        test::call_service(&mut app, req).await;
+
+        let lines = buf.lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["fields"]["message"], "Evaluated");
+        assert_eq!(lines[0]["fields"]["token"], "M");
+        assert!(approx_eq(
+            lines[0]["fields"]["value"].as_f64().unwrap(),
+            2.6,
+            1e-9
+        ));
+        assert_eq!(lines[0]["spans"][0]["name"], "eval");
+        assert_eq!(lines[0]["spans"][0]["a"], "Some(true)");
+        assert_eq!(lines[0]["spans"][0]["e"], "Some(3)");
+    }
+}
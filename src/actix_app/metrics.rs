@@ -0,0 +1,183 @@
+//! Counters of `/eval` requests shared by all workers of the server.
+//!
+//! With `prometheus` feature counters are exposed in Prometheus text format at `/metrics/prometheus`.
+
+#[cfg(feature = "prometheus")]
+use actix_web::{get, web, HttpResponse, Result};
+use std::{
+    error::Error,
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::assignment::arithmetic_rule::SubstitutionToken;
+
+/// Tokens in order of `EvalMetrics` counters.
+const TOKENS: [SubstitutionToken; 3] = [
+    SubstitutionToken::M,
+    SubstitutionToken::P,
+    SubstitutionToken::T,
+];
+
+/// Counters of evaluations, set as server application data.
+#[derive(Default)]
+pub struct EvalMetrics {
+    /// Successful evaluations by token, in order of `TOKENS`.
+    evals: [AtomicU64; 3],
+    errors: AtomicU64,
+}
+
+impl EvalMetrics {
+    /// Counts evaluation result.
+    pub fn record<T>(
+        &self,
+        res: &std::result::Result<(SubstitutionToken, T), Box<dyn Error + Send + Sync>>,
+    ) {
+        let counter = match res {
+            Ok((token, _)) => &self.evals[token.clone() as usize],
+            Err(_) => &self.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns number of successful evaluations that found given token.
+    pub fn evals(&self, token: &SubstitutionToken) -> u64 {
+        self.evals[token.clone() as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns number of failed evaluations.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Renders counters in Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut res = String::new();
+        res.push_str(
+            "# HELP assignment_eval_total Number of successful evaluations by substitution token.\n",
+        );
+        res.push_str("# TYPE assignment_eval_total counter\n");
+        for token in &TOKENS {
+            writeln!(
+                res,
+                "assignment_eval_total{{token=\"{:?}\"}} {}",
+                token,
+                self.evals(token)
+            )
+            .unwrap();
+        }
+        res.push_str("# HELP assignment_eval_errors_total Number of failed evaluations.\n");
+        res.push_str("# TYPE assignment_eval_errors_total counter\n");
+        writeln!(res, "assignment_eval_errors_total {}", self.errors()).unwrap();
+        res
+    }
+}
+
+/// Endpoint exposing `EvalMetrics` in Prometheus text exposition format.
+///
+/// Returns `HttpResponse::Ok()` with `text/plain; version=0.0.4` body,
+/// all counters are zero if `EvalMetrics` is not set as application data.
+#[cfg(feature = "prometheus")]
+#[get("/metrics/prometheus")]
+pub async fn metrics_prometheus(metrics: Option<web::Data<EvalMetrics>>) -> Result<HttpResponse> {
+    let body = match metrics {
+        Some(metrics) => metrics.to_prometheus(),
+        None => EvalMetrics::default().to_prometheus(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use super::*;
+    use crate::{
+        actix_app::eval,
+        assignment::{Assignment, InputSet},
+    };
+    use actix_web::{http, test, App};
+    use regex::Regex;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[actix_rt::test]
+    async fn test_metrics_prometheus() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let metrics = web::Data::new(EvalMetrics::default());
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .app_data(metrics.clone())
+                .service(eval)
+                .service(metrics_prometheus),
+        )
+        .await;
+
+        for input in [
+            InputSet::new(true, true, false, 2.0, 5, 4),
+            InputSet::new(true, true, false, 3.0, 5, 4),
+            InputSet::new(true, true, true, 2.0, 5, 4),
+            InputSet::default(),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/eval")
+                .set_json(&input)
+                .to_request();
+            test::call_service(&mut app, req).await;
+        }
+        assert_eq!(metrics.evals(&SubstitutionToken::M), 2);
+        assert_eq!(metrics.errors(), 1);
+
+        let req = test::TestRequest::get()
+            .uri("/metrics/prometheus")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(http::header::CONTENT_TYPE).unwrap(),
+            "text/plain; version=0.0.4"
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+        let comment = Regex::new(r"^# (HELP [a-z_]+ .+|TYPE [a-z_]+ counter)$").unwrap();
+        let sample =
+            Regex::new(r#"^([a-zA-Z_:][a-zA-Z0-9_:]*)(\{[a-zA-Z_]\w*="[^"]*"\})? (\d+)$"#).unwrap();
+        let mut samples = Vec::new();
+        for line in body.lines() {
+            if line.starts_with('#') {
+                assert!(comment.is_match(line), "{}", line);
+            } else {
+                let caps = sample.captures(line).expect(line);
+                samples.push((
+                    caps[1].to_owned(),
+                    caps.get(2).map_or("", |m| m.as_str()).to_owned(),
+                    caps[3].parse::<u64>().unwrap(),
+                ));
+            }
+        }
+        assert_eq!(
+            samples,
+            vec![
+                (
+                    "assignment_eval_total".to_owned(),
+                    r#"{token="M"}"#.to_owned(),
+                    2
+                ),
+                (
+                    "assignment_eval_total".to_owned(),
+                    r#"{token="P"}"#.to_owned(),
+                    1
+                ),
+                (
+                    "assignment_eval_total".to_owned(),
+                    r#"{token="T"}"#.to_owned(),
+                    0
+                ),
+                ("assignment_eval_errors_total".to_owned(), String::new(), 1),
+            ]
+        );
+    }
+}
@@ -3,42 +3,241 @@
 //! Implements function to create and run HttpServer,
 //! add Assignment as server application data and bind endpoints.
 //!
+//! `Assignment` is shared between workers behind `tokio::sync::RwLock`,
+//! so handlers await the lock instead of blocking the executor thread
+//! and the lock can't be poisoned by a panicking handler.
+//!
+//! If `RULES_CONFIG` environment variable is set, rules are loaded from that file
+//! and reloaded on SIGHUP with `reload_rules`.
+//!
+//! JSON request bodies larger than `MAX_PAYLOAD_SIZE` bytes (16 KiB by default)
+//! are rejected with `HttpResponse::PayloadTooLarge()`.
+//! With `rate-limit` feature enabled, requests are limited per client IP, see `rate_limit`.
+//!
+//! Endpoints working with rules and evaluation are also served for each tenant
+//! under `/t/{tenant}` prefix or with `X-Tenant-Id` header, see `tenant`.
+//!
 //! # Endpoints
 //!
 //! * /add_logical_rule
 //!
-//!     Endpoint to add new `LogicalRule` to `Assignment`.
-//!     Accepts `AddRuleReq` in JSON format.
+//!   Endpoint to add new `LogicalRule` to `Assignment`.
+//!   Accepts `AddRuleReq` in JSON format.
 //!
-//!     Returns `HttpResponse::Ok()` if new rule added successfully,
-//!     otherwise returns `HttpResponse::BadRequest` with error message in JSON.
+//!   Returns `HttpResponse::Ok()` if new rule added successfully,
+//!   otherwise returns `HttpResponse::BadRequest` with `RuleErrorResp` in JSON.
 //!
 //! * /add_arithmetic_rule
 //!
-//!     Endpoint to add new `ArithmeticRule` to `Assignment`.
-//!     Accepts `AddRuleReq` in JSON format.
+//!   Endpoint to add new `ArithmeticRule` to `Assignment`.
+//!   Accepts `AddRuleReq` in JSON format.
 //!
-//!     Returns `HttpResponse::Ok()` if new rule added successfully,
-//!     otherwise returns `HttpResponse::BadRequest` with error message in JSON.
+//!   Returns `HttpResponse::Ok()` if new rule added successfully,
+//!   otherwise returns `HttpResponse::BadRequest` with `RuleErrorResp` in JSON.
 //!
 //! * /remove_rules
 //!
-//!     Endpoint to remove rules from `Assignment`.
+//!   Endpoint to remove rules from `Assignment`.
 //!
 //! * /eval
 //!
-//!     Endpoint for assignment calculation.
-//!     Accepts `InputSet` in JSON format.
+//!   Endpoint for assignment calculation.
+//!   Accepts `PartialInputSet` in JSON format.
+//!   Absent fields use defaults set by `Assignment::set_input_default`.
+//!   Optional `profile` query parameter selects named rule profile of `Assignment`.
+//!
+//!   If calculation is successful, returns `HttpResponse::Ok()` with `EvalResponse` in JSON
+//!   holding precise `value` and `display_value` rounded to output precision of `Assignment`,
+//!   or with `[token, display_value]` tuple if `Accept` header is `application/vnd.legacy+json`,
+//!   `HttpResponse::BadRequest()` with `InputErrorsResp` in JSON listing every field of wrong type,
+//!   otherwise `HttpResponse::BadRequest()` with error message in JSON.
+//!   `NaN` or infinite value is handled according to `NonFinitePolicy` set with `NON_FINITE_POLICY`
+//!   environment variable: rejected with `HttpResponse::UnprocessableEntity()`,
+//!   replaced with `null` (default) or with `"NaN"`, `"inf"` or `"-inf"` string.
+//!   Input outside of bounds set by `Assignment::set_input_bounds` is rejected
+//!   with `HttpResponse::UnprocessableEntity()` and error message in JSON.
+//!   If matched logical rule has no arithmetic rule, error is `MissingArithmeticRuleResp`
+//!   with `matched_token`.
+//!
+//! * GET /eval
+//!
+//!   Endpoint for assignment calculation from browser address bar.
+//!   Accepts `PartialInputSet` and optional `profile` in query parameters.
+//!   Absent fields use defaults set by `Assignment::set_input_default`, or default `InputSet` fields.
+//!
+//!   Returns the same responses as `/eval`.
+//!
+//! * /eval_form
+//!
+//!   Endpoint for assignment calculation from HTML form or legacy clients.
+//!   Accepts `PartialInputSet` in `application/x-www-form-urlencoded` format.
+//!
+//!   Returns the same responses as `/eval`.
+//!
+//! * /matches
+//!
+//!   Endpoint to check if input is handled by any logical rule without calculating result.
+//!   Accepts `PartialInputSet` in JSON format.
+//!
+//!   Returns `HttpResponse::Ok()` with `MatchesResp` in JSON,
+//!   `HttpResponse::BadRequest()` with `InputErrorsResp` in JSON if any field has wrong type,
+//!   or `HttpResponse::BadRequest()` with error message in JSON if input can't be completed with defaults.
+//!
+//! * /classify
+//!
+//!   Endpoint to find token of logical rules for input without calculating result.
+//!   Accepts `PartialInputSet` in JSON format.
+//!
+//!   Returns `HttpResponse::Ok()` with `ClassifyResp` in JSON even if token has no arithmetic rule,
+//!   `HttpResponse::BadRequest()` with `InputErrorsResp` in JSON if any field has wrong type,
+//!   `HttpResponse::UnprocessableEntity()` with error message in JSON if input is out of bounds,
+//!   otherwise `HttpResponse::BadRequest()` with error message in JSON.
+//!
+//! * /eval_batch.csv
+//!
+//!   Endpoint to calculate assignment for a batch of inputs as CSV, e.g. for spreadsheets.
+//!   Accepts JSON array of `InputSet`.
+//!
+//!   Returns `HttpResponse::Ok()` with `text/csv` body with columns `a,b,c,d,e,f,token,value,error`,
+//!   one row per input; failed rows have error message in `error` column.
+//!
+//! * /eval_grid
+//!
+//!   Endpoint to calculate assignment for each input of a grid, e.g. for sensitivity analysis.
+//!   Accepts `EvalGrid` in JSON format.
+//!
+//!   Returns `HttpResponse::Ok()` with array of `EvalGridItem` in JSON,
+//!   or `HttpResponse::BadRequest()` with error message in JSON if any range is invalid
+//!   or grid exceeds the limit set by `Assignment::set_max_grid_size`.
+//!
+//! * /preview
+//!
+//!   Endpoint to preview how a candidate rule would change results of sample inputs.
+//!   Accepts `PreviewReq` in JSON format.
+//!
+//!   The candidate is added to a copy of `Assignment`, the live one is not changed.
+//!   Returns `HttpResponse::Ok()` with array of `PreviewItem` in JSON,
+//!   otherwise returns `HttpResponse::BadRequest` with `RuleErrorResp` in JSON if the candidate is invalid.
+//!
+//! * /validate_rule
+//!
+//!   Endpoint to validate rule string without adding it to `Assignment`.
+//!   Accepts `ValidateRuleReq` in JSON format.
+//!
+//!   Returns `HttpResponse::Ok()` with `ValidateRuleResp` in JSON.
+//!
+//! * /parse_tree
+//!
+//!   Endpoint to show how `evalexpr` parses rule string, e.g. to debug operator precedence.
+//!   Accepts `ValidateRuleReq` in JSON format.
+//!
+//!   Returns `HttpResponse::Ok()` with root `ParseTreeNode` in JSON if rule is valid,
+//!   otherwise returns `HttpResponse::BadRequest()` with `RuleErrorResp` in JSON.
 //!
-//!     If calculation is successful, returns `HttpResponse::Ok()` with result in JSON,
-//!     otherwise `HttpResponse::BadRequest()` with error message in JSON.
+//! * /try_rule
+//!
+//!   Endpoint to apply a single rule to `InputSet` without adding it to `Assignment`.
+//!   Accepts `TryRuleReq` in JSON format.
+//!
+//!   Returns `HttpResponse::Ok()` with `RuleOutput` in JSON if rule is valid,
+//!   otherwise returns `HttpResponse::BadRequest()` with error message in JSON.
+//!
+//! * /rules/replace
+//!
+//!   Endpoint to replace all rules of `Assignment` at once.
+//!   Accepts `SerializableAssignment` in JSON format, e.g. returned by `/rules/export`.
+//!
+//!   All rules are validated before replacement, so either all of them are applied or none.
+//!   Returns `HttpResponse::Ok()` if rules replaced successfully,
+//!   otherwise returns `HttpResponse::BadRequest()` with `InvalidRuleResp` in JSON.
+//!
+//! * /eval_stream
+//!
+//!   Endpoint for calculation of newline-delimited stream of inputs.
+//!   Accepts `PartialInputSet` objects in NDJSON format.
+//!
+//!   Returns `HttpResponse::Ok()` with NDJSON stream of `EvalStreamItem`, one per input line.
+//!   Lines are evaluated as they arrive, so the whole batch is never buffered.
+//!   Lines longer than `MAX_PAYLOAD_SIZE` produce error item and are skipped.
+//!
+//! * /audit
+//!
+//!   Endpoint to retrieve audit log of rule mutations of `Assignment`.
+//!
+//!   Returns `HttpResponse::Ok()` with array of `AuditEntry` in JSON in order of mutations,
+//!   empty if audit log is disabled.
+//!
+//! * /rules/export
+//!
+//!   Endpoint to export string rules of `Assignment` as `rules.json` attachment.
+//!
+//!   Returns `HttpResponse::Ok()` with `ExportRulesResp` in JSON,
+//!   which can be passed back to `/rules/replace`.
+//!
+//! * /schema/input_set
+//!
+//!   Endpoint describing `InputSet` request body.
+//!   Returns `HttpResponse::Ok()` with JSON Schema document.
+//!
+//! * /schema/add_rule
+//!
+//!   Endpoint describing `AddRuleReq` request body.
+//!   Returns `HttpResponse::Ok()` with JSON Schema document.
+//!
+//! * /health
+//!
+//!   Liveness probe endpoint.
+//!   Always returns `HttpResponse::Ok()` with `StatusResp` in JSON.
+//!
+//! * /ready
+//!
+//!   Readiness probe endpoint.
+//!   Returns `HttpResponse::Ok()` if `Assignment` has at least one logical and one arithmetic rule,
+//!   otherwise returns `HttpResponse::ServiceUnavailable()`.
+//!
+//! * /metrics/prometheus
+//!
+//!   Endpoint exposing counters of `/eval` and `/eval_form` results in Prometheus text format,
+//!   available with `prometheus` feature, see `metrics` module.
 
-use actix_web::{delete, middleware, post, web, App, HttpResponse, HttpServer, Result};
+#[cfg(feature = "client")]
+pub mod client;
+pub mod json_log;
+pub mod metrics;
+#[cfg(feature = "rate-limit")]
+pub mod rate_limit;
+pub mod tenant;
+
+use actix_web::{
+    delete, dev::Server, error::ErrorInternalServerError, get, http::header, middleware, post, web,
+    web::Bytes, App, HttpRequest, HttpResponse, HttpServer, Result,
+};
+use futures::{stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::RwLock;
+
+use metrics::EvalMetrics;
+use tenant::{TenantAssignment, Tenants};
 
-use crate::assignment::{arithmetic_rule::SubstitutionToken, Assignment, InputSet};
+use crate::assignment::{
+    arithmetic_rule::{ArithmeticRuleStr, SubstitutionToken},
+    lenient::{Lenient, LenientValue},
+    logical_rule::LogicalRuleStr,
+    normalize_rule_str,
+    parse_tree::ParseTreeNode,
+    rule::Rule,
+    serializable::{self, InvalidRuleSetError, RuleSetElement, SerializableAssignment},
+    Assignment, AssignmentError, EvalGrid, InputSet, MissingArithmeticRuleError, PartialInputSet,
+    ValidationError,
+};
 
 /// Request to add new rule.
 #[derive(Serialize, Deserialize)]
@@ -47,6 +246,318 @@ pub struct AddRuleReq {
     rule_str: String,
 }
 
+/// Response to `/eval` which matched a logical rule without arithmetic rule.
+#[derive(Serialize, Deserialize)]
+pub struct MissingArithmeticRuleResp {
+    error: String,
+    /// Token of the matched logical rule.
+    matched_token: SubstitutionToken,
+}
+
+/// Response to `/eval` and `/matches` with fields of input that failed to parse.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct InputErrorsResp {
+    /// Error message for each invalid field, by field name.
+    errors: BTreeMap<String, String>,
+}
+
+/// Parses `PartialInputSet` from flat JSON object field by field.
+///
+/// Returns `InputErrorsResp` listing every field of wrong type, not just the first.
+/// `null` fields are treated as absent, unknown fields are ignored.
+pub fn parse_partial_input(value: &serde_json::Value) -> Result<PartialInputSet, InputErrorsResp> {
+    fn field<T: LenientValue>(
+        object: &serde_json::Map<String, serde_json::Value>,
+        name: &str,
+        errors: &mut BTreeMap<String, String>,
+    ) -> Option<T> {
+        match object.get(name) {
+            None | Some(serde_json::Value::Null) => None,
+            Some(value) => serde_json::from_value::<Lenient<T>>(value.clone())
+                .map(|v| v.0)
+                .map_err(|e| errors.insert(name.to_owned(), e.to_string()))
+                .ok(),
+        }
+    }
+
+    let mut errors = BTreeMap::new();
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => {
+            errors.insert(String::new(), "Input must be a JSON object.".to_owned());
+            return Err(InputErrorsResp { errors });
+        }
+    };
+    let input = PartialInputSet {
+        a: field(object, "a", &mut errors),
+        b: field(object, "b", &mut errors),
+        c: field(object, "c", &mut errors),
+        d: field(object, "d", &mut errors),
+        e: field(object, "e", &mut errors),
+        f: field(object, "f", &mut errors),
+    };
+    if errors.is_empty() {
+        Ok(input)
+    } else {
+        Err(InputErrorsResp { errors })
+    }
+}
+
+/// Kind of substitution rule.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    Logical,
+    Arithmetic,
+}
+
+/// Request to validate rule string.
+#[derive(Serialize, Deserialize)]
+pub struct ValidateRuleReq {
+    kind: RuleKind,
+    rule_str: String,
+}
+
+/// Result of rule string validation.
+#[derive(Serialize, Deserialize)]
+pub struct ValidateRuleResp {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Detailed `evalexpr` error, if expression can't be evaluated by `evalexpr`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    evalexpr_error: Option<String>,
+}
+
+/// Description of rejected rule.
+#[derive(Serialize, Deserialize)]
+pub struct RuleErrorResp {
+    error: String,
+    /// Detailed `evalexpr` error, if expression can't be evaluated by `evalexpr`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    evalexpr_error: Option<String>,
+}
+
+impl From<Box<dyn Error + Send + Sync>> for RuleErrorResp {
+    fn from(e: Box<dyn Error + Send + Sync>) -> Self {
+        Self {
+            evalexpr_error: e
+                .downcast_ref::<ValidationError>()
+                .and_then(ValidationError::evalexpr_error)
+                .map(|e| e.to_string()),
+            error: e.to_string(),
+        }
+    }
+}
+
+/// Query parameters of `/eval` and `/eval_form`.
+#[derive(Serialize, Deserialize)]
+pub struct EvalQuery {
+    /// Name of rule profile used instead of main rules.
+    profile: Option<String>,
+}
+
+/// Request to apply a single rule to given input.
+#[derive(Serialize, Deserialize)]
+pub struct TryRuleReq {
+    kind: RuleKind,
+    token: SubstitutionToken,
+    rule_str: String,
+    input: InputSet,
+}
+
+/// Rule set exported from `Assignment`.
+/// Has the same format as `SerializableAssignment` with additional `skipped` field,
+/// so it can be imported back with `/rules/replace`.
+#[derive(Serialize, Deserialize)]
+pub struct ExportRulesResp {
+    #[serde(flatten)]
+    rules: SerializableAssignment,
+    /// Function-based rules that have no source string and can't be exported.
+    skipped: Vec<SkippedRule>,
+}
+
+/// Rule that wasn't exported.
+#[derive(Serialize, Deserialize)]
+pub struct SkippedRule {
+    kind: RuleKind,
+    /// Token of the rule, `None` for fallback arithmetic rule.
+    token: Option<SubstitutionToken>,
+}
+
+impl From<serializable::SkippedRule> for SkippedRule {
+    fn from(rule: serializable::SkippedRule) -> Self {
+        match rule {
+            serializable::SkippedRule::Logical(token) => Self {
+                kind: RuleKind::Logical,
+                token: Some(token),
+            },
+            serializable::SkippedRule::Arithmetic(token) => Self {
+                kind: RuleKind::Arithmetic,
+                token: Some(token),
+            },
+            serializable::SkippedRule::FallbackArithmetic => Self {
+                kind: RuleKind::Arithmetic,
+                token: None,
+            },
+        }
+    }
+}
+
+/// Description of a constant or rule of `SerializableAssignment` that failed validation.
+/// Has `kind` field with the kind of the element and its fields, e.g. `token` and `rule_str` of a rule.
+#[derive(Serialize, Deserialize)]
+pub struct InvalidRuleResp {
+    #[serde(flatten)]
+    element: RuleSetElement,
+    error: String,
+}
+
+impl From<InvalidRuleSetError> for InvalidRuleResp {
+    fn from(e: InvalidRuleSetError) -> Self {
+        Self {
+            element: e.element().clone(),
+            error: e.error().to_string(),
+        }
+    }
+}
+
+/// Status of the server.
+#[derive(Serialize, Deserialize)]
+pub struct StatusResp {
+    status: String,
+}
+
+/// Environment variable with path to rules config file, reloaded on SIGHUP.
+pub const RULES_CONFIG_VAR: &str = "RULES_CONFIG";
+
+/// Default maximum size of JSON request body in bytes.
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 16 * 1024;
+
+/// Media type requesting legacy `[token, value]` tuple from `/eval`.
+pub const LEGACY_EVAL_MEDIA_TYPE: &str = "application/vnd.legacy+json";
+
+/// Environment variable selecting `NonFinitePolicy`: `reject`, `null` or `string`.
+pub const NON_FINITE_POLICY_VAR: &str = "NON_FINITE_POLICY";
+
+/// Handling of `NaN` and infinite results of `/eval`, which have no JSON representation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Result is rejected with `HttpResponse::UnprocessableEntity()` and error message in JSON.
+    Reject,
+    /// Value is replaced with `null`.
+    #[default]
+    Null,
+    /// Value is replaced with `"NaN"`, `"inf"` or `"-inf"` string.
+    String,
+}
+
+impl NonFinitePolicy {
+    /// Returns policy from `NON_FINITE_POLICY` environment variable,
+    /// `NonFinitePolicy::Null` if it's not set or invalid.
+    pub fn from_env() -> Self {
+        match std::env::var(NON_FINITE_POLICY_VAR).as_deref() {
+            Ok("reject") => NonFinitePolicy::Reject,
+            Ok("string") => NonFinitePolicy::String,
+            _ => NonFinitePolicy::Null,
+        }
+    }
+}
+
+/// Response of `/matches` endpoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MatchesResp {
+    matches: bool,
+}
+
+/// Response of `/classify` endpoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClassifyResp {
+    token: SubstitutionToken,
+}
+
+/// Result of assignment calculation.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EvalResponse {
+    token: SubstitutionToken,
+    /// Precise calculated value.
+    value: f64,
+    /// Value rounded to precision set by `Assignment::set_output_precision`, equals `value` if it's not set.
+    display_value: f64,
+}
+
+impl EvalResponse {
+    /// Builds `EvalResponse` from token, raw value and rounded value.
+    pub fn new(token: SubstitutionToken, value: f64, display_value: f64) -> Self {
+        Self {
+            token,
+            value,
+            display_value,
+        }
+    }
+}
+
+impl From<(SubstitutionToken, f64)> for EvalResponse {
+    fn from((token, value): (SubstitutionToken, f64)) -> Self {
+        Self::new(token, value, value)
+    }
+}
+
+/// Converts to token and rounded value, the same as result of `Assignment::eval`.
+impl From<EvalResponse> for (SubstitutionToken, f64) {
+    fn from(resp: EvalResponse) -> Self {
+        (resp.token, resp.display_value)
+    }
+}
+
+/// Result of a single line of `/eval_stream`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum EvalStreamItem {
+    /// Calculated token and value, same as `/eval` result.
+    Ok(SubstitutionToken, f64),
+    /// Line is malformed or calculation failed.
+    Err { error: String },
+}
+
+/// Input of `/eval_grid` with its result.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EvalGridItem {
+    input: InputSet,
+    /// Calculated token and value or error, same as line of `/eval_stream`.
+    result: EvalStreamItem,
+}
+
+/// Request to preview effect of a candidate rule on sample inputs.
+#[derive(Serialize, Deserialize)]
+pub struct PreviewReq {
+    kind: RuleKind,
+    token: SubstitutionToken,
+    rule_str: String,
+    inputs: Vec<InputSet>,
+}
+
+/// Sample input of `/preview` with its results without and with the candidate rule.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PreviewItem {
+    input: InputSet,
+    before: EvalStreamItem,
+    after: EvalStreamItem,
+    /// `true` if `before` and `after` differ.
+    changed: bool,
+}
+
+impl From<Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>>> for EvalStreamItem {
+    fn from(res: Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>>) -> Self {
+        match res {
+            Ok((token, value)) => EvalStreamItem::Ok(token, value),
+            Err(e) => EvalStreamItem::Err {
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
 /// Endpoint to add new `LogicalRule` to `Assignment`.
 /// Accepts `AddRuleReq` in JSON format.
 ///
@@ -54,16 +565,19 @@ pub struct AddRuleReq {
 /// otherwise returns `HttpResponse::BadRequest` with error message in JSON.
 #[post("/add_logical_rule")]
 pub async fn add_logical_rule(
-    data: web::Data<Arc<RwLock<Assignment>>>,
+    data: TenantAssignment,
     item: web::Json<AddRuleReq>,
 ) -> Result<HttpResponse> {
-    let mut data = (*data).write().unwrap();
-    let res = data.add_logical_rule_from_str(item.token.clone(), item.rule_str.clone());
-
-    if res.is_err() {
-        Ok(HttpResponse::BadRequest().json(res.unwrap_err().to_string()))
-    } else {
-        Ok(HttpResponse::Ok().json(res.unwrap()))
+    let mut data = data.write().await;
+    match data.add_logical_rule_from_str(item.token.clone(), item.rule_str.clone()) {
+        Ok(()) => {
+            tracing::info!(token = ?item.token, rule_str = %item.rule_str, "Logical rule added");
+            Ok(HttpResponse::Ok().finish())
+        }
+        Err(e) => {
+            tracing::info!(token = ?item.token, rule_str = %item.rule_str, error = %e, "Logical rule rejected");
+            Ok(HttpResponse::BadRequest().json(RuleErrorResp::from(e)))
+        }
     }
 }
 
@@ -74,81 +588,1886 @@ pub async fn add_logical_rule(
 /// otherwise returns `HttpResponse::BadRequest` with error message in JSON.
 #[post("/add_arithmetic_rule")]
 pub async fn add_arithmetic_rule(
-    data: web::Data<Arc<RwLock<Assignment>>>,
+    data: TenantAssignment,
     item: web::Json<AddRuleReq>,
 ) -> Result<HttpResponse> {
-    let mut data = (*data).write().unwrap();
-    let res = data.add_arithmetic_rule_from_str(item.token.clone(), item.rule_str.clone());
-
-    if res.is_err() {
-        Ok(HttpResponse::BadRequest().json(res.unwrap_err().to_string()))
-    } else {
-        Ok(HttpResponse::Ok().json(res.unwrap()))
+    let mut data = data.write().await;
+    match data.add_arithmetic_rule_from_str(item.token.clone(), item.rule_str.clone()) {
+        Ok(()) => {
+            tracing::info!(token = ?item.token, rule_str = %item.rule_str, "Arithmetic rule added");
+            Ok(HttpResponse::Ok().finish())
+        }
+        Err(e) => {
+            tracing::info!(token = ?item.token, rule_str = %item.rule_str, error = %e, "Arithmetic rule rejected");
+            Ok(HttpResponse::BadRequest().json(RuleErrorResp::from(e)))
+        }
     }
 }
 
 /// Endpoint to remove rules from `Assignment`.
 #[delete("/remove_rules")]
-pub async fn remove_rules(data: web::Data<Arc<RwLock<Assignment>>>) -> Result<HttpResponse> {
-    let mut data = (*data).write().unwrap();
+pub async fn remove_rules(data: TenantAssignment) -> Result<HttpResponse> {
+    let mut data = data.write().await;
     data.remove_rules();
 
     Ok(HttpResponse::Ok().finish())
 }
 
 /// Endpoint for assignment calculation.
-/// Accepts `InputSet` in JSON format.
+/// Accepts `PartialInputSet` in JSON format.
+/// Absent fields use defaults set by `Assignment::set_input_default`.
+/// Optional `profile` query parameter selects named rule profile of `Assignment`.
 ///
-/// If calculation is successful, returns `HttpResponse::Ok()` with result in JSON,
+/// If calculation is successful, returns `HttpResponse::Ok()` with `EvalResponse` in JSON,
+/// or with `[token, display_value]` tuple if `Accept` header is `LEGACY_EVAL_MEDIA_TYPE`,
+/// `HttpResponse::BadRequest()` with `InputErrorsResp` in JSON if any field has wrong type,
 /// otherwise `HttpResponse::BadRequest()` with error message in JSON.
 #[post("/eval")]
 pub async fn eval(
-    data: web::Data<Arc<RwLock<Assignment>>>,
-    item: web::Json<InputSet>,
+    req: HttpRequest,
+    data: TenantAssignment,
+    query: web::Query<EvalQuery>,
+    item: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse> {
-    let data = (*data).read().unwrap();
-    let res = data.eval(item.0);
+    match parse_partial_input(&item) {
+        Ok(input) => eval_input(&req, &data, query.profile.as_deref(), &input).await,
+        Err(errors) => Ok(HttpResponse::BadRequest().json(errors)),
+    }
+}
 
-    if res.is_err() {
-        Ok(HttpResponse::BadRequest().json(res.unwrap_err().to_string()))
-    } else {
-        Ok(HttpResponse::Ok().json(res.unwrap()))
+/// Endpoint for assignment calculation from browser address bar,
+/// e.g. `/eval?a=true&b=true&c=false&d=2.0&e=3&f=4`.
+/// Accepts `PartialInputSet` and optional `profile` in query parameters.
+/// Absent fields use defaults set by `Assignment::set_input_default`, or default `InputSet` fields if there are none.
+///
+/// Returns the same responses as `/eval`.
+#[get("/eval")]
+pub async fn eval_get(
+    req: HttpRequest,
+    data: TenantAssignment,
+    query: web::Query<EvalQuery>,
+    item: web::Query<PartialInputSet>,
+) -> Result<HttpResponse> {
+    let fallback = InputSet::default();
+    let defaults = data.read().await.input_defaults().clone();
+    let item = PartialInputSet {
+        a: item.a.or(defaults.a).or(Some(fallback.a)),
+        b: item.b.or(defaults.b).or(Some(fallback.b)),
+        c: item.c.or(defaults.c).or(Some(fallback.c)),
+        d: item.d.or(defaults.d).or(Some(fallback.d)),
+        e: item.e.or(defaults.e).or(Some(fallback.e)),
+        f: item.f.or(defaults.f).or(Some(fallback.f)),
+    };
+    eval_input(&req, &data, query.profile.as_deref(), &item).await
+}
+
+/// Endpoint for assignment calculation from HTML form or legacy clients.
+/// Accepts `PartialInputSet` in `application/x-www-form-urlencoded` format.
+///
+/// Returns the same responses as `/eval`.
+#[post("/eval_form")]
+pub async fn eval_form(
+    req: HttpRequest,
+    data: TenantAssignment,
+    query: web::Query<EvalQuery>,
+    item: web::Form<PartialInputSet>,
+) -> Result<HttpResponse> {
+    eval_input(&req, &data, query.profile.as_deref(), &item.0).await
+}
+
+/// Calculates result for `/eval` and `/eval_form` and builds response.
+///
+/// If `Assignment` has evaluation timeout, evaluates its snapshot on the blocking thread pool,
+/// so neither the worker thread nor the lock is held while waiting, see `run_eval`.
+async fn eval_input(
+    req: &HttpRequest,
+    data: &Arc<RwLock<Assignment>>,
+    profile: Option<&str>,
+    item: &PartialInputSet,
+) -> Result<HttpResponse> {
+    let data = data.read().await;
+    let span = tracing::info_span!(
+        "eval",
+        profile = ?profile,
+        a = ?item.a,
+        b = ?item.b,
+        c = ?item.c,
+        d = ?item.d,
+        e = ?item.e,
+        f = ?item.f,
+    );
+    let assignment = match profile {
+        Some(name) => match data.profile(name) {
+            Some(assignment) => assignment,
+            None => {
+                return Ok(HttpResponse::BadRequest().json(format!("Unknown profile: {}.", name)))
+            }
+        },
+        None => &*data,
+    };
+    let input = item.complete(assignment.input_defaults());
+    match assignment.eval_timeout() {
+        Some(_) => {
+            let snapshot = assignment.eval_snapshot();
+            drop(data);
+            let res = match input {
+                Ok(input) => {
+                    let eval_snapshot = Arc::clone(&snapshot);
+                    block_eval(move || eval_snapshot.eval_raw(&input))
+                        .await
+                        .and_then(|res| res)
+                }
+                Err(e) => Err(e),
+            };
+            span.in_scope(|| eval_response(req, &snapshot, res))
+        }
+        None => span.in_scope(|| {
+            let res = input.and_then(|input| assignment.eval_raw(&input));
+            eval_response(req, assignment, res)
+        }),
     }
 }
 
-/// Creates and runs `HttpServer`, adds `Assignment` as server application data and binds endpoints.
-pub async fn run_actix_app() -> std::io::Result<()> {
-    std::env::set_var("RUST_LOG", "actix_web=info");
-    env_logger::init();
+/// Runs evaluation `f` with `Assignment` of `data`.
+///
+/// Evaluation with timeout set by `Assignment::set_eval_timeout` blocks until the rule returns or timeout expires,
+/// so then `f` runs with snapshot of `Assignment` on the blocking thread pool, whose size is bounded by actix,
+/// and neither the worker thread nor the lock is held while waiting.
+/// Otherwise `f` runs on the worker thread.
+async fn run_eval<T, F>(data: &Arc<RwLock<Assignment>>, f: F) -> Result<T>
+where
+    F: FnOnce(&Assignment) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let data = data.read().await;
+    if data.eval_timeout().is_none() {
+        return Ok(f(&data));
+    }
 
-    let data = web::Data::new(Arc::new(RwLock::new(
-        Assignment::new().with_rules(true, true),
-    )));
+    let snapshot = data.eval_snapshot();
+    drop(data);
+    block_eval(move || f(&snapshot))
+        .await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))
+}
 
-    HttpServer::new(move || {
-        App::new()
-            .wrap(middleware::Logger::default())
-            .app_data(data.clone())
-            .service(add_logical_rule)
-            .service(add_arithmetic_rule)
-            .service(eval)
+/// Runs `f` on the blocking thread pool.
+/// Returns error if the pool thread panicked.
+async fn block_eval<T, F>(f: F) -> std::result::Result<T, Box<dyn Error + Send + Sync>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(move || Ok::<_, ()>(f()))
+        .await
+        .map_err(|_| "Evaluation thread panicked.".into())
+}
+
+/// Builds response of `/eval` and `/eval_form` for calculated result.
+fn eval_response(
+    req: &HttpRequest,
+    assignment: &Assignment,
+    res: std::result::Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>>,
+) -> Result<HttpResponse> {
+    let legacy = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(LEGACY_EVAL_MEDIA_TYPE));
+
+    if let Some(metrics) = req.app_data::<web::Data<EvalMetrics>>() {
+        metrics.record(&res);
+    }
+    match res {
+        Ok(res) if !res.1.is_finite() => {
+            tracing::info!(token = ?res.0, value = res.1, "Evaluated");
+            let policy = req
+                .app_data::<web::Data<NonFinitePolicy>>()
+                .map(|policy| *policy.get_ref())
+                .unwrap_or_default();
+            let value = match policy {
+                NonFinitePolicy::Reject => {
+                    return Ok(HttpResponse::UnprocessableEntity()
+                        .json(format!("Result is not a finite number: {}.", res.1)))
+                }
+                NonFinitePolicy::Null => serde_json::Value::Null,
+                NonFinitePolicy::String => res.1.to_string().into(),
+            };
+            if legacy {
+                Ok(HttpResponse::Ok().json(serde_json::json!([res.0, value])))
+            } else {
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "token": res.0,
+                    "value": value,
+                    "display_value": value,
+                })))
+            }
+        }
+        Ok((token, value)) => {
+            tracing::info!(token = ?token, value, "Evaluated");
+            let display_value = assignment.round_output(value);
+            if legacy {
+                Ok(HttpResponse::Ok().json((token, display_value)))
+            } else {
+                Ok(HttpResponse::Ok().json(EvalResponse::new(token, value, display_value)))
+            }
+        }
+        Err(e) => {
+            tracing::info!(error = %e, "Evaluation failed");
+            if let Some(AssignmentError::InputOutOfRange { .. }) = e.downcast_ref() {
+                return Ok(HttpResponse::UnprocessableEntity().json(e.to_string()));
+            }
+            match e.downcast_ref::<MissingArithmeticRuleError>() {
+                Some(missing) => Ok(HttpResponse::BadRequest().json(MissingArithmeticRuleResp {
+                    error: e.to_string(),
+                    matched_token: missing.token().clone(),
+                })),
+                None => Ok(HttpResponse::BadRequest().json(e.to_string())),
+            }
+        }
+    }
+}
+
+/// Endpoint to check if input is handled by any logical rule without calculating result.
+/// Accepts `PartialInputSet` in JSON format.
+///
+/// Returns `HttpResponse::Ok()` with `MatchesResp` in JSON,
+/// `HttpResponse::BadRequest()` with `InputErrorsResp` in JSON if any field has wrong type,
+/// or `HttpResponse::BadRequest()` with error message in JSON if input can't be completed with defaults.
+#[post("/matches")]
+pub async fn matches(
+    data: TenantAssignment,
+    item: web::Json<serde_json::Value>,
+) -> Result<HttpResponse> {
+    let item = match parse_partial_input(&item) {
+        Ok(item) => item,
+        Err(errors) => return Ok(HttpResponse::BadRequest().json(errors)),
+    };
+    let res = run_eval(&data, move |data| {
+        item.complete(data.input_defaults())
+            .map(|input| data.matches_any(&input))
     })
-    .bind("127.0.0.25:8080")?
-    .run()
-    .await
+    .await?;
+    match res {
+        Ok(matches) => Ok(HttpResponse::Ok().json(MatchesResp { matches })),
+        Err(e) => Ok(HttpResponse::BadRequest().json(e.to_string())),
+    }
+}
+
+/// Endpoint to find token of logical rules for input without calculating result.
+/// Accepts `PartialInputSet` in JSON format.
+///
+/// Returns `HttpResponse::Ok()` with `ClassifyResp` in JSON even if token has no arithmetic rule,
+/// `HttpResponse::BadRequest()` with `InputErrorsResp` in JSON if any field has wrong type,
+/// `HttpResponse::UnprocessableEntity()` with error message in JSON if input is out of bounds,
+/// otherwise `HttpResponse::BadRequest()` with error message in JSON.
+#[post("/classify")]
+pub async fn classify(
+    data: TenantAssignment,
+    item: web::Json<serde_json::Value>,
+) -> Result<HttpResponse> {
+    let item = match parse_partial_input(&item) {
+        Ok(item) => item,
+        Err(errors) => return Ok(HttpResponse::BadRequest().json(errors)),
+    };
+    let res = run_eval(&data, move |data| {
+        item.complete(data.input_defaults())
+            .and_then(|input| data.classify(&input))
+    })
+    .await?;
+    match res {
+        Ok(token) => Ok(HttpResponse::Ok().json(ClassifyResp { token })),
+        Err(e) => match e.downcast_ref() {
+            Some(AssignmentError::InputOutOfRange { .. }) => {
+                Ok(HttpResponse::UnprocessableEntity().json(e.to_string()))
+            }
+            _ => Ok(HttpResponse::BadRequest().json(e.to_string())),
+        },
+    }
+}
+
+/// Endpoint to calculate assignment for a batch of inputs as CSV, e.g. for spreadsheets.
+/// Accepts JSON array of `InputSet`.
+///
+/// Returns `HttpResponse::Ok()` with `text/csv` body with columns `a,b,c,d,e,f,token,value,error`,
+/// one row per input, see `Assignment::eval_batch_csv`.
+#[post("/eval_batch.csv")]
+pub async fn eval_batch_csv(
+    data: TenantAssignment,
+    item: web::Json<Vec<InputSet>>,
+) -> Result<HttpResponse> {
+    let csv = run_eval(&data, move |data| data.eval_batch_csv(&item)).await?;
+    Ok(HttpResponse::Ok().content_type("text/csv").body(csv))
+}
+
+/// Endpoint to calculate assignment for each input of a grid, e.g. for sensitivity analysis.
+/// Accepts `EvalGrid` in JSON format.
+///
+/// Returns `HttpResponse::Ok()` with array of `EvalGridItem` in JSON,
+/// or `HttpResponse::BadRequest()` with error message in JSON if any range is invalid
+/// or grid exceeds the limit set by `Assignment::set_max_grid_size`.
+#[post("/eval_grid")]
+pub async fn eval_grid(data: TenantAssignment, item: web::Json<EvalGrid>) -> Result<HttpResponse> {
+    match run_eval(&data, move |data| data.eval_grid(&item)).await? {
+        Ok(res) => Ok(HttpResponse::Ok().json(
+            res.into_iter()
+                .map(|(input, res)| EvalGridItem {
+                    input,
+                    result: match res {
+                        Ok((token, value)) => EvalStreamItem::Ok(token, value),
+                        Err(e) => EvalStreamItem::Err {
+                            error: e.to_string(),
+                        },
+                    },
+                })
+                .collect::<Vec<_>>(),
+        )),
+        Err(e) => Ok(HttpResponse::BadRequest().json(e.to_string())),
+    }
+}
+
+/// Endpoint to preview how a candidate rule would change results of sample inputs.
+/// Accepts `PreviewReq` in JSON format.
+///
+/// The candidate is added to a copy of `Assignment`, the live one is not changed.
+/// Returns `HttpResponse::Ok()` with array of `PreviewItem` in JSON, one per sample input,
+/// otherwise returns `HttpResponse::BadRequest` with `RuleErrorResp` in JSON if the candidate is invalid.
+#[post("/preview")]
+pub async fn preview(data: TenantAssignment, item: web::Json<PreviewReq>) -> Result<HttpResponse> {
+    let mut candidate = data.read().await.clone();
+    let added = match item.kind {
+        RuleKind::Logical => {
+            candidate.add_logical_rule_from_str(item.token.clone(), item.rule_str.clone())
+        }
+        RuleKind::Arithmetic => {
+            candidate.add_arithmetic_rule_from_str(item.token.clone(), item.rule_str.clone())
+        }
+    };
+    if let Err(e) = added {
+        return Ok(HttpResponse::BadRequest().json(RuleErrorResp::from(e)));
+    }
+
+    let inputs = item.into_inner().inputs;
+    let res: Vec<_> = run_eval(&data, move |data| {
+        inputs
+            .into_iter()
+            .map(|input| {
+                let before = EvalStreamItem::from(data.eval(&input));
+                let after = EvalStreamItem::from(candidate.eval(&input));
+                PreviewItem {
+                    input,
+                    changed: before != after,
+                    before,
+                    after,
+                }
+            })
+            .collect()
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Endpoint to validate rule string without adding it to `Assignment`.
+/// Accepts `ValidateRuleReq` in JSON format.
+///
+/// Returns `HttpResponse::Ok()` with `ValidateRuleResp` in JSON.
+#[post("/validate_rule")]
+pub async fn validate_rule(item: web::Json<ValidateRuleReq>) -> Result<HttpResponse> {
+    let res = match item.kind {
+        RuleKind::Logical => LogicalRuleStr::validate(&item.rule_str),
+        RuleKind::Arithmetic => ArithmeticRuleStr::validate(&item.rule_str),
+    };
+
+    Ok(HttpResponse::Ok().json(match res {
+        Ok(()) => ValidateRuleResp {
+            valid: true,
+            error: None,
+            evalexpr_error: None,
+        },
+        Err(e) => {
+            let e = RuleErrorResp::from(e);
+            ValidateRuleResp {
+                valid: false,
+                error: Some(e.error),
+                evalexpr_error: e.evalexpr_error,
+            }
+        }
+    }))
+}
+
+/// Endpoint to show how `evalexpr` parses rule string, e.g. to debug operator precedence.
+/// Accepts `ValidateRuleReq` in JSON format.
+///
+/// Returns `HttpResponse::Ok()` with root `ParseTreeNode` in JSON if rule is valid,
+/// otherwise returns `HttpResponse::BadRequest()` with `RuleErrorResp` in JSON.
+#[post("/parse_tree")]
+pub async fn parse_tree(item: web::Json<ValidateRuleReq>) -> Result<HttpResponse> {
+    let res = match item.kind {
+        RuleKind::Logical => LogicalRuleStr::validate(&item.rule_str),
+        RuleKind::Arithmetic => ArithmeticRuleStr::validate(&item.rule_str),
+    }
+    .and_then(|()| ParseTreeNode::build(&normalize_rule_str(&item.rule_str)));
+
+    match res {
+        Ok(tree) => Ok(HttpResponse::Ok().json(tree)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(RuleErrorResp::from(e))),
+    }
+}
+
+/// Endpoint to apply a single rule to `InputSet` without adding it to `Assignment`.
+/// Accepts `TryRuleReq` in JSON format.
+///
+/// Returns `HttpResponse::Ok()` with `RuleOutput` in JSON if rule is valid and can be applied,
+/// otherwise returns `HttpResponse::BadRequest()` with error message in JSON.
+#[post("/try_rule")]
+pub async fn try_rule(item: web::Json<TryRuleReq>) -> Result<HttpResponse> {
+    let rule = match item.kind {
+        RuleKind::Logical => {
+            LogicalRuleStr::new(item.token.clone(), item.rule_str.clone()).map(Rule::Logical)
+        }
+        RuleKind::Arithmetic => ArithmeticRuleStr::new(item.rule_str.clone())
+            .map(|rule| Rule::Arithmetic(item.token.clone(), rule)),
+    };
+
+    match rule.and_then(|rule| rule.apply(&item.input)) {
+        Ok(output) => Ok(HttpResponse::Ok().json(output)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(e.to_string())),
+    }
+}
+
+/// Endpoint to replace all rules of `Assignment` at once.
+/// Accepts `SerializableAssignment` in JSON format, e.g. returned by `/rules/export`.
+///
+/// All rules are validated before replacement, so either all of them are applied or none.
+/// Returns `HttpResponse::Ok()` if rules replaced successfully,
+/// otherwise returns `HttpResponse::BadRequest()` with `InvalidRuleResp` in JSON.
+#[post("/rules/replace")]
+pub async fn replace_rules(
+    data: TenantAssignment,
+    item: web::Json<SerializableAssignment>,
+) -> Result<HttpResponse> {
+    // Rules are validated under the write lock, so constants can't change before replacement.
+    match data.write().await.replace_from_serializable(&item) {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) => Ok(HttpResponse::BadRequest().json(InvalidRuleResp::from(e))),
+    }
+}
+
+/// Endpoint to retrieve audit log of rule mutations of `Assignment`.
+///
+/// Returns `HttpResponse::Ok()` with array of `AuditEntry` in JSON in order of mutations,
+/// empty if audit log is disabled with `Assignment::set_audit_log_enabled`.
+#[get("/audit")]
+pub async fn audit(data: TenantAssignment) -> Result<HttpResponse> {
+    let data = data.read().await;
+    Ok(HttpResponse::Ok().json(data.audit_log()))
+}
+
+/// Endpoint to export string rules of `Assignment` as `rules.json` attachment.
+///
+/// Returns `HttpResponse::Ok()` with `ExportRulesResp` in JSON,
+/// which can be passed back to `/rules/replace`.
+/// Arithmetic rules are sorted by token.
+#[get("/rules/export")]
+pub async fn export_rules(data: TenantAssignment) -> Result<HttpResponse> {
+    let (rules, skipped) = data.read().await.to_serializable_lossy();
+    let resp = ExportRulesResp {
+        rules,
+        skipped: skipped.into_iter().map(SkippedRule::from).collect(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"rules.json\"",
+        )
+        .json(resp))
+}
+
+/// Reads rules config file and replaces all rules of `Assignment` with its content.
+/// Config file has the same JSON format as `SerializableAssignment`.
+///
+/// All rules are validated before replacement, so either all of them are applied or none.
+/// Returns error and keeps existing rules if file can't be read or parsed, or any rule is invalid.
+pub async fn reload_rules(
+    data: &RwLock<Assignment>,
+    path: &Path,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let config = std::fs::read_to_string(path)?;
+    let rules: SerializableAssignment = serde_json::from_str(&config)?;
+
+    data.write().await.replace_from_serializable(&rules)?;
+    Ok(())
+}
+
+/// Loads rules from `RULES_CONFIG` file and reloads them on every SIGHUP.
+/// Failed load is logged and existing rules are kept.
+#[cfg(unix)]
+fn watch_rules_config(data: Arc<RwLock<Assignment>>) -> std::io::Result<()> {
+    use actix_rt::signal::unix::{signal, SignalKind};
+
+    let path = match std::env::var(RULES_CONFIG_VAR) {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => return Ok(()),
+    };
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    actix_rt::spawn(async move {
+        loop {
+            match reload_rules(&data, &path).await {
+                Ok(()) => log::info!("Rules loaded from {}", path.display()),
+                Err(e) => log::error!("Failed to load rules from {}: {}", path.display(), e),
+            }
+            if hangup.recv().await.is_none() {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Endpoint for calculation of newline-delimited stream of inputs.
+/// Accepts `PartialInputSet` objects in NDJSON format.
+///
+/// Returns `HttpResponse::Ok()` with NDJSON stream of `EvalStreamItem`, one per input line.
+/// Lines are evaluated as they arrive, so the whole batch is never buffered.
+/// Malformed lines produce error item and don't stop the stream.
+/// Lines longer than `MAX_PAYLOAD_SIZE` produce error item and are skipped up to the next newline
+/// without being buffered.
+#[post("/eval_stream")]
+pub async fn eval_stream(data: TenantAssignment, payload: web::Payload) -> Result<HttpResponse> {
+    let lines = eval_lines(Arc::clone(&data), payload, max_payload_size());
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(Box::pin(lines)))
+}
+
+/// Returns stream of `/eval_stream` response lines calculated from NDJSON `payload`.
+/// Lines longer than `max_line` bytes produce error item and are dropped as they arrive.
+fn eval_lines<S, E>(
+    data: Arc<RwLock<Assignment>>,
+    payload: S,
+    max_line: usize,
+) -> impl Stream<Item = Result<Bytes>>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin,
+    E: Into<actix_web::Error>,
+{
+    let state = (payload, Vec::new(), data, false, false);
+
+    stream::unfold(
+        state,
+        move |(mut payload, mut buf, data, mut done, mut skipping)| async move {
+            loop {
+                let line = match buf.iter().position(|b| *b == b'\n') {
+                    Some(pos) if skipping => {
+                        buf.drain(..=pos);
+                        skipping = false;
+                        continue;
+                    }
+                    Some(pos) => buf.drain(..=pos).collect::<Vec<u8>>(),
+                    None if done && skipping => return None,
+                    None if done => std::mem::take(&mut buf),
+                    None => {
+                        if skipping {
+                            buf.clear();
+                        } else if buf.len() > max_line {
+                            buf.clear();
+                            skipping = true;
+                            let item = EvalStreamItem::Err {
+                                error: format!("Line exceeds the limit of {} bytes.", max_line),
+                            };
+                            return Some((
+                                Ok(stream_line(&item)),
+                                (payload, buf, data, done, skipping),
+                            ));
+                        }
+                        match payload.next().await {
+                            Some(Ok(chunk)) => buf.extend_from_slice(&chunk),
+                            Some(Err(e)) => {
+                                return Some((
+                                    Err(e.into()),
+                                    (payload, Vec::new(), data, true, false),
+                                ))
+                            }
+                            None => done = true,
+                        }
+                        continue;
+                    }
+                };
+
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    if done && buf.is_empty() {
+                        return None;
+                    }
+                    continue;
+                }
+
+                let item = if line.len() > max_line + 1 {
+                    EvalStreamItem::Err {
+                        error: format!("Line exceeds the limit of {} bytes.", max_line),
+                    }
+                } else {
+                    eval_stream_line(&data, &line).await
+                };
+                return Some((
+                    Ok::<_, actix_web::Error>(stream_line(&item)),
+                    (payload, buf, data, done, skipping),
+                ));
+            }
+        },
+    )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::assignment::arithmetic_rule::SubstitutionToken;
-    use actix_web::{http, test, web, App};
+/// Calculates `EvalStreamItem` for a single line of `/eval_stream`.
+async fn eval_stream_line(data: &Arc<RwLock<Assignment>>, line: &[u8]) -> EvalStreamItem {
+    match serde_json::from_slice::<PartialInputSet>(line) {
+        Ok(input) => match run_eval(data, move |data| data.eval_partial(&input)).await {
+            Ok(Ok((token, value))) => EvalStreamItem::Ok(token, value),
+            Ok(Err(e)) => EvalStreamItem::Err {
+                error: e.to_string(),
+            },
+            Err(e) => EvalStreamItem::Err {
+                error: e.to_string(),
+            },
+        },
+        Err(e) => EvalStreamItem::Err {
+            error: e.to_string(),
+        },
+    }
+}
+
+/// Serializes `EvalStreamItem` as NDJSON line of `/eval_stream` response.
+fn stream_line(item: &EvalStreamItem) -> Bytes {
+    let mut out = serde_json::to_vec(item).unwrap_or_default();
+    out.push(b'\n');
+    Bytes::from(out)
+}
+
+/// Returns JSON Schema of `InputSet`.
+pub fn input_set_schema() -> serde_json::Value {
+    let integer = serde_json::json!({
+        "type": "integer",
+        "minimum": i32::MIN,
+        "maximum": i32::MAX,
+    });
+
+    serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "InputSet",
+        "type": "object",
+        "properties": {
+            "a": { "type": "boolean" },
+            "b": { "type": "boolean" },
+            "c": { "type": "boolean" },
+            "d": { "type": "number" },
+            "e": integer,
+            "f": integer,
+        },
+        "required": ["a", "b", "c", "d", "e", "f"],
+    })
+}
+
+/// Returns JSON Schema of `AddRuleReq`.
+pub fn add_rule_schema() -> serde_json::Value {
+    serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "AddRuleReq",
+        "type": "object",
+        "properties": {
+            "token": { "type": "string", "enum": ["M", "P", "T"] },
+            "rule_str": { "type": "string" },
+        },
+        "required": ["token", "rule_str"],
+    })
+}
+
+/// Endpoint describing `InputSet` request body.
+/// Returns `HttpResponse::Ok()` with JSON Schema document.
+#[get("/schema/input_set")]
+pub async fn schema_input_set() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(input_set_schema()))
+}
+
+/// Endpoint describing `AddRuleReq` request body.
+/// Returns `HttpResponse::Ok()` with JSON Schema document.
+#[get("/schema/add_rule")]
+pub async fn schema_add_rule() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(add_rule_schema()))
+}
+
+/// Liveness probe endpoint.
+/// Always returns `HttpResponse::Ok()` with `StatusResp` in JSON.
+#[get("/health")]
+pub async fn health() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(StatusResp {
+        status: "ok".to_owned(),
+    }))
+}
+
+/// Readiness probe endpoint.
+/// Returns `HttpResponse::Ok()` if `Assignment` has at least one logical and one arithmetic rule,
+/// otherwise returns `HttpResponse::ServiceUnavailable()`.
+#[get("/ready")]
+pub async fn ready(data: web::Data<Arc<RwLock<Assignment>>>) -> Result<HttpResponse> {
+    let data = data.read().await;
+
+    if data.has_rules() {
+        Ok(HttpResponse::Ok().json(StatusResp {
+            status: "ready".to_owned(),
+        }))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(StatusResp {
+            status: "not ready".to_owned(),
+        }))
+    }
+}
+
+/// Builds `web::JsonConfig` rejecting bodies larger than `limit` bytes
+/// with `HttpResponse::PayloadTooLarge()`.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default().limit(limit)
+}
+
+/// Returns maximum size of JSON request body from `MAX_PAYLOAD_SIZE` environment variable,
+/// `DEFAULT_MAX_PAYLOAD_SIZE` if it's not set or invalid.
+fn max_payload_size() -> usize {
+    std::env::var("MAX_PAYLOAD_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PAYLOAD_SIZE)
+}
+
+/// Registers endpoints working with `Assignment` of request tenant, see `tenant` module.
+pub fn tenant_services(cfg: &mut web::ServiceConfig) {
+    cfg.service(add_logical_rule)
+        .service(add_arithmetic_rule)
+        .service(remove_rules)
+        .service(eval)
+        .service(eval_get)
+        .service(eval_form)
+        .service(matches)
+        .service(classify)
+        .service(eval_grid)
+        .service(eval_batch_csv)
+        .service(preview)
+        .service(replace_rules)
+        .service(export_rules)
+        .service(audit)
+        .service(eval_stream);
+}
+
+/// Creates `HttpServer` with given `Assignment` as server application data, binds endpoints
+/// and starts accepting connections.
+///
+/// Endpoints working with rules are also available for each tenant under `/t/{tenant}` prefix
+/// or with `X-Tenant-Id` header, tenants get isolated copies of base and custom rules on first use.
+///
+/// Returned `Server` is a handle to stop the server, e.g. with `stop(true)` for graceful shutdown,
+/// and a future which resolves when the server is stopped, so it can be embedded into larger application.
+/// Returns error if server address can't be bound.
+pub fn build_actix_server(data: web::Data<Arc<RwLock<Assignment>>>) -> std::io::Result<Server> {
+    let max_payload_size = max_payload_size();
+    #[cfg(feature = "rate-limit")]
+    let limiter = Arc::new(rate_limit::RateLimiter::from_env());
+
+    let mut template = Assignment::new().with_rules(true, true);
+    template.set_audit_log_enabled(true);
+    let tenants = web::Data::new(Tenants::from_env(template));
+    let non_finite_policy = web::Data::new(NonFinitePolicy::from_env());
+    let eval_metrics = web::Data::new(EvalMetrics::default());
+
+    let server = HttpServer::new(move || {
+        let app = App::new()
+            .app_data(data.clone())
+            .app_data(tenants.clone())
+            .app_data(non_finite_policy.clone())
+            .app_data(eval_metrics.clone())
+            .app_data(json_config(max_payload_size))
+            .configure(tenant_services)
+            .service(validate_rule)
+            .service(parse_tree)
+            .service(try_rule)
+            .service(schema_input_set)
+            .service(schema_add_rule)
+            .service(health)
+            .service(ready)
+            .service(web::scope("/t/{tenant}").configure(tenant_services));
+        #[cfg(feature = "prometheus")]
+        let app = app.service(metrics::metrics_prometheus);
+        #[cfg(feature = "rate-limit")]
+        let app = app.wrap(rate_limit::RateLimit::new(limiter.clone()));
+        app.wrap(middleware::Logger::default())
+    })
+    .bind("127.0.0.25:8080")?
+    .run();
+
+    Ok(server)
+}
+
+/// Creates and runs `HttpServer`, adds `Assignment` as server application data and binds endpoints.
+///
+/// Server is stopped gracefully on ctrl-c. SIGTERM is handled by actix in the same way.
+pub async fn run_actix_app() -> std::io::Result<()> {
+    run_actix_app_with_shutdown(async {
+        if actix_rt::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    })
+    .await
+}
+
+/// Creates and runs `HttpServer`, adds `Assignment` as server application data and binds endpoints.
+///
+/// If `RULES_CONFIG` environment variable is set, rules are loaded from that file
+/// and reloaded on SIGHUP. Invalid config is logged and doesn't replace existing rules.
+///
+/// When `shutdown` future resolves, server stops accepting new connections,
+/// waits for in-flight requests to complete and returns.
+pub async fn run_actix_app_with_shutdown<F>(shutdown: F) -> std::io::Result<()>
+where
+    F: Future<Output = ()> + 'static,
+{
+    std::env::set_var("RUST_LOG", "actix_web=info,st_test=info");
+    json_log::init();
+
+    let mut assignment = Assignment::new().with_rules(true, true);
+    assignment.set_audit_log_enabled(true);
+    let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+    #[cfg(unix)]
+    watch_rules_config(data.get_ref().clone())?;
+
+    let server = build_actix_server(data)?;
+
+    let handle = server.clone();
+    actix_rt::spawn(async move {
+        shutdown.await;
+        handle.stop(true).await;
+    });
+
+    server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assignment::{
+        approx_eq,
+        arithmetic_rule::SubstitutionToken,
+        audit::{AuditEntry, AuditOperation},
+        rule::RuleOutput,
+        InputBounds,
+    };
+    use actix_web::{http, test, web, App};
+    use std::time::Duration;
+
+    #[actix_rt::test]
+    async fn test_add_logical_rule() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app =
+            test::init_service(App::new().app_data(data.clone()).service(add_logical_rule)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/add_logical_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::M,
+                rule_str: "A && B".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/add_logical_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::M,
+                rule_str: "A + B".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_add_arithmetic_rule() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(add_arithmetic_rule),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/add_arithmetic_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::M,
+                rule_str: "D + E".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/add_arithmetic_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::M,
+                rule_str: "D && E".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/add_arithmetic_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::M,
+                rule_str: "D ** E".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let body: RuleErrorResp = test::read_body_json(resp).await;
+        assert_eq!(
+            body.evalexpr_error.unwrap(),
+            "An operator expected 2 arguments, but got 1."
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_remove_rules() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(remove_rules)
+                .service(eval),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet {
+                a: true,
+                b: true,
+                c: false,
+                d: 1.0,
+                e: 2,
+                f: 3,
+            })
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::M, 1.2)));
+
+        let req = test::TestRequest::delete()
+            .uri("/remove_rules")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet {
+                a: true,
+                b: true,
+                c: false,
+                d: 1.0,
+                e: 2,
+                f: 3,
+            })
+            .to_request();
+        let resp: String = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, "Failed to apply logical rule.");
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_empty() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::default())
+            .to_request();
+        let resp: String = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, "Failed to apply logical rule.");
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_base_rules() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::default())
+            .to_request();
+        let resp: String = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, "Failed to apply logical rule.");
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet {
+                a: true,
+                b: true,
+                c: false,
+                d: 2.0,
+                e: 3,
+                f: 4,
+            })
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.token, SubstitutionToken::M);
+        assert!(approx_eq(resp.value, 2.6, 1e-9));
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_override_rules() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, true),
+        )));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::default())
+            .to_request();
+        let resp: String = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, "Failed to apply logical rule.");
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet {
+                a: true,
+                b: true,
+                c: false,
+                d: 2.0,
+                e: 3,
+                f: 15,
+            })
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.token, SubstitutionToken::T);
+        assert_eq!(resp.value, 1.0);
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_partial() {
+        let mut assignment = Assignment::new().with_rules(true, false);
+        assignment.set_input_default(PartialInputSet {
+            e: Some(3),
+            ..Default::default()
+        });
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&serde_json::json!({ "a": true, "b": true, "c": false, "d": 2.0, "f": 4 }))
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.token, SubstitutionToken::M);
+        assert!(approx_eq(resp.value, 2.6, 1e-9));
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&serde_json::json!({ "a": true, "b": true, "c": false, "e": 3, "f": 4 }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let resp: String = test::read_body_json(resp).await;
+        assert_eq!(resp, "Field `d` is absent and has no default value.");
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_timeout() {
+        let mut assignment = Assignment::new().with_rules(true, false);
+        assignment.add_arithmetic_rule_from_fn(
+            SubstitutionToken::M,
+            Box::new(|d, e, _| {
+                std::thread::sleep(Duration::from_millis(e as u64));
+                d
+            }),
+        );
+        assignment.set_eval_timeout(Some(Duration::from_millis(100)));
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(eval)
+                .service(eval_grid),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(
+                &serde_json::json!({ "a": true, "b": true, "c": false, "d": 2.0, "e": 0, "f": 4 }),
+            )
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.token, SubstitutionToken::M);
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&serde_json::json!({ "a": true, "b": true, "c": false, "d": 2.0, "e": 1000, "f": 4 }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let resp: String = test::read_body_json(resp).await;
+        assert_eq!(resp, "Evaluation timed out after 100ms.");
+
+        // Grid is evaluated on the blocking thread pool, so the worker keeps running other tasks meanwhile.
+        let grid = EvalGrid {
+            a: true,
+            b: true,
+            c: false,
+            d_range: [2.0, 2.0, 1.0],
+            e_range: [1000, 1000, 1],
+            f_range: [4, 5, 1],
+        };
+        let req = test::TestRequest::post()
+            .uri("/eval_grid")
+            .set_json(&grid)
+            .to_request();
+        let started = std::time::Instant::now();
+        let (resp, ticked): (Vec<EvalGridItem>, _) =
+            futures::future::join(test::read_response_json(&mut app, req), async {
+                actix_rt::time::delay_for(Duration::from_millis(10)).await;
+                started.elapsed()
+            })
+            .await;
+        assert!(ticked < Duration::from_millis(100));
+        assert_eq!(resp.len(), 2);
+        for item in resp {
+            assert_eq!(
+                item.result,
+                EvalStreamItem::Err {
+                    error: "Evaluation timed out after 100ms.".to_owned()
+                }
+            );
+        }
+
+        // Lock is not held by timed out evaluation.
+        data.write().await.set_eval_timeout(None);
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_form() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(eval)
+                .service(eval_form),
+        )
+        .await;
+
+        let input = InputSet::new(true, true, false, 2.0, 3, 4);
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&input)
+            .to_request();
+        let json: EvalResponse = test::read_response_json(&mut app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval_form")
+            .set_form(&input)
+            .to_request();
+        let form: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(form, json);
+        assert_eq!(form.token, SubstitutionToken::M);
+        assert!(approx_eq(form.value, 2.6, 1e-9));
+
+        let req = test::TestRequest::post()
+            .uri("/eval_form")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .set_payload("a=true&b=true&c=false&d=2.0&e=3")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let resp: String = test::read_body_json(resp).await;
+        assert_eq!(resp, "Field `f` is absent and has no default value.");
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_profile() {
+        let mut profile = Assignment::new().with_rules(true, false);
+        profile
+            .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * 3".to_owned())
+            .unwrap();
+        let mut assignment = Assignment::new().with_rules(true, false);
+        assignment.add_profile("2024", profile);
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let input = InputSet::new(true, true, false, 2.0, 3, 4);
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&input)
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.token, SubstitutionToken::M);
+        assert!(approx_eq(resp.value, 2.6, 1e-9));
+
+        let req = test::TestRequest::post()
+            .uri("/eval?profile=2024")
+            .set_json(&input)
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::M, 6.0)));
+
+        let req = test::TestRequest::post()
+            .uri("/eval?profile=2025")
+            .set_json(&input)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let resp: String = test::read_body_json(resp).await;
+        assert_eq!(resp, "Unknown profile: 2025.");
+    }
+
+    #[actix_rt::test]
+    async fn test_matches() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(matches)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/matches")
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 4))
+            .to_request();
+        let resp: MatchesResp = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, MatchesResp { matches: true });
+
+        let req = test::TestRequest::post()
+            .uri("/matches")
+            .set_json(&InputSet::default())
+            .to_request();
+        let resp: MatchesResp = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, MatchesResp { matches: false });
+
+        let req = test::TestRequest::post()
+            .uri("/matches")
+            .set_json(&serde_json::json!({ "a": true }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_classify() {
+        let mut assignment = Assignment::new();
+        assignment
+            .add_logical_rule_from_str(SubstitutionToken::P, "A && !B".to_owned())
+            .unwrap();
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(classify)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/classify")
+            .set_json(&InputSet::new(true, false, false, 2.0, 3, 4))
+            .to_request();
+        let resp: ClassifyResp = test::read_response_json(&mut app, req).await;
+        assert_eq!(
+            resp,
+            ClassifyResp {
+                token: SubstitutionToken::P
+            }
+        );
+
+        for body in [
+            serde_json::to_value(InputSet::default()).unwrap(),
+            serde_json::json!({ "a": true }),
+            serde_json::json!({ "a": "yes", "b": false, "c": false, "d": 0, "e": 0, "f": 0 }),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/classify")
+                .set_json(&body)
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_output_precision() {
+        let mut assignment = Assignment::new().with_rules(true, false);
+        assignment.set_output_precision(Some(0));
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 4))
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::new(SubstitutionToken::M, 2.6, 3.0));
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .header(http::header::ACCEPT, LEGACY_EVAL_MEDIA_TYPE)
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 4))
+            .to_request();
+        let resp: (SubstitutionToken, f64) = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, (SubstitutionToken::M, 3.0));
+
+        data.write().await.set_output_precision(None);
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 4))
+            .to_request();
+        let body: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(
+            body,
+            serde_json::json!({"token": "M", "value": 2.6, "display_value": 2.6})
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_str_rules() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(add_logical_rule)
+                .service(add_arithmetic_rule)
+                .service(eval),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/add_logical_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::M,
+                rule_str: "A && B".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/add_arithmetic_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::M,
+                rule_str: "D + E".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::default())
+            .to_request();
+        let resp: String = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, "Failed to apply logical rule.");
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet {
+                a: true,
+                b: true,
+                c: false,
+                d: 1.0,
+                e: 2,
+                f: 0,
+            })
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::M, 3.0)));
+    }
+
+    #[actix_rt::test]
+    async fn test_validate_rule() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(validate_rule)
+                .service(eval),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/validate_rule")
+            .set_json(&ValidateRuleReq {
+                kind: RuleKind::Logical,
+                rule_str: "A && B".to_owned(),
+            })
+            .to_request();
+        let resp: ValidateRuleResp = test::read_response_json(&mut app, req).await;
+        assert!(resp.valid);
+        assert_eq!(resp.error, None);
+
+        let req = test::TestRequest::post()
+            .uri("/validate_rule")
+            .set_json(&ValidateRuleReq {
+                kind: RuleKind::Arithmetic,
+                rule_str: "D && E".to_owned(),
+            })
+            .to_request();
+        let resp: ValidateRuleResp = test::read_response_json(&mut app, req).await;
+        assert!(!resp.valid);
+        assert_eq!(
+            resp.error.unwrap(),
+            "Expression contains invalid variables or operators."
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/validate_rule")
+            .header("content-type", "application/json")
+            .set_payload(r#"{"kind": "unknown", "rule_str": "A"}"#)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+        // Validation doesn't add rules to `Assignment`.
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::default())
+            .to_request();
+        let resp: String = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, "Failed to apply logical rule.");
+    }
+
+    #[actix_rt::test]
+    async fn test_health() {
+        let mut app = test::init_service(App::new().service(health)).await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp: StatusResp = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp.status, "ok");
+    }
+
+    #[actix_rt::test]
+    async fn test_schema() {
+        let mut app = test::init_service(
+            App::new()
+                .service(schema_input_set)
+                .service(schema_add_rule),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/schema/input_set")
+            .to_request();
+        let schema: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(schema["type"], "object");
+        for field in &["a", "b", "c", "d", "e", "f"] {
+            assert!(schema["properties"].get(field).is_some());
+        }
+        assert_eq!(schema["properties"]["a"]["type"], "boolean");
+        assert_eq!(schema["properties"]["d"]["type"], "number");
+        assert_eq!(schema["properties"]["e"]["type"], "integer");
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["a", "b", "c", "d", "e", "f"])
+        );
+        // Schema describes what server actually accepts.
+        let input = InputSet::new(true, false, true, 1.5, -2, 3);
+        let value = serde_json::to_value(&input).unwrap();
+        assert_eq!(
+            value.as_object().unwrap().len(),
+            schema["properties"].as_object().unwrap().len()
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/schema/add_rule")
+            .to_request();
+        let schema: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(
+            schema["properties"]["token"]["enum"],
+            serde_json::json!(["M", "P", "T"])
+        );
+        assert_eq!(schema["properties"]["rule_str"]["type"], "string");
+        assert_eq!(schema["required"], serde_json::json!(["token", "rule_str"]));
+    }
+
+    #[actix_rt::test]
+    async fn test_ready() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(ready)).await;
+
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(ready)).await;
+
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_async_lock() {
+        let data = Arc::new(RwLock::new(Assignment::new()));
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        let mut guard = data.write().await;
+
+        let reader = data.clone();
+        actix_rt::spawn(async move {
+            let data = reader.read().await;
+            tx.send(data.has_rules()).ok();
+        });
+
+        // Reader waits for the lock without blocking the runtime thread,
+        // so the writer keeps running on the same runtime while holding the lock.
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        guard
+            .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+            .unwrap();
+        guard.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+        drop(guard);
+
+        assert!(rx.await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_replace_rules() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(replace_rules)
+                .service(eval),
+        )
+        .await;
+
+        let input = InputSet {
+            a: true,
+            b: true,
+            c: false,
+            d: 2.0,
+            e: 3,
+            f: 4,
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/rules/replace")
+            .set_json(&serde_json::json!({
+                "logical": [{"token": "P", "rule_str": "A && B"}],
+                "arithmetic": [{"token": "P", "rule_str": "D * E"}],
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&input)
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::P, 6.0)));
+
+        // Replacement with invalid rule keeps existing rules.
+        let req = test::TestRequest::post()
+            .uri("/rules/replace")
+            .set_json(&serde_json::json!({
+                "logical": [{"token": "M", "rule_str": "A"}],
+                "arithmetic": [
+                    {"token": "M", "rule_str": "D"},
+                    {"token": "T", "rule_str": "D && E"},
+                ],
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let resp: InvalidRuleResp = test::read_body_json(resp).await;
+        assert_eq!(
+            resp.element,
+            RuleSetElement::Arithmetic {
+                index: 1,
+                token: SubstitutionToken::T,
+                rule_str: "D && E".to_owned(),
+            }
+        );
+        assert_eq!(
+            resp.error,
+            "Expression contains invalid variables or operators."
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&input)
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::P, 6.0)));
+
+        // Rules may use constants of the assignment.
+        data.write().await.add_constant("rate", 2.5).unwrap();
+        let req = test::TestRequest::post()
+            .uri("/rules/replace")
+            .set_json(&serde_json::json!({
+                "logical": [{"token": "P", "rule_str": "A && B", "priority": 2}],
+                "arithmetic": [{"token": "P", "rule_str": "D * rate"}],
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&input)
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::P, 5.0)));
+        assert_eq!(data.read().await.logical_rule_infos()[0].priority, 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_legacy() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let input = InputSet::new(true, true, false, 2.0, 3, 4);
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .header(http::header::ACCEPT, "application/json")
+            .set_json(&input)
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(
+            resp,
+            serde_json::json!({"token": "M", "value": 2.6, "display_value": 2.6})
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .header(http::header::ACCEPT, LEGACY_EVAL_MEDIA_TYPE)
+            .set_json(&input)
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, serde_json::json!(["M", 2.6]));
+
+        let resp: (SubstitutionToken, f64) = EvalResponse::from((SubstitutionToken::M, 2.6)).into();
+        assert_eq!(resp, (SubstitutionToken::M, 2.6));
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_stream() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app =
+            test::init_service(App::new().app_data(data.clone()).service(eval_stream)).await;
+
+        let body = concat!(
+            r#"{"a":true,"b":true,"c":false,"d":2.0,"e":3,"f":4}"#,
+            "\n",
+            "not json\n",
+            "\n",
+            r#"{"a":false,"b":false,"c":false,"d":0.0,"e":0,"f":0}"#,
+            "\n",
+            r#"{"a":true,"b":true,"c":true,"d":1.0,"e":2,"f":3}"#,
+        );
+        let req = test::TestRequest::post()
+            .uri("/eval_stream")
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        let items: Vec<EvalStreamItem> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(items.len(), 4);
+        assert!(
+            matches!(items[0], EvalStreamItem::Ok(SubstitutionToken::M, value) if approx_eq(value, 2.6, 1e-9))
+        );
+        assert!(matches!(items[1], EvalStreamItem::Err { .. }));
+        assert_eq!(
+            items[2],
+            EvalStreamItem::Err {
+                error: "Failed to apply logical rule.".to_owned()
+            }
+        );
+        assert!(matches!(
+            items[3],
+            EvalStreamItem::Ok(SubstitutionToken::P, _)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_stream_long_line() {
+        let data = Arc::new(RwLock::new(Assignment::new().with_rules(true, false)));
+        let input = r#"{"a":true,"b":true,"c":false,"d":2.0,"e":3,"f":4}"#;
+
+        // Line over the limit arrives in several chunks and is skipped up to its newline.
+        let long_line = format!("{}{}\n{}\n", input, " ".repeat(100), input);
+        let chunks = long_line
+            .as_bytes()
+            .chunks(16)
+            .map(|chunk| Ok::<_, actix_web::Error>(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let items = eval_lines(data.clone(), stream::iter(chunks), 64)
+            .map(|line| serde_json::from_slice::<EvalStreamItem>(&line.unwrap()).unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0],
+            EvalStreamItem::Err {
+                error: "Line exceeds the limit of 64 bytes.".to_owned()
+            }
+        );
+        assert!(matches!(
+            items[1],
+            EvalStreamItem::Ok(SubstitutionToken::M, _)
+        ));
+
+        // Line over the limit, received at once, is rejected through endpoint.
+        let data = web::Data::new(data);
+        let mut app =
+            test::init_service(App::new().app_data(data.clone()).service(eval_stream)).await;
+        let body = format!(
+            "{}{}\n{}\n",
+            input,
+            " ".repeat(DEFAULT_MAX_PAYLOAD_SIZE),
+            input
+        );
+        let req = test::TestRequest::post()
+            .uri("/eval_stream")
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        let items: Vec<EvalStreamItem> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0],
+            EvalStreamItem::Err {
+                error: format!(
+                    "Line exceeds the limit of {} bytes.",
+                    DEFAULT_MAX_PAYLOAD_SIZE
+                )
+            }
+        );
+        assert!(matches!(
+            items[1],
+            EvalStreamItem::Ok(SubstitutionToken::M, _)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_parse_tree() {
+        let mut app = test::init_service(App::new().service(parse_tree)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/parse_tree")
+            .set_json(&serde_json::json!({"kind": "logical", "rule_str": "a || B && !c"}))
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        let var = |name: &str| serde_json::json!({"operator": "VariableIdentifier", "value": name, "children": []});
+        assert_eq!(
+            resp,
+            serde_json::json!({
+                "operator": "RootNode",
+                "value": null,
+                "children": [{
+                    "operator": "Or",
+                    "value": null,
+                    "children": [
+                        var("A"),
+                        {
+                            "operator": "And",
+                            "value": null,
+                            "children": [
+                                var("B"),
+                                {"operator": "Not", "value": null, "children": [var("C")]},
+                            ],
+                        },
+                    ],
+                }],
+            })
+        );
+
+        for (kind, rule_str) in [("logical", "A + B"), ("arithmetic", "D *")] {
+            let req = test::TestRequest::post()
+                .uri("/parse_tree")
+                .set_json(&serde_json::json!({"kind": kind, "rule_str": rule_str}))
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_try_rule() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(try_rule)).await;
+
+        let input = InputSet::new(true, false, false, 1.5, 2, 3);
+        let try_req = |kind, rule_str: &str| {
+            test::TestRequest::post()
+                .uri("/try_rule")
+                .set_json(&TryRuleReq {
+                    kind,
+                    token: SubstitutionToken::T,
+                    rule_str: rule_str.to_owned(),
+                    input: input.clone(),
+                })
+                .to_request()
+        };
+
+        let req = try_req(RuleKind::Logical, "A && !B");
+        let resp: RuleOutput = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, RuleOutput::Logical(Some(SubstitutionToken::T)));
+
+        let req = try_req(RuleKind::Logical, "A && B");
+        let resp: RuleOutput = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, RuleOutput::Logical(None));
+
+        let req = try_req(RuleKind::Arithmetic, "D * E + F");
+        let resp: RuleOutput = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, RuleOutput::Arithmetic(6.0));
+
+        let req = try_req(RuleKind::Arithmetic, "A + D");
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+        // Shared `Assignment` is not changed.
+        assert!(!data.read().await.has_rules());
+    }
 
     #[actix_rt::test]
-    async fn test_add_logical_rule() {
+    async fn test_payload_too_large() {
         let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
-        let mut app =
-            test::init_service(App::new().app_data(data.clone()).service(add_logical_rule)).await;
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .app_data(json_config(64))
+                .service(add_logical_rule),
+        )
+        .await;
 
         let req = test::TestRequest::post()
             .uri("/add_logical_rule")
@@ -164,216 +2483,648 @@ mod tests {
             .uri("/add_logical_rule")
             .set_json(&AddRuleReq {
                 token: SubstitutionToken::M,
-                rule_str: "A + B".to_owned(),
+                rule_str: vec!["A"; 100].join(" && "),
             })
             .to_request();
         let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(resp.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
     }
 
     #[actix_rt::test]
-    async fn test_add_arithmetic_rule() {
-        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+    async fn test_export_rules() {
+        let mut assignment = Assignment::new().with_rules(true, false);
+        assignment.add_arithmetic_rule_from_fn(SubstitutionToken::P, Box::new(|d, _, _| d));
+        assignment.add_constant("rate", 2.0).unwrap();
+        let fallback = assignment
+            .build_arithmetic_rule("D * rate".to_owned())
+            .unwrap();
+        assignment.set_fallback_arithmetic_rule(Some(fallback));
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app =
+            test::init_service(App::new().app_data(data.clone()).service(export_rules)).await;
+
+        let req = test::TestRequest::get().uri("/rules/export").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(
+            resp.headers()
+                .get(http::header::CONTENT_DISPOSITION)
+                .unwrap(),
+            "attachment; filename=\"rules.json\""
+        );
+
+        let body = test::read_body(resp).await;
+        let export: ExportRulesResp = serde_json::from_slice(&body).unwrap();
+        assert_eq!(export.rules.logical.len(), 3);
+        assert_eq!(export.rules.arithmetic.len(), 2);
+        assert_eq!(export.rules.constants["rate"], 2.0);
+        assert_eq!(
+            export.rules.fallback_arithmetic.as_deref(),
+            Some("D * rate")
+        );
+        assert_eq!(export.skipped.len(), 1);
+        assert!(matches!(export.skipped[0].kind, RuleKind::Arithmetic));
+        assert_eq!(export.skipped[0].token, Some(SubstitutionToken::P));
+
+        // Exported rules can be imported back.
+        let imported = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
         let mut app = test::init_service(
             App::new()
-                .app_data(data.clone())
-                .service(add_arithmetic_rule),
+                .app_data(imported.clone())
+                .service(replace_rules)
+                .service(eval),
         )
         .await;
-
         let req = test::TestRequest::post()
-            .uri("/add_arithmetic_rule")
-            .set_json(&AddRuleReq {
-                token: SubstitutionToken::M,
-                rule_str: "D + E".to_owned(),
-            })
+            .uri("/rules/replace")
+            .set_payload(body)
+            .header(http::header::CONTENT_TYPE, "application/json")
             .to_request();
         let resp = test::call_service(&mut app, req).await;
         assert_eq!(resp.status(), http::StatusCode::OK);
 
+        let input = InputSet::new(true, true, false, 2.0, 3, 4);
         let req = test::TestRequest::post()
-            .uri("/add_arithmetic_rule")
-            .set_json(&AddRuleReq {
-                token: SubstitutionToken::M,
-                rule_str: "D && E".to_owned(),
-            })
+            .uri("/eval")
+            .set_json(&input)
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(
+            resp,
+            EvalResponse::from(data.read().await.eval(&input).unwrap())
+        );
+        let imported = imported.read().await;
+        assert_eq!(imported.constants()["rate"], 2.0);
+        assert_eq!(
+            imported.fallback_arithmetic_rule().unwrap().source(),
+            Some("D * rate")
+        );
     }
 
     #[actix_rt::test]
-    async fn test_remove_rules() {
+    async fn test_reload_rules() {
         let data = web::Data::new(Arc::new(RwLock::new(
             Assignment::new().with_rules(true, false),
         )));
-        let mut app = test::init_service(
-            App::new()
-                .app_data(data.clone())
-                .service(remove_rules)
-                .service(eval),
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+
+        let input = InputSet::new(true, true, false, 2.0, 3, 4);
+        let path = std::env::temp_dir().join(format!("st_test_rules_{}.json", std::process::id()));
+
+        std::fs::write(
+            &path,
+            r#"{
+                "logical": [{"token": "P", "rule_str": "A && B"}],
+                "arithmetic": [{"token": "P", "rule_str": "D * E"}]
+            }"#,
         )
-        .await;
+        .unwrap();
+        reload_rules(&data, &path).await.unwrap();
 
         let req = test::TestRequest::post()
             .uri("/eval")
-            .set_json(&InputSet {
-                a: true,
-                b: true,
-                c: false,
-                d: 1.0,
-                e: 2,
-                f: 3,
-            })
+            .set_json(&input)
             .to_request();
-        let resp: (SubstitutionToken, f64) = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp, (SubstitutionToken::M, 1.2));
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::P, 6.0)));
 
-        let req = test::TestRequest::delete()
-            .uri("/remove_rules")
-            .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), http::StatusCode::OK);
+        // Invalid config keeps existing rules.
+        std::fs::write(
+            &path,
+            r#"{
+                "logical": [{"token": "M", "rule_str": "A"}],
+                "arithmetic": [{"token": "M", "rule_str": "D && E"}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            reload_rules(&data, &path).await.unwrap_err().to_string(),
+            "Invalid arithmetic rule \"D && E\": Expression contains invalid variables or operators."
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(reload_rules(&data, &path).await.is_err());
 
         let req = test::TestRequest::post()
             .uri("/eval")
-            .set_json(&InputSet {
-                a: true,
-                b: true,
-                c: false,
-                d: 1.0,
-                e: 2,
-                f: 3,
-            })
+            .set_json(&input)
             .to_request();
-        let resp: String = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp, "Failed to apply logical rule.");
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::P, 6.0)));
     }
 
     #[actix_rt::test]
-    async fn test_eval_empty() {
-        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+    async fn test_run_actix_app_with_shutdown() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let server = run_actix_app_with_shutdown(async {
+            rx.await.ok();
+        });
+
+        tx.send(()).unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(10), server).await;
+        assert!(res.expect("Server should stop after shutdown.").is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_missing_arithmetic_rule() {
+        let mut assignment = Assignment::new();
+        assignment
+            .add_logical_rule_from_str(SubstitutionToken::P, "A".to_owned())
+            .unwrap();
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
         let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
 
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::new(true, false, false, 1.0, 2, 3))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "Failed to find arithmetic rule for token.",
+                "matched_token": "P",
+            })
+        );
+
+        // Other errors are still plain messages.
         let req = test::TestRequest::post()
             .uri("/eval")
             .set_json(&InputSet::default())
             .to_request();
-        let resp: String = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp, "Failed to apply logical rule.");
+        let resp = test::call_service(&mut app, req).await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert!(body.is_string());
     }
 
     #[actix_rt::test]
-    async fn test_eval_base_rules() {
+    async fn test_build_actix_server() {
         let data = web::Data::new(Arc::new(RwLock::new(
-            Assignment::new().with_rules(true, false),
+            Assignment::new().with_rules(true, true),
         )));
-        let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
+        let server = build_actix_server(data).unwrap();
 
+        let addr = "127.0.0.25:8080";
+        let connected = actix_web::web::block(move || std::net::TcpStream::connect(addr)).await;
+        assert!(connected.is_ok());
+
+        let handle = server.clone();
+        actix_rt::spawn(async move { handle.stop(true).await });
+        assert!(server.await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_grid() {
+        let mut assignment = Assignment::new().with_rules(true, false);
+        assignment.set_max_grid_size(4);
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app =
+            test::init_service(App::new().app_data(data.clone()).service(eval_grid)).await;
+
+        let mut grid = EvalGrid {
+            a: true,
+            b: true,
+            c: false,
+            d_range: [1.0, 2.0, 1.0],
+            e_range: [3, 3, 1],
+            f_range: [4, 5, 1],
+        };
         let req = test::TestRequest::post()
-            .uri("/eval")
-            .set_json(&InputSet::default())
+            .uri("/eval_grid")
+            .set_json(&grid)
             .to_request();
-        let resp: String = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp, "Failed to apply logical rule.");
+        let resp: Vec<EvalGridItem> = test::read_response_json(&mut app, req).await;
+        let assignment = data.read().await;
+        let expected: Vec<_> = [(1.0, 4), (1.0, 5), (2.0, 4), (2.0, 5)]
+            .iter()
+            .map(|&(d, f)| {
+                let input = InputSet::new(true, true, false, d, 3, f);
+                let (token, value) = assignment.eval(&input).unwrap();
+                EvalGridItem {
+                    input,
+                    result: EvalStreamItem::Ok(token, value),
+                }
+            })
+            .collect();
+        assert_eq!(resp, expected);
+        drop(assignment);
+
+        grid.f_range = [4, 6, 1];
+        let req = test::TestRequest::post()
+            .uri("/eval_grid")
+            .set_json(&grid)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let body: String = test::read_body_json(resp).await;
+        assert_eq!(body, "Grid of 6 inputs exceeds the limit of 4.");
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_non_finite() {
+        let assignment = Assignment::new()
+            .with_logical_rule_str(SubstitutionToken::M, "A")
+            .unwrap()
+            .with_arithmetic_rule_str(SubstitutionToken::M, "D / F")
+            .unwrap();
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let input = InputSet::new(true, false, false, 1.0, 0, 0);
+
+        let expected = [
+            (
+                None,
+                http::StatusCode::OK,
+                serde_json::json!({ "token": "M", "value": null, "display_value": null }),
+            ),
+            (
+                Some(NonFinitePolicy::Null),
+                http::StatusCode::OK,
+                serde_json::json!({ "token": "M", "value": null, "display_value": null }),
+            ),
+            (
+                Some(NonFinitePolicy::String),
+                http::StatusCode::OK,
+                serde_json::json!({ "token": "M", "value": "inf", "display_value": "inf" }),
+            ),
+            (
+                Some(NonFinitePolicy::Reject),
+                http::StatusCode::UNPROCESSABLE_ENTITY,
+                serde_json::json!("Result is not a finite number: inf."),
+            ),
+        ];
+        for (policy, status, body) in expected.iter() {
+            let mut app = App::new().app_data(data.clone()).service(eval);
+            if let Some(policy) = policy {
+                app = app.app_data(web::Data::new(*policy));
+            }
+            let mut app = test::init_service(app).await;
 
+            let req = test::TestRequest::post()
+                .uri("/eval")
+                .set_json(&input)
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), *status);
+            let resp: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(&resp, body);
+        }
+
+        // NaN in legacy format.
+        data.write()
+            .await
+            .add_arithmetic_rule_from_str(SubstitutionToken::P, "(D - D) / F".to_owned())
+            .unwrap();
+        {
+            let mut assignment = data.write().await;
+            let rule = assignment
+                .build_logical_rule(SubstitutionToken::P, "A".to_owned())
+                .unwrap();
+            assignment.add_logical_rule_with_priority(rule, 1).unwrap();
+        }
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .app_data(web::Data::new(NonFinitePolicy::String))
+                .service(eval),
+        )
+        .await;
         let req = test::TestRequest::post()
             .uri("/eval")
-            .set_json(&InputSet {
-                a: true,
-                b: true,
-                c: false,
-                d: 2.0,
-                e: 3,
-                f: 4,
-            })
+            .header(http::header::ACCEPT, LEGACY_EVAL_MEDIA_TYPE)
+            .set_json(&input)
             .to_request();
-        let resp: (SubstitutionToken, f64) = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp.0, SubstitutionToken::M);
-        assert_eq!(resp.1, 2.6);
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, serde_json::json!(["P", "NaN"]));
     }
 
     #[actix_rt::test]
-    async fn test_eval_override_rules() {
-        let data = web::Data::new(Arc::new(RwLock::new(
-            Assignment::new().with_rules(true, true),
-        )));
+    async fn test_eval_input_bounds() {
+        let mut assignment = Assignment::new().with_rules(true, false);
+        assignment.set_input_bounds(InputBounds {
+            d: None,
+            e: Some((0, 10)),
+            f: Some((0, 10)),
+        });
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
         let mut app = test::init_service(App::new().app_data(data.clone()).service(eval)).await;
 
         let req = test::TestRequest::post()
             .uri("/eval")
-            .set_json(&InputSet::default())
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 4))
             .to_request();
-        let resp: String = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp, "Failed to apply logical rule.");
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
 
         let req = test::TestRequest::post()
             .uri("/eval")
-            .set_json(&InputSet {
-                a: true,
-                b: true,
-                c: false,
-                d: 2.0,
-                e: 3,
-                f: 15,
-            })
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 11))
             .to_request();
-        let resp: (SubstitutionToken, f64) = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp.0, SubstitutionToken::T);
-        assert_eq!(resp.1, 1.0);
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+        let body: String = test::read_body_json(resp).await;
+        assert_eq!(body, "Field `f` is out of range: 11.");
     }
 
     #[actix_rt::test]
-    async fn test_eval_str_rules() {
-        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+    async fn test_eval_batch_csv() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app =
+            test::init_service(App::new().app_data(data.clone()).service(eval_batch_csv)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval_batch.csv")
+            .set_json(&vec![
+                InputSet::new(true, true, false, 2.0, 5, 4),
+                InputSet::default(),
+            ])
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(http::header::CONTENT_TYPE).unwrap(),
+            "text/csv"
+        );
+        let body = test::read_body(resp).await;
+        let lines: Vec<_> = std::str::from_utf8(&body).unwrap().lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "a,b,c,d,e,f,token,value,error",
+                "true,true,false,2,5,4,M,3,",
+                "false,false,false,0,0,0,,,Failed to apply logical rule.",
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_input_errors() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
         let mut app = test::init_service(
             App::new()
                 .app_data(data.clone())
-                .service(add_logical_rule)
-                .service(add_arithmetic_rule)
-                .service(eval),
+                .service(eval)
+                .service(matches),
         )
         .await;
 
+        for uri in ["/eval", "/matches"] {
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .set_json(&serde_json::json!({
+                    "a": "yes",
+                    "b": true,
+                    "c": null,
+                    "d": "2.0x",
+                    "e": 1.5,
+                    "f": 4,
+                }))
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+            let body: InputErrorsResp = test::read_body_json(resp).await;
+            let fields: Vec<_> = body.errors.keys().map(String::as_str).collect();
+            assert_eq!(fields, vec!["a", "d", "e"]);
+            assert!(body.errors["a"].contains("expected a boolean"));
+        }
+
         let req = test::TestRequest::post()
-            .uri("/add_logical_rule")
-            .set_json(&AddRuleReq {
-                token: SubstitutionToken::M,
-                rule_str: "A && B".to_owned(),
-            })
+            .uri("/eval")
+            .set_json(&serde_json::json!([true]))
             .to_request();
         let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
 
         let req = test::TestRequest::post()
-            .uri("/add_arithmetic_rule")
-            .set_json(&AddRuleReq {
-                token: SubstitutionToken::M,
-                rule_str: "D + E".to_owned(),
-            })
+            .uri("/eval")
+            .set_json(&serde_json::json!({"a": true, "b": true, "c": false, "d": 2.0, "e": 5, "f": 4, "g": "x"}))
             .to_request();
         let resp = test::call_service(&mut app, req).await;
         assert_eq!(resp.status(), http::StatusCode::OK);
 
         let req = test::TestRequest::post()
             .uri("/eval")
-            .set_json(&InputSet::default())
+            .set_json(&serde_json::json!({"a": "true", "b": true, "c": "false", "d": "2.0", "e": "5", "f": 4}))
             .to_request();
-        let resp: String = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp, "Failed to apply logical rule.");
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::M, 3.0)));
+    }
+
+    #[actix_rt::test]
+    async fn test_preview() {
+        let assignment = Assignment::new()
+            .with_rules(true, false)
+            .with_arithmetic_rule_str(SubstitutionToken::M, "D * 2")
+            .unwrap();
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app = test::init_service(App::new().app_data(data.clone()).service(preview)).await;
 
+        let inputs = vec![
+            InputSet::new(true, true, false, 2.0, 5, 4),
+            InputSet::new(false, false, false, 2.0, 4, 4),
+        ];
         let req = test::TestRequest::post()
-            .uri("/eval")
-            .set_json(&InputSet {
-                a: true,
-                b: true,
-                c: false,
-                d: 1.0,
-                e: 2,
-                f: 0,
+            .uri("/preview")
+            .set_json(&PreviewReq {
+                kind: RuleKind::Logical,
+                token: SubstitutionToken::P,
+                rule_str: "!A && !B && !C".to_owned(),
+                inputs: inputs.clone(),
             })
             .to_request();
-        let resp: (SubstitutionToken, f64) = test::read_response_json(&mut app, req).await;
-        assert_eq!(resp, (SubstitutionToken::M, 3.0));
+        let resp: Vec<PreviewItem> = test::read_response_json(&mut app, req).await;
+        let m = data.read().await.eval(&inputs[0]).unwrap();
+        assert_eq!(
+            resp,
+            vec![
+                PreviewItem {
+                    input: inputs[0].clone(),
+                    before: EvalStreamItem::Ok(m.0.clone(), m.1),
+                    after: EvalStreamItem::Ok(m.0, m.1),
+                    changed: false,
+                },
+                PreviewItem {
+                    input: inputs[1].clone(),
+                    before: EvalStreamItem::Err {
+                        error: "Failed to apply logical rule.".to_owned()
+                    },
+                    after: EvalStreamItem::Ok(SubstitutionToken::P, 2.0),
+                    changed: true,
+                },
+            ]
+        );
+
+        // Candidate arithmetic rule replaces `D * 2`.
+        let req = test::TestRequest::post()
+            .uri("/preview")
+            .set_json(&PreviewReq {
+                kind: RuleKind::Arithmetic,
+                token: SubstitutionToken::M,
+                rule_str: "D * 3".to_owned(),
+                inputs: inputs[..1].to_vec(),
+            })
+            .to_request();
+        let resp: Vec<PreviewItem> = test::read_response_json(&mut app, req).await;
+        assert_eq!(
+            resp[0].before,
+            EvalStreamItem::Ok(SubstitutionToken::M, 4.0)
+        );
+        assert_eq!(resp[0].after, EvalStreamItem::Ok(SubstitutionToken::M, 6.0));
+        assert!(resp[0].changed);
+
+        let req = test::TestRequest::post()
+            .uri("/preview")
+            .set_json(&PreviewReq {
+                kind: RuleKind::Arithmetic,
+                token: SubstitutionToken::M,
+                rule_str: "D +".to_owned(),
+                inputs,
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+        // Live assignment is not changed.
+        let assignment = data.read().await;
+        assert_eq!(assignment.logical_rules().count(), 3);
+        assert_eq!(assignment.arithmetic_rules().count(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_add_rule_sloppy_token() {
+        let data = web::Data::new(Arc::new(RwLock::new(Assignment::new())));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(add_logical_rule)
+                .service(add_arithmetic_rule),
+        )
+        .await;
+
+        for (uri, token, rule_str, status) in [
+            ("/add_logical_rule", " m ", "A", http::StatusCode::OK),
+            ("/add_arithmetic_rule", "M", "D", http::StatusCode::OK),
+            (
+                "/add_arithmetic_rule",
+                "x",
+                "D",
+                http::StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .set_json(&serde_json::json!({"token": token, "rule_str": rule_str}))
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), status, "{} {}", uri, token);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_audit() {
+        let mut assignment = Assignment::new();
+        assignment.set_audit_log_enabled(true);
+        let data = web::Data::new(Arc::new(RwLock::new(assignment)));
+        let mut app =
+            test::init_service(App::new().app_data(data.clone()).configure(tenant_services)).await;
+
+        for req in [
+            test::TestRequest::post()
+                .uri("/add_logical_rule")
+                .set_json(&AddRuleReq {
+                    token: SubstitutionToken::M,
+                    rule_str: "A && B".to_owned(),
+                }),
+            test::TestRequest::post()
+                .uri("/add_arithmetic_rule")
+                .set_json(&AddRuleReq {
+                    token: SubstitutionToken::M,
+                    rule_str: "D + E".to_owned(),
+                }),
+            test::TestRequest::delete().uri("/remove_rules"),
+        ] {
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), http::StatusCode::OK);
+        }
+
+        let req = test::TestRequest::get().uri("/audit").to_request();
+        let resp: Vec<AuditEntry> = test::read_response_json(&mut app, req).await;
+        let entries: Vec<_> = resp
+            .into_iter()
+            .map(|e| (e.operation, e.token, e.source))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    AuditOperation::AddLogical,
+                    Some(SubstitutionToken::M),
+                    Some("A && B".to_owned())
+                ),
+                (
+                    AuditOperation::AddArithmetic,
+                    Some(SubstitutionToken::M),
+                    Some("D + E".to_owned())
+                ),
+                (AuditOperation::Remove, None, None),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_eval_get() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .service(eval)
+                .service(eval_get),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .set_json(&InputSet::new(true, true, false, 2.0, 3, 4))
+            .to_request();
+        let post: EvalResponse = test::read_response_json(&mut app, req).await;
+        let req = test::TestRequest::get()
+            .uri("/eval?a=true&b=true&c=false&d=2.0&e=3&f=4")
+            .to_request();
+        let get: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(get, post);
+
+        // Absent `c`, `e` and `f` are `false` and `0`.
+        let req = test::TestRequest::get()
+            .uri("/eval?a=true&b=true&d=2")
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        let expected = data
+            .read()
+            .await
+            .eval(&InputSet::new(true, true, false, 2.0, 0, 0))
+            .unwrap();
+        assert_eq!(resp, EvalResponse::from(expected));
+
+        // Defaults of `Assignment` take precedence.
+        data.write().await.set_input_default(PartialInputSet {
+            e: Some(3),
+            f: Some(4),
+            ..PartialInputSet::default()
+        });
+        let req = test::TestRequest::get()
+            .uri("/eval?a=true&b=true&d=2.0")
+            .to_request();
+        let get: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(get, post);
+
+        let req = test::TestRequest::get().uri("/eval?d=abc").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
     }
 }
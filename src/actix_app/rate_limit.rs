@@ -0,0 +1,284 @@
+//! Per-IP token bucket rate limiting middleware.
+//!
+//! Each client IP has a bucket of `burst` tokens refilled at `per_sec` tokens per second.
+//! Every request takes one token, requests without tokens get `HttpResponse::TooManyRequests()`.
+//! Buckets of clients idle for `idle_ttl` are evicted whatever their tokens, so memory doesn't grow with every client seen.
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorTooManyRequests,
+    Error,
+};
+use futures::future::{ok, Either, Ready};
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Default time after which bucket of idle client is evicted.
+const DEFAULT_IDLE_TTL: Duration = Duration::from_secs(60);
+
+/// Token bucket of a single client.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Returns number of tokens refilled up to `burst` at given time.
+    fn tokens_at(&self, now: Instant, burst: f64, per_sec: f64) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * per_sec).min(burst)
+    }
+}
+
+/// Token buckets of all clients with time of the last eviction sweep.
+struct Buckets {
+    map: HashMap<IpAddr, Bucket>,
+    swept: Instant,
+}
+
+/// Shared state of token buckets for all clients.
+pub struct RateLimiter {
+    burst: f64,
+    per_sec: f64,
+    idle_ttl: Duration,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Builds `RateLimiter`.
+    ///
+    /// # Arguments
+    /// * `burst` - Maximum number of requests client can make at once.
+    /// * `per_sec` - Number of requests per second client can make on average.
+    pub fn new(burst: u32, per_sec: f64) -> Self {
+        Self {
+            burst: burst as f64,
+            per_sec,
+            idle_ttl: DEFAULT_IDLE_TTL,
+            buckets: Mutex::new(Buckets {
+                map: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// Sets time after which bucket of idle client is evicted, 60 seconds by default.
+    /// Buckets are evicted even if they are not refilled to `burst`, e.g. when `per_sec` is zero,
+    /// and evicted client starts with a full bucket again.
+    pub fn with_idle_ttl(mut self, idle_ttl: Duration) -> Self {
+        self.idle_ttl = idle_ttl;
+        self
+    }
+
+    /// Builds `RateLimiter` with limits from `RATE_LIMIT_BURST` and `RATE_LIMIT_PER_SEC`
+    /// environment variables, 100 requests and 50 requests per second by default.
+    pub fn from_env() -> Self {
+        let burst = std::env::var("RATE_LIMIT_BURST")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(100);
+        let per_sec = std::env::var("RATE_LIMIT_PER_SEC")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(50.0);
+        Self::new(burst, per_sec)
+    }
+
+    /// Takes a token from the bucket of given client.
+    /// Returns `true` if request is allowed, `false` if client exceeded the limit.
+    ///
+    /// Buckets idle for `idle_ttl` are evicted at most once per `idle_ttl`.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(buckets.swept) >= self.idle_ttl {
+            let idle_ttl = self.idle_ttl;
+            buckets
+                .map
+                .retain(|_, bucket| now.duration_since(bucket.updated) < idle_ttl);
+            buckets.swept = now;
+        }
+
+        let bucket = buckets.map.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.tokens_at(now, self.burst, self.per_sec);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Middleware limiting request rate per client IP with shared `RateLimiter`.
+#[derive(Clone)]
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    /// Builds `RateLimit` middleware.
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S> for RateLimit
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        })
+    }
+}
+
+/// Service created by `RateLimit` middleware.
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service for RateLimitMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        // Requests without known peer address are not limited.
+        let allowed = req
+            .peer_addr()
+            .is_none_or(|addr| self.limiter.check(addr.ip()));
+
+        if allowed {
+            Either::Left(self.service.call(req))
+        } else {
+            Either::Right(ok(
+                req.error_response(ErrorTooManyRequests("Too many requests."))
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{get, http, test, App, HttpResponse};
+    use std::time::Duration;
+
+    #[test]
+    fn test_check() {
+        let limiter = RateLimiter::new(2, 0.0);
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+
+        assert!(limiter.check(ip));
+        assert!(limiter.check(ip));
+        assert!(!limiter.check(ip));
+        assert!(limiter.check(other));
+    }
+
+    #[test]
+    fn test_refill() {
+        let limiter = RateLimiter::new(1, 1000.0);
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert!(limiter.check(ip));
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(limiter.check(ip));
+    }
+
+    #[test]
+    fn test_evict_idle() {
+        let limiter = RateLimiter::new(1, 1000.0).with_idle_ttl(Duration::from_millis(20));
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+
+        assert!(limiter.check(ip));
+        assert!(limiter.check(other));
+        assert_eq!(limiter.buckets.lock().unwrap().map.len(), 2);
+
+        // Both buckets are refilled and evicted by the next check, which creates a new bucket of `ip`.
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check(ip));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.map.len(), 1);
+        assert!(buckets.map.contains_key(&ip));
+    }
+
+    #[test]
+    fn test_evict_empty_idle() {
+        // Bucket which is never refilled is evicted after `idle_ttl` as well.
+        let limiter = RateLimiter::new(1, 0.0).with_idle_ttl(Duration::from_millis(20));
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+
+        assert!(limiter.check(ip));
+        assert!(!limiter.check(ip));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check(other));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.map.len(), 1);
+        assert!(buckets.map.contains_key(&other));
+    }
+
+    #[get("/")]
+    async fn index() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_rt::test]
+    async fn test_rate_limit() {
+        let limiter = Arc::new(RateLimiter::new(1, 0.0));
+        let mut app =
+            test::init_service(App::new().wrap(RateLimit::new(limiter)).service(index)).await;
+
+        let addr = "127.0.0.1:1234".parse().unwrap();
+        let req = test::TestRequest::get()
+            .uri("/")
+            .peer_addr(addr)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .peer_addr(addr)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,289 @@
+//! Isolated `Assignment` per tenant behind a single server.
+//!
+//! Tenant is taken from `{tenant}` segment of `/t/{tenant}/...` path or from `X-Tenant-Id` header,
+//! path takes precedence. Requests without tenant use `Assignment` set as server application data.
+//! Assignment of a tenant is created from template on first use,
+//! up to `MAX_TENANTS` tenants (100 by default); requests of further new tenants get `HttpResponse::Forbidden()`.
+
+use actix_web::{
+    dev::Payload,
+    error::{ErrorForbidden, ErrorInternalServerError},
+    web, Error, FromRequest, HttpRequest,
+};
+use futures::future::{ready, Ready};
+
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::RwLock;
+
+use crate::assignment::Assignment;
+
+/// Name of header selecting tenant.
+pub const TENANT_HEADER: &str = "X-Tenant-Id";
+
+/// Default maximum number of tenants.
+pub const DEFAULT_MAX_TENANTS: usize = 100;
+
+/// Shared assignments of all tenants.
+pub struct Tenants {
+    template: Assignment,
+    max_tenants: usize,
+    assignments: Mutex<HashMap<String, Arc<RwLock<Assignment>>>>,
+}
+
+impl Tenants {
+    /// Builds `Tenants` without tenants, allowing up to `DEFAULT_MAX_TENANTS` of them.
+    ///
+    /// # Arguments
+    /// * `template` - Assignment copied for each new tenant.
+    ///   Its audit log is cleared, so tenants don't inherit mutation history of the template.
+    pub fn new(mut template: Assignment) -> Self {
+        if template.is_audit_log_enabled() {
+            template.set_audit_log_enabled(true);
+        }
+        Self {
+            template,
+            max_tenants: DEFAULT_MAX_TENANTS,
+            assignments: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets maximum number of tenants, `DEFAULT_MAX_TENANTS` by default.
+    pub fn with_max_tenants(mut self, max_tenants: usize) -> Self {
+        self.max_tenants = max_tenants;
+        self
+    }
+
+    /// Builds `Tenants` with maximum number of tenants from `MAX_TENANTS` environment variable,
+    /// `DEFAULT_MAX_TENANTS` if it's not set or invalid.
+    pub fn from_env(template: Assignment) -> Self {
+        let max_tenants = std::env::var("MAX_TENANTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_TENANTS);
+        Self::new(template).with_max_tenants(max_tenants)
+    }
+
+    /// Returns `Assignment` of given tenant, creating it from template on first use.
+    /// Returns error if tenant is new and the number of tenants reached the limit.
+    pub fn get_or_create(
+        &self,
+        tenant: &str,
+    ) -> Result<Arc<RwLock<Assignment>>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut assignments = self.assignments.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(assignment) = assignments.get(tenant) {
+            return Ok(assignment.clone());
+        }
+        if assignments.len() >= self.max_tenants {
+            Err(format!(
+                "Number of tenants can't exceed {}.",
+                self.max_tenants
+            ))?
+        }
+
+        let assignment = Arc::new(RwLock::new(self.template.clone()));
+        assignments.insert(tenant.to_owned(), assignment.clone());
+        Ok(assignment)
+    }
+
+    /// Returns sorted ids of tenants created so far.
+    pub fn tenants(&self) -> Vec<String> {
+        let mut tenants: Vec<_> = self
+            .assignments
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect();
+        tenants.sort();
+        tenants
+    }
+}
+
+/// Extractor of `Assignment` of request tenant.
+///
+/// Resolves to assignment of tenant from `Tenants` application data if request has tenant,
+/// otherwise to `Assignment` application data.
+pub struct TenantAssignment(Arc<RwLock<Assignment>>);
+
+impl Deref for TenantAssignment {
+    type Target = Arc<RwLock<Assignment>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Returns tenant of request from path or header, `None` if it has none.
+fn request_tenant(req: &HttpRequest) -> Option<&str> {
+    req.match_info()
+        .get("tenant")
+        .or_else(|| {
+            req.headers()
+                .get(TENANT_HEADER)
+                .and_then(|v| v.to_str().ok())
+        })
+        .filter(|tenant| !tenant.is_empty())
+}
+
+impl FromRequest for TenantAssignment {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let res = match request_tenant(req) {
+            Some(tenant) => match req.app_data::<web::Data<Tenants>>() {
+                Some(tenants) => tenants
+                    .get_or_create(tenant)
+                    .map(TenantAssignment)
+                    .map_err(|e| ErrorForbidden(e.to_string())),
+                None => Err(ErrorInternalServerError("Tenants are not configured.")),
+            },
+            None => req
+                .app_data::<web::Data<Arc<RwLock<Assignment>>>>()
+                .map(|data| TenantAssignment(data.get_ref().clone()))
+                .ok_or_else(|| ErrorInternalServerError("Assignment is not configured.")),
+        };
+        ready(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actix_app::{tenant_services, AddRuleReq, EvalResponse},
+        assignment::{arithmetic_rule::SubstitutionToken, InputSet},
+    };
+    use actix_web::{http, test, App};
+
+    #[actix_rt::test]
+    async fn test_tenants() {
+        let data = web::Data::new(Arc::new(RwLock::new(
+            Assignment::new().with_rules(true, false),
+        )));
+        let tenants = web::Data::new(Tenants::new(Assignment::new().with_rules(true, false)));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .app_data(tenants.clone())
+                .configure(tenant_services)
+                .service(web::scope("/t/{tenant}").configure(tenant_services)),
+        )
+        .await;
+
+        // Tenant `a` routes `!A && !B && !C` to `T`.
+        let req = test::TestRequest::post()
+            .uri("/t/a/add_logical_rule")
+            .set_json(&AddRuleReq {
+                token: SubstitutionToken::T,
+                rule_str: "!A && !B && !C".to_owned(),
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(tenants.tenants(), vec!["a"]);
+
+        let input = InputSet::new(false, false, false, 3.0, 0, 0);
+        let req = test::TestRequest::post()
+            .uri("/t/a/eval")
+            .set_json(&input)
+            .to_request();
+        let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp, EvalResponse::from((SubstitutionToken::T, 3.0)));
+
+        // The same tenant selected with header.
+        let req = test::TestRequest::post()
+            .uri("/eval")
+            .header(TENANT_HEADER, "a")
+            .set_json(&input)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        // Neither tenant `b` nor the default assignment see the rule.
+        for req in [
+            test::TestRequest::post().uri("/t/b/eval"),
+            test::TestRequest::post()
+                .uri("/eval")
+                .header(TENANT_HEADER, "b"),
+            test::TestRequest::post().uri("/eval"),
+        ] {
+            let resp = test::call_service(&mut app, req.set_json(&input).to_request()).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(tenants.tenants(), vec!["a", "b"]);
+        assert_eq!(data.read().await.logical_rules().count(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_max_tenants() {
+        let mut template = Assignment::new().with_rules(true, false);
+        template.set_audit_log_enabled(true);
+        template
+            .add_logical_rule_from_str(SubstitutionToken::T, "!A".to_owned())
+            .unwrap();
+        assert_eq!(template.audit_log().len(), 1);
+        let tenants = web::Data::new(Tenants::new(template).with_max_tenants(2));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(tenants.clone())
+                .service(web::scope("/t/{tenant}").configure(tenant_services)),
+        )
+        .await;
+
+        let input = InputSet::new(true, true, false, 2.0, 5, 4);
+        for (tenant, status) in [
+            ("a", http::StatusCode::OK),
+            ("b", http::StatusCode::OK),
+            ("c", http::StatusCode::FORBIDDEN),
+            ("a", http::StatusCode::OK),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/t/{}/eval", tenant))
+                .set_json(&input)
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), status, "{}", tenant);
+        }
+        assert_eq!(tenants.tenants(), vec!["a", "b"]);
+
+        // Tenants start with empty audit log, but keep rules of the template.
+        let a = tenants.get_or_create("a").unwrap();
+        assert!(a.read().await.audit_log().is_empty());
+        assert_eq!(a.read().await.logical_rules().count(), 4);
+    }
+
+    #[actix_rt::test]
+    async fn test_tenants_poisoned_lock() {
+        let tenants = web::Data::new(Tenants::new(Assignment::new().with_rules(true, false)));
+        let poisoner = tenants.clone();
+        std::thread::spawn(move || {
+            let _guard = poisoner.assignments.lock().unwrap();
+            panic!("Handler panicked while holding the lock.");
+        })
+        .join()
+        .unwrap_err();
+        assert!(tenants.assignments.is_poisoned());
+
+        let mut app = test::init_service(
+            App::new()
+                .app_data(tenants.clone())
+                .service(web::scope("/t/{tenant}").configure(tenant_services)),
+        )
+        .await;
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .uri("/t/a/eval")
+                .set_json(&InputSet::new(true, true, false, 2.0, 5, 4))
+                .to_request();
+            let resp: EvalResponse = test::read_response_json(&mut app, req).await;
+            assert_eq!(resp, EvalResponse::from((SubstitutionToken::M, 3.0)));
+        }
+        assert_eq!(tenants.tenants(), vec!["a"]);
+    }
+}
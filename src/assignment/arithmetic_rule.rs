@@ -1,20 +1,217 @@
 use evalexpr::*;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use std::error::Error;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+
+use crate::assignment::{
+    constants_pattern, normalize_rule_str, referenced_vars, set_constants, RuleLimits,
+    RuleMetadata, ValidationError,
+};
 
 /// Contains possible substitution tokens for `LogicalRule` and `ArithmeticRule`.
-#[derive(Hash, Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+///
+/// Tokens are ordered by declaration: `M < P < T`.
+/// Serialized as its name, so non-self-describing formats like bincode can read it back.
+/// Deserialized the same way as parsed with `FromStr`, e.g. from `" m "`.
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Debug)]
 pub enum SubstitutionToken {
     M,
     P,
     T,
 }
 
+impl FromStr for SubstitutionToken {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    /// Parses token name ignoring case and surrounding whitespace, including Unicode one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_uppercase().as_str() {
+            "M" => Ok(SubstitutionToken::M),
+            "P" => Ok(SubstitutionToken::P),
+            "T" => Ok(SubstitutionToken::T),
+            _ => Err(format!(
+                "Unknown substitution token `{}`, expected M, P or T.",
+                s
+            ))?,
+        }
+    }
+}
+
+impl Serialize for SubstitutionToken {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            SubstitutionToken::M => "M",
+            SubstitutionToken::P => "P",
+            SubstitutionToken::T => "T",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for SubstitutionToken {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Defines how `f64` rule result is converted to integer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Rounds to the nearest integer, half-way cases away from zero.
+    #[default]
+    Nearest,
+    /// Rounds down to the largest integer less than or equal to the value.
+    Floor,
+    /// Drops fractional part of the value.
+    Trunc,
+}
+
+impl RoundingMode {
+    /// Returns `value` rounded according to the mode as `i64`.
+    /// Returns error if rounded value is not finite or doesn't fit into `i64`.
+    pub fn round(self, value: f64) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let rounded = match self {
+            RoundingMode::Nearest => value.round(),
+            RoundingMode::Floor => value.floor(),
+            RoundingMode::Trunc => value.trunc(),
+        };
+
+        if !rounded.is_finite() || rounded < i64::MIN as f64 || rounded >= i64::MAX as f64 {
+            Err("Arithmetic rule result can't be represented as integer.")?
+        }
+
+        Ok(rounded as i64)
+    }
+}
+
+/// Defines how division by zero in `ArithmeticRuleStr` is treated.
+///
+/// Rules built from functions are not affected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DivisionPolicy {
+    /// Division follows `evalexpr`, so division by zero gives infinity or NaN.
+    #[default]
+    Standard,
+    /// Division by zero gives zero.
+    SafeZero,
+    /// Division by zero is an error.
+    Error,
+}
+
+/// Defines how result is obtained when several arithmetic rules are registered for the same token.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Result of the first registered rule.
+    First,
+    /// Result of the last registered rule.
+    #[default]
+    Last,
+    /// Sum of results of all rules.
+    Sum,
+    /// Average of results of all rules.
+    Average,
+}
+
+/// Returns `a / b`, or `fallback` if `b` is zero.
+pub fn safe_divide(a: f64, b: f64, fallback: f64) -> f64 {
+    if b == 0.0 {
+        fallback
+    } else {
+        a / b
+    }
+}
+
 pub trait ArithmeticRule: Send + Sync {
     /// Returns result of rule calculation as `f64`.
-    fn apply(&self, d: f64, e: i32, f: i32) -> f64;
+    /// Returns error if rule can't be evaluated.
+    fn apply(&self, d: f64, e: i32, f: i32) -> Result<f64, Box<dyn Error + Send + Sync>>;
+
+    /// Returns result of rule calculation as `f64` with division treated according to `DivisionPolicy`.
+    /// Returns error if divisor is zero and policy is `DivisionPolicy::Error`.
+    ///
+    /// By default policy is ignored and result of `apply` is returned.
+    fn apply_with_policy(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        _policy: DivisionPolicy,
+    ) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        self.apply(d, e, f)
+    }
+
+    /// Returns result of rule calculation the same way as `apply_with_policy`,
+    /// with given named constants available to the rule.
+    ///
+    /// By default constants are ignored and result of `apply_with_policy` is returned.
+    fn apply_with_constants(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        policy: DivisionPolicy,
+        _constants: &HashMap<String, f64>,
+    ) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        self.apply_with_policy(d, e, f, policy)
+    }
+
+    /// Returns result of rule calculation rounded to `i64` with given `RoundingMode`.
+    /// Returns error if rule can't be evaluated or result can't be represented as `i64`.
+    fn apply_int(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        mode: RoundingMode,
+    ) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        mode.round(self.apply(d, e, f)?)
+    }
+
+    /// Returns result of rule calculation as `f32`.
+    ///
+    /// Result is calculated in `f64` and rounded to the nearest `f32` once,
+    /// so it has about 7 significant decimal digits instead of 15,
+    /// and values beyond `f32::MAX` become infinite.
+    /// Returns error if rule can't be evaluated.
+    fn apply_f32(&self, d: f64, e: i32, f: i32) -> Result<f32, Box<dyn Error + Send + Sync>> {
+        Ok(self.apply(d, e, f)? as f32)
+    }
+
+    /// Checks that the rule can be evaluated with given named constants.
+    /// Returns error if it can't, e.g. if its expression doesn't compile.
+    ///
+    /// By default the rule is always ready.
+    fn prepare(
+        &self,
+        _constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Returns boxed copy of the rule.
+    fn clone_box(&self) -> Box<dyn ArithmeticRule>;
+
+    /// Returns source string of the rule if it has one.
+    fn source(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns human-readable name of the rule if it has one.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns human-readable description of the rule if it has one.
+    fn description(&self) -> Option<&str> {
+        None
+    }
 }
 
 pub type RuleFn = Box<dyn Fn(f64, i32, i32) -> f64 + Send + Sync>;
@@ -24,12 +221,17 @@ pub type RuleFn = Box<dyn Fn(f64, i32, i32) -> f64 + Send + Sync>;
 /// # Examples
 ///
 /// ```
+/// use st_test::assignment::arithmetic_rule::{ArithmeticRule, ArithmeticRuleFn};
+///
 /// let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 42.0));
-/// let res = rule.apply(0.0, 0, 0);
+/// let res = rule.apply(0.0, 0, 0).unwrap();
 /// assert_eq!(res, 42.0);
 /// ```
+/// Function is stored in `Arc`, so cloned rules share it.
+#[derive(Clone)]
 pub struct ArithmeticRuleFn {
-    rule_fn: RuleFn,
+    rule_fn: Arc<dyn Fn(f64, i32, i32) -> f64 + Send + Sync>,
+    metadata: RuleMetadata,
 }
 
 impl ArithmeticRuleFn {
@@ -38,14 +240,56 @@ impl ArithmeticRuleFn {
     /// # Arguments
     /// * `rule_fn` `RuleFn` function.
     pub fn new(rule_fn: RuleFn) -> Self {
-        Self { rule_fn }
+        Self {
+            rule_fn: Arc::from(rule_fn),
+            metadata: RuleMetadata::default(),
+        }
+    }
+
+    /// Builds `ArithmeticRuleFn` that returns `d` unchanged.
+    pub fn identity_d() -> Self {
+        Self::new(Box::new(|d, _, _| d))
+    }
+
+    /// Builds `ArithmeticRuleFn` that returns `e` as `f64`.
+    pub fn identity_e() -> Self {
+        Self::new(Box::new(|_, e, _| e as f64))
+    }
+
+    /// Builds `ArithmeticRuleFn` that returns `f` as `f64`.
+    pub fn identity_f() -> Self {
+        Self::new(Box::new(|_, _, f| f as f64))
+    }
+
+    /// Sets human-readable name of the rule.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.metadata.name = Some(name.into());
+        self
+    }
+
+    /// Sets human-readable description of the rule.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.metadata.description = Some(description.into());
+        self
     }
 }
 
 impl ArithmeticRule for ArithmeticRuleFn {
     /// Returns result of stored function for given arguments.
-    fn apply(&self, d: f64, e: i32, f: i32) -> f64 {
-        (self.rule_fn)(d, e, f)
+    fn apply(&self, d: f64, e: i32, f: i32) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        Ok((self.rule_fn)(d, e, f))
+    }
+
+    fn clone_box(&self) -> Box<dyn ArithmeticRule> {
+        Box::new(self.clone())
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.metadata.name.as_deref()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.metadata.description.as_deref()
     }
 }
 
@@ -56,56 +300,455 @@ impl ArithmeticRule for ArithmeticRuleFn {
 /// # Examples
 ///
 /// ```
-/// let rule = ArithmeticRuleStr::from_str("D + E");
-/// let res = rule.apply(1.0, 2, 0);
+/// use st_test::assignment::arithmetic_rule::{ArithmeticRule, ArithmeticRuleStr};
+///
+/// let rule = ArithmeticRuleStr::new("D + E".to_owned()).unwrap();
+/// let res = rule.apply(1.0, 2, 0).unwrap();
 /// assert_eq!(res, 3.0);
 /// ```
+#[derive(Clone)]
 pub struct ArithmeticRuleStr {
     rule_str: String,
+    /// Rule string with hexadecimal and scientific literals expanded to plain numbers, if rule has any.
+    expanded_rule_str: Option<String>,
+    /// Rule string with each division rewritten to `div(a, b)` call, if rule has division.
+    division_rule_str: Option<String>,
+    /// Input variables referenced by the rule.
+    referenced_vars: HashSet<char>,
+    metadata: RuleMetadata,
 }
 
 impl ArithmeticRuleStr {
-    pub fn new(rule_str: String) -> Result<Self, Box<dyn Error>> {
-        ArithmeticRuleStr::validate(&rule_str)?;
-        Ok(Self { rule_str })
+    /// Normalizes and validates provided rule string and builds `ArithmeticRuleStr`.
+    /// Returns `Ok(ArithmeticRuleStr)` if validation is successful,
+    /// otherwise returns error with description.
+    pub fn new(rule_str: String) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        ArithmeticRuleStr::new_with_constants(rule_str, &HashMap::new())
+    }
+
+    /// Builds `ArithmeticRuleStr` the same way as `new`, but allows names of given constants in rule string.
+    /// Values of constants are provided on evaluation with `apply_with_constants`.
+    pub fn new_with_constants(
+        rule_str: String,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let rule_str = normalize_rule_str(&rule_str);
+        ArithmeticRuleStr::validate_with_constants(&rule_str, constants)?;
+        let expanded_rule_str = expand_literals(&rule_str)?;
+        let eval_rule_str = expanded_rule_str.as_deref().unwrap_or(&rule_str);
+        let division_rule_str = if eval_rule_str.contains('/') {
+            rewrite_division(eval_rule_str)
+        } else {
+            None
+        };
+        Ok(Self {
+            referenced_vars: referenced_vars(eval_rule_str),
+            rule_str,
+            expanded_rule_str,
+            division_rule_str,
+            metadata: RuleMetadata::default(),
+        })
+    }
+
+    /// Sets human-readable name of the rule.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.metadata.name = Some(name.into());
+        self
     }
 
-    /// Validates provided rule string.
-    /// Returns error if it contains invalid variables or operators,
+    /// Sets human-readable description of the rule.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.metadata.description = Some(description.into());
+        self
+    }
+
+    /// Returns rule string with hexadecimal and scientific literals expanded to plain numbers.
+    pub(crate) fn expanded_rule_str(&self) -> &str {
+        self.expanded_rule_str.as_deref().unwrap_or(&self.rule_str)
+    }
+
+    /// Returns input variables `D`, `E` and `F` referenced by the rule, e.g. `{'D', 'E'}` for `D * E + D`.
+    pub fn referenced_vars(&self) -> HashSet<char> {
+        self.referenced_vars.clone()
+    }
+
+    /// Validates provided rule string with default `RuleLimits`.
+    /// Trailing `# comment` and extra whitespace are ignored.
+    /// Function `if(condition, a, b)` with comparison operators in condition is allowed.
+    /// Numbers can be written in scientific notation, e.g. `1e3` or `2.5E-2`,
+    /// or as hexadecimal integers, e.g. `0xFF`.
+    /// Returns error if it exceeds the limits,
+    /// if it contains invalid variables or operators,
     /// or if it's not compilable by `evalexpr`,
     /// otherwise returns `Ok`.
-    fn validate(rule_str: &String) -> Result<(), Box<dyn Error>> {
-        let re = Regex::new(r"^([\dDEF ]|\+|-|\*|/|\(|\))+$").unwrap();
-        if !re.is_match(&rule_str) {
-            Err("Expression contains invalid variables or operators.")?
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use st_test::assignment::arithmetic_rule::ArithmeticRuleStr;
+    ///
+    /// assert!(ArithmeticRuleStr::validate("D * (E - F)").is_ok());
+    /// assert!(ArithmeticRuleStr::validate("if(E > F, D * 2, D)").is_ok());
+    /// assert!(ArithmeticRuleStr::validate("D * 1e3 + 0xFF").is_ok());
+    /// assert!(ArithmeticRuleStr::validate("D && E").is_err());
+    /// assert!(ArithmeticRuleStr::validate("D ** E").is_err());
+    /// ```
+    pub fn validate(rule_str: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        ArithmeticRuleStr::validate_with_limits(rule_str, &RuleLimits::default())
+    }
+
+    /// Validates provided rule string the same way as `validate`, but with given `RuleLimits`.
+    pub fn validate_with_limits(
+        rule_str: &str,
+        limits: &RuleLimits,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        ArithmeticRuleStr::check(rule_str, limits, &HashMap::new())
+    }
+
+    /// Validates provided rule string the same way as `validate`, but allows names of given constants.
+    pub fn validate_with_constants(
+        rule_str: &str,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        ArithmeticRuleStr::check(rule_str, &RuleLimits::default(), constants)
+    }
+
+    /// Validates provided rule string with given `RuleLimits` and constants.
+    fn check(
+        rule_str: &str,
+        limits: &RuleLimits,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        limits.check(rule_str)?;
+        let rule_str = &normalize_rule_str(rule_str);
+
+        let re = if constants.is_empty() {
+            Cow::Borrowed(rule_str_regex())
+        } else {
+            Cow::Owned(Regex::new(&rule_str_pattern(constants)).map_err(ValidationError::from)?)
+        };
+        if !re.is_match(rule_str) {
+            Err(ValidationError::rejected(
+                "Expression contains invalid variables or operators.",
+                rule_str,
+            ))?
         }
 
         // Try to evaluate expression with some input to check if it's valid for `evalexpr`.
-        let context = context_map! {
+        let mut context = context_map! {
             "D" => 0.0,
             "E" => 0 as f64,
             "F" => 0 as f64,
+            "if" => Function::new(Box::new(if_function)),
         }
         .unwrap();
-        eval_float_with_context(&rule_str, &context)?;
+        set_constants(&mut context, constants);
+        let expanded = expand_literals(rule_str)?;
+        eval_float_with_context(expanded.as_deref().unwrap_or(rule_str), &context)
+            .map_err(ValidationError::from)?;
 
         Ok(())
     }
 }
 
+/// Returns pattern of valid arithmetic rule string with given constants.
+fn rule_str_pattern(constants: &HashMap<String, f64>) -> String {
+    format!(
+        r"^({}|{}|[\dDEF ,.]|\+|-|\*|/|\(|\)|if|>=|<=|==|!=|>|<{})+$",
+        SCIENTIFIC_LITERAL,
+        HEX_LITERAL,
+        constants_pattern(constants)
+    )
+}
+
+/// Returns regex of `rule_str_pattern` without constants, compiled once per process.
+fn rule_str_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(&rule_str_pattern(&HashMap::new())).unwrap())
+}
+
+/// Pattern of number in scientific notation, e.g. `1e3` or `2.5E-2`.
+const SCIENTIFIC_LITERAL: &str = r"\b(\d+\.?\d*|\.\d+)[eE][+-]?\d+\b";
+
+/// Pattern of hexadecimal integer, e.g. `0xFF`.
+const HEX_LITERAL: &str = r"\b0[xX][\dA-Fa-f]+\b";
+
+/// Expands scientific and hexadecimal literals of rule string to plain numbers understood by `evalexpr`.
+/// Scientific literals become floats and hexadecimal literals become integers.
+/// A literal is recognized only as a whole word, so `E` variable is never taken for an exponent.
+///
+/// Returns `None` if rule string has no such literals,
+/// or error if hexadecimal literal doesn't fit into `i64`.
+fn expand_literals(rule_str: &str) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re =
+        RE.get_or_init(|| Regex::new(&format!("{}|{}", SCIENTIFIC_LITERAL, HEX_LITERAL)).unwrap());
+    if !re.is_match(rule_str) {
+        return Ok(None);
+    }
+
+    let mut res = String::with_capacity(rule_str.len());
+    let mut last = 0;
+    for m in re.find_iter(rule_str) {
+        let literal = m.as_str();
+        let value = if let Some(digits) = literal
+            .strip_prefix("0x")
+            .or_else(|| literal.strip_prefix("0X"))
+        {
+            i64::from_str_radix(digits, 16)
+                .map_err(|_| {
+                    ValidationError::rejected("Hexadecimal literal is too large.", literal)
+                })?
+                .to_string()
+        } else {
+            // `Display` of `f64` never uses exponent, keep it float for `evalexpr`.
+            let value = literal.parse::<f64>()?.to_string();
+            if value.contains('.') {
+                value
+            } else {
+                value + ".0"
+            }
+        };
+        res.push_str(&rule_str[last..m.start()]);
+        res.push_str(&value);
+        last = m.end();
+    }
+    res.push_str(&rule_str[last..]);
+
+    Ok(Some(res))
+}
+
+/// Implementation of `if(condition, a, b)` function for arithmetic rules.
+/// Returns `a` if `condition` is true, otherwise returns `b`.
+/// Both branches must be numeric and result is always float.
+pub(crate) fn if_function(argument: &Value) -> EvalexprResult<Value> {
+    let args = argument.as_fixed_len_tuple(3)?;
+    let (a, b) = (args[1].as_number()?, args[2].as_number()?);
+    let res = if args[0].as_boolean()? { a } else { b };
+    Ok(Value::Float(res))
+}
+
+/// Implementation of `div(a, b)` function, which replaces division when `DivisionPolicy` isn't standard.
+/// Returns the same result as `a / b` if `b` isn't zero.
+fn division_function(argument: &Value, policy: DivisionPolicy) -> EvalexprResult<Value> {
+    let args = argument.as_fixed_len_tuple(2)?;
+    let (a, b) = (args[0].as_number()?, args[1].as_number()?);
+
+    if b == 0.0 && policy == DivisionPolicy::Error {
+        return Err(EvalexprError::DivisionError {
+            dividend: args[0].clone(),
+            divisor: args[1].clone(),
+        });
+    }
+
+    match (args[0].as_int(), args[1].as_int()) {
+        (Ok(a), Ok(b)) if b != 0 => Ok(Value::Int(a / b)),
+        _ => Ok(Value::Float(safe_divide(a, b, 0.0))),
+    }
+}
+
+/// Rewrites each division in validated arithmetic rule string to `div(a, b)` function call,
+/// keeping operator precedence.
+/// Returns `None` if rule string can't be parsed.
+fn rewrite_division(rule_str: &str) -> Option<String> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"[\d.]+|[A-Za-z_]\w*|>=|<=|==|!=|[-+*/(),<>]").unwrap());
+    let tokens: Vec<&str> = re.find_iter(rule_str).map(|m| m.as_str()).collect();
+
+    let mut parser = DivisionRewriter { tokens, pos: 0 };
+    let res = parser.comparison()?;
+    if parser.pos == parser.tokens.len() {
+        Some(res)
+    } else {
+        None
+    }
+}
+
+/// Recursive descent parser of arithmetic rule strings used by `rewrite_division`.
+struct DivisionRewriter<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> DivisionRewriter<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn expect(&mut self, token: &str) -> Option<()> {
+        if self.next()? == token {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn comparison(&mut self) -> Option<String> {
+        let mut res = self.sum()?;
+        while let Some(op @ (">=" | "<=" | "==" | "!=" | ">" | "<")) = self.peek() {
+            self.pos += 1;
+            res = format!("{} {} {}", res, op, self.sum()?);
+        }
+        Some(res)
+    }
+
+    fn sum(&mut self) -> Option<String> {
+        let mut res = self.product()?;
+        while let Some(op @ ("+" | "-")) = self.peek() {
+            self.pos += 1;
+            res = format!("{} {} {}", res, op, self.product()?);
+        }
+        Some(res)
+    }
+
+    fn product(&mut self) -> Option<String> {
+        let mut res = self.unary()?;
+        while let Some(op @ ("*" | "/")) = self.peek() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            res = if op == "*" {
+                format!("{} * {}", res, rhs)
+            } else {
+                format!("div({}, {})", res, rhs)
+            };
+        }
+        Some(res)
+    }
+
+    fn unary(&mut self) -> Option<String> {
+        if self.peek()? == "-" {
+            self.pos += 1;
+            return Some(format!("-{}", self.unary()?));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<String> {
+        match self.next()? {
+            "(" => {
+                let res = self.comparison()?;
+                self.expect(")")?;
+                Some(format!("({})", res))
+            }
+            "if" => {
+                self.expect("(")?;
+                let condition = self.comparison()?;
+                self.expect(",")?;
+                let a = self.comparison()?;
+                self.expect(",")?;
+                let b = self.comparison()?;
+                self.expect(")")?;
+                Some(format!("if({}, {}, {})", condition, a, b))
+            }
+            token if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+                Some(token.to_owned())
+            }
+            token if token.starts_with(|c: char| c.is_ascii_digit() || c == '.') => {
+                Some(token.to_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
 impl ArithmeticRule for ArithmeticRuleStr {
-    fn apply(&self, d: f64, e: i32, f: i32) -> f64 {
-        let context = context_map! {
+    fn apply(&self, d: f64, e: i32, f: i32) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        self.apply_with_constants(d, e, f, DivisionPolicy::Standard, &HashMap::new())
+    }
+
+    fn apply_with_policy(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        policy: DivisionPolicy,
+    ) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        self.apply_with_constants(d, e, f, policy, &HashMap::new())
+    }
+
+    fn apply_with_constants(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        policy: DivisionPolicy,
+        constants: &HashMap<String, f64>,
+    ) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        let rule_str = match (&self.division_rule_str, policy) {
+            (Some(rule_str), DivisionPolicy::SafeZero | DivisionPolicy::Error) => rule_str.as_str(),
+            _ => self.expanded_rule_str(),
+        };
+
+        let mut context = context_map! {
             "D" => d,
             "E" => e as f64,
             "F" => f as f64,
+            "if" => Function::new(Box::new(if_function)),
+            "div" => Function::new(Box::new(move |argument| division_function(argument, policy))),
         }
         .unwrap();
+        set_constants(&mut context, constants);
+
+        Ok(eval_float_with_context(rule_str, &context)?)
+    }
 
-        eval_float_with_context(&self.rule_str, &context).unwrap()
+    fn prepare(
+        &self,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        ArithmeticRuleStr::validate_with_constants(&self.rule_str, constants)?;
+        if let Some(division_rule_str) = &self.division_rule_str {
+            build_operator_tree(division_rule_str).map_err(ValidationError::from)?;
+        }
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ArithmeticRule> {
+        Box::new(self.clone())
+    }
+
+    fn source(&self) -> Option<&str> {
+        Some(&self.rule_str)
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.metadata.name.as_deref()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.metadata.description.as_deref()
     }
 }
 
+#[test]
+fn test_token_order() {
+    let mut tokens = vec![
+        SubstitutionToken::T,
+        SubstitutionToken::M,
+        SubstitutionToken::P,
+        SubstitutionToken::M,
+        SubstitutionToken::T,
+    ];
+    tokens.sort();
+
+    assert_eq!(
+        tokens,
+        vec![
+            SubstitutionToken::M,
+            SubstitutionToken::M,
+            SubstitutionToken::P,
+            SubstitutionToken::T,
+            SubstitutionToken::T,
+        ]
+    );
+}
+
 #[test]
 fn test_new() {
     let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 2.0));
@@ -115,64 +758,93 @@ fn test_new() {
 #[test]
 fn test_apply() {
     let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 2.0));
-    assert_eq!(rule.apply(0.0, 0, 0), 2.0);
+    assert_eq!(rule.apply(0.0, 0, 0).unwrap(), 2.0);
 
-    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 1.0 / 0.0 as f64));
-    assert!(!rule.apply(0.0, 0, 0).is_normal());
+    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 1.0 / 0.0_f64));
+    assert!(!rule.apply(0.0, 0, 0).unwrap().is_normal());
 }
 
 #[test]
-fn test_validate() {
-    assert!(ArithmeticRuleStr::validate(&"D".to_owned()).is_ok());
-    assert!(ArithmeticRuleStr::validate(&"-D + E".to_owned()).is_ok());
-    assert!(ArithmeticRuleStr::validate(&"D * (-E + F)".to_owned()).is_ok());
-    assert!(ArithmeticRuleStr::validate(&"-2 * D".to_owned()).is_ok());
+fn test_identity() {
+    assert_eq!(
+        ArithmeticRuleFn::identity_d().apply(2.0, 3, 4).unwrap(),
+        2.0
+    );
+    assert_eq!(
+        ArithmeticRuleFn::identity_e().apply(2.0, 3, 4).unwrap(),
+        3.0
+    );
+    assert_eq!(
+        ArithmeticRuleFn::identity_f().apply(2.0, 3, 4).unwrap(),
+        4.0
+    );
+}
+
+#[test]
+fn test_apply_int() {
+    let rule = ArithmeticRuleFn::new(Box::new(|d, _, _| d));
+
+    assert_eq!(rule.apply(2.5, 0, 0).unwrap(), 2.5);
+    assert_eq!(rule.apply_int(2.5, 0, 0, RoundingMode::Nearest).unwrap(), 3);
+    assert_eq!(rule.apply_int(2.5, 0, 0, RoundingMode::Floor).unwrap(), 2);
+    assert_eq!(rule.apply_int(2.5, 0, 0, RoundingMode::Trunc).unwrap(), 2);
 
+    assert_eq!(rule.apply(-2.7, 0, 0).unwrap(), -2.7);
     assert_eq!(
-        ArithmeticRuleStr::validate(&"".to_owned())
-            .unwrap_err()
-            .to_string(),
-        "Expression contains invalid variables or operators."
+        rule.apply_int(-2.7, 0, 0, RoundingMode::Nearest).unwrap(),
+        -3
     );
+    assert_eq!(rule.apply_int(-2.7, 0, 0, RoundingMode::Floor).unwrap(), -3);
+    assert_eq!(rule.apply_int(-2.7, 0, 0, RoundingMode::Trunc).unwrap(), -2);
+
+    let rule = ArithmeticRuleStr::new("D / 0".to_owned()).unwrap();
     assert_eq!(
-        ArithmeticRuleStr::validate(&"A".to_owned())
+        rule.apply_int(1.0, 0, 0, RoundingMode::Nearest)
             .unwrap_err()
             .to_string(),
-        "Expression contains invalid variables or operators."
+        "Arithmetic rule result can't be represented as integer."
     );
+}
+
+#[test]
+fn test_validate() {
+    assert!(ArithmeticRuleStr::validate("D").is_ok());
+    assert!(ArithmeticRuleStr::validate("-D + E").is_ok());
+    assert!(ArithmeticRuleStr::validate("D * (-E + F)").is_ok());
+    assert!(ArithmeticRuleStr::validate("-2 * D").is_ok());
+
     assert_eq!(
-        ArithmeticRuleStr::validate(&"D && E".to_owned())
-            .unwrap_err()
-            .to_string(),
+        ArithmeticRuleStr::validate("").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        ArithmeticRuleStr::validate(&"D || E".to_owned())
-            .unwrap_err()
-            .to_string(),
+        ArithmeticRuleStr::validate("A").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        ArithmeticRuleStr::validate(&"D == E".to_owned())
+        ArithmeticRuleStr::validate("D && E")
             .unwrap_err()
             .to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        ArithmeticRuleStr::validate(&"D != E".to_owned())
+        ArithmeticRuleStr::validate("D || E")
             .unwrap_err()
             .to_string(),
         "Expression contains invalid variables or operators."
     );
+    // Comparison operators are allowed for `if` condition, but result must be numeric.
+    assert!(ArithmeticRuleStr::validate("D == E").is_err());
+    assert!(ArithmeticRuleStr::validate("D != E").is_err());
 
     assert_eq!(
-        ArithmeticRuleStr::validate(&"/D * E".to_owned())
+        ArithmeticRuleStr::validate("/D * E")
             .unwrap_err()
             .to_string(),
         "An operator expected 2 arguments, but got 1."
     );
     assert_eq!(
-        ArithmeticRuleStr::validate(&"D ** E".to_owned())
+        ArithmeticRuleStr::validate("D ** E")
             .unwrap_err()
             .to_string(),
         "An operator expected 2 arguments, but got 1."
@@ -182,12 +854,494 @@ fn test_validate() {
 #[test]
 fn test_apply_str() {
     let rule = ArithmeticRuleStr::new("D".to_owned()).unwrap();
-    assert_eq!(rule.apply(0.0, 0, 0), 0.0);
-    assert_eq!(rule.apply(1.0, 0, 0), 1.0);
+    assert_eq!(rule.apply(0.0, 0, 0).unwrap(), 0.0);
+    assert_eq!(rule.apply(1.0, 0, 0).unwrap(), 1.0);
 
     let rule = ArithmeticRuleStr::new("2 * D * E + F".to_owned()).unwrap();
-    assert_eq!(rule.apply(2.0, 3, 4), 16.0);
+    assert_eq!(rule.apply(2.0, 3, 4).unwrap(), 16.0);
 
     let rule = ArithmeticRuleStr::new("D / 0".to_owned()).unwrap();
-    assert!(!rule.apply(1.0, 0, 0).is_normal());
+    assert!(!rule.apply(1.0, 0, 0).unwrap().is_normal());
+}
+
+#[test]
+fn test_clone_box() {
+    let rule = ArithmeticRuleFn::new(Box::new(|d, _, _| d * 2.0));
+    let cloned = rule.clone_box();
+    assert_eq!(cloned.apply(2.0, 0, 0).unwrap(), 4.0);
+
+    let rule = ArithmeticRuleStr::new("D + E".to_owned()).unwrap();
+    let cloned = rule.clone_box();
+    assert_eq!(cloned.apply(1.0, 2, 0).unwrap(), 3.0);
+}
+
+#[test]
+fn test_new_str_normalization() {
+    let rule = ArithmeticRuleStr::new("  D +\tE\n".to_owned()).unwrap();
+    assert_eq!(rule.rule_str, "D + E");
+    assert_eq!(rule.apply(1.0, 2, 0).unwrap(), 3.0);
+
+    let rule = ArithmeticRuleStr::new("D * F # doubled price".to_owned()).unwrap();
+    assert_eq!(rule.rule_str, "D * F");
+    assert_eq!(rule.apply(2.0, 0, 3).unwrap(), 6.0);
+
+    assert!(ArithmeticRuleStr::validate("\tD\n-\r\nE  # comment").is_ok());
+    assert!(ArithmeticRuleStr::new("# only comment".to_owned()).is_err());
+    assert!(ArithmeticRuleStr::new("D # E && F".to_owned()).is_ok());
+}
+
+#[test]
+fn test_validate_limits() {
+    let nested = format!("{}D{}", "(".repeat(100), ")".repeat(100));
+    assert_eq!(
+        ArithmeticRuleStr::validate(&nested)
+            .unwrap_err()
+            .to_string(),
+        "Expression is nested too deeply: maximum depth is 32."
+    );
+    let long = vec!["D"; 3000].join("+");
+    assert_eq!(
+        ArithmeticRuleStr::validate(&long).unwrap_err().to_string(),
+        "Expression is too long: maximum length is 4096."
+    );
+
+    let limits = RuleLimits {
+        max_len: 100,
+        max_depth: 3,
+    };
+    assert!(ArithmeticRuleStr::validate_with_limits("((D + E) * F) / 2", &limits).is_ok());
+    assert!(ArithmeticRuleStr::validate_with_limits("((((D))))", &limits).is_err());
+}
+
+#[test]
+fn test_if() {
+    let rule = ArithmeticRuleStr::new("if(E > F, D * 2, D)".to_owned()).unwrap();
+    assert_eq!(rule.apply(1.5, 3, 2).unwrap(), 3.0);
+    assert_eq!(rule.apply(1.5, 2, 3).unwrap(), 1.5);
+    assert_eq!(rule.apply(1.5, 2, 2).unwrap(), 1.5);
+
+    let rule = ArithmeticRuleStr::new("if(E >= F, 2, D) + 1".to_owned()).unwrap();
+    assert_eq!(rule.apply(1.5, 2, 2).unwrap(), 3.0);
+    assert_eq!(rule.apply(1.5, 1, 2).unwrap(), 2.5);
+
+    assert!(ArithmeticRuleStr::validate("if(D == E, if(E != F, D, E), F)").is_ok());
+    // Comparison result isn't numeric.
+    assert!(ArithmeticRuleStr::validate("D > E").is_err());
+    assert!(ArithmeticRuleStr::validate("if(D > E, D)").is_err());
+    // Only `if` function is allowed.
+    assert!(ArithmeticRuleStr::validate("min(D, E)").is_err());
+    assert!(ArithmeticRuleStr::validate("iff(D > E, D, E)").is_err());
+}
+
+#[test]
+fn test_metadata() {
+    let rule: Box<dyn ArithmeticRule> = Box::new(
+        ArithmeticRuleStr::new("D * 2".to_owned())
+            .unwrap()
+            .with_name("double")
+            .with_description("Doubles D."),
+    );
+    assert_eq!(rule.name(), Some("double"));
+    assert_eq!(rule.description(), Some("Doubles D."));
+    assert_eq!(rule.clone_box().description(), Some("Doubles D."));
+
+    let rule: Box<dyn ArithmeticRule> =
+        Box::new(ArithmeticRuleFn::identity_d().with_description("Returns D."));
+    assert_eq!(rule.name(), None);
+    assert_eq!(rule.description(), Some("Returns D."));
+}
+
+#[test]
+fn test_apply_f32() {
+    let rule = ArithmeticRuleStr::new("D + E / F".to_owned()).unwrap();
+    assert_eq!(rule.apply(0.1, 2, 3).unwrap(), 0.1 + 2.0 / 3.0);
+    assert_eq!(rule.apply_f32(0.1, 2, 3).unwrap(), 0.766_666_65_f32);
+    assert_eq!(rule.apply_f32(16_777_217.0, 0, 1).unwrap(), 16_777_216.0);
+}
+
+#[test]
+fn test_validate_evalexpr_error() {
+    let e = ArithmeticRuleStr::validate("D ** E").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "An operator expected 2 arguments, but got 1."
+    );
+    assert!(e
+        .downcast_ref::<ValidationError>()
+        .unwrap()
+        .evalexpr_error()
+        .is_some());
+
+    // Expression with unsupported operator still reports `evalexpr` error, if any.
+    let e = ArithmeticRuleStr::validate("D % % E").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "Expression contains invalid variables or operators."
+    );
+    assert_eq!(
+        e.downcast_ref::<ValidationError>()
+            .unwrap()
+            .evalexpr_error()
+            .unwrap()
+            .to_string(),
+        "An operator expected 2 arguments, but got 1."
+    );
+
+    // Expression valid for `evalexpr`, but with unsupported operator has no `evalexpr` error.
+    let e = ArithmeticRuleStr::validate("D % E").unwrap_err();
+    assert!(e
+        .downcast_ref::<ValidationError>()
+        .unwrap()
+        .evalexpr_error()
+        .is_none());
+}
+
+#[test]
+fn test_rewrite_division() {
+    assert_eq!(rewrite_division("D / F").unwrap(), "div(D, F)");
+    assert_eq!(rewrite_division("D + E / F").unwrap(), "D + div(E, F)");
+    assert_eq!(
+        rewrite_division("D * E / F / 2").unwrap(),
+        "div(div(D * E, F), 2)"
+    );
+    assert_eq!(
+        rewrite_division("-D / (E - F)").unwrap(),
+        "div(-D, (E - F))"
+    );
+    assert_eq!(
+        rewrite_division("if(E / F > 1, D / E, D)").unwrap(),
+        "if(div(E, F) > 1, div(D, E), D)"
+    );
+    assert_eq!(rewrite_division("D / "), None);
+}
+
+#[test]
+fn test_apply_with_policy() {
+    let rule = ArithmeticRuleStr::new("D / F".to_owned()).unwrap();
+
+    assert_eq!(
+        rule.apply_with_policy(1.0, 0, 0, DivisionPolicy::Standard)
+            .unwrap(),
+        f64::INFINITY
+    );
+    assert_eq!(
+        rule.apply_with_policy(1.0, 0, 0, DivisionPolicy::SafeZero)
+            .unwrap(),
+        0.0
+    );
+    assert_eq!(
+        rule.apply_with_policy(1.0, 0, 0, DivisionPolicy::Error)
+            .unwrap_err()
+            .to_string(),
+        "Error dividing 1 / 0"
+    );
+
+    // Non-zero divisor gives the same result under any policy.
+    for policy in [
+        DivisionPolicy::Standard,
+        DivisionPolicy::SafeZero,
+        DivisionPolicy::Error,
+    ] {
+        assert_eq!(rule.apply_with_policy(3.0, 0, 2, policy).unwrap(), 1.5);
+    }
+
+    // Only zero division is replaced.
+    let rule = ArithmeticRuleStr::new("D + E / F".to_owned()).unwrap();
+    assert_eq!(
+        rule.apply_with_policy(1.5, 2, 0, DivisionPolicy::SafeZero)
+            .unwrap(),
+        1.5
+    );
+
+    // Function rules ignore the policy.
+    let rule = ArithmeticRuleFn::new(Box::new(|d, _, f| d / f as f64));
+    assert!(rule
+        .apply_with_policy(1.0, 0, 0, DivisionPolicy::Error)
+        .unwrap()
+        .is_infinite());
+}
+
+#[test]
+fn test_safe_divide() {
+    assert_eq!(safe_divide(3.0, 2.0, 0.0), 1.5);
+    assert_eq!(safe_divide(3.0, 0.0, 0.0), 0.0);
+    assert_eq!(safe_divide(3.0, 0.0, -1.0), -1.0);
+}
+
+/// Strategy of arithmetic rule strings following validation grammar:
+/// variables and float literals, negation and parentheses joined by arithmetic operators,
+/// and `if` with comparison of two expressions.
+#[cfg(test)]
+fn rule_str_strategy() -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::{prelude::*, sample::select};
+
+    select(&["D", "E", "F", "0.0", "2.5", "1e3", "2.5E-2"][..])
+        .prop_map(str::to_owned)
+        .prop_recursive(4, 32, 3, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|e| format!("-{}", e)),
+                inner.clone().prop_map(|e| format!("({})", e)),
+                (
+                    inner.clone(),
+                    select(&["+", "-", "*", "/"][..]),
+                    inner.clone()
+                )
+                    .prop_map(|(l, op, r)| format!("{} {} {}", l, op, r)),
+                (
+                    inner.clone(),
+                    select(&[">", "<", ">=", "<=", "==", "!="][..]),
+                    inner.clone(),
+                    inner.clone(),
+                    inner,
+                )
+                    .prop_map(|(l, op, r, then, otherwise)| {
+                        format!("if({} {} {}, {}, {})", l, op, r, then, otherwise)
+                    }),
+            ]
+        })
+}
+
+/// Inputs of fuzz tests, including extreme and non-finite values.
+#[cfg(test)]
+const FUZZ_INPUTS: [(f64, i32, i32); 4] = [
+    (0.0, 0, 0),
+    (1.5, 2, -3),
+    (-1e300, i32::MAX, i32::MIN),
+    (f64::NAN, 1, 0),
+];
+
+// `DivisionPolicy::Error` fails on zero divisor by design.
+#[cfg(test)]
+const FUZZ_POLICIES: [DivisionPolicy; 2] = [DivisionPolicy::Standard, DivisionPolicy::SafeZero];
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_validate_grammar(rule_str in rule_str_strategy()) {
+        let rule = ArithmeticRuleStr::new(rule_str.clone());
+        proptest::prop_assert!(rule.is_ok(), "Rule {:?} is rejected: {:?}.", rule_str, rule.err());
+        let rule = rule.unwrap();
+
+        for &(d, e, f) in &FUZZ_INPUTS {
+            for &policy in &FUZZ_POLICIES {
+                proptest::prop_assert!(
+                    rule.apply_with_policy(d, e, f, policy).is_ok(),
+                    "Valid rule {:?} fails for {:?} with {:?}.",
+                    rule_str,
+                    (d, e, f),
+                    policy
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #![proptest_config(proptest::test_runner::Config::with_cases(2_000))]
+
+    #[test]
+    fn test_validate_fuzz(
+        rule_str in crate::assignment::noisy_rule_str_strategy(
+            &[
+                "D",
+                "E",
+                "F",
+                "0",
+                "1",
+                "2.5",
+                "-E",
+                "(D + F)",
+                "(",
+                ")",
+                "if(E > F, D, 1)",
+                "if(",
+            ],
+            &["+", "-", "*", "/", " / ", ",", ">", "<", ">=", "<=", "==", "!=", "(", ")"],
+            6,
+        )
+    ) {
+        if let Ok(rule) = ArithmeticRuleStr::new(rule_str.clone()) {
+            for &(d, e, f) in &FUZZ_INPUTS {
+                for &policy in &FUZZ_POLICIES {
+                    proptest::prop_assert!(
+                        rule.apply_with_policy(d, e, f, policy).is_ok(),
+                        "Valid rule {:?} fails for {:?} with {:?}.",
+                        rule_str,
+                        (d, e, f),
+                        policy
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_apply_eval_error() {
+    // Rule string that bypasses validation fails on evaluation without panic.
+    let rule = ArithmeticRuleStr {
+        rule_str: "D ** E".to_owned(),
+        expanded_rule_str: None,
+        division_rule_str: None,
+        referenced_vars: HashSet::new(),
+        metadata: RuleMetadata::default(),
+    };
+    assert_eq!(
+        rule.apply(1.0, 2, 3).unwrap_err().to_string(),
+        "An operator expected 2 arguments, but got 1."
+    );
+    assert!(rule.apply_int(1.0, 2, 3, RoundingMode::Nearest).is_err());
+    assert!(rule.apply_f32(1.0, 2, 3).is_err());
+}
+
+#[test]
+fn test_constants() {
+    let constants: HashMap<String, f64> = vec![("rate".to_owned(), 0.5)].into_iter().collect();
+
+    assert!(ArithmeticRuleStr::validate("D * rate").is_err());
+    assert!(ArithmeticRuleStr::validate_with_constants("D * rate", &constants).is_ok());
+    assert!(ArithmeticRuleStr::validate_with_constants("D * rates", &constants).is_err());
+
+    let rule =
+        ArithmeticRuleStr::new_with_constants("D / F * rate".to_owned(), &constants).unwrap();
+    assert_eq!(
+        rule.apply_with_constants(3.0, 0, 2, DivisionPolicy::Standard, &constants)
+            .unwrap(),
+        0.75
+    );
+    assert_eq!(
+        rule.apply_with_constants(3.0, 0, 0, DivisionPolicy::SafeZero, &constants)
+            .unwrap(),
+        0.0
+    );
+    // Rule can't be evaluated without its constants.
+    assert!(rule.apply(3.0, 0, 2).is_err());
+
+    assert_eq!(rewrite_division("D / rate").unwrap(), "div(D, rate)");
+}
+
+#[test]
+fn test_lowercase() {
+    let lower = ArithmeticRuleStr::new("d + (d * (e - f) / 25.5)".to_owned()).unwrap();
+    let upper = ArithmeticRuleStr::new("D + (D * (E - F) / 25.5)".to_owned()).unwrap();
+    assert_eq!(lower.rule_str, upper.rule_str);
+    assert_eq!(
+        lower.apply(2.0, 3, 4).unwrap(),
+        upper.apply(2.0, 3, 4).unwrap()
+    );
+
+    let rule = ArithmeticRuleStr::new("if(e > f, d * 2, d)".to_owned()).unwrap();
+    assert_eq!(rule.apply(1.5, 3, 2).unwrap(), 3.0);
+    assert!(ArithmeticRuleStr::validate("d && e").is_err());
+    assert!(ArithmeticRuleStr::validate("de * f").is_err());
+}
+
+#[test]
+fn test_prepare() {
+    let rule = ArithmeticRuleStr::new("D / (E - F)".to_owned()).unwrap();
+    assert!(rule.prepare(&HashMap::new()).is_ok());
+
+    let rule = ArithmeticRuleStr::new_with_constants(
+        "D * rate".to_owned(),
+        &vec![("rate".to_owned(), 1.5)].into_iter().collect(),
+    )
+    .unwrap();
+    assert!(rule.prepare(&HashMap::new()).is_err());
+
+    // Rule registered bypassing validation.
+    let broken = ArithmeticRuleStr {
+        rule_str: "D *".to_owned(),
+        expanded_rule_str: None,
+        division_rule_str: None,
+        referenced_vars: HashSet::new(),
+        metadata: RuleMetadata::default(),
+    };
+    assert!(broken.prepare(&HashMap::new()).is_err());
+}
+
+#[test]
+fn test_numeric_literals() {
+    let rule = ArithmeticRuleStr::new("D * 1e3".to_owned()).unwrap();
+    assert_eq!(rule.apply(1.5, 0, 0).unwrap(), 1500.0);
+    assert_eq!(rule.source(), Some("D * 1e3"));
+
+    let rule = ArithmeticRuleStr::new("D * 2.5E-2 + 1E+2".to_owned()).unwrap();
+    assert_eq!(rule.apply(100.0, 0, 0).unwrap(), 102.5);
+
+    let rule = ArithmeticRuleStr::new("0xFF - E + 0x10".to_owned()).unwrap();
+    assert_eq!(rule.apply(0.0, 5, 0).unwrap(), 266.0);
+
+    // `E` stays a variable next to literals.
+    let rule = ArithmeticRuleStr::new("E * 1e2 - E".to_owned()).unwrap();
+    assert_eq!(rule.apply(0.0, 3, 0).unwrap(), 297.0);
+    let rule = ArithmeticRuleStr::new("1E3 / E".to_owned()).unwrap();
+    assert_eq!(
+        rule.apply_with_policy(0.0, 0, 0, DivisionPolicy::SafeZero)
+            .unwrap(),
+        0.0
+    );
+    assert_eq!(
+        rule.apply_with_policy(0.0, 4, 0, DivisionPolicy::SafeZero)
+            .unwrap(),
+        250.0
+    );
+
+    assert!(ArithmeticRuleStr::validate("D * 1e").is_err());
+    assert!(ArithmeticRuleStr::validate("D * 2E").is_err());
+    assert!(ArithmeticRuleStr::validate("D1e3").is_err());
+    assert!(ArithmeticRuleStr::validate("0xG1").is_err());
+    assert!(ArithmeticRuleStr::validate("0xFFFFFFFFFFFFFFFFF").is_err());
+}
+
+#[test]
+fn test_rule_str_regex_compiled_once() {
+    let re = rule_str_regex();
+    for _ in 0..3 {
+        ArithmeticRuleStr::validate("D + E * 1e3").unwrap();
+    }
+    assert!(std::ptr::eq(re, rule_str_regex()));
+    assert_eq!(re.as_str(), rule_str_pattern(&HashMap::new()));
+}
+
+#[test]
+fn test_token_from_str() {
+    for s in ["M", " M ", "m", "\u{a0}m\t"] {
+        assert_eq!(
+            s.parse::<SubstitutionToken>().unwrap(),
+            SubstitutionToken::M
+        );
+    }
+    assert_eq!(
+        "X".parse::<SubstitutionToken>().unwrap_err().to_string(),
+        "Unknown substitution token `X`, expected M, P or T."
+    );
+    assert!("".parse::<SubstitutionToken>().is_err());
+    assert!("MP".parse::<SubstitutionToken>().is_err());
+
+    let token: SubstitutionToken = serde_json::from_str(r#"" M ""#).unwrap();
+    assert_eq!(token, SubstitutionToken::M);
+    let token: SubstitutionToken = serde_json::from_str(r#""t""#).unwrap();
+    assert_eq!(token, SubstitutionToken::T);
+    let e = serde_json::from_str::<SubstitutionToken>(r#""X""#).unwrap_err();
+    assert!(e.to_string().contains("Unknown substitution token `X`"));
+    assert!(serde_json::from_str::<SubstitutionToken>("1").is_err());
+    assert_eq!(
+        serde_json::to_string(&SubstitutionToken::P).unwrap(),
+        r#""P""#
+    );
+}
+
+#[test]
+fn test_referenced_vars() {
+    let vars = |chars: &[char]| chars.iter().copied().collect::<HashSet<char>>();
+    for (rule_str, expected) in [
+        ("D * E + D", vars(&['D', 'E'])),
+        ("f / 2", vars(&['F'])),
+        ("D + (D * (E - F) / 25.5)", vars(&['D', 'E', 'F'])),
+        ("0xFF + 1e3", vars(&[])),
+    ] {
+        let rule = ArithmeticRuleStr::new(rule_str.to_owned()).unwrap();
+        assert_eq!(rule.referenced_vars(), expected, "{}", rule_str);
+    }
 }
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::assignment::arithmetic_rule::SubstitutionToken;
+
+/// Kind of rule mutation recorded in audit log of `Assignment`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    /// Logical rule added.
+    AddLogical,
+    /// Arithmetic rule added.
+    AddArithmetic,
+    /// Chained rule added.
+    AddChained,
+    /// Decimal rule added.
+    AddDecimal,
+    /// All rules removed.
+    Remove,
+    /// All rules replaced, followed by entries of added rules.
+    Replace,
+    /// Rules of another `Assignment` merged in.
+    Merge,
+}
+
+/// Record of a single rule mutation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub operation: AuditOperation,
+    /// Token of added rule, `None` for operations on all rules.
+    pub token: Option<SubstitutionToken>,
+    /// Source string of added rule, `None` for function-based rules and operations on all rules.
+    pub source: Option<String>,
+    /// Time of mutation in milliseconds since Unix epoch.
+    pub timestamp_ms: u64,
+}
+
+impl AuditEntry {
+    /// Builds `AuditEntry` stamped with current time.
+    pub fn new(
+        operation: AuditOperation,
+        token: Option<SubstitutionToken>,
+        source: Option<String>,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        Self {
+            operation,
+            token,
+            source,
+            timestamp_ms,
+        }
+    }
+}
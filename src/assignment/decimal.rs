@@ -0,0 +1,371 @@
+//! Exact decimal arithmetic for money formulas.
+
+use std::{collections::HashMap, error::Error, str::FromStr};
+
+pub use rust_decimal::Decimal;
+
+use crate::assignment::{
+    arithmetic_rule::{ArithmeticRule, ArithmeticRuleStr, DivisionPolicy},
+    parse_tree::ParseTreeNode,
+};
+
+/// Converts `f64` to `Decimal` through its shortest decimal representation, so `0.1` becomes exactly `0.1`.
+/// Returns error if value is not finite or doesn't fit into `Decimal`.
+fn decimal_from_f64(value: f64) -> Result<Decimal, Box<dyn Error + Send + Sync>> {
+    if !value.is_finite() {
+        Err(format!("Can't convert {} to decimal.", value))?
+    }
+    Ok(Decimal::from_str(&value.to_string())?)
+}
+
+/// Parses number literal printed by `evalexpr`, e.g. `3`, `1.5` or `1e21`.
+fn decimal_from_literal(literal: &str) -> Result<Decimal, Box<dyn Error + Send + Sync>> {
+    Decimal::from_str(literal)
+        .or_else(|_| Decimal::from_scientific(literal))
+        .map_err(|_| format!("Invalid decimal number: {}.", literal).into())
+}
+
+fn overflow() -> Box<dyn Error + Send + Sync> {
+    "Decimal overflow.".into()
+}
+
+/// Comparison operator of `if` condition.
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Eq,
+    Neq,
+    Gt,
+    Lt,
+    Geq,
+    Leq,
+}
+
+/// Expression of `DecimalArithmeticRule` built from `evalexpr` operator tree.
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(Decimal),
+    Variable(char),
+    Constant(String),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    /// `if(a <op> b, then, otherwise)`.
+    If(Comparison, Box<[Expr; 4]>),
+}
+
+impl Expr {
+    /// Builds expression from operator tree of validated arithmetic rule string.
+    /// Returns error for operators arithmetic rules don't allow, e.g. comparison outside of `if`.
+    fn build(node: &ParseTreeNode) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let node = Self::unwrap_root(node);
+        let child = |i: usize| -> Result<Box<Expr>, Box<dyn Error + Send + Sync>> {
+            match node.children.get(i) {
+                Some(child) => Ok(Box::new(Self::build(child)?)),
+                None => Err(format!("Missing operand of `{}`.", node.operator))?,
+            }
+        };
+
+        Ok(match (node.operator.as_str(), node.value.as_deref()) {
+            ("Const", Some(literal)) => Expr::Number(decimal_from_literal(literal)?),
+            ("VariableIdentifier", Some(name @ ("D" | "E" | "F"))) => {
+                Expr::Variable(name.chars().next().unwrap())
+            }
+            ("VariableIdentifier", Some(name)) => Expr::Constant(name.to_owned()),
+            ("Neg", _) => Expr::Neg(child(0)?),
+            ("Add", _) => Expr::Add(child(0)?, child(1)?),
+            ("Sub", _) => Expr::Sub(child(0)?, child(1)?),
+            ("Mul", _) => Expr::Mul(child(0)?, child(1)?),
+            ("Div", _) => Expr::Div(child(0)?, child(1)?),
+            ("FunctionIdentifier", Some("if")) => Self::build_if(node)?,
+            (operator, _) => Err(format!(
+                "Operator `{}` is not supported by decimal rules.",
+                operator
+            ))?,
+        })
+    }
+
+    /// Builds `if` call from its `FunctionIdentifier` node with tuple of three arguments.
+    fn build_if(node: &ParseTreeNode) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let args = node
+            .children
+            .first()
+            .map(Self::unwrap_root)
+            .filter(|args| args.operator == "Tuple" && args.children.len() == 3)
+            .ok_or("Function `if` expects condition and two numbers.")?;
+
+        let condition = Self::unwrap_root(&args.children[0]);
+        let comparison = match condition.operator.as_str() {
+            "Eq" => Comparison::Eq,
+            "Neq" => Comparison::Neq,
+            "Gt" => Comparison::Gt,
+            "Lt" => Comparison::Lt,
+            "Geq" => Comparison::Geq,
+            "Leq" => Comparison::Leq,
+            _ => Err("Condition of `if` must be a comparison.")?,
+        };
+        if condition.children.len() != 2 {
+            Err("Comparison expects two operands.")?
+        }
+
+        Ok(Expr::If(
+            comparison,
+            Box::new([
+                Self::build(&condition.children[0])?,
+                Self::build(&condition.children[1])?,
+                Self::build(&args.children[1])?,
+                Self::build(&args.children[2])?,
+            ]),
+        ))
+    }
+
+    /// Skips `RootNode`s of parentheses and of the whole expression.
+    fn unwrap_root(mut node: &ParseTreeNode) -> &ParseTreeNode {
+        while node.operator == "RootNode" && node.children.len() == 1 {
+            node = &node.children[0];
+        }
+        node
+    }
+
+    /// Evaluates expression over given variables and constants.
+    fn eval(&self, ctx: &EvalContext) -> Result<Decimal, Box<dyn Error + Send + Sync>> {
+        Ok(match self {
+            Expr::Number(n) => *n,
+            Expr::Variable('D') => ctx.d,
+            Expr::Variable('E') => ctx.e,
+            Expr::Variable(_) => ctx.f,
+            Expr::Constant(name) => {
+                let value = ctx
+                    .constants
+                    .get(name)
+                    .ok_or_else(|| format!("Unknown constant `{}`.", name))?;
+                decimal_from_f64(*value)?
+            }
+            Expr::Neg(a) => -a.eval(ctx)?,
+            Expr::Add(a, b) => a
+                .eval(ctx)?
+                .checked_add(b.eval(ctx)?)
+                .ok_or_else(overflow)?,
+            Expr::Sub(a, b) => a
+                .eval(ctx)?
+                .checked_sub(b.eval(ctx)?)
+                .ok_or_else(overflow)?,
+            Expr::Mul(a, b) => a
+                .eval(ctx)?
+                .checked_mul(b.eval(ctx)?)
+                .ok_or_else(overflow)?,
+            Expr::Div(a, b) => {
+                let (a, b) = (a.eval(ctx)?, b.eval(ctx)?);
+                if b.is_zero() {
+                    match ctx.policy {
+                        DivisionPolicy::SafeZero => Decimal::ZERO,
+                        _ => Err("Division by zero.")?,
+                    }
+                } else {
+                    a.checked_div(b).ok_or_else(overflow)?
+                }
+            }
+            Expr::If(comparison, args) => {
+                let (a, b) = (args[0].eval(ctx)?, args[1].eval(ctx)?);
+                let condition = match comparison {
+                    Comparison::Eq => a == b,
+                    Comparison::Neq => a != b,
+                    Comparison::Gt => a > b,
+                    Comparison::Lt => a < b,
+                    Comparison::Geq => a >= b,
+                    Comparison::Leq => a <= b,
+                };
+                if condition {
+                    args[2].eval(ctx)?
+                } else {
+                    args[3].eval(ctx)?
+                }
+            }
+        })
+    }
+}
+
+/// Variables, constants and division policy of a single evaluation.
+struct EvalContext<'a> {
+    d: Decimal,
+    e: Decimal,
+    f: Decimal,
+    constants: &'a HashMap<String, f64>,
+    policy: DivisionPolicy,
+}
+
+/// Arithmetic rule evaluated over `Decimal` instead of `f64`.
+///
+/// Rule string has the same grammar as `ArithmeticRuleStr` and is validated the same way:
+/// D, E, F variables, constants, numbers in plain, scientific or hexadecimal notation, parentheses,
+/// +, -, *, / operators and `if(condition, a, b)` with comparison in condition.
+/// Expression is parsed by `evalexpr` and its operator tree is evaluated over `Decimal`.
+///
+/// `d`, number literals and constants are converted through the shortest decimal representation of `f64`,
+/// so they keep up to 17 significant digits, e.g. `0.1` is exactly `0.1`.
+/// Division keeps up to 28 fractional digits. Since `Decimal` has no infinity,
+/// division by zero is an error unless `DivisionPolicy::SafeZero` makes it zero.
+///
+/// # Examples
+///
+/// ```
+/// use st_test::assignment::decimal::DecimalArithmeticRule;
+///
+/// let rule = DecimalArithmeticRule::new("D + D * E / 10".to_owned()).unwrap();
+/// assert_eq!(rule.apply(2.0, 3, 0).unwrap().normalize().to_string(), "2.6");
+/// ```
+#[derive(Clone, Debug)]
+pub struct DecimalArithmeticRule {
+    rule_str: String,
+    expr: Expr,
+}
+
+impl DecimalArithmeticRule {
+    /// Validates and parses provided rule string and builds `DecimalArithmeticRule`.
+    /// Returns error with description if validation fails.
+    pub fn new(rule_str: String) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        DecimalArithmeticRule::new_with_constants(rule_str, &HashMap::new())
+    }
+
+    /// Builds `DecimalArithmeticRule` the same way as `new`, but allows names of given constants in rule string.
+    /// Values of constants are provided on evaluation with `apply_with_constants`.
+    pub fn new_with_constants(
+        rule_str: String,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        // Validation expands scientific and hexadecimal literals, so `evalexpr` reads them as numbers.
+        let rule = ArithmeticRuleStr::new_with_constants(rule_str, constants)?;
+        let expr = Expr::build(&ParseTreeNode::build(rule.expanded_rule_str())?)?;
+
+        Ok(Self {
+            rule_str: rule.source().unwrap_or_default().to_owned(),
+            expr,
+        })
+    }
+
+    /// Returns normalized rule string.
+    pub fn rule_str(&self) -> &str {
+        &self.rule_str
+    }
+
+    /// Applies the rule to given arguments without constants and with `DivisionPolicy::Standard`.
+    /// Returns error on division by zero or overflow.
+    pub fn apply(&self, d: f64, e: i32, f: i32) -> Result<Decimal, Box<dyn Error + Send + Sync>> {
+        self.apply_with_constants(d, e, f, DivisionPolicy::Standard, &HashMap::new())
+    }
+
+    /// Applies the rule to given arguments with given `DivisionPolicy` and named constants.
+    /// Returns error on division by zero not allowed by the policy, overflow or unknown constant.
+    pub fn apply_with_constants(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        policy: DivisionPolicy,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Decimal, Box<dyn Error + Send + Sync>> {
+        self.expr.eval(&EvalContext {
+            d: decimal_from_f64(d)?,
+            e: Decimal::from(e),
+            f: Decimal::from(f),
+            constants,
+            policy,
+        })
+    }
+}
+
+#[test]
+fn test_decimal_rule() {
+    let parse = |s: &str| s.parse::<Decimal>().unwrap();
+
+    // Loses precision in `f64`.
+    assert_ne!(0.1 + 0.2, 0.3);
+    let rule = DecimalArithmeticRule::new("D + 0.2".to_owned()).unwrap();
+    assert_eq!(rule.apply(0.1, 0, 0).unwrap(), parse("0.3"));
+
+    assert_ne!(1.1 * 3.0, 3.3);
+    let rule = DecimalArithmeticRule::new("D * E".to_owned()).unwrap();
+    assert_eq!(rule.apply(1.1, 3, 0).unwrap(), parse("3.3"));
+
+    let rule = DecimalArithmeticRule::new("d + (d * (e - f) / 25.5)".to_owned()).unwrap();
+    assert_eq!(rule.rule_str(), "D + (D * (E - F) / 25.5)");
+    assert_eq!(rule.apply(2.55, 4, -1).unwrap(), parse("3.05"));
+    assert_eq!(
+        DecimalArithmeticRule::new("-(D - E) * -F".to_owned())
+            .unwrap()
+            .apply(1.5, 2, 3)
+            .unwrap(),
+        parse("-1.5")
+    );
+    assert_eq!(
+        DecimalArithmeticRule::new("D / 3".to_owned())
+            .unwrap()
+            .apply(2.0, 0, 0)
+            .unwrap()
+            .to_string(),
+        "0.6666666666666666666666666667"
+    );
+
+    assert!(DecimalArithmeticRule::new("D +".to_owned()).is_err());
+    assert!(DecimalArithmeticRule::new("D && E".to_owned()).is_err());
+    assert!(DecimalArithmeticRule::new("D * rate".to_owned()).is_err());
+    assert!(DecimalArithmeticRule::new("D > E".to_owned()).is_err());
+    assert!(rule.apply(f64::NAN, 0, 0).is_err());
+}
+
+#[test]
+fn test_decimal_rule_grammar() {
+    let parse = |s: &str| s.parse::<Decimal>().unwrap();
+    let apply = |rule_str: &str, d, e, f| {
+        DecimalArithmeticRule::new(rule_str.to_owned())
+            .unwrap()
+            .apply(d, e, f)
+            .unwrap()
+    };
+
+    // Scientific and hexadecimal literals.
+    assert_eq!(apply("D * 1e2 + 0x10", 0.07, 0, 0), parse("23"));
+    assert_eq!(apply("D * 2.5E-2", 4.0, 0, 0), parse("0.1"));
+
+    // `if` with every comparison.
+    for (op, expected) in [
+        (">", "0.1"),
+        ("<", "0.2"),
+        (">=", "0.1"),
+        ("<=", "0.2"),
+        ("==", "0.2"),
+        ("!=", "0.1"),
+    ] {
+        let rule_str = format!("if(E {} F, D, D * 2)", op);
+        assert_eq!(apply(&rule_str, 0.1, 3, 2), parse(expected), "{}", op);
+    }
+    assert_eq!(
+        apply("if((E - 1) * 2 > F, D + 0.2, 0) - 0.1", 0.1, 3, 2),
+        parse("0.2")
+    );
+}
+
+#[test]
+fn test_decimal_rule_constants_and_division() {
+    let mut constants = HashMap::new();
+    constants.insert("rate".to_owned(), 0.1);
+    let rule =
+        DecimalArithmeticRule::new_with_constants("D * rate / E".to_owned(), &constants).unwrap();
+    assert_eq!(
+        rule.apply_with_constants(0.2, 1, 0, DivisionPolicy::Standard, &constants)
+            .unwrap(),
+        "0.02".parse::<Decimal>().unwrap()
+    );
+    assert!(rule.apply(0.2, 1, 0).is_err());
+
+    // `Decimal` has no infinity, so only `SafeZero` allows division by zero.
+    for (policy, ok) in [
+        (DivisionPolicy::Standard, false),
+        (DivisionPolicy::Error, false),
+        (DivisionPolicy::SafeZero, true),
+    ] {
+        let res = rule.apply_with_constants(0.2, 0, 0, policy, &constants);
+        assert_eq!(res.ok(), Some(Decimal::ZERO).filter(|_| ok), "{:?}", policy);
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use crate::assignment::{arithmetic_rule::SubstitutionToken, InputSet};
+
+/// Entry of `EvalCache` linked into usage order by slot indices.
+struct Node {
+    args: InputSet,
+    res: (SubstitutionToken, f64),
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// Least recently used cache of `Assignment::eval` results keyed by `InputSet`.
+///
+/// Only successful results are stored.
+/// Cache has to be cleared by owner whenever substitution rules change.
+///
+/// Entries are kept in a doubly linked list over slots of a `Vec` in usage order,
+/// so lookup, insertion and eviction take constant time.
+pub struct EvalCache {
+    capacity: usize,
+    slots: HashMap<InputSet, usize>,
+    nodes: Vec<Node>,
+    /// Least recently used entry.
+    head: Option<usize>,
+    /// Most recently used entry.
+    tail: Option<usize>,
+}
+
+impl EvalCache {
+    /// Builds `EvalCache`.
+    ///
+    /// # Arguments
+    /// * `capacity` - Maximum number of stored results. Zero disables caching.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            slots: HashMap::with_capacity(capacity),
+            nodes: Vec::with_capacity(capacity),
+            head: None,
+            tail: None,
+        }
+    }
+
+    /// Returns cached result for given input and marks it as most recently used.
+    pub fn get(&mut self, args: &InputSet) -> Option<(SubstitutionToken, f64)> {
+        let slot = *self.slots.get(args)?;
+        self.touch(slot);
+        Some(self.nodes[slot].res.clone())
+    }
+
+    /// Stores result for given input.
+    /// Evicts least recently used result if cache is full.
+    pub fn insert(&mut self, args: InputSet, res: (SubstitutionToken, f64)) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(&slot) = self.slots.get(&args) {
+            self.nodes[slot].res = res;
+            self.touch(slot);
+            return;
+        }
+
+        let node = Node {
+            args: args.clone(),
+            res,
+            prev: None,
+            next: None,
+        };
+        let slot = if self.nodes.len() < self.capacity {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        } else {
+            // Full cache reuses slot of least recently used entry.
+            let oldest = self.head.expect("full cache has entries");
+            self.unlink(oldest);
+            let evicted = std::mem::replace(&mut self.nodes[oldest], node);
+            self.slots.remove(&evicted.args);
+            oldest
+        };
+        self.slots.insert(args, slot);
+        self.push_back(slot);
+    }
+
+    /// Removes all stored results.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.nodes.clear();
+        self.head = None;
+        self.tail = None;
+    }
+
+    /// Returns maximum number of stored results.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns number of stored results.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns `true` if there are no stored results.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Moves entry in given slot to the back of usage order.
+    fn touch(&mut self, slot: usize) {
+        if self.tail != Some(slot) {
+            self.unlink(slot);
+            self.push_back(slot);
+        }
+    }
+
+    /// Detaches entry in given slot from usage order.
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = (self.nodes[slot].prev, self.nodes[slot].next);
+        match prev {
+            Some(prev) => self.nodes[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+        self.nodes[slot].prev = None;
+        self.nodes[slot].next = None;
+    }
+
+    /// Attaches detached entry in given slot to the back of usage order.
+    fn push_back(&mut self, slot: usize) {
+        self.nodes[slot].prev = self.tail;
+        match self.tail {
+            Some(tail) => self.nodes[tail].next = Some(slot),
+            None => self.head = Some(slot),
+        }
+        self.tail = Some(slot);
+    }
+}
+
+#[cfg(test)]
+fn input(d: f64) -> InputSet {
+    InputSet {
+        d,
+        ..InputSet::default()
+    }
+}
+
+#[test]
+fn test_get_insert() {
+    let mut cache = EvalCache::new(2);
+    assert!(cache.is_empty());
+    assert_eq!(cache.get(&input(1.0)), None);
+
+    cache.insert(input(1.0), (SubstitutionToken::M, 1.0));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get(&input(1.0)), Some((SubstitutionToken::M, 1.0)));
+    assert_eq!(cache.get(&input(2.0)), None);
+}
+
+#[test]
+fn test_eviction() {
+    let mut cache = EvalCache::new(2);
+
+    cache.insert(input(1.0), (SubstitutionToken::M, 1.0));
+    cache.insert(input(2.0), (SubstitutionToken::P, 2.0));
+
+    // Use first input so the second one becomes least recently used.
+    assert!(cache.get(&input(1.0)).is_some());
+
+    cache.insert(input(3.0), (SubstitutionToken::T, 3.0));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&input(1.0)), Some((SubstitutionToken::M, 1.0)));
+    assert_eq!(cache.get(&input(2.0)), None);
+    assert_eq!(cache.get(&input(3.0)), Some((SubstitutionToken::T, 3.0)));
+}
+
+#[test]
+fn test_zero_capacity() {
+    let mut cache = EvalCache::new(0);
+
+    cache.insert(input(1.0), (SubstitutionToken::M, 1.0));
+    assert!(cache.is_empty());
+    assert_eq!(cache.get(&input(1.0)), None);
+}
+
+#[test]
+fn test_clear() {
+    let mut cache = EvalCache::new(2);
+
+    cache.insert(input(1.0), (SubstitutionToken::M, 1.0));
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.get(&input(1.0)), None);
+}
+
+#[test]
+fn test_usage_order() {
+    let mut cache = EvalCache::new(3);
+
+    for d in 1..=3 {
+        cache.insert(input(d as f64), (SubstitutionToken::M, d as f64));
+    }
+    // Touch entries in the middle, at the back and by reinsertion.
+    assert!(cache.get(&input(2.0)).is_some());
+    assert!(cache.get(&input(2.0)).is_some());
+    cache.insert(input(1.0), (SubstitutionToken::P, 10.0));
+
+    // Usage order is 3, 2, 1, so 3 and then 2 are evicted.
+    cache.insert(input(4.0), (SubstitutionToken::M, 4.0));
+    assert_eq!(cache.get(&input(3.0)), None);
+    cache.insert(input(5.0), (SubstitutionToken::M, 5.0));
+    assert_eq!(cache.get(&input(2.0)), None);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.get(&input(1.0)), Some((SubstitutionToken::P, 10.0)));
+    assert_eq!(cache.get(&input(4.0)), Some((SubstitutionToken::M, 4.0)));
+    assert_eq!(cache.get(&input(5.0)), Some((SubstitutionToken::M, 5.0)));
+
+    // Single entry cache replaces its only entry.
+    let mut cache = EvalCache::new(1);
+    cache.insert(input(1.0), (SubstitutionToken::M, 1.0));
+    cache.insert(input(2.0), (SubstitutionToken::M, 2.0));
+    assert_eq!(cache.get(&input(1.0)), None);
+    assert_eq!(cache.get(&input(2.0)), Some((SubstitutionToken::M, 2.0)));
+}
@@ -0,0 +1,135 @@
+//! Lenient deserialization of `InputSet` fields.
+//!
+//! Numeric fields accept JSON numbers or numeric strings, e.g. `"2.0"`,
+//! boolean fields accept JSON booleans or `"true"`/`"false"` strings.
+
+use serde::{
+    de::{self, Unexpected, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{convert::TryFrom, fmt, marker::PhantomData, str::FromStr};
+
+/// Field value which may be sent either as its own JSON type or as a string.
+pub(crate) trait LenientValue: FromStr + Sized {
+    /// Description of accepted values used in error messages.
+    const EXPECTED: &'static str;
+
+    fn from_bool(_v: bool) -> Option<Self> {
+        None
+    }
+
+    fn from_i64(_v: i64) -> Option<Self> {
+        None
+    }
+
+    fn from_u64(_v: u64) -> Option<Self> {
+        None
+    }
+
+    fn from_f64(_v: f64) -> Option<Self> {
+        None
+    }
+}
+
+impl LenientValue for bool {
+    const EXPECTED: &'static str = "a boolean or \"true\"/\"false\" string";
+
+    fn from_bool(v: bool) -> Option<Self> {
+        Some(v)
+    }
+}
+
+impl LenientValue for f64 {
+    const EXPECTED: &'static str = "a number or numeric string";
+
+    fn from_i64(v: i64) -> Option<Self> {
+        Some(v as f64)
+    }
+
+    fn from_u64(v: u64) -> Option<Self> {
+        Some(v as f64)
+    }
+
+    fn from_f64(v: f64) -> Option<Self> {
+        Some(v)
+    }
+}
+
+impl LenientValue for i32 {
+    const EXPECTED: &'static str = "an integer or integer string";
+
+    fn from_i64(v: i64) -> Option<Self> {
+        i32::try_from(v).ok()
+    }
+
+    fn from_u64(v: u64) -> Option<Self> {
+        i32::try_from(v).ok()
+    }
+}
+
+/// Wrapper deserializing `T` leniently.
+pub(crate) struct Lenient<T>(pub T);
+
+struct LenientVisitor<T>(PhantomData<T>);
+
+impl<T: LenientValue> LenientVisitor<T> {
+    fn value<E: de::Error>(v: Option<T>, unexpected: Unexpected) -> Result<Lenient<T>, E> {
+        v.map(Lenient)
+            .ok_or_else(|| E::invalid_type(unexpected, &T::EXPECTED))
+    }
+}
+
+impl<'de, T: LenientValue> Visitor<'de> for LenientVisitor<T> {
+    type Value = Lenient<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(T::EXPECTED)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Self::value(T::from_bool(v), Unexpected::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Self::value(T::from_i64(v), Unexpected::Signed(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Self::value(T::from_u64(v), Unexpected::Unsigned(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Self::value(T::from_f64(v), Unexpected::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.trim()
+            .parse()
+            .map(Lenient)
+            .map_err(|_| E::invalid_value(Unexpected::Str(v), &T::EXPECTED))
+    }
+}
+
+impl<'de, T: LenientValue> Deserialize<'de> for Lenient<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(LenientVisitor(PhantomData))
+    }
+}
+
+/// Deserializes `T` from its own type or from a string.
+pub(crate) fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: LenientValue,
+{
+    Lenient::deserialize(deserializer).map(|v| v.0)
+}
+
+/// Deserializes optional `T` from its own type or from a string, `null` is `None`.
+pub(crate) fn deserialize_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: LenientValue,
+{
+    Option::<Lenient<T>>::deserialize(deserializer).map(|v| v.map(|v| v.0))
+}
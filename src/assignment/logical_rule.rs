@@ -1,13 +1,72 @@
 use evalexpr::*;
 use regex::Regex;
 
-use std::error::Error;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::{Arc, OnceLock},
+};
 
-use crate::assignment::arithmetic_rule::SubstitutionToken;
+use crate::assignment::{
+    arithmetic_rule::SubstitutionToken, constants_pattern, normalize_rule_str, referenced_vars,
+    set_constants, RuleLimits, RuleMetadata, ValidationError,
+};
 
 pub trait LogicalRule: Send + Sync {
     /// Returns `Some(SubstitutionToken)` if logical rule result is `true`, `None` otherwise.
-    fn apply(&self, a: bool, b: bool, c: bool) -> Option<SubstitutionToken>;
+    /// Returns error if rule can't be evaluated.
+    fn apply(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>>;
+
+    /// Returns result of the rule the same way as `apply`, with given named constants available to the rule.
+    ///
+    /// By default constants are ignored and result of `apply` is returned.
+    fn apply_with_constants(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+        _constants: &HashMap<String, f64>,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>> {
+        self.apply(a, b, c)
+    }
+
+    /// Returns `SubstitutionToken` the rule maps to.
+    fn token(&self) -> &SubstitutionToken;
+
+    /// Checks that the rule can be evaluated with given named constants.
+    /// Returns error if it can't, e.g. if its expression doesn't compile.
+    ///
+    /// By default the rule is always ready.
+    fn prepare(
+        &self,
+        _constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Returns boxed copy of the rule.
+    fn clone_box(&self) -> Box<dyn LogicalRule>;
+
+    /// Returns source string of the rule if it has one.
+    fn source(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns human-readable name of the rule if it has one.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns human-readable description of the rule if it has one.
+    fn description(&self) -> Option<&str> {
+        None
+    }
 }
 
 pub type RuleFn = Box<dyn Fn(bool, bool, bool) -> bool + Send + Sync>;
@@ -17,15 +76,21 @@ pub type RuleFn = Box<dyn Fn(bool, bool, bool) -> bool + Send + Sync>;
 /// # Examples
 ///
 /// ```
+/// use st_test::assignment::arithmetic_rule::SubstitutionToken;
+/// use st_test::assignment::logical_rule::{LogicalRule, LogicalRuleFn};
+///
 /// let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
-/// let res = rule.apply(true, false, false);
+/// let res = rule.apply(true, false, false).unwrap();
 /// assert_eq!(res, Some(SubstitutionToken::M));
-/// let res = rule.apply(false, false, false);
+/// let res = rule.apply(false, false, false).unwrap();
 /// assert_eq!(res, None);
 /// ```
+/// Function is stored in `Arc`, so cloned rules share it.
+#[derive(Clone)]
 pub struct LogicalRuleFn {
     token: SubstitutionToken,
-    rule_fn: RuleFn,
+    rule_fn: Arc<dyn Fn(bool, bool, bool) -> bool + Send + Sync>,
+    metadata: RuleMetadata,
 }
 
 impl LogicalRuleFn {
@@ -34,87 +99,625 @@ impl LogicalRuleFn {
     /// # Arguments
     /// * `rule_fn` `RuleFn` function.
     pub fn new(token: SubstitutionToken, rule_fn: RuleFn) -> Self {
-        Self { token, rule_fn }
+        Self {
+            token,
+            rule_fn: Arc::from(rule_fn),
+            metadata: RuleMetadata::default(),
+        }
+    }
+
+    /// Sets human-readable name of the rule.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.metadata.name = Some(name.into());
+        self
+    }
+
+    /// Sets human-readable description of the rule.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.metadata.description = Some(description.into());
+        self
     }
 }
 
 impl LogicalRule for LogicalRuleFn {
     /// Returns `Some(SunstitutionToken)` if result of stored function is `true`,
     /// None otherwise.
-    fn apply(&self, a: bool, b: bool, c: bool) -> Option<SubstitutionToken> {
+    fn apply(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>> {
         if (self.rule_fn)(a, b, c) {
-            Some(self.token.clone())
+            Ok(Some(self.token.clone()))
         } else {
-            None
+            Ok(None)
         }
     }
+
+    fn token(&self) -> &SubstitutionToken {
+        &self.token
+    }
+
+    fn clone_box(&self) -> Box<dyn LogicalRule> {
+        Box::new(self.clone())
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.metadata.name.as_deref()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.metadata.description.as_deref()
+    }
 }
 
 /// Stores rule in a `String` and corresponding `SubstitutionToken`.
 ///
-/// Rule string can contain only A, B or C variables and !, &&, ||, ==, != operators.
+/// Rule string can contain only A, B or C variables and !, &&, ||, ==, !=, >, <, >=, <= operators.
+/// Ordering comparisons treat `true` as greater than `false`.
 ///
 /// # Examples
 ///
 /// ```
-/// let rule = LogicalRuleStr::new(SubstitutionToken::M, "A && B").unwrap();
-/// let res = rule.apply(true, true, false);
+/// use st_test::assignment::arithmetic_rule::SubstitutionToken;
+/// use st_test::assignment::logical_rule::{LogicalRule, LogicalRuleStr};
+///
+/// let rule = LogicalRuleStr::new(SubstitutionToken::M, "A && B".to_owned()).unwrap();
+/// let res = rule.apply(true, true, false).unwrap();
 /// assert_eq!(res, Some(SubstitutionToken::M));
-/// let res = rule.apply(false, true, false);
+/// let res = rule.apply(false, true, false).unwrap();
 /// assert_eq!(res, None);
 /// ```
+#[derive(Clone)]
 pub struct LogicalRuleStr {
     token: SubstitutionToken,
     rule_str: String,
+    /// Rule string with each ordering comparison rewritten to function call, if rule has comparisons.
+    comparison_rule_str: Option<String>,
+    /// Input variables referenced by the rule.
+    referenced_vars: HashSet<char>,
+    metadata: RuleMetadata,
 }
 
 impl LogicalRuleStr {
     /// Validates provided rule string and builds `LogicalRuleFn`.
     /// Returns `Ok(LogicalRuleStr)` if validation is successful,
     /// otherwise returns error with description.
-    pub fn new(token: SubstitutionToken, rule_str: String) -> Result<Self, Box<dyn Error>> {
-        LogicalRuleStr::validate(&rule_str)?;
-        Ok(Self { token, rule_str })
+    pub fn new(
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        LogicalRuleStr::new_with_constants(token, rule_str, &HashMap::new())
+    }
+
+    /// Builds `LogicalRuleStr` the same way as `new`, but allows names of given constants in rule string.
+    /// Values of constants are provided on evaluation with `apply_with_constants`.
+    pub fn new_with_constants(
+        token: SubstitutionToken,
+        rule_str: String,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let rule_str = normalize_rule_str(&rule_str);
+        LogicalRuleStr::validate_with_constants(&rule_str, constants)?;
+        Ok(Self {
+            token,
+            comparison_rule_str: rewrite_comparisons(&rule_str),
+            referenced_vars: referenced_vars(&rule_str),
+            rule_str,
+            metadata: RuleMetadata::default(),
+        })
+    }
+
+    /// Sets human-readable name of the rule.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.metadata.name = Some(name.into());
+        self
     }
 
-    /// Validates provided rule string.
-    /// Returns error if it contains invalid variables or operators,
+    /// Sets human-readable description of the rule.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.metadata.description = Some(description.into());
+        self
+    }
+
+    /// Returns input variables `A`, `B` and `C` referenced by the rule, e.g. `{'A'}` for `A && A`.
+    pub fn referenced_vars(&self) -> HashSet<char> {
+        self.referenced_vars.clone()
+    }
+
+    /// Validates provided rule string with default `RuleLimits`.
+    /// Trailing `# comment` and extra whitespace are ignored.
+    /// Returns error if it exceeds the limits,
+    /// if it contains invalid variables or operators,
     /// or if it's not compilable by `evalexpr`,
     /// otherwise returns `Ok`.
-    fn validate(rule_str: &String) -> Result<(), Box<dyn Error>> {
-        let re = Regex::new(r"^([ABC ]|&&|==|!=|!|\|\|)+$").unwrap();
-        if !re.is_match(&rule_str) {
-            Err("Expression contains invalid variables or operators.")?
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use st_test::assignment::logical_rule::LogicalRuleStr;
+    ///
+    /// assert!(LogicalRuleStr::validate("A && !B").is_ok());
+    /// assert!(LogicalRuleStr::validate("A + B").is_err());
+    /// assert!(LogicalRuleStr::validate("A &&").is_err());
+    /// ```
+    pub fn validate(rule_str: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        LogicalRuleStr::validate_with_limits(rule_str, &RuleLimits::default())
+    }
+
+    /// Validates provided rule string the same way as `validate`, but with given `RuleLimits`.
+    pub fn validate_with_limits(
+        rule_str: &str,
+        limits: &RuleLimits,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        LogicalRuleStr::check(rule_str, limits, &HashMap::new())
+    }
+
+    /// Validates provided rule string the same way as `validate`, but allows names of given constants.
+    pub fn validate_with_constants(
+        rule_str: &str,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        LogicalRuleStr::check(rule_str, &RuleLimits::default(), constants)
+    }
+
+    /// Validates provided rule string with given `RuleLimits` and constants.
+    fn check(
+        rule_str: &str,
+        limits: &RuleLimits,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        check_rule_str(rule_str, limits, constants, false)
+    }
+}
+
+/// Validates logical rule string with given `RuleLimits` and constants.
+/// If `chained` is `true`, token variables `M`, `P` and `T` of `ChainedRuleStr` are allowed as well.
+fn check_rule_str(
+    rule_str: &str,
+    limits: &RuleLimits,
+    constants: &HashMap<String, f64>,
+    chained: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    limits.check(rule_str)?;
+    let rule_str = &normalize_rule_str(rule_str);
+
+    let re = if constants.is_empty() {
+        Cow::Borrowed(rule_str_regex(chained))
+    } else {
+        Cow::Owned(
+            Regex::new(&rule_str_pattern(chained, constants)).map_err(ValidationError::from)?,
+        )
+    };
+    if !re.is_match(rule_str) {
+        Err(ValidationError::rejected(
+            "Expression contains invalid variables or operators.",
+            rule_str,
+        ))?
+    }
+
+    // Try to evaluate expression with some input to check if it's valid for `evalexpr`.
+    let mut context = logical_context(true, true, true, constants);
+    if chained {
+        set_token_variables(&mut context, &SubstitutionToken::M);
+    }
+    let comparison_rule_str = rewrite_comparisons(rule_str);
+    eval_boolean_with_context(comparison_rule_str.as_ref().unwrap_or(rule_str), &context)
+        .map_err(ValidationError::from)?;
+
+    Ok(())
+}
+
+/// Returns pattern of valid logical rule string with given constants,
+/// with token variables of `ChainedRuleStr` if `chained` is `true`.
+fn rule_str_pattern(chained: bool, constants: &HashMap<String, f64>) -> String {
+    let variables = if chained { "ABCMPT" } else { "ABC" };
+    format!(
+        r"^([{} ]|&&|==|!=|>=|<=|>|<|!|\|\|{})+$",
+        variables,
+        constants_pattern(constants)
+    )
+}
+
+/// Returns regex of `rule_str_pattern` without constants, compiled once per process.
+fn rule_str_regex(chained: bool) -> &'static Regex {
+    static PLAIN: OnceLock<Regex> = OnceLock::new();
+    static CHAINED: OnceLock<Regex> = OnceLock::new();
+    let cell = if chained { &CHAINED } else { &PLAIN };
+    cell.get_or_init(|| Regex::new(&rule_str_pattern(chained, &HashMap::new())).unwrap())
+}
+
+/// Builds `evalexpr` context of logical rule with given variables, constants
+/// and functions replacing ordering comparisons.
+fn logical_context(a: bool, b: bool, c: bool, constants: &HashMap<String, f64>) -> HashMapContext {
+    let mut context = context_map! {
+        "A" => a,
+        "B" => b,
+        "C" => c,
+        "gt" => Function::new(Box::new(|argument| comparison_function(argument, |x, y| x > y))),
+        "lt" => Function::new(Box::new(|argument| comparison_function(argument, |x, y| x < y))),
+        "ge" => Function::new(Box::new(|argument| comparison_function(argument, |x, y| x >= y))),
+        "le" => Function::new(Box::new(|argument| comparison_function(argument, |x, y| x <= y))),
+    }
+    .unwrap();
+    set_constants(&mut context, constants);
+    context
+}
+
+/// Sets token variables `M`, `P` and `T` of chained rule context,
+/// only the variable of `tentative` token is `true`.
+fn set_token_variables(context: &mut HashMapContext, tentative: &SubstitutionToken) {
+    for (name, token) in [
+        ("M", SubstitutionToken::M),
+        ("P", SubstitutionToken::P),
+        ("T", SubstitutionToken::T),
+    ] {
+        context
+            .set_value(name.to_owned(), Value::Boolean(*tentative == token))
+            .unwrap();
+    }
+}
+
+/// Implementation of functions replacing ordering comparisons in logical rules.
+/// Booleans are compared as numbers, `true` as `1` and `false` as `0`.
+fn comparison_function(argument: &Value, cmp: fn(f64, f64) -> bool) -> EvalexprResult<Value> {
+    let args = argument.as_fixed_len_tuple(2)?;
+    let as_number = |value: &Value| match value {
+        Value::Boolean(b) => Ok(if *b { 1.0 } else { 0.0 }),
+        value => value.as_number(),
+    };
+    Ok(Value::Boolean(cmp(
+        as_number(&args[0])?,
+        as_number(&args[1])?,
+    )))
+}
+
+/// Rewrites each ordering comparison in logical rule string to `gt`, `lt`, `ge` or `le` function call,
+/// keeping operator precedence.
+/// Returns `None` if rule string has no ordering comparisons or can't be parsed.
+fn rewrite_comparisons(rule_str: &str) -> Option<String> {
+    if !rule_str.contains(['<', '>']) {
+        return None;
+    }
+
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"[A-Za-z_]\w*|&&|\|\||==|!=|>=|<=|[!<>()]").unwrap());
+    let tokens: Vec<&str> = re.find_iter(rule_str).map(|m| m.as_str()).collect();
+
+    let mut parser = ComparisonRewriter { tokens, pos: 0 };
+    let res = parser.or()?;
+    if parser.pos == parser.tokens.len() {
+        Some(res)
+    } else {
+        None
+    }
+}
+
+/// Recursive descent parser of logical rule strings used by `rewrite_comparisons`.
+struct ComparisonRewriter<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> ComparisonRewriter<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn or(&mut self) -> Option<String> {
+        let mut res = self.and()?;
+        while self.peek() == Some("||") {
+            self.pos += 1;
+            res = format!("{} || {}", res, self.and()?);
         }
+        Some(res)
+    }
 
-        // Try to evaluate expression with some input to check if it's valid for `evalexpr`.
-        let context = context_map! {
-            "A" => true,
-            "B" => true,
-            "C" => true,
+    fn and(&mut self) -> Option<String> {
+        let mut res = self.comparison()?;
+        while self.peek() == Some("&&") {
+            self.pos += 1;
+            res = format!("{} && {}", res, self.comparison()?);
         }
-        .unwrap();
-        eval_boolean_with_context(&rule_str, &context)?;
+        Some(res)
+    }
 
-        Ok(())
+    fn comparison(&mut self) -> Option<String> {
+        let mut res = self.unary()?;
+        while let Some(op @ ("==" | "!=" | ">" | "<" | ">=" | "<=")) = self.peek() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            res = match op {
+                ">" => format!("gt({}, {})", res, rhs),
+                "<" => format!("lt({}, {})", res, rhs),
+                ">=" => format!("ge({}, {})", res, rhs),
+                "<=" => format!("le({}, {})", res, rhs),
+                _ => format!("{} {} {}", res, op, rhs),
+            };
+        }
+        Some(res)
+    }
+
+    fn unary(&mut self) -> Option<String> {
+        if self.peek()? == "!" {
+            self.pos += 1;
+            return Some(format!("!{}", self.unary()?));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<String> {
+        match self.next()? {
+            "(" => {
+                let res = self.or()?;
+                if self.next()? != ")" {
+                    return None;
+                }
+                Some(format!("({})", res))
+            }
+            token if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+                Some(token.to_owned())
+            }
+            _ => None,
+        }
     }
 }
 
 impl LogicalRule for LogicalRuleStr {
-    fn apply(&self, a: bool, b: bool, c: bool) -> Option<SubstitutionToken> {
-        let context = context_map! {
-            "A" => a,
-            "B" => b,
-            "C" => c,
-        }
-        .unwrap();
-        let res = eval_boolean_with_context(&self.rule_str, &context).unwrap();
+    fn apply(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>> {
+        self.apply_with_constants(a, b, c, &HashMap::new())
+    }
+
+    fn apply_with_constants(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>> {
+        let context = logical_context(a, b, c, constants);
+        let rule_str = self.comparison_rule_str.as_ref().unwrap_or(&self.rule_str);
+        let res = eval_boolean_with_context(rule_str, &context)?;
 
         if res {
-            Some(self.token.clone())
+            Ok(Some(self.token.clone()))
         } else {
-            None
+            Ok(None)
+        }
+    }
+
+    fn token(&self) -> &SubstitutionToken {
+        &self.token
+    }
+
+    fn prepare(
+        &self,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        LogicalRuleStr::validate_with_constants(&self.rule_str, constants)
+    }
+
+    fn clone_box(&self) -> Box<dyn LogicalRule> {
+        Box::new(self.clone())
+    }
+
+    fn source(&self) -> Option<&str> {
+        Some(&self.rule_str)
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.metadata.name.as_deref()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.metadata.description.as_deref()
+    }
+}
+
+/// Combines logical rules, so its `SubstitutionToken` is produced only if all of them match.
+///
+/// Tokens of sub-rules are ignored. Sub-rules are applied in order until the first one that doesn't match.
+///
+/// # Examples
+///
+/// ```
+/// use st_test::assignment::arithmetic_rule::SubstitutionToken;
+/// use st_test::assignment::logical_rule::{LogicalRule, LogicalRuleAll, LogicalRuleStr};
+///
+/// let rule = LogicalRuleAll::new(
+///     SubstitutionToken::T,
+///     vec![
+///         Box::new(LogicalRuleStr::new(SubstitutionToken::M, "A".to_owned()).unwrap()),
+///         Box::new(LogicalRuleStr::new(SubstitutionToken::M, "!C".to_owned()).unwrap()),
+///     ],
+/// )
+/// .unwrap();
+/// assert_eq!(rule.apply(true, false, false).unwrap(), Some(SubstitutionToken::T));
+/// assert_eq!(rule.apply(true, false, true).unwrap(), None);
+/// ```
+pub struct LogicalRuleAll {
+    token: SubstitutionToken,
+    rules: Vec<Box<dyn LogicalRule>>,
+}
+
+impl LogicalRuleAll {
+    /// Builds `LogicalRuleAll` from sub-rules.
+    /// Returns error if there are no sub-rules.
+    pub fn new(
+        token: SubstitutionToken,
+        rules: Vec<Box<dyn LogicalRule>>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if rules.is_empty() {
+            Err("Combined logical rule needs at least one sub-rule.")?
         }
+        Ok(Self { token, rules })
+    }
+
+    /// Returns sub-rules in order.
+    pub fn rules(&self) -> impl Iterator<Item = &dyn LogicalRule> {
+        self.rules.iter().map(|rule| rule.as_ref())
+    }
+}
+
+impl LogicalRule for LogicalRuleAll {
+    fn apply(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>> {
+        self.apply_with_constants(a, b, c, &HashMap::new())
+    }
+
+    fn apply_with_constants(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>> {
+        for rule in &self.rules {
+            if rule.apply_with_constants(a, b, c, constants)?.is_none() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.token.clone()))
+    }
+
+    fn token(&self) -> &SubstitutionToken {
+        &self.token
+    }
+
+    fn prepare(
+        &self,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.rules
+            .iter()
+            .try_for_each(|rule| rule.prepare(constants))
+    }
+
+    fn clone_box(&self) -> Box<dyn LogicalRule> {
+        Box::new(Self {
+            token: self.token.clone(),
+            rules: self.rules.iter().map(|rule| rule.clone_box()).collect(),
+        })
+    }
+}
+
+/// Stores rule of the second, chained pass of logical rules in a `String` and corresponding `SubstitutionToken`.
+///
+/// Besides A, B and C variables and operators of `LogicalRuleStr`, rule string can contain
+/// token variables `M`, `P` and `T`: the variable of token found by the first pass is `true`, others are `false`.
+/// Chained rules see only the first pass token, never results of each other, so there is exactly one extra pass.
+///
+/// # Examples
+///
+/// ```
+/// use st_test::assignment::arithmetic_rule::SubstitutionToken;
+/// use st_test::assignment::logical_rule::ChainedRuleStr;
+/// use std::collections::HashMap;
+///
+/// let rule = ChainedRuleStr::new(SubstitutionToken::T, "M && C".to_owned()).unwrap();
+/// let constants = HashMap::new();
+/// let res = rule.apply(true, true, true, &SubstitutionToken::M, &constants).unwrap();
+/// assert_eq!(res, Some(SubstitutionToken::T));
+/// let res = rule.apply(true, true, true, &SubstitutionToken::P, &constants).unwrap();
+/// assert_eq!(res, None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ChainedRuleStr {
+    token: SubstitutionToken,
+    rule_str: String,
+    /// Rule string with each ordering comparison rewritten to function call, if rule has comparisons.
+    comparison_rule_str: Option<String>,
+    /// Input variables referenced by the rule.
+    referenced_vars: HashSet<char>,
+}
+
+impl ChainedRuleStr {
+    /// Validates provided rule string and builds `ChainedRuleStr`.
+    /// Returns `Ok(ChainedRuleStr)` if validation is successful,
+    /// otherwise returns error with description.
+    pub fn new(
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        ChainedRuleStr::new_with_constants(token, rule_str, &HashMap::new())
+    }
+
+    /// Builds `ChainedRuleStr` the same way as `new`, but allows names of given constants in rule string.
+    pub fn new_with_constants(
+        token: SubstitutionToken,
+        rule_str: String,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let rule_str = normalize_rule_str(&rule_str);
+        ChainedRuleStr::validate_with_constants(&rule_str, constants)?;
+        Ok(Self {
+            token,
+            comparison_rule_str: rewrite_comparisons(&rule_str),
+            referenced_vars: referenced_vars(&rule_str),
+            rule_str,
+        })
+    }
+
+    /// Validates provided rule string the same way as `LogicalRuleStr::validate_with_constants`,
+    /// but allows token variables `M`, `P` and `T`.
+    pub fn validate_with_constants(
+        rule_str: &str,
+        constants: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        check_rule_str(rule_str, &RuleLimits::default(), constants, true)
+    }
+
+    /// Returns `Some(SubstitutionToken)` if rule result is `true` for given arguments
+    /// and `tentative` token of the first pass, `None` otherwise.
+    /// Returns error if rule can't be evaluated.
+    pub fn apply(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+        tentative: &SubstitutionToken,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Option<SubstitutionToken>, Box<dyn Error + Send + Sync>> {
+        let mut context = logical_context(a, b, c, constants);
+        set_token_variables(&mut context, tentative);
+        let rule_str = self.comparison_rule_str.as_ref().unwrap_or(&self.rule_str);
+
+        if eval_boolean_with_context(rule_str, &context)? {
+            Ok(Some(self.token.clone()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns `SubstitutionToken` the rule maps to.
+    pub fn token(&self) -> &SubstitutionToken {
+        &self.token
+    }
+
+    /// Returns source string of the rule.
+    pub fn source(&self) -> &str {
+        &self.rule_str
+    }
+
+    /// Returns input variables `A`, `B` and `C` referenced by the rule, token variables are not included.
+    pub fn referenced_vars(&self) -> HashSet<char> {
+        self.referenced_vars.clone()
     }
 }
 
@@ -123,14 +726,9 @@ fn test_new() {
     let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
 
     assert_eq!(rule.token, SubstitutionToken::M, "Invalid token is set.");
-    assert_eq!(
-        (rule.rule_fn)(true, true, true),
-        true,
-        "Invalid rule_fn is set."
-    );
-    assert_eq!(
-        (rule.rule_fn)(false, true, true),
-        false,
+    assert!((rule.rule_fn)(true, true, true), "Invalid rule_fn is set.");
+    assert!(
+        !(rule.rule_fn)(false, true, true),
         "Invalid rule_fn is set."
     );
 }
@@ -139,65 +737,52 @@ fn test_new() {
 fn test_apply() {
     let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
 
-    assert_eq!(rule.apply(true, true, true), Some(SubstitutionToken::M));
-    assert_eq!(rule.apply(false, true, true), None);
+    assert_eq!(
+        rule.apply(true, true, true).unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(rule.apply(false, true, true).unwrap(), None);
 }
 
 #[test]
 fn test_validate() {
-    assert!(LogicalRuleStr::validate(&"A".to_owned()).is_ok());
-    assert!(LogicalRuleStr::validate(&"A && B || C".to_owned()).is_ok());
-    assert!(LogicalRuleStr::validate(&"A && !B || C".to_owned()).is_ok());
-    assert!(LogicalRuleStr::validate(&"A == B".to_owned()).is_ok());
-    assert!(LogicalRuleStr::validate(&"A != B".to_owned()).is_ok());
+    assert!(LogicalRuleStr::validate("A").is_ok());
+    assert!(LogicalRuleStr::validate("A && B || C").is_ok());
+    assert!(LogicalRuleStr::validate("A && !B || C").is_ok());
+    assert!(LogicalRuleStr::validate("A == B").is_ok());
+    assert!(LogicalRuleStr::validate("A != B").is_ok());
 
     assert_eq!(
-        LogicalRuleStr::validate(&"".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        LogicalRuleStr::validate(&"A || D".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("A || D").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        LogicalRuleStr::validate(&"A + B".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("A + B").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        LogicalRuleStr::validate(&"A - B".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("A - B").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        LogicalRuleStr::validate(&"A * B".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("A * B").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
     assert_eq!(
-        LogicalRuleStr::validate(&"A / B".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("A / B").unwrap_err().to_string(),
         "Expression contains invalid variables or operators."
     );
 
     assert_eq!(
-        LogicalRuleStr::validate(&"A&&&&B".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("A&&&&B").unwrap_err().to_string(),
         "An operator expected 2 arguments, but got 1."
     );
     assert_eq!(
-        LogicalRuleStr::validate(&"&&A".to_owned())
-            .unwrap_err()
-            .to_string(),
+        LogicalRuleStr::validate("&&A").unwrap_err().to_string(),
         "An operator expected 2 arguments, but got 1."
     );
 }
@@ -206,6 +791,381 @@ fn test_validate() {
 fn test_apply_str() {
     let rule = LogicalRuleStr::new(SubstitutionToken::M, "A".to_owned()).unwrap();
 
-    assert_eq!(rule.apply(true, true, true), Some(SubstitutionToken::M));
-    assert_eq!(rule.apply(false, true, true), None);
+    assert_eq!(
+        rule.apply(true, true, true).unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(rule.apply(false, true, true).unwrap(), None);
+}
+
+#[test]
+fn test_clone_box() {
+    let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
+    let cloned = rule.clone_box();
+    assert_eq!(
+        cloned.apply(true, false, false).unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(cloned.apply(false, false, false).unwrap(), None);
+
+    let rule = LogicalRuleStr::new(SubstitutionToken::T, "B".to_owned()).unwrap();
+    let cloned = rule.clone_box();
+    assert_eq!(
+        cloned.apply(false, true, false).unwrap(),
+        Some(SubstitutionToken::T)
+    );
+    assert_eq!(cloned.apply(true, false, true).unwrap(), None);
+}
+
+#[test]
+fn test_new_str_normalization() {
+    let rule = LogicalRuleStr::new(SubstitutionToken::M, " A\t&&\n  B ".to_owned()).unwrap();
+    assert_eq!(rule.rule_str, "A && B");
+    assert_eq!(
+        rule.apply(true, true, false).unwrap(),
+        Some(SubstitutionToken::M)
+    );
+
+    let rule =
+        LogicalRuleStr::new(SubstitutionToken::M, "A && !C # weekend bonus".to_owned()).unwrap();
+    assert_eq!(rule.rule_str, "A && !C");
+    assert_eq!(
+        rule.apply(true, false, false).unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(rule.apply(true, false, true).unwrap(), None);
+
+    assert!(LogicalRuleStr::validate("A\r\n||\tB   # comment").is_ok());
+    assert!(LogicalRuleStr::new(SubstitutionToken::M, "   # comment".to_owned()).is_err());
+}
+
+#[test]
+fn test_validate_limits() {
+    let long = vec!["A"; 3000].join("&&");
+    assert_eq!(
+        LogicalRuleStr::validate(&long).unwrap_err().to_string(),
+        "Expression is too long: maximum length is 4096."
+    );
+
+    let limits = RuleLimits {
+        max_len: 10,
+        max_depth: 1,
+    };
+    assert!(LogicalRuleStr::validate_with_limits("A && !B", &limits).is_ok());
+    assert!(LogicalRuleStr::validate_with_limits("A && B || C", &limits).is_err());
+}
+
+#[test]
+fn test_metadata() {
+    let rule: Box<dyn LogicalRule> = Box::new(
+        LogicalRuleStr::new(SubstitutionToken::M, "A".to_owned())
+            .unwrap()
+            .with_name("weekend bonus")
+            .with_description("Applied on weekends."),
+    );
+    assert_eq!(rule.name(), Some("weekend bonus"));
+    assert_eq!(rule.description(), Some("Applied on weekends."));
+    assert_eq!(rule.clone_box().name(), Some("weekend bonus"));
+
+    let rule: Box<dyn LogicalRule> =
+        Box::new(LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a)).with_name("a"));
+    assert_eq!(rule.name(), Some("a"));
+    assert_eq!(rule.description(), None);
+}
+
+#[test]
+fn test_token() {
+    let rule = LogicalRuleFn::new(SubstitutionToken::P, Box::new(|a, _, _| a));
+    assert_eq!(rule.token(), &SubstitutionToken::P);
+    let rule = LogicalRuleStr::new(SubstitutionToken::T, "A".to_owned()).unwrap();
+    assert_eq!(rule.token(), &SubstitutionToken::T);
+}
+
+/// Strategy of logical rule strings following validation grammar:
+/// variables and negation joined by boolean and comparison operators, without parentheses.
+#[cfg(test)]
+fn rule_str_strategy() -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::{prelude::*, sample::select};
+
+    select(&["A", "B", "C"][..])
+        .prop_map(str::to_owned)
+        .prop_recursive(4, 32, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|e| format!("!{}", e)),
+                (
+                    inner.clone(),
+                    select(&["&&", "||", "==", "!=", ">", "<", ">=", "<="][..]),
+                    inner,
+                )
+                    .prop_map(|(l, op, r)| format!("{} {} {}", l, op, r)),
+            ]
+        })
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_validate_grammar(rule_str in rule_str_strategy()) {
+        let rule = LogicalRuleStr::new(SubstitutionToken::M, rule_str.clone());
+        proptest::prop_assert!(rule.is_ok(), "Rule {:?} is rejected: {:?}.", rule_str, rule.err());
+        let rule = rule.unwrap();
+
+        for i in 0..8 {
+            let (a, b, c) = (i & 1 != 0, i & 2 != 0, i & 4 != 0);
+            proptest::prop_assert!(
+                rule.apply(a, b, c).is_ok(),
+                "Valid rule {:?} fails for {:?}.",
+                rule_str,
+                (a, b, c)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #![proptest_config(proptest::test_runner::Config::with_cases(2_000))]
+
+    #[test]
+    fn test_validate_fuzz(
+        rule_str in crate::assignment::noisy_rule_str_strategy(
+            &["A", "B", "C", "!A", "!!B", " C "],
+            &["&&", "||", "==", "!=", " && ", "!", ">", "<", ">=", " <= "],
+            6,
+        )
+    ) {
+        if let Ok(rule) = LogicalRuleStr::new(SubstitutionToken::M, rule_str.clone()) {
+            for i in 0..8 {
+                let (a, b, c) = (i & 1 != 0, i & 2 != 0, i & 4 != 0);
+                proptest::prop_assert!(
+                    rule.apply(a, b, c).is_ok(),
+                    "Valid rule {:?} fails for {:?}.",
+                    rule_str,
+                    (a, b, c)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_apply_eval_error() {
+    // Rule string that bypasses validation fails on evaluation without panic.
+    let rule = LogicalRuleStr {
+        token: SubstitutionToken::M,
+        rule_str: "A && D".to_owned(),
+        comparison_rule_str: None,
+        referenced_vars: HashSet::new(),
+        metadata: RuleMetadata::default(),
+    };
+    assert!(rule.apply(true, true, true).is_err());
+}
+
+#[test]
+fn test_comparisons() {
+    let cases = [
+        ("A > B", [false, true, false, false]),
+        ("A < B", [false, false, true, false]),
+        ("A >= B", [true, true, false, true]),
+        ("A <= B", [true, false, true, true]),
+    ];
+    // Inputs are (false, false), (true, false), (false, true), (true, true).
+    for (rule_str, expected) in &cases {
+        let rule = LogicalRuleStr::new(SubstitutionToken::M, (*rule_str).to_owned()).unwrap();
+        for (i, expected) in expected.iter().enumerate() {
+            let (a, b) = (i & 1 != 0, i & 2 != 0);
+            for &c in &[false, true] {
+                assert_eq!(
+                    rule.apply(a, b, c).unwrap().is_some(),
+                    *expected,
+                    "{} for {:?}",
+                    rule_str,
+                    (a, b, c)
+                );
+            }
+        }
+    }
+
+    // Negation binds tighter than comparison, comparison tighter than `&&`.
+    let rule = LogicalRuleStr::new(SubstitutionToken::M, "!A > B && C".to_owned()).unwrap();
+    assert_eq!(
+        rule.apply(false, false, true).unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(rule.apply(false, false, false).unwrap(), None);
+    assert_eq!(rule.apply(true, false, true).unwrap(), None);
+    assert_eq!(rule.source(), Some("!A > B && C"));
+
+    assert!(LogicalRuleStr::validate("A > > B").is_err());
+    assert!(LogicalRuleStr::validate("A >").is_err());
+}
+
+#[test]
+fn test_rewrite_comparisons() {
+    assert_eq!(rewrite_comparisons("A && B"), None);
+    assert_eq!(rewrite_comparisons("A > B").unwrap(), "gt(A, B)");
+    assert_eq!(
+        rewrite_comparisons("A || !B <= C == A").unwrap(),
+        "A || le(!B, C) == A"
+    );
+    assert_eq!(rewrite_comparisons("A >"), None);
+}
+
+#[test]
+fn test_lowercase() {
+    let lower = LogicalRuleStr::new(SubstitutionToken::M, "a && !b || c".to_owned()).unwrap();
+    let upper = LogicalRuleStr::new(SubstitutionToken::M, "A && !B || C".to_owned()).unwrap();
+    assert_eq!(lower.rule_str, upper.rule_str);
+    for &(a, b, c) in &[
+        (true, false, false),
+        (true, true, false),
+        (false, false, true),
+    ] {
+        assert_eq!(lower.apply(a, b, c).unwrap(), upper.apply(a, b, c).unwrap());
+    }
+
+    assert!(LogicalRuleStr::validate("a > b").is_ok());
+    assert!(LogicalRuleStr::validate("a && d").is_err());
+    assert!(LogicalRuleStr::validate("ab && c").is_err());
+}
+
+#[test]
+fn test_prepare() {
+    use crate::assignment::{Assignment, InputSet};
+
+    let rule = LogicalRuleStr::new(SubstitutionToken::M, "A && B".to_owned()).unwrap();
+    assert!(rule.prepare(&HashMap::new()).is_ok());
+
+    // Rule registered bypassing validation.
+    let broken = LogicalRuleStr {
+        token: SubstitutionToken::P,
+        rule_str: "A &&".to_owned(),
+        comparison_rule_str: None,
+        referenced_vars: HashSet::new(),
+        metadata: RuleMetadata::default(),
+    };
+    let mut assignment = Assignment::new().with_rules(true, false);
+    assert!(assignment.prepare().is_ok());
+    assignment.add_logical_rule(Box::new(broken)).unwrap();
+
+    let err = assignment.prepare().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Logical rule `A &&`: {}",
+            LogicalRuleStr::validate("A &&").unwrap_err()
+        )
+    );
+    assert!(assignment
+        .eval(&InputSet::new(true, true, false, 2.0, 3, 4))
+        .is_err());
+}
+
+#[test]
+fn test_chained_rule() {
+    let constants = HashMap::new();
+    let rule = ChainedRuleStr::new(SubstitutionToken::T, "M && !c".to_owned()).unwrap();
+    assert_eq!(rule.source(), "M && !C");
+
+    let apply = |tentative| {
+        rule.apply(true, true, false, &tentative, &constants)
+            .unwrap()
+    };
+    assert_eq!(apply(SubstitutionToken::M), Some(SubstitutionToken::T));
+    assert_eq!(apply(SubstitutionToken::P), None);
+    assert_eq!(apply(SubstitutionToken::T), None);
+
+    let rule = ChainedRuleStr::new(SubstitutionToken::M, "P && A > B || T".to_owned()).unwrap();
+    assert_eq!(
+        rule.apply(true, false, false, &SubstitutionToken::P, &constants)
+            .unwrap(),
+        Some(SubstitutionToken::M)
+    );
+
+    // Token variables are only allowed in chained rules.
+    assert!(LogicalRuleStr::validate("M && A").is_err());
+    assert!(ChainedRuleStr::new(SubstitutionToken::M, "M + A".to_owned()).is_err());
+    assert!(ChainedRuleStr::new(SubstitutionToken::M, "X && A".to_owned()).is_err());
+}
+
+#[test]
+fn test_rule_str_regex_compiled_once() {
+    assert!(std::ptr::eq(rule_str_regex(false), rule_str_regex(false)));
+    assert!(!std::ptr::eq(rule_str_regex(false), rule_str_regex(true)));
+    for _ in 0..3 {
+        LogicalRuleStr::validate("A && !B").unwrap();
+    }
+    assert!(std::ptr::eq(rule_str_regex(false), rule_str_regex(false)));
+    assert!(rule_str_regex(true).is_match("M && !A"));
+    assert!(!rule_str_regex(false).is_match("M && !A"));
+}
+
+#[test]
+fn test_logical_rule_all() {
+    assert!(LogicalRuleAll::new(SubstitutionToken::M, Vec::new()).is_err());
+
+    let constants: HashMap<_, _> = vec![("rate".to_owned(), 1.0), ("base".to_owned(), 2.0)]
+        .into_iter()
+        .collect();
+    let rule = LogicalRuleAll::new(
+        SubstitutionToken::P,
+        vec![
+            Box::new(LogicalRuleStr::new(SubstitutionToken::M, "A || B".to_owned()).unwrap()),
+            Box::new(LogicalRuleFn::new(
+                SubstitutionToken::T,
+                Box::new(|_, _, c| c),
+            )),
+            Box::new(
+                LogicalRuleStr::new_with_constants(
+                    SubstitutionToken::M,
+                    "rate != base".to_owned(),
+                    &constants,
+                )
+                .unwrap(),
+            ),
+        ],
+    )
+    .unwrap();
+    for a in [false, true] {
+        for b in [false, true] {
+            for c in [false, true] {
+                let expected = if (a || b) && c {
+                    Some(SubstitutionToken::P)
+                } else {
+                    None
+                };
+                assert_eq!(
+                    rule.apply_with_constants(a, b, c, &constants).unwrap(),
+                    expected
+                );
+            }
+        }
+    }
+    assert_eq!(rule.rules().count(), 3);
+    assert!(rule.source().is_none());
+    assert!(rule.prepare(&constants).is_ok());
+    assert!(rule.prepare(&HashMap::new()).is_err());
+    assert_eq!(
+        rule.clone_box()
+            .apply_with_constants(true, false, true, &constants)
+            .unwrap(),
+        Some(SubstitutionToken::P)
+    );
+}
+
+#[test]
+fn test_referenced_vars() {
+    let vars = |chars: &[char]| chars.iter().copied().collect::<HashSet<char>>();
+    for (rule_str, expected) in [
+        ("A && A", vars(&['A'])),
+        ("b", vars(&['B'])),
+        ("!A || b && C", vars(&['A', 'B', 'C'])),
+        ("C || c && B", vars(&['B', 'C'])),
+    ] {
+        let rule = LogicalRuleStr::new(SubstitutionToken::M, rule_str.to_owned()).unwrap();
+        assert_eq!(rule.referenced_vars(), expected, "{}", rule_str);
+    }
+
+    let rule = ChainedRuleStr::new(SubstitutionToken::T, "M && !C".to_owned()).unwrap();
+    assert_eq!(rule.referenced_vars(), vars(&['C']));
 }
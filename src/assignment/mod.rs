@@ -1,535 +1,6355 @@
 //! Implementation of assignment's main logic.
 
 pub mod arithmetic_rule;
+pub mod audit;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+pub mod eval_cache;
+pub(crate) mod lenient;
 pub mod logical_rule;
+pub mod multi_rule;
+pub mod parse_tree;
+pub mod rule;
+pub mod serializable;
 
+use evalexpr::{
+    build_operator_tree, context_map, eval_with_context, Context, EvalexprError, HashMapContext,
+    Value,
+};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, error::Error};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
+#[cfg(feature = "decimal")]
+use crate::assignment::decimal::{Decimal, DecimalArithmeticRule};
 use crate::assignment::{
-    arithmetic_rule::{ArithmeticRule, ArithmeticRuleFn, ArithmeticRuleStr, SubstitutionToken},
-    logical_rule::{LogicalRule, LogicalRuleFn, LogicalRuleStr},
+    arithmetic_rule::{
+        if_function, ArithmeticRule, ArithmeticRuleFn, ArithmeticRuleStr, DivisionPolicy,
+        RoundingMode, SelectionStrategy, SubstitutionToken,
+    },
+    audit::{AuditEntry, AuditOperation},
+    eval_cache::EvalCache,
+    logical_rule::{ChainedRuleStr, LogicalRule, LogicalRuleAll, LogicalRuleFn, LogicalRuleStr},
+    multi_rule::{MultiArithmeticRule, MultiArithmeticRuleStr},
+    rule::Rule,
+    serializable::{
+        InvalidRuleSetError, RuleSetElement, SerializableArithmeticRule, SerializableAssignment,
+        SerializableLogicalRule, SkippedRule,
+    },
 };
 
 /// Set of input arguments for calculation.
-#[derive(Default, Serialize, Deserialize)]
+///
+/// `f64` field is compared and hashed by its bit pattern,
+/// so `InputSet` can be used as a key for `EvalCache`.
+///
+/// Deserialization also accepts numeric strings for `d`, `e`, `f`
+/// and `"true"`/`"false"` strings for `a`, `b`, `c`.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct InputSet {
+    #[serde(deserialize_with = "lenient::deserialize")]
     pub a: bool,
+    #[serde(deserialize_with = "lenient::deserialize")]
     pub b: bool,
+    #[serde(deserialize_with = "lenient::deserialize")]
     pub c: bool,
+    #[serde(deserialize_with = "lenient::deserialize")]
     pub d: f64,
+    #[serde(deserialize_with = "lenient::deserialize")]
     pub e: i32,
+    #[serde(deserialize_with = "lenient::deserialize")]
     pub f: i32,
 }
 
-/// Main class for substitution calculation.
-/// Contains set of `LogicalRule` and `ArithmeticRule`
-/// and implements methods to work with them.
-///
-/// # Examples
-///
-/// ```
-/// let assignment = Assignment::new();
-/// let l_rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|_, _, _| true));
-/// let a_rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 42.0));
-/// assignment.add_logical_rule(Box::new(l_rule));
-/// assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(a_rule));
-/// let res = assignment.eval(InputSet::default());
-/// assert_eq!(res, (SubStitutionToken::M, 42.0));
-/// ```
-pub struct Assignment {
-    logical_rules: Vec<Box<dyn LogicalRule>>,
-    arithmetic_rules: HashMap<SubstitutionToken, Box<dyn ArithmeticRule>>,
+impl InputSet {
+    /// Builds `InputSet` from positional arguments.
+    pub fn new(a: bool, b: bool, c: bool, d: f64, e: i32, f: i32) -> Self {
+        Self { a, b, c, d, e, f }
+    }
 }
 
-impl Assignment {
-    /// Builds `Assignment`.
-    pub fn new() -> Self {
-        Self {
-            logical_rules: Vec::new(),
-            arithmetic_rules: HashMap::new(),
-        }
+impl From<(bool, bool, bool, f64, i32, i32)> for InputSet {
+    fn from((a, b, c, d, e, f): (bool, bool, bool, f64, i32, i32)) -> Self {
+        Self::new(a, b, c, d, e, f)
     }
+}
 
-    /// Adds predefined rules for `Assignment` object.
+/// Set of input arguments where any field may be absent.
+///
+/// Absent fields are filled with defaults set by `Assignment::set_input_default`.
+/// Fields are deserialized as leniently as fields of `InputSet`.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct PartialInputSet {
+    #[serde(default, deserialize_with = "lenient::deserialize_option")]
+    pub a: Option<bool>,
+    #[serde(default, deserialize_with = "lenient::deserialize_option")]
+    pub b: Option<bool>,
+    #[serde(default, deserialize_with = "lenient::deserialize_option")]
+    pub c: Option<bool>,
+    #[serde(default, deserialize_with = "lenient::deserialize_option")]
+    pub d: Option<f64>,
+    #[serde(default, deserialize_with = "lenient::deserialize_option")]
+    pub e: Option<i32>,
+    #[serde(default, deserialize_with = "lenient::deserialize_option")]
+    pub f: Option<i32>,
+}
+
+impl PartialInputSet {
+    /// Fills absent fields with fields of `defaults`.
     ///
-    /// # Arguments
-    /// * `base` - If true adds base set of rules.
-    /// * `custom` - If true adds custom set of rules.
-    pub fn with_rules(mut self, base: bool, custom: bool) -> Self {
-        if base {
-            Self::add_base_rules(&mut self);
-        }
-        if custom {
-            Self::add_custom_rules(&mut self);
+    /// Returns error naming the first field absent in both.
+    pub fn complete(
+        &self,
+        defaults: &PartialInputSet,
+    ) -> Result<InputSet, Box<dyn Error + Send + Sync>> {
+        fn field<T: Copy>(
+            name: &str,
+            value: Option<T>,
+            default: Option<T>,
+        ) -> Result<T, Box<dyn Error + Send + Sync>> {
+            value.or(default).ok_or_else(|| {
+                format!("Field `{}` is absent and has no default value.", name).into()
+            })
         }
-        self
-    }
 
-    /// Removes all rules from `Assignment`.
-    pub fn remove_rules(&mut self) {
-        self.logical_rules.clear();
-        self.arithmetic_rules.clear();
-    }
-
-    /// Adds `LogicalRule` to `Assignment`.
-    pub fn add_logical_rule(&mut self, rule: Box<dyn LogicalRule>) {
-        self.logical_rules.push(rule);
+        Ok(InputSet {
+            a: field("a", self.a, defaults.a)?,
+            b: field("b", self.b, defaults.b)?,
+            c: field("c", self.c, defaults.c)?,
+            d: field("d", self.d, defaults.d)?,
+            e: field("e", self.e, defaults.e)?,
+            f: field("f", self.f, defaults.f)?,
+        })
     }
+}
 
-    /// Creates `LogicalRule` from `Fn` and adds it to `Assignment`.
-    pub fn add_logical_rule_from_fn(
-        &mut self,
-        token: SubstitutionToken,
-        rule_fn: logical_rule::RuleFn,
-    ) {
-        let rule = LogicalRuleFn::new(token, rule_fn);
-        self.add_logical_rule(Box::new(rule));
+impl From<InputSet> for PartialInputSet {
+    fn from(input: InputSet) -> Self {
+        Self {
+            a: Some(input.a),
+            b: Some(input.b),
+            c: Some(input.c),
+            d: Some(input.d),
+            e: Some(input.e),
+            f: Some(input.f),
+        }
     }
+}
 
-    /// Creates `LogicalRule` from `String` and adds it to `Assignment`.
-    pub fn add_logical_rule_from_str(
-        &mut self,
-        token: SubstitutionToken,
-        rule_str: String,
-    ) -> Result<(), Box<dyn Error>> {
-        let rule = LogicalRuleStr::new(token, rule_str)?;
-        self.add_logical_rule(Box::new(rule));
-        Ok(())
+impl PartialEq for InputSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.a == other.a
+            && self.b == other.b
+            && self.c == other.c
+            && self.d.to_bits() == other.d.to_bits()
+            && self.e == other.e
+            && self.f == other.f
     }
+}
 
-    /// Adds `ArithmeticRule` to `Assignment`.
-    pub fn add_arithmetic_rule(&mut self, token: SubstitutionToken, rule: Box<dyn ArithmeticRule>) {
-        self.arithmetic_rules.insert(token, rule);
-    }
+impl Eq for InputSet {}
 
-    /// Creates `ArithmeticRule` from `Fn` and adds it to `Assignment`.
-    pub fn add_arithmetic_rule_from_fn(
-        &mut self,
-        token: SubstitutionToken,
-        rule_fn: arithmetic_rule::RuleFn,
-    ) {
-        let rule = ArithmeticRuleFn::new(rule_fn);
-        self.add_arithmetic_rule(token, Box::new(rule));
+impl Hash for InputSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.a.hash(state);
+        self.b.hash(state);
+        self.c.hash(state);
+        self.d.to_bits().hash(state);
+        self.e.hash(state);
+        self.f.hash(state);
     }
+}
 
-    /// Creates `ArithmeticRule` from `String` and adds it to `Assignment`.
-    pub fn add_arithmetic_rule_from_str(
-        &mut self,
-        token: SubstitutionToken,
-        rule_str: String,
-    ) -> Result<(), Box<dyn Error>> {
-        let rule = ArithmeticRuleStr::new(rule_str)?;
-        self.add_arithmetic_rule(token, Box::new(rule));
-        Ok(())
-    }
+/// Grid of inputs with fixed `a`, `b` and `c` and ranges of `d`, `e` and `f`
+/// for `Assignment::eval_grid`.
+///
+/// Each range is `[start, end, step]` with inclusive `end` and positive `step`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvalGrid {
+    pub a: bool,
+    pub b: bool,
+    pub c: bool,
+    pub d_range: [f64; 3],
+    pub e_range: [i32; 3],
+    pub f_range: [i32; 3],
+}
 
-    /// Calculates result of substitution rules for given arguments.
-    ///
-    /// First, goes through all logical rules to get `SubstitutionToken` for arithmetical rules.
-    /// If there are several suitable logical rules, result of the last rule will be taken.
-    /// Returns `Error` if there is no suitable rule for given input.
-    ///
-    /// Then, calculates result of arithmetical rule for found `SubstitutionToken`.
-    /// Returns `Error` if there is no rule for `SubstitutionToken`.
-    ///
-    /// Returns tuple of `SubstitutionToken` and arithmetical rule result as `f64`.
-    pub fn eval(&self, args: InputSet) -> Result<(SubstitutionToken, f64), Box<dyn Error>> {
-        let mut token = None;
-        for r in &self.logical_rules {
-            let t = r.apply(args.a, args.b, args.c);
-            if t.is_some() {
-                token = t;
-            }
+impl EvalGrid {
+    /// Returns number of inputs in the grid without building them,
+    /// `usize::MAX` if it doesn't fit.
+    /// Returns error if any range has non-positive step or non-finite bound.
+    pub fn size(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let [start, end, step] = self.d_range;
+        if !(step > 0.0 && step.is_finite() && start.is_finite() && end.is_finite()) {
+            Err("Range of `d` must be finite and have positive step.")?
         }
+        // Tolerance keeps `end` when it's reached with floating-point error.
+        let d_count = ((end - start) / step + 1e-9).floor() + 1.0;
+        let d_count = d_count.clamp(0.0, usize::MAX as f64) as usize;
 
-        let token = token.ok_or("Failed to apply logical rule.")?;
-
-        let rule = self
-            .arithmetic_rules
-            .get(&token)
-            .ok_or("Failed to find arithmetic rule for token.")?;
+        let e_count = EvalGrid::int_count('e', self.e_range)?;
+        let f_count = EvalGrid::int_count('f', self.f_range)?;
 
-        Ok((token, rule.apply(args.d, args.e, args.f)))
+        Ok(d_count
+            .checked_mul(e_count)
+            .and_then(|n| n.checked_mul(f_count))
+            .unwrap_or(usize::MAX))
     }
 
-    /// Adds set of predefined base rules to `Assignment`.
-    fn add_base_rules(obj: &mut Assignment) {
-        obj.add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, b, c| a && b && !c));
-        obj.add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|a, b, c| a && b && c));
-        obj.add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|a, b, c| !a && b && c));
-
-        obj.add_arithmetic_rule_from_fn(
-            SubstitutionToken::M,
-            Box::new(|d, e, _| d + (d * e as f64 / 10.0)),
-        );
-        obj.add_arithmetic_rule_from_fn(
-            SubstitutionToken::P,
-            Box::new(|d, e, f| d + (d * (e - f) as f64 / 25.5)),
-        );
-        obj.add_arithmetic_rule_from_fn(
-            SubstitutionToken::T,
-            Box::new(|d, _, f| d - (d * f as f64 / 30.0)),
-        );
+    /// Returns number of values in integer range `[start, end, step]` of field `name`.
+    fn int_count(
+        name: char,
+        [start, end, step]: [i32; 3],
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        if step <= 0 {
+            Err(format!("Range of `{}` must have positive step.", name))?
+        }
+        if end < start {
+            return Ok(0);
+        }
+        Ok(((end as i64 - start as i64) / step as i64 + 1) as usize)
     }
 
-    /// Adds set of predefined custom rules to `Assignment`.
-    fn add_custom_rules(obj: &mut Assignment) {
-        obj.add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|a, b, c| a && b && !c));
-        obj.add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, b, c| a && !b && c));
+    /// Returns inputs of the grid, `f` changes fastest and `d` slowest.
+    /// Ranges must be validated with `size`.
+    fn inputs(&self) -> impl Iterator<Item = InputSet> + '_ {
+        let [d_start, d_end, d_step] = self.d_range;
+        let d_count = ((d_end - d_start) / d_step + 1e-9).floor() + 1.0;
+        let [e_start, e_end, e_step] = self.e_range;
+        let [f_start, f_end, f_step] = self.f_range;
 
-        obj.add_arithmetic_rule_from_fn(
-            SubstitutionToken::P,
-            Box::new(|d, e, _| 2.0 * d + (d * e as f64 / 100.0)),
-        );
-        obj.add_arithmetic_rule_from_fn(
-            SubstitutionToken::M,
-            Box::new(|d, e, f| f as f64 + d + (d * e as f64 / 100.0)),
-        );
+        (0..d_count.max(0.0) as usize).flat_map(move |i| {
+            let d = d_start + i as f64 * d_step;
+            (e_start..=e_end)
+                .step_by(e_step as usize)
+                .flat_map(move |e| {
+                    (f_start..=f_end)
+                        .step_by(f_step as usize)
+                        .map(move |f| InputSet::new(self.a, self.b, self.c, d, e, f))
+                })
+        })
     }
 }
 
-#[test]
-fn test_new() {
-    let assignment = Assignment::new();
+/// Default maximum number of inputs evaluated by `Assignment::eval_grid`.
+pub const DEFAULT_MAX_GRID_SIZE: usize = 10_000;
 
-    assert!(assignment.logical_rules.is_empty());
-    assert!(assignment.arithmetic_rules.is_empty());
+/// Default maximum number of evaluation threads with timeout running at once,
+/// see `Assignment::set_max_eval_threads`.
+pub const DEFAULT_MAX_EVAL_THREADS: usize = 64;
+
+/// Maximum output precision `Assignment::round_output` rounds to.
+/// `f64` holds at most 15 significant decimal digits, so larger precision leaves value unchanged.
+pub const MAX_OUTPUT_PRECISION: u32 = 15;
+
+/// Returns `true` if `a` and `b` differ by at most `epsilon`, e.g. to compare arithmetic results
+/// without relying on exact floating-point equality.
+/// Equal infinities are equal, `NaN` is never equal to anything.
+///
+/// # Examples
+///
+/// ```
+/// use st_test::assignment::approx_eq;
+///
+/// assert!(approx_eq(0.1 + 0.2, 0.3, 1e-9));
+/// assert!(!approx_eq(0.1, 0.2, 1e-9));
+/// ```
+pub fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
+    a == b || (a - b).abs() <= epsilon
 }
 
-#[test]
-fn test_with_rules() {
-    let assignment = Assignment::new().with_rules(false, false);
-    assert!(assignment.logical_rules.is_empty());
-    assert!(assignment.arithmetic_rules.is_empty());
+/// Result of substitution rules calculation.
+pub type EvalResult = Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>>;
 
-    let assignment = Assignment::new().with_rules(true, false);
-    assert!(!assignment.logical_rules.is_empty());
-    assert!(!assignment.arithmetic_rules.is_empty());
+/// Preprocessing of input applied before evaluation, see `Assignment::set_input_transform`.
+pub type InputTransform = Box<InputTransformFn>;
 
-    let assignment = Assignment::new().with_rules(false, true);
-    assert!(!assignment.logical_rules.is_empty());
-    assert!(!assignment.arithmetic_rules.is_empty());
+/// Function of `InputTransform`.
+pub type InputTransformFn = dyn Fn(&mut InputSet) + Send + Sync;
 
-    let assignment = Assignment::new().with_rules(true, true);
-    assert!(!assignment.logical_rules.is_empty());
-    assert!(!assignment.arithmetic_rules.is_empty());
+/// Durations of `Assignment::eval_timed` stages.
+/// Stage duration is `None` if the stage wasn't reached.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct EvalTiming {
+    /// Time spent on scanning logical rules.
+    pub logical: Option<Duration>,
+    /// Time spent on applying arithmetic rule.
+    pub arithmetic: Option<Duration>,
 }
 
-#[test]
-fn test_remove_rules() {
-    let mut assignment = Assignment::new();
+/// Detailed result of `Assignment::eval_explain`.
+///
+/// Serialized as JSON object with stable field names, which don't follow renames of struct fields:
+/// `matched_token`, `attempted`, `logical_source`, `arithmetic_source`,
+/// `logical_metadata`, `arithmetic_metadata`, `value` and `unused_fields`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalTrace {
+    /// Token used by `eval`: token of the winning logical rule or default token,
+    /// replaced by the first matching chained rule, if any.
+    pub matched_token: SubstitutionToken,
+    /// Number of logical rules applied to find the winner.
+    pub attempted: usize,
+    /// Source string of the winning logical rule, `None` for function-based rules
+    /// and if default token is used.
+    pub logical_source: Option<String>,
+    /// Source string of the applied arithmetic rule, `None` for function-based rules
+    /// and if results of several rules are combined.
+    pub arithmetic_source: Option<String>,
+    /// Metadata of the winning logical rule, empty if default token is used.
+    pub logical_metadata: RuleMetadata,
+    /// Metadata of the applied arithmetic rule, empty if results of several rules are combined.
+    pub arithmetic_metadata: RuleMetadata,
+    /// Result of the arithmetic rule.
+    pub value: f64,
+    /// Names of `InputSet` fields not referenced by applied rules, including chained rules.
+    /// `None` if it's unknown because one of the rules is function-based or default token is used.
+    pub unused_fields: Option<Vec<char>>,
+}
 
-    assignment
-        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
-        .unwrap();
-    assignment
-        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
-        .unwrap();
+/// Names that can't be used for constants of `Assignment`.
+pub(crate) const RESERVED_NAMES: &[&str] = &[
+    "A", "B", "C", "D", "E", "F", "a", "b", "c", "d", "e", "f", "if", "div", "gt", "lt", "ge",
+    "le", "true", "false", "M", "P", "T",
+];
 
-    assert_eq!(assignment.logical_rules.len(), 1);
-    assert_eq!(assignment.arithmetic_rules.len(), 1);
+/// Base set of predefined logical rules.
+pub const BASE_LOGICAL_RULES: &[(SubstitutionToken, &str)] = &[
+    (SubstitutionToken::M, "A && B && !C"),
+    (SubstitutionToken::P, "A && B && C"),
+    (SubstitutionToken::T, "!A && B && C"),
+];
 
-    assignment.remove_rules();
+/// Base set of predefined arithmetic rules.
+pub const BASE_ARITHMETIC_RULES: &[(SubstitutionToken, &str)] = &[
+    (SubstitutionToken::M, "D + (D * E / 10)"),
+    (SubstitutionToken::P, "D + (D * (E - F) / 25.5)"),
+    (SubstitutionToken::T, "D - (D * F / 30)"),
+];
 
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
+/// Custom set of predefined logical rules.
+pub const CUSTOM_LOGICAL_RULES: &[(SubstitutionToken, &str)] = &[
+    (SubstitutionToken::T, "A && B && !C"),
+    (SubstitutionToken::M, "A && !B && C"),
+];
 
-    assignment
-        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
-        .unwrap();
-    assignment
-        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
-        .unwrap();
+/// Custom set of predefined arithmetic rules.
+pub const CUSTOM_ARITHMETIC_RULES: &[(SubstitutionToken, &str)] = &[
+    (SubstitutionToken::P, "2 * D + (D * E / 100)"),
+    (SubstitutionToken::M, "F + D + (D * E / 100)"),
+];
 
-    assert_eq!(assignment.logical_rules.len(), 1);
-    assert_eq!(assignment.arithmetic_rules.len(), 1);
-}
+impl Serialize for EvalTrace {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
 
-#[test]
-fn test_add_logical_rule() {
-    let mut assignment = Assignment::new();
+        let mut s = serializer.serialize_struct("EvalTrace", 8)?;
+        s.serialize_field("matched_token", &self.matched_token)?;
+        s.serialize_field("attempted", &self.attempted)?;
+        s.serialize_field("logical_source", &self.logical_source)?;
+        s.serialize_field("arithmetic_source", &self.arithmetic_source)?;
+        s.serialize_field("logical_metadata", &self.logical_metadata)?;
+        s.serialize_field("arithmetic_metadata", &self.arithmetic_metadata)?;
+        s.serialize_field("value", &self.value)?;
+        s.serialize_field("unused_fields", &self.unused_fields)?;
+        s.end()
+    }
+}
 
-    let rule0 = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
-    assignment.add_logical_rule(Box::new(rule0));
+/// Serialized form of `EvalTrace` used by its `Deserialize` implementation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EvalTraceFields {
+    #[serde(rename = "matched_token")]
+    matched_token: SubstitutionToken,
+    #[serde(rename = "attempted")]
+    attempted: usize,
+    #[serde(rename = "logical_source")]
+    logical_source: Option<String>,
+    #[serde(rename = "arithmetic_source")]
+    arithmetic_source: Option<String>,
+    #[serde(rename = "logical_metadata")]
+    logical_metadata: RuleMetadata,
+    #[serde(rename = "arithmetic_metadata")]
+    arithmetic_metadata: RuleMetadata,
+    #[serde(rename = "value")]
+    value: f64,
+    #[serde(rename = "unused_fields")]
+    unused_fields: Option<Vec<char>>,
+}
 
-    assert_eq!(assignment.logical_rules.len(), 1);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
-    assert_eq!(
-        assignment.logical_rules[0].apply(true, true, true),
-        Some(SubstitutionToken::M)
-    );
-    assert_eq!(assignment.logical_rules[0].apply(false, true, true), None);
+impl<'de> Deserialize<'de> for EvalTrace {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = EvalTraceFields::deserialize(deserializer)?;
+        Ok(Self {
+            matched_token: fields.matched_token,
+            attempted: fields.attempted,
+            logical_source: fields.logical_source,
+            arithmetic_source: fields.arithmetic_source,
+            logical_metadata: fields.logical_metadata,
+            arithmetic_metadata: fields.arithmetic_metadata,
+            value: fields.value,
+            unused_fields: fields.unused_fields,
+        })
+    }
+}
 
-    let rule1 = LogicalRuleStr::new(SubstitutionToken::T, "B".to_owned()).unwrap();
-    assignment.add_logical_rule(Box::new(rule1));
+/// Optional human-readable metadata of a rule.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
 
-    assert_eq!(assignment.logical_rules.len(), 2);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
-    assert_eq!(
-        assignment.logical_rules[1].apply(true, true, true),
-        Some(SubstitutionToken::T)
-    );
-    assert_eq!(assignment.logical_rules[1].apply(true, false, true), None);
+impl RuleMetadata {
+    /// Builds `RuleMetadata` from borrowed name and description.
+    fn from_parts(name: Option<&str>, description: Option<&str>) -> Self {
+        Self {
+            name: name.map(str::to_owned),
+            description: description.map(str::to_owned),
+        }
+    }
 }
 
-#[test]
-fn test_add_logical_rule_from_fn() {
-    let mut assignment = Assignment::new();
+/// Names of `InputSet` fields.
+const INPUT_FIELDS: [char; 6] = ['a', 'b', 'c', 'd', 'e', 'f'];
 
-    assignment.add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a));
-    assert_eq!(assignment.logical_rules.len(), 1);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
-    assert_eq!(
-        assignment.logical_rules[0].apply(true, true, true),
-        Some(SubstitutionToken::M)
-    );
-    assert_eq!(assignment.logical_rules[0].apply(false, true, true), None);
+/// Returns set of input variables (A to F) referenced by rule string.
+pub(crate) fn referenced_vars(rule_str: &str) -> HashSet<char> {
+    build_operator_tree(rule_str)
+        .map(|tree| {
+            tree.iter_variable_identifiers()
+                .filter_map(|id| {
+                    let mut chars = id.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) if ('A'..='F').contains(&c) => Some(c),
+                        _ => None,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
 
-    assignment.add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|_, b, _| b));
-    assert_eq!(assignment.logical_rules.len(), 2);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
-    assert_eq!(
-        assignment.logical_rules[1].apply(true, true, true),
-        Some(SubstitutionToken::P)
-    );
-    assert_eq!(assignment.logical_rules[1].apply(true, false, true), None);
+/// Limits for rule strings checked before they are parsed by `evalexpr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuleLimits {
+    /// Maximum length of rule string in bytes, including comment and whitespace.
+    pub max_len: usize,
+    /// Maximum nesting depth of parentheses.
+    pub max_depth: usize,
 }
 
-#[test]
-fn test_add_logical_rule_from_str() {
-    let mut assignment = Assignment::new();
+impl Default for RuleLimits {
+    fn default() -> Self {
+        Self {
+            max_len: 4096,
+            max_depth: 32,
+        }
+    }
+}
 
-    assignment
-        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
-        .expect("Should not fail.");
+impl RuleLimits {
+    /// Returns error if rule string exceeds the limits, otherwise returns `Ok`.
+    pub fn check(&self, rule_str: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if rule_str.len() > self.max_len {
+            Err(format!(
+                "Expression is too long: maximum length is {}.",
+                self.max_len
+            ))?
+        }
 
-    assert_eq!(assignment.logical_rules.len(), 1);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
-    assert_eq!(
-        assignment.logical_rules[0].apply(true, true, true),
-        Some(SubstitutionToken::M)
-    );
-    assert_eq!(assignment.logical_rules[0].apply(false, true, true), None);
+        let mut depth = 0usize;
+        for c in rule_str.chars() {
+            match c {
+                '(' => {
+                    depth += 1;
+                    if depth > self.max_depth {
+                        Err(format!(
+                            "Expression is nested too deeply: maximum depth is {}.",
+                            self.max_depth
+                        ))?
+                    }
+                }
+                ')' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
 
-    assignment
-        .add_logical_rule_from_str(SubstitutionToken::T, "B".to_owned())
-        .expect("Should not fail.");
-    assert_eq!(assignment.logical_rules.len(), 2);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
-    assert_eq!(
-        assignment.logical_rules[1].apply(true, true, true),
-        Some(SubstitutionToken::T)
-    );
-    assert_eq!(assignment.logical_rules[1].apply(true, false, true), None);
+        Ok(())
+    }
+}
 
-    assignment
-        .add_logical_rule_from_str(SubstitutionToken::P, "Z+X".to_owned())
-        .expect_err("Should fail.");
-    assert_eq!(assignment.logical_rules.len(), 2);
-    assert_eq!(assignment.arithmetic_rules.len(), 0);
+/// Error of rule string validation.
+///
+/// Keeps underlying `evalexpr` error if expression can't be evaluated by `evalexpr`.
+/// Note that `evalexpr` errors don't carry position in the expression.
+#[derive(Debug)]
+pub struct ValidationError {
+    message: String,
+    evalexpr_error: Option<EvalexprError>,
 }
 
-#[test]
-fn test_add_arithmetic_rule() {
-    let mut assignment = Assignment::new();
+impl ValidationError {
+    /// Builds `ValidationError` for expression rejected before evaluation.
+    /// Expression is still evaluated over all six variables to find `evalexpr` error, if any.
+    pub(crate) fn rejected(message: &str, rule_str: &str) -> Self {
+        let context = context_map! {
+            "A" => true,
+            "B" => true,
+            "C" => true,
+            "D" => 0.0,
+            "E" => 0 as f64,
+            "F" => 0 as f64,
+            "if" => Function::new(Box::new(if_function)),
+        }
+        .unwrap();
 
-    let rule0 = ArithmeticRuleFn::new(Box::new(|d, _, _| d));
-    assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(rule0));
+        Self {
+            message: message.to_owned(),
+            evalexpr_error: eval_with_context(rule_str, &context).err(),
+        }
+    }
+
+    /// Returns underlying `evalexpr` error if there is one.
+    pub fn evalexpr_error(&self) -> Option<&EvalexprError> {
+        self.evalexpr_error.as_ref()
+    }
+}
+
+impl From<EvalexprError> for ValidationError {
+    fn from(e: EvalexprError) -> Self {
+        Self {
+            message: e.to_string(),
+            evalexpr_error: Some(e),
+        }
+    }
+}
+
+impl From<regex::Error> for ValidationError {
+    fn from(e: regex::Error) -> Self {
+        Self {
+            message: e.to_string(),
+            evalexpr_error: None,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ValidationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.evalexpr_error
+            .as_ref()
+            .map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+/// Error of `Assignment` evaluation, which can be propagated with `?`
+/// from `evalexpr` and `regex` errors.
+///
+/// Evaluation methods return it boxed, so it's found with `downcast_ref` among other errors.
+#[derive(Debug)]
+pub enum AssignmentError {
+    /// Expression can't be evaluated by `evalexpr`.
+    Evalexpr(EvalexprError),
+    /// Pattern built from rule can't be compiled.
+    Regex(regex::Error),
+    /// Evaluation took longer than timeout set by `Assignment::set_eval_timeout`.
+    Timeout(Duration),
+    /// Input field is outside of bounds set by `Assignment::set_input_bounds`.
+    InputOutOfRange {
+        /// Name of the field out of range.
+        field: char,
+        /// Value of the field.
+        value: f64,
+    },
+}
+
+impl From<EvalexprError> for AssignmentError {
+    fn from(e: EvalexprError) -> Self {
+        Self::Evalexpr(e)
+    }
+}
+
+impl From<regex::Error> for AssignmentError {
+    fn from(e: regex::Error) -> Self {
+        Self::Regex(e)
+    }
+}
+
+impl fmt::Display for AssignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Evalexpr(e) => write!(f, "{}", e),
+            Self::Regex(e) => write!(f, "{}", e),
+            Self::Timeout(timeout) => write!(f, "Evaluation timed out after {:?}.", timeout),
+            Self::InputOutOfRange { field, value } => {
+                write!(f, "Field `{}` is out of range: {}.", field, value)
+            }
+        }
+    }
+}
+
+impl Error for AssignmentError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Evalexpr(e) => Some(e),
+            Self::Regex(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Decrements counter of running evaluation threads when evaluation thread finishes.
+#[cfg(not(target_arch = "wasm32"))]
+struct EvalThreadGuard(Arc<AtomicUsize>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for EvalThreadGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Inclusive bounds of `InputSet` numeric fields checked before evaluation, `None` means unbounded.
+///
+/// `d` outside of its bounds, including `NaN`, is rejected.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputBounds {
+    pub d: Option<(f64, f64)>,
+    pub e: Option<(i32, i32)>,
+    pub f: Option<(i32, i32)>,
+}
+
+impl InputBounds {
+    /// Returns `AssignmentError::InputOutOfRange` for the first field of `args` outside of its bounds.
+    fn check(&self, args: &InputSet) -> Result<(), AssignmentError> {
+        let out_of_range = |field, value| Err(AssignmentError::InputOutOfRange { field, value });
+        if let Some((min, max)) = self.d {
+            if !(min..=max).contains(&args.d) {
+                return out_of_range('d', args.d);
+            }
+        }
+        for (field, bounds, value) in [('e', self.e, args.e), ('f', self.f, args.f)] {
+            if let Some((min, max)) = bounds {
+                if !(min..=max).contains(&value) {
+                    return out_of_range(field, value as f64);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error of evaluation which matched a logical rule, but found no arithmetic rule for its token.
+#[derive(Debug)]
+pub struct MissingArithmeticRuleError {
+    token: SubstitutionToken,
+}
+
+impl MissingArithmeticRuleError {
+    /// Returns `SubstitutionToken` of the matched logical rule.
+    pub fn token(&self) -> &SubstitutionToken {
+        &self.token
+    }
+}
+
+impl fmt::Display for MissingArithmeticRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to find arithmetic rule for token.")
+    }
+}
+
+impl Error for MissingArithmeticRuleError {}
+
+/// Quotes CSV field if it contains separators, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Strategy of rule strings of up to `max_len` operands alternating with operators.
+/// Random operands or operators are inserted now and then, so result is not always valid.
+/// Used by fuzz tests of rule validation.
+#[cfg(test)]
+pub(crate) fn noisy_rule_str_strategy(
+    operands: &'static [&'static str],
+    operators: &'static [&'static str],
+    max_len: usize,
+) -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::{prelude::*, sample::select};
+
+    let noise =
+        move || prop::option::weighted(0.125, prop_oneof![select(operands), select(operators)]);
+    prop::collection::vec(
+        (noise(), select(operators), noise(), select(operands)),
+        1..=max_len,
+    )
+    .prop_map(|pieces| {
+        let mut res = String::new();
+        for (i, (operator_noise, operator, operand_noise, operand)) in
+            pieces.into_iter().enumerate()
+        {
+            if i > 0 {
+                res.extend(operator_noise);
+                res.push_str(operator);
+            }
+            res.extend(operand_noise);
+            res.push_str(operand);
+        }
+        res
+    })
+}
+
+/// Returns regex alternatives matching names of given constants, to be appended to validation regex.
+pub(crate) fn constants_pattern(constants: &HashMap<String, f64>) -> String {
+    constants
+        .keys()
+        .map(|name| format!("|{}", regex::escape(name)))
+        .collect()
+}
+
+/// Adds given named constants to `evalexpr` context.
+pub(crate) fn set_constants(context: &mut HashMapContext, constants: &HashMap<String, f64>) {
+    for (name, value) in constants {
+        context
+            .set_value(name.clone(), Value::Float(*value))
+            .unwrap();
+    }
+}
+
+/// Strips trailing `# comment` from rule string, collapses whitespace to single spaces
+/// and uppercases lowercase variables `a`-`f`, so `a && b` is the same rule as `A && B`.
+pub(crate) fn normalize_rule_str(rule_str: &str) -> String {
+    let expr = rule_str.split('#').next().unwrap_or_default();
+    let expr = expr.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"\b[a-f]\b").unwrap());
+    re.replace_all(&expr, |caps: &regex::Captures| caps[0].to_uppercase())
+        .into_owned()
+}
+
+/// Resolution of conflicts when `Assignment::merge` meets the same arithmetic token
+/// or constant name in both `Assignment` objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Rules or value of `self` are kept.
+    KeepSelf,
+    /// Rules or value of `other` replace those of `self`.
+    TakeOther,
+    /// Merge fails and `self` is left unchanged.
+    Error,
+}
+
+/// Inserts entries of `other` into `target`, keys present in both are resolved with `on_conflict`.
+fn merge_map<K: Eq + Hash, V>(
+    target: &mut HashMap<K, V>,
+    other: HashMap<K, V>,
+    on_conflict: ConflictPolicy,
+) {
+    for (key, value) in other {
+        if on_conflict == ConflictPolicy::KeepSelf && target.contains_key(&key) {
+            continue;
+        }
+        target.insert(key, value);
+    }
+}
+
+/// Sets `target` to `other` if it is set, unless both are set and `on_conflict` is `ConflictPolicy::KeepSelf`.
+fn merge_option<T>(target: &mut Option<T>, other: Option<T>, on_conflict: ConflictPolicy) {
+    if other.is_some() && (target.is_none() || on_conflict != ConflictPolicy::KeepSelf) {
+        *target = other;
+    }
+}
+
+/// Description of logical rule of `Assignment` returned by `Assignment::logical_rule_infos`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogicalRuleInfo {
+    pub token: SubstitutionToken,
+    /// Source string of the rule, `None` for function-based rules.
+    pub source: Option<String>,
+    pub priority: i32,
+    pub stop_on_match: bool,
+    /// `false` if the rule is disabled with `Assignment::set_logical_rule_enabled`.
+    pub enabled: bool,
+}
+
+/// Description of arithmetic rule of `Assignment` returned by `Assignment::arithmetic_rule_infos`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArithmeticRuleInfo {
+    pub token: SubstitutionToken,
+    /// Source string of the rule, `None` for function-based rules.
+    pub source: Option<String>,
+    /// `false` if the rule is disabled with `Assignment::set_arithmetic_rule_enabled`.
+    pub enabled: bool,
+}
+
+/// `LogicalRule` stored in `Assignment` with its priority.
+struct LogicalRuleEntry {
+    rule: Box<dyn LogicalRule>,
+    priority: i32,
+    /// If true, matching rule stops the scan of the rest of logical rules.
+    stop_on_match: bool,
+    /// If false, the rule is skipped by evaluation.
+    enabled: bool,
+}
+
+impl Clone for LogicalRuleEntry {
+    fn clone(&self) -> Self {
+        Self {
+            rule: self.rule.clone_box(),
+            priority: self.priority,
+            stop_on_match: self.stop_on_match,
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// `ArithmeticRule` stored in `Assignment` with its flag.
+struct ArithmeticRuleEntry {
+    rule: Box<dyn ArithmeticRule>,
+    /// If false, the rule is skipped by evaluation.
+    enabled: bool,
+}
+
+impl ArithmeticRuleEntry {
+    fn new(rule: Box<dyn ArithmeticRule>) -> Self {
+        Self {
+            rule,
+            enabled: true,
+        }
+    }
+}
+
+impl Clone for ArithmeticRuleEntry {
+    fn clone(&self) -> Self {
+        Self {
+            rule: self.rule.clone_box(),
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// Main class for substitution calculation.
+/// Contains set of `LogicalRule` and `ArithmeticRule`
+/// and implements methods to work with them.
+///
+/// # Examples
+///
+/// ```
+/// use st_test::assignment::arithmetic_rule::{ArithmeticRuleFn, SubstitutionToken};
+/// use st_test::assignment::logical_rule::LogicalRuleFn;
+/// use st_test::assignment::{Assignment, InputSet};
+///
+/// let mut assignment = Assignment::new();
+/// let l_rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|_, _, _| true));
+/// let a_rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 42.0));
+/// assignment.add_logical_rule(Box::new(l_rule)).unwrap();
+/// assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(a_rule));
+/// let res = assignment.eval(&InputSet::default()).unwrap();
+/// assert_eq!(res, (SubstitutionToken::M, 42.0));
+/// ```
+pub struct Assignment {
+    logical_rules: Vec<LogicalRuleEntry>,
+    arithmetic_rules: HashMap<SubstitutionToken, Vec<ArithmeticRuleEntry>>,
+    cache: Option<Mutex<EvalCache>>,
+    rounding_mode: RoundingMode,
+    terminal_tokens: HashSet<SubstitutionToken>,
+    division_policy: DivisionPolicy,
+    selection_strategy: SelectionStrategy,
+    constants: HashMap<String, f64>,
+    input_defaults: PartialInputSet,
+    eval_timeout: Option<Duration>,
+    max_eval_threads: usize,
+    eval_threads: Arc<AtomicUsize>,
+    #[cfg(feature = "decimal")]
+    decimal_rules: HashMap<SubstitutionToken, DecimalArithmeticRule>,
+    profiles: HashMap<String, Assignment>,
+    max_logical_rules: Option<usize>,
+    output_precision: Option<u32>,
+    max_grid_size: usize,
+    chained_rules: Vec<ChainedRuleStr>,
+    input_bounds: InputBounds,
+    default_token: Option<SubstitutionToken>,
+    audit_log: Option<Vec<AuditEntry>>,
+    multi_rules: HashMap<SubstitutionToken, Box<dyn MultiArithmeticRule>>,
+    input_transform: Option<Arc<InputTransformFn>>,
+    fallback_arithmetic_rule: Option<Box<dyn ArithmeticRule>>,
+    timed_snapshot: Mutex<Option<Arc<Assignment>>>,
+}
+
+impl Clone for Assignment {
+    /// Deep-clones every rule.
+    /// Cached results are not copied, cloned `Assignment` starts with empty cache of the same capacity.
+    /// Counter of running evaluation threads is shared, so clones are limited by `set_max_eval_threads` together.
+    fn clone(&self) -> Self {
+        Self {
+            logical_rules: self.logical_rules.clone(),
+            arithmetic_rules: self.arithmetic_rules.clone(),
+            cache: self.cache.as_ref().map(|c| {
+                Mutex::new(EvalCache::new(
+                    c.lock().unwrap_or_else(|e| e.into_inner()).capacity(),
+                ))
+            }),
+            rounding_mode: self.rounding_mode,
+            terminal_tokens: self.terminal_tokens.clone(),
+            division_policy: self.division_policy,
+            selection_strategy: self.selection_strategy,
+            constants: self.constants.clone(),
+            input_defaults: self.input_defaults.clone(),
+            eval_timeout: self.eval_timeout,
+            max_eval_threads: self.max_eval_threads,
+            eval_threads: Arc::clone(&self.eval_threads),
+            #[cfg(feature = "decimal")]
+            decimal_rules: self.decimal_rules.clone(),
+            profiles: self.profiles.clone(),
+            max_logical_rules: self.max_logical_rules,
+            output_precision: self.output_precision,
+            max_grid_size: self.max_grid_size,
+            chained_rules: self.chained_rules.clone(),
+            input_bounds: self.input_bounds.clone(),
+            default_token: self.default_token.clone(),
+            audit_log: self.audit_log.clone(),
+            multi_rules: self
+                .multi_rules
+                .iter()
+                .map(|(token, rule)| (token.clone(), rule.clone_box()))
+                .collect(),
+            input_transform: self.input_transform.clone(),
+            fallback_arithmetic_rule: self
+                .fallback_arithmetic_rule
+                .as_ref()
+                .map(|r| r.clone_box()),
+            timed_snapshot: Mutex::new(None),
+        }
+    }
+}
+
+impl Default for Assignment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Assignment {
+    /// Builds `Assignment`.
+    pub fn new() -> Self {
+        Self {
+            logical_rules: Vec::new(),
+            arithmetic_rules: HashMap::new(),
+            cache: None,
+            rounding_mode: RoundingMode::default(),
+            terminal_tokens: HashSet::new(),
+            division_policy: DivisionPolicy::default(),
+            selection_strategy: SelectionStrategy::default(),
+            constants: HashMap::new(),
+            input_defaults: PartialInputSet::default(),
+            eval_timeout: None,
+            max_eval_threads: DEFAULT_MAX_EVAL_THREADS,
+            eval_threads: Arc::new(AtomicUsize::new(0)),
+            #[cfg(feature = "decimal")]
+            decimal_rules: HashMap::new(),
+            profiles: HashMap::new(),
+            max_logical_rules: None,
+            output_precision: None,
+            max_grid_size: DEFAULT_MAX_GRID_SIZE,
+            chained_rules: Vec::new(),
+            input_bounds: InputBounds::default(),
+            default_token: None,
+            audit_log: None,
+            multi_rules: HashMap::new(),
+            input_transform: None,
+            fallback_arithmetic_rule: None,
+            timed_snapshot: Mutex::new(None),
+        }
+    }
+
+    /// Adds predefined rules for `Assignment` object.
+    ///
+    /// # Arguments
+    /// * `base` - If true adds base set of rules.
+    /// * `custom` - If true adds custom set of rules.
+    pub fn with_rules(mut self, base: bool, custom: bool) -> Self {
+        if base {
+            Self::add_base_rules(&mut self);
+        }
+        if custom {
+            Self::add_custom_rules(&mut self);
+        }
+        self
+    }
+
+    /// Enables caching of `eval` results for `Assignment` object.
+    /// Cache is cleared whenever rules are added or removed.
+    ///
+    /// # Arguments
+    /// * `capacity` - Maximum number of cached results.
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Mutex::new(EvalCache::new(capacity)));
+        self
+    }
+
+    /// Adds logical rule from `String` the same way as `add_logical_rule_from_str` in a builder chain.
+    /// Returns error if rule is invalid or exceeds the limit of logical rules.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use st_test::assignment::{arithmetic_rule::SubstitutionToken, Assignment, InputSet};
+    ///
+    /// let assignment = Assignment::new()
+    ///     .with_logical_rule_str(SubstitutionToken::M, "A && B")?
+    ///     .with_arithmetic_rule_str(SubstitutionToken::M, "D + E")?;
+    /// let res = assignment.eval(&InputSet::new(true, true, false, 1.0, 2, 0))?;
+    /// assert_eq!(res, (SubstitutionToken::M, 3.0));
+    /// # Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+    /// ```
+    pub fn with_logical_rule_str(
+        mut self,
+        token: SubstitutionToken,
+        rule_str: impl Into<String>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        self.add_logical_rule_from_str(token, rule_str.into())?;
+        Ok(self)
+    }
+
+    /// Adds logical rule from `RuleFn` function the same way as `add_logical_rule_from_fn` in a builder chain.
+    /// Returns error if rule exceeds the limit of logical rules.
+    pub fn with_logical_rule_fn(
+        mut self,
+        token: SubstitutionToken,
+        rule_fn: logical_rule::RuleFn,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        self.add_logical_rule_from_fn(token, rule_fn)?;
+        Ok(self)
+    }
+
+    /// Adds arithmetic rule from `String` the same way as `add_arithmetic_rule_from_str` in a builder chain.
+    /// Returns error if rule is invalid.
+    pub fn with_arithmetic_rule_str(
+        mut self,
+        token: SubstitutionToken,
+        rule_str: impl Into<String>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        self.add_arithmetic_rule_from_str(token, rule_str.into())?;
+        Ok(self)
+    }
+
+    /// Adds arithmetic rule from `RuleFn` function the same way as `add_arithmetic_rule_from_fn` in a builder chain.
+    pub fn with_arithmetic_rule_fn(
+        mut self,
+        token: SubstitutionToken,
+        rule_fn: arithmetic_rule::RuleFn,
+    ) -> Self {
+        self.add_arithmetic_rule_from_fn(token, rule_fn);
+        self
+    }
+
+    /// Sets `RoundingMode` used by `eval_int`.
+    pub fn set_rounding_mode(&mut self, mode: RoundingMode) {
+        self.rounding_mode = mode;
+    }
+
+    /// Sets number of decimal places `eval` result is rounded to, half to even.
+    /// `None` disables rounding, precision above `MAX_OUTPUT_PRECISION` has no effect.
+    /// Applies to `eval` and methods based on it, e.g. `eval_batch` and `eval_partial`.
+    /// Profiles have their own precision.
+    /// Cache is cleared, since results may change.
+    pub fn set_output_precision(&mut self, precision: Option<u32>) {
+        self.output_precision = precision;
+        self.clear_cache();
+    }
+
+    /// Sets `DivisionPolicy` used by arithmetic rules.
+    /// Cache is cleared, since results may change.
+    pub fn set_division_policy(&mut self, policy: DivisionPolicy) {
+        self.division_policy = policy;
+        self.clear_cache();
+    }
+
+    /// Sets `SelectionStrategy` used when several arithmetic rules are registered for the same token.
+    /// Cache is cleared, since results may change.
+    pub fn set_selection_strategy(&mut self, strategy: SelectionStrategy) {
+        self.selection_strategy = strategy;
+        self.clear_cache();
+    }
+
+    /// Sets maximum number of logical rules, `None` removes the limit.
+    ///
+    /// Adding logical rules beyond the limit fails with error.
+    /// Rules added before the limit was set are kept even if there are more of them,
+    /// and removing rules with `remove_rules` or `replace_rules` frees slots for new ones.
+    pub fn set_max_logical_rules(&mut self, max: Option<usize>) {
+        self.max_logical_rules = max;
+    }
+
+    /// Sets bounds of `InputSet` fields checked before evaluation.
+    /// Evaluation of input outside of bounds fails with `AssignmentError::InputOutOfRange`.
+    /// Cache is cleared.
+    pub fn set_input_bounds(&mut self, bounds: InputBounds) {
+        self.input_bounds = bounds;
+        self.clear_cache();
+    }
+
+    /// Sets transform applied to a copy of input at the start of `eval` and other evaluation methods,
+    /// before bounds checks, logical and arithmetic stages, e.g. to clamp or scale fields for all rules.
+    /// `None` removes it. Cache is cleared.
+    pub fn set_input_transform(&mut self, transform: Option<InputTransform>) {
+        self.input_transform = transform.map(Arc::from);
+        self.clear_cache();
+    }
+
+    /// Sets fallback token used by `eval` when no logical rule matches, `None` by default.
+    /// With `None` evaluation of such input fails as before.
+    /// The token still needs an arithmetic rule and is passed to chained rules as a matched one.
+    /// Cache is cleared.
+    pub fn set_default_token(&mut self, token: Option<SubstitutionToken>) {
+        self.default_token = token;
+        self.clear_cache();
+    }
+
+    /// Returns fallback token set by `set_default_token`.
+    pub fn default_token(&self) -> Option<&SubstitutionToken> {
+        self.default_token.as_ref()
+    }
+
+    /// Sets arithmetic rule applied to tokens that have no arithmetic rule of their own, `None` by default.
+    /// With `None` evaluation of such token fails with `MissingArithmeticRuleError` as before.
+    /// Tokens with every rule disabled with `set_arithmetic_rule_enabled` don't use the fallback.
+    /// Cache is cleared.
+    pub fn set_fallback_arithmetic_rule(&mut self, rule: Option<Box<dyn ArithmeticRule>>) {
+        self.fallback_arithmetic_rule = rule;
+        self.clear_cache();
+    }
+
+    /// Returns fallback arithmetic rule set by `set_fallback_arithmetic_rule`.
+    pub fn fallback_arithmetic_rule(&self) -> Option<&dyn ArithmeticRule> {
+        self.fallback_arithmetic_rule.as_deref()
+    }
+
+    /// Enables or disables audit log of rule mutations, disabled by default.
+    /// Enabling starts an empty log, disabling drops recorded entries.
+    pub fn set_audit_log_enabled(&mut self, enabled: bool) {
+        self.audit_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns `true` if audit log is enabled with `set_audit_log_enabled`.
+    pub fn is_audit_log_enabled(&self) -> bool {
+        self.audit_log.is_some()
+    }
+
+    /// Returns recorded rule mutations in order, empty if audit log is disabled.
+    ///
+    /// Adding logical, arithmetic and chained rules, `remove_rules`, `replace_rules` and `merge` are recorded.
+    pub fn audit_log(&self) -> &[AuditEntry] {
+        self.audit_log.as_deref().unwrap_or_default()
+    }
+
+    /// Sets maximum number of inputs evaluated by `eval_grid`, `DEFAULT_MAX_GRID_SIZE` by default.
+    pub fn set_max_grid_size(&mut self, max: usize) {
+        self.max_grid_size = max;
+    }
+
+    /// Sets maximum duration of a single evaluation, `None` disables timeout.
+    ///
+    /// Evaluation with timeout runs on a separate thread with a snapshot of rules shared through `Arc`,
+    /// which is taken once and reused until rules or settings change.
+    /// Timed out evaluation is not cancelled: its thread is left running until the rule returns,
+    /// and `eval` returns `AssignmentError::Timeout` without waiting for it.
+    /// Number of such threads is limited by `set_max_eval_threads`.
+    ///
+    /// Not available on `wasm32`, which has no threads.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_eval_timeout(&mut self, timeout: Option<Duration>) {
+        self.eval_timeout = timeout;
+    }
+
+    /// Returns maximum duration of a single evaluation set by `set_eval_timeout`.
+    pub fn eval_timeout(&self) -> Option<Duration> {
+        self.eval_timeout
+    }
+
+    /// Sets maximum number of evaluation threads with timeout running at once, `DEFAULT_MAX_EVAL_THREADS` by default.
+    /// Evaluation returns error instead of starting a new thread while the limit is reached,
+    /// e.g. when timed out rules are still running.
+    ///
+    /// Not available on `wasm32`, which has no threads.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_max_eval_threads(&mut self, max: usize) {
+        self.max_eval_threads = max;
+    }
+
+    /// Sets value of named constant available to string rules added after it.
+    /// Changed value is used by all rules referencing the constant on next evaluation.
+    /// Cache is cleared, since results may change.
+    ///
+    /// Returns error if name is not an identifier or is one of variables or functions of rules.
+    pub fn add_constant(
+        &mut self,
+        name: impl Into<String>,
+        value: f64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let name = name.into();
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| Regex::new(r"^[A-Za-z_]\w*$").unwrap());
+        if !re.is_match(&name) || RESERVED_NAMES.contains(&name.as_str()) {
+            Err("Constant name must be an identifier other than rule variables and functions.")?
+        }
+
+        self.constants.insert(name, value);
+        self.clear_cache();
+        Ok(())
+    }
+
+    /// Sets default values of input fields used by `eval_partial`.
+    /// Only fields present in `defaults` are changed.
+    ///
+    /// `InputSet::default().into()` makes every absent field zero or `false`.
+    pub fn set_input_default(&mut self, defaults: PartialInputSet) {
+        let current = &mut self.input_defaults;
+        current.a = defaults.a.or(current.a);
+        current.b = defaults.b.or(current.b);
+        current.c = defaults.c.or(current.c);
+        current.d = defaults.d.or(current.d);
+        current.e = defaults.e.or(current.e);
+        current.f = defaults.f.or(current.f);
+    }
+
+    /// Adds named profile with its own rules, replacing previous profile with the same name.
+    /// Profiles are evaluated with `eval_profile` and aren't affected by `remove_rules`.
+    pub fn add_profile(&mut self, name: impl Into<String>, profile: Assignment) {
+        self.profiles.insert(name.into(), profile);
+    }
+
+    /// Removes named profile and returns it, if it exists.
+    pub fn remove_profile(&mut self, name: &str) -> Option<Assignment> {
+        self.profiles.remove(name)
+    }
+
+    /// Returns sorted names of profiles.
+    pub fn list_profiles(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns named profile, if it exists.
+    pub fn profile(&self, name: &str) -> Option<&Assignment> {
+        self.profiles.get(name)
+    }
+
+    /// Returns default values of input fields set by `set_input_default`.
+    pub fn input_defaults(&self) -> &PartialInputSet {
+        &self.input_defaults
+    }
+
+    /// Checks that every registered rule can be evaluated, e.g. after bulk loading of rules,
+    /// so broken rules are reported up front instead of failing `eval`.
+    /// Rules of profiles are checked as well.
+    ///
+    /// Returns the first error with source of the failed rule.
+    pub fn prepare(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let describe = |kind: &str, source: Option<&str>, e: Box<dyn Error + Send + Sync>| {
+            format!("{} rule `{}`: {}", kind, source.unwrap_or("<fn>"), e)
+        };
+
+        for entry in &self.logical_rules {
+            entry
+                .rule
+                .prepare(&self.constants)
+                .map_err(|e| describe("Logical", entry.rule.source(), e))?;
+        }
+        for rule in self
+            .arithmetic_rules
+            .values()
+            .flatten()
+            .map(|entry| &entry.rule)
+            .chain(&self.fallback_arithmetic_rule)
+        {
+            rule.prepare(&self.constants)
+                .map_err(|e| describe("Arithmetic", rule.source(), e))?;
+        }
+        for profile in self.profiles.values_mut() {
+            profile.prepare()?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if `Assignment` has at least one logical and one arithmetic rule.
+    pub fn has_rules(&self) -> bool {
+        !self.logical_rules.is_empty() && !self.arithmetic_rules.is_empty()
+    }
+
+    /// Returns iterator over logical rules in order of registration.
+    pub fn logical_rules(&self) -> impl Iterator<Item = &dyn LogicalRule> {
+        self.logical_rules.iter().map(|entry| entry.rule.as_ref())
+    }
+
+    /// Returns descriptions of logical rules with their tokens, sources and options in order of registration,
+    /// e.g. for admin dashboards.
+    pub fn logical_rule_infos(&self) -> Vec<LogicalRuleInfo> {
+        self.logical_rules
+            .iter()
+            .map(|entry| LogicalRuleInfo {
+                token: entry.rule.token().clone(),
+                source: entry.rule.source().map(str::to_owned),
+                priority: entry.priority,
+                stop_on_match: entry.stop_on_match,
+                enabled: entry.enabled,
+            })
+            .collect()
+    }
+
+    /// Returns rule set of `Assignment` as `SerializableAssignment`, e.g. to store it in a compact format.
+    /// Returns error if there is a function-based logical or arithmetic rule, since it has no source.
+    pub fn to_serializable(&self) -> Result<SerializableAssignment, Box<dyn Error + Send + Sync>> {
+        let (rules, skipped) = self.to_serializable_lossy();
+        match skipped.first() {
+            Some(rule) => Err(format!("{} can't be serialized.", rule).into()),
+            None => Ok(rules),
+        }
+    }
+
+    /// Returns rule set of `Assignment` as `SerializableAssignment` leaving out function-based rules,
+    /// e.g. to export as much as possible. Left out rules are returned in the same order as rules of the set.
+    pub fn to_serializable_lossy(&self) -> (SerializableAssignment, Vec<SkippedRule>) {
+        let mut skipped = Vec::new();
+
+        let mut logical = Vec::with_capacity(self.logical_rules.len());
+        for entry in &self.logical_rules {
+            let token = entry.rule.token().clone();
+            match entry.rule.source() {
+                Some(rule_str) => logical.push(SerializableLogicalRule {
+                    token,
+                    rule_str: rule_str.to_owned(),
+                    priority: entry.priority,
+                    stop_on_match: entry.stop_on_match,
+                    enabled: entry.enabled,
+                }),
+                None => skipped.push(SkippedRule::Logical(token)),
+            }
+        }
+
+        let mut arithmetic = Vec::new();
+        for ArithmeticRuleInfo {
+            token,
+            source,
+            enabled,
+        } in self.arithmetic_rule_infos()
+        {
+            match source {
+                Some(rule_str) => arithmetic.push(SerializableArithmeticRule {
+                    token,
+                    rule_str,
+                    enabled,
+                }),
+                None => skipped.push(SkippedRule::Arithmetic(token)),
+            }
+        }
+
+        let mut fallback_arithmetic = None;
+        if let Some(rule) = &self.fallback_arithmetic_rule {
+            match rule.source() {
+                Some(rule_str) => fallback_arithmetic = Some(rule_str.to_owned()),
+                None => skipped.push(SkippedRule::FallbackArithmetic),
+            }
+        }
+
+        let rules = SerializableAssignment {
+            constants: self
+                .constants
+                .iter()
+                .map(|(k, v)| (k.clone(), *v))
+                .collect(),
+            logical,
+            arithmetic,
+            fallback_arithmetic,
+        };
+        (rules, skipped)
+    }
+
+    /// Builds `Assignment` from rule set returned by `to_serializable`.
+    /// Every rule is validated, so returns error with the first invalid constant or rule, if any.
+    pub fn from_serializable(rules: &SerializableAssignment) -> Result<Self, InvalidRuleSetError> {
+        let mut assignment = Assignment::new();
+        assignment.load_serializable(rules)?;
+        Ok(assignment)
+    }
+
+    /// Returns rule set of `Assignment` returned by `to_serializable` encoded with bincode.
+    #[cfg(feature = "bincode")]
+    pub fn to_bincode(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        Ok(bincode::serialize(&self.to_serializable()?)?)
+    }
+
+    /// Builds `Assignment` from rule set encoded with `to_bincode`.
+    /// Rules are validated the same way as by `from_serializable`.
+    #[cfg(feature = "bincode")]
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let rules: SerializableAssignment = bincode::deserialize(bytes)?;
+        Ok(Self::from_serializable(&rules)?)
+    }
+
+    /// Replaces all rules of `Assignment` with rule set returned by `to_serializable`.
+    /// Constants of the rule set are added to constants of `Assignment`, so rules may use existing constants too.
+    ///
+    /// Every rule is validated before replacement, so returns error with the first invalid constant or rule
+    /// and keeps existing rules if there is one, or if there are more logical rules than allowed by `set_max_logical_rules`.
+    pub fn replace_from_serializable(
+        &mut self,
+        rules: &SerializableAssignment,
+    ) -> Result<(), InvalidRuleSetError> {
+        self.check_logical_rules_limit(rules.logical.len())
+            .map_err(|e| InvalidRuleSetError::new(RuleSetElement::LogicalRulesLimit, e))?;
+        let mut staged = Assignment::new();
+        staged.constants = self.constants.clone();
+        staged.load_serializable(rules)?;
+
+        self.clear_rules();
+        self.audit(AuditOperation::Replace, None, None);
+        self.load_serializable(rules)
+    }
+
+    /// Adds constants and rules of rule set returned by `to_serializable`, validating every rule.
+    fn load_serializable(
+        &mut self,
+        rules: &SerializableAssignment,
+    ) -> Result<(), InvalidRuleSetError> {
+        for (name, value) in &rules.constants {
+            self.add_constant(name.clone(), *value).map_err(|e| {
+                InvalidRuleSetError::new(RuleSetElement::Constant { name: name.clone() }, e)
+            })?;
+        }
+        for (index, rule) in rules.logical.iter().enumerate() {
+            let element = || RuleSetElement::Logical {
+                index,
+                token: rule.token.clone(),
+                rule_str: rule.rule_str.clone(),
+            };
+            let logical_rule = self
+                .build_logical_rule(rule.token.clone(), rule.rule_str.clone())
+                .map_err(|e| InvalidRuleSetError::new(element(), e))?;
+            self.add_logical_rule_with_options(logical_rule, rule.priority, rule.stop_on_match)
+                .map_err(|e| InvalidRuleSetError::new(element(), e))?;
+            if let Some(entry) = self.logical_rules.last_mut() {
+                entry.enabled = rule.enabled;
+            }
+        }
+        // Rules are pushed, so several rules of the same token are all kept.
+        for (index, rule) in rules.arithmetic.iter().enumerate() {
+            let arithmetic_rule =
+                self.build_arithmetic_rule(rule.rule_str.clone())
+                    .map_err(|e| {
+                        let element = RuleSetElement::Arithmetic {
+                            index,
+                            token: rule.token.clone(),
+                            rule_str: rule.rule_str.clone(),
+                        };
+                        InvalidRuleSetError::new(element, e)
+                    })?;
+            self.push_arithmetic_rule(rule.token.clone(), arithmetic_rule);
+            if let Some(entry) = self
+                .arithmetic_rules
+                .get_mut(&rule.token)
+                .and_then(|rules| rules.last_mut())
+            {
+                entry.enabled = rule.enabled;
+            }
+        }
+        if let Some(rule_str) = &rules.fallback_arithmetic {
+            let rule = self.build_arithmetic_rule(rule_str.clone()).map_err(|e| {
+                let element = RuleSetElement::FallbackArithmetic {
+                    rule_str: rule_str.clone(),
+                };
+                InvalidRuleSetError::new(element, e)
+            })?;
+            self.set_fallback_arithmetic_rule(Some(rule));
+        }
+        Ok(())
+    }
+
+    /// Returns descriptions of arithmetic rules with their tokens and sources.
+    /// Tokens are sorted, rules of the same token are in order of registration.
+    pub fn arithmetic_rule_infos(&self) -> Vec<ArithmeticRuleInfo> {
+        let mut tokens: Vec<_> = self.arithmetic_rules.keys().collect();
+        tokens.sort();
+        tokens
+            .into_iter()
+            .flat_map(|token| {
+                self.arithmetic_rules[token]
+                    .iter()
+                    .map(move |entry| ArithmeticRuleInfo {
+                        token: token.clone(),
+                        source: entry.rule.source().map(str::to_owned),
+                        enabled: entry.enabled,
+                    })
+            })
+            .collect()
+    }
+
+    /// Enables or disables logical rule with given index in order of registration without removing it.
+    ///
+    /// Disabled rule is skipped by evaluation and still listed by `logical_rule_infos`.
+    /// Returns error if there is no rule with given index.
+    pub fn set_logical_rule_enabled(
+        &mut self,
+        index: usize,
+        enabled: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let entry = self
+            .logical_rules
+            .get_mut(index)
+            .ok_or_else(|| format!("There is no logical rule with index {}.", index))?;
+        entry.enabled = enabled;
+        self.clear_cache();
+        Ok(())
+    }
+
+    /// Returns whether logical rule with given index in order of registration is enabled,
+    /// `None` if there is no such rule.
+    pub fn is_logical_rule_enabled(&self, index: usize) -> Option<bool> {
+        self.logical_rules.get(index).map(|entry| entry.enabled)
+    }
+
+    /// Enables or disables arithmetic rule of given token without removing it.
+    /// Rule is addressed by its index among rules of the token in order of registration.
+    ///
+    /// Disabled rule is skipped by evaluation and still listed by `arithmetic_rule_infos`.
+    /// Evaluation of a token with every rule disabled fails with `MissingArithmeticRuleError`.
+    /// Returns error if there is no rule with given index.
+    pub fn set_arithmetic_rule_enabled(
+        &mut self,
+        token: &SubstitutionToken,
+        index: usize,
+        enabled: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let entry = self
+            .arithmetic_rules
+            .get_mut(token)
+            .and_then(|rules| rules.get_mut(index))
+            .ok_or_else(|| {
+                format!(
+                    "There is no arithmetic rule of token {:?} with index {}.",
+                    token, index
+                )
+            })?;
+        entry.enabled = enabled;
+        self.clear_cache();
+        Ok(())
+    }
+
+    /// Returns whether arithmetic rule of given token with given index is enabled,
+    /// `None` if there is no such rule.
+    pub fn is_arithmetic_rule_enabled(
+        &self,
+        token: &SubstitutionToken,
+        index: usize,
+    ) -> Option<bool> {
+        self.arithmetic_rules
+            .get(token)
+            .and_then(|rules| rules.get(index))
+            .map(|entry| entry.enabled)
+    }
+
+    /// Returns deterministic fingerprint of the rule set, e.g. for cache invalidation or deployment verification.
+    ///
+    /// Hashes sorted `(token, kind, source)` tuples of logical and arithmetic rules
+    /// followed by source of fallback arithmetic rule with 64-bit FNV-1a,
+    /// so `Assignment`s with the same string rules have the same fingerprint regardless of insertion order,
+    /// across runs and platforms. Function-based rules are hashed by a `<fn>` marker.
+    /// Priorities and other settings don't affect the fingerprint.
+    pub fn fingerprint(&self) -> u64 {
+        let mut rules: Vec<(&SubstitutionToken, &str, &str)> = self
+            .logical_rules()
+            .map(|rule| (rule.token(), "logical", rule.source().unwrap_or("<fn>")))
+            .chain(
+                self.arithmetic_rules()
+                    .map(|(token, rule)| (token, "arithmetic", rule.source().unwrap_or("<fn>"))),
+            )
+            .collect();
+        rules.sort();
+
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |tuple: String| {
+            for byte in tuple.bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for (token, kind, source) in rules {
+            feed(format!("{:?}\u{1f}{}\u{1f}{}\u{1e}", token, kind, source));
+        }
+        if let Some(rule) = &self.fallback_arithmetic_rule {
+            feed(format!(
+                "fallback\u{1f}{}\u{1e}",
+                rule.source().unwrap_or("<fn>")
+            ));
+        }
+        hash
+    }
+
+    /// Returns iterator over arithmetic rules with their tokens.
+    /// Tokens are in arbitrary order, rules of the same token are in order of registration.
+    pub fn arithmetic_rules(
+        &self,
+    ) -> impl Iterator<Item = (&SubstitutionToken, &dyn ArithmeticRule)> {
+        self.arithmetic_rules
+            .iter()
+            .flat_map(|(token, rules)| rules.iter().map(move |entry| (token, entry.rule.as_ref())))
+    }
+
+    /// Returns iterator over tokens that have an arithmetic rule, in arbitrary order.
+    pub fn arithmetic_tokens(&self) -> impl Iterator<Item = &SubstitutionToken> {
+        self.arithmetic_rules.keys()
+    }
+
+    /// Returns `true` if there is an arithmetic rule for given token.
+    pub fn has_arithmetic_rule(&self, token: &SubstitutionToken) -> bool {
+        self.arithmetic_rules.contains_key(token)
+    }
+
+    /// Returns sorted tokens of enabled logical rules and default token that can't be calculated:
+    /// tokens with every arithmetic rule disabled, and tokens without arithmetic rules if fallback rule is not set.
+    /// Terminal tokens don't need arithmetic rule and are not reported.
+    ///
+    /// Empty result means every matched token can be calculated.
+    pub fn validate_coverage(&self) -> Vec<SubstitutionToken> {
+        let mut missing: Vec<SubstitutionToken> = self
+            .enabled_logical_rules()
+            .map(|entry| entry.rule.token())
+            .chain(self.default_token.as_ref())
+            .filter(|token| !self.can_calculate(token) && !self.terminal_tokens.contains(token))
+            .cloned()
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Returns tokens the logical stage can produce for any of 8 combinations of `a`, `b` and `c`,
+    /// respecting priorities, `stop_on_match` and chained rules, e.g. to check arithmetic coverage.
+    ///
+    /// Unlike `validate_coverage`, tokens of rules always shadowed by other rules are not reported.
+    /// Combinations without matching rule or with rule failing to evaluate add nothing.
+    pub fn reachable_tokens(&self) -> HashSet<SubstitutionToken> {
+        let mut tokens = HashSet::new();
+        for a in [false, true] {
+            for b in [false, true] {
+                for c in [false, true] {
+                    if let Ok(token) = self.resolve_token(a, b, c) {
+                        tokens.insert(token);
+                    }
+                }
+            }
+        }
+        tokens
+    }
+
+    /// Removes all rules from `Assignment`.
+    pub fn remove_rules(&mut self) {
+        self.clear_rules();
+        self.audit(AuditOperation::Remove, None, None);
+    }
+
+    /// Removes all rules without recording it in audit log.
+    fn clear_rules(&mut self) {
+        self.logical_rules.clear();
+        self.chained_rules.clear();
+        self.arithmetic_rules.clear();
+        self.terminal_tokens.clear();
+        #[cfg(feature = "decimal")]
+        self.decimal_rules.clear();
+        self.multi_rules.clear();
+        self.fallback_arithmetic_rule = None;
+        self.clear_cache();
+    }
+
+    /// Replaces all rules of `Assignment` with given ones.
+    /// Logical rules are added with default priority `0` in given order.
+    /// Returns error without changing rules if there are more logical rules than allowed by `set_max_logical_rules`.
+    pub fn replace_rules(
+        &mut self,
+        logical_rules: Vec<Box<dyn LogicalRule>>,
+        arithmetic_rules: Vec<(SubstitutionToken, Box<dyn ArithmeticRule>)>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.check_logical_rules_limit(logical_rules.len())?;
+        self.clear_rules();
+        self.audit(AuditOperation::Replace, None, None);
+        for rule in logical_rules {
+            self.add_logical_rule(rule)?;
+        }
+        for (token, rule) in arithmetic_rules {
+            self.add_arithmetic_rule(token, rule);
+        }
+        Ok(())
+    }
+
+    /// Moves rules of `other` into `Assignment`.
+    ///
+    /// Logical rules of `other` are appended with their priorities, so they win ties with rules of `self`.
+    /// Arithmetic, multi-output and decimal rules and constants of `other` are inserted,
+    /// tokens and constant names present in both are resolved with `on_conflict`.
+    /// Fallback arithmetic rule and default token of `other` are taken if `self` has none,
+    /// if both have them they are resolved with `on_conflict` as well.
+    /// Enabled flags move with their rules. Terminal tokens are combined, chained rules of `other` are appended.
+    /// Settings, profiles, cache and audit log of `other` are dropped.
+    ///
+    /// Constants are shared by rules of both sides, so a constant resolved with `ConflictPolicy::TakeOther`
+    /// changes results of rules of `self` using it, and one resolved with `ConflictPolicy::KeepSelf`
+    /// changes results of rules of `other`.
+    ///
+    /// Returns error listing conflicting tokens, constants, fallback rule and default token
+    /// if `on_conflict` is `ConflictPolicy::Error`,
+    /// or if merged rules exceed the limit set by `set_max_logical_rules`; `self` is unchanged then.
+    pub fn merge(
+        &mut self,
+        other: Assignment,
+        on_conflict: ConflictPolicy,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.check_logical_rules_limit(self.logical_rules.len() + other.logical_rules.len())?;
+        if on_conflict == ConflictPolicy::Error {
+            let mut conflicts: Vec<String> = other
+                .arithmetic_rules
+                .keys()
+                .filter(|t| self.arithmetic_rules.contains_key(t))
+                .map(|t| format!("{:?}", t))
+                .collect();
+            conflicts.extend(
+                other
+                    .constants
+                    .iter()
+                    .filter(|(name, value)| self.constants.get(*name).is_some_and(|v| v != *value))
+                    .map(|(name, _)| name.clone()),
+            );
+            conflicts.extend(
+                other
+                    .multi_rules
+                    .keys()
+                    .filter(|t| self.multi_rules.contains_key(t))
+                    .map(|t| format!("{:?} multi-output rule", t)),
+            );
+            #[cfg(feature = "decimal")]
+            conflicts.extend(
+                other
+                    .decimal_rules
+                    .keys()
+                    .filter(|t| self.decimal_rules.contains_key(t))
+                    .map(|t| format!("{:?} decimal rule", t)),
+            );
+            if self.fallback_arithmetic_rule.is_some() && other.fallback_arithmetic_rule.is_some() {
+                conflicts.push("fallback arithmetic rule".to_owned());
+            }
+            if self.default_token.is_some()
+                && other.default_token.is_some()
+                && self.default_token != other.default_token
+            {
+                conflicts.push("default token".to_owned());
+            }
+            if !conflicts.is_empty() {
+                conflicts.sort();
+                Err(format!("Merge conflicts on: {}.", conflicts.join(", ")))?
+            }
+        }
+
+        self.logical_rules.extend(other.logical_rules);
+        self.chained_rules.extend(other.chained_rules);
+        merge_map(
+            &mut self.arithmetic_rules,
+            other.arithmetic_rules,
+            on_conflict,
+        );
+        merge_map(&mut self.constants, other.constants, on_conflict);
+        merge_map(&mut self.multi_rules, other.multi_rules, on_conflict);
+        #[cfg(feature = "decimal")]
+        merge_map(&mut self.decimal_rules, other.decimal_rules, on_conflict);
+        merge_option(
+            &mut self.fallback_arithmetic_rule,
+            other.fallback_arithmetic_rule,
+            on_conflict,
+        );
+        merge_option(&mut self.default_token, other.default_token, on_conflict);
+        self.terminal_tokens.extend(other.terminal_tokens);
+        self.clear_cache();
+        self.audit(AuditOperation::Merge, None, None);
+        Ok(())
+    }
+
+    /// Adds `LogicalRule` with default priority `0` to `Assignment`.
+    /// Returns error if the number of logical rules reached the limit set by `set_max_logical_rules`.
+    pub fn add_logical_rule(
+        &mut self,
+        rule: Box<dyn LogicalRule>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.add_logical_rule_with_priority(rule, 0)
+    }
+
+    /// Adds `LogicalRule` with given priority to `Assignment`.
+    ///
+    /// If several logical rules can be applied, rule with the highest priority is taken.
+    /// Rules with equal priority are resolved by insertion order, the last rule wins.
+    pub fn add_logical_rule_with_priority(
+        &mut self,
+        rule: Box<dyn LogicalRule>,
+        priority: i32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.add_logical_rule_with_options(rule, priority, false)
+    }
+
+    /// Adds `LogicalRule` with given priority to `Assignment`.
+    ///
+    /// If `stop_on_match` is true and the rule matches, rules added after it aren't applied,
+    /// and the winner is chosen among rules applied so far, so it can't be overridden by later rules.
+    pub fn add_logical_rule_with_options(
+        &mut self,
+        rule: Box<dyn LogicalRule>,
+        priority: i32,
+        stop_on_match: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.check_logical_rules_limit(self.logical_rules.len() + 1)?;
+        if self.audit_log.is_some() {
+            let token = rule.token().clone();
+            let source = rule.source().map(str::to_owned);
+            self.audit(AuditOperation::AddLogical, Some(token), source);
+        }
+        self.logical_rules.push(LogicalRuleEntry {
+            rule,
+            priority,
+            stop_on_match,
+            enabled: true,
+        });
+        self.clear_cache();
+        Ok(())
+    }
+
+    /// Creates `LogicalRule` from `Fn` and adds it to `Assignment`.
+    pub fn add_logical_rule_from_fn(
+        &mut self,
+        token: SubstitutionToken,
+        rule_fn: logical_rule::RuleFn,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = LogicalRuleFn::new(token, rule_fn);
+        self.add_logical_rule(Box::new(rule))
+    }
+
+    /// Creates `LogicalRuleAll` producing given token only if all sub-rules match and adds it to `Assignment`.
+    /// Tokens of sub-rules are ignored.
+    /// Returns error if there are no sub-rules or the number of logical rules reached the limit.
+    pub fn add_logical_rule_all(
+        &mut self,
+        token: SubstitutionToken,
+        rules: Vec<Box<dyn LogicalRule>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = LogicalRuleAll::new(token, rules)?;
+        self.add_logical_rule(Box::new(rule))
+    }
+
+    /// Creates `LogicalRule` from `String` and adds it to `Assignment`.
+    pub fn add_logical_rule_from_str(
+        &mut self,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = LogicalRuleStr::new_with_constants(token, rule_str, &self.constants)?;
+        self.add_logical_rule(Box::new(rule))
+    }
+
+    /// Creates `ChainedRuleStr` from `String` with constants of `Assignment` and adds it to the chained pass.
+    ///
+    /// After logical rules find a token, chained rules are applied once in order of registration
+    /// with that tentative token available as `M`, `P` or `T` variable,
+    /// and the first matching chained rule replaces the token.
+    /// Chained rules don't see results of each other.
+    ///
+    /// The chained pass is applied by methods returning a single token, e.g. `eval`;
+    /// `eval_trace`, `eval_map` and `eval_with_candidates` report logical rules of the first pass only.
+    pub fn add_chained_rule_from_str(
+        &mut self,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = ChainedRuleStr::new_with_constants(token, rule_str, &self.constants)?;
+        self.audit(
+            AuditOperation::AddChained,
+            Some(rule.token().clone()),
+            Some(rule.source().to_owned()),
+        );
+        self.chained_rules.push(rule);
+        self.clear_cache();
+        Ok(())
+    }
+
+    /// Returns chained rules in order of registration.
+    pub fn chained_rules(&self) -> &[ChainedRuleStr] {
+        &self.chained_rules
+    }
+
+    /// Creates `LogicalRule` from `String` with constants of `Assignment` without adding it.
+    /// Returned rule can be added later with `add_logical_rule`.
+    pub fn build_logical_rule(
+        &self,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<Box<dyn LogicalRule>, Box<dyn Error + Send + Sync>> {
+        Ok(Box::new(LogicalRuleStr::new_with_constants(
+            token,
+            rule_str,
+            &self.constants,
+        )?))
+    }
+
+    /// Adds `ArithmeticRule` to `Assignment`.
+    /// Replaces all previous arithmetic rules of the token.
+    pub fn add_arithmetic_rule(&mut self, token: SubstitutionToken, rule: Box<dyn ArithmeticRule>) {
+        if self.audit_log.is_some() {
+            let source = rule.source().map(str::to_owned);
+            self.audit(AuditOperation::AddArithmetic, Some(token.clone()), source);
+        }
+        self.arithmetic_rules
+            .insert(token, vec![ArithmeticRuleEntry::new(rule)]);
+        self.clear_cache();
+    }
+
+    /// Adds `ArithmeticRule` to `Assignment` after previous rules of the token.
+    /// Rules of the same token are kept in order of registration
+    /// and combined with `SelectionStrategy` set by `set_selection_strategy`.
+    pub fn push_arithmetic_rule(
+        &mut self,
+        token: SubstitutionToken,
+        rule: Box<dyn ArithmeticRule>,
+    ) {
+        if self.audit_log.is_some() {
+            let source = rule.source().map(str::to_owned);
+            self.audit(AuditOperation::AddArithmetic, Some(token.clone()), source);
+        }
+        self.arithmetic_rules
+            .entry(token)
+            .or_default()
+            .push(ArithmeticRuleEntry::new(rule));
+        self.clear_cache();
+    }
+
+    /// Validates `DecimalArithmeticRule` with constants of `Assignment` and adds it for `eval_decimal` of given token.
+    /// Replaces previous decimal rule of the token.
+    /// Returns error if rule string is invalid.
+    #[cfg(feature = "decimal")]
+    pub fn add_decimal_rule_from_str(
+        &mut self,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = DecimalArithmeticRule::new_with_constants(rule_str, &self.constants)?;
+        self.audit(
+            AuditOperation::AddDecimal,
+            Some(token.clone()),
+            Some(rule.rule_str().to_owned()),
+        );
+        self.decimal_rules.insert(token, rule);
+        Ok(())
+    }
+
+    /// Adds `MultiArithmeticRule` used by `eval_multi` for given token.
+    /// Replaces previous multi-output rule of the token.
+    pub fn add_multi_rule(&mut self, token: SubstitutionToken, rule: Box<dyn MultiArithmeticRule>) {
+        self.multi_rules.insert(token, rule);
+    }
+
+    /// Creates `MultiArithmeticRuleStr` from `String` with constants of `Assignment`
+    /// and adds it the same way as `add_multi_rule`.
+    /// Returns error if rule string is invalid.
+    pub fn add_multi_rule_from_str(
+        &mut self,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = MultiArithmeticRuleStr::new_with_constants(rule_str, &self.constants)?;
+        self.add_multi_rule(token, Box::new(rule));
+        Ok(())
+    }
+
+    /// Creates `ArithmeticRule` from `Fn` and adds it to `Assignment`.
+    pub fn add_arithmetic_rule_from_fn(
+        &mut self,
+        token: SubstitutionToken,
+        rule_fn: arithmetic_rule::RuleFn,
+    ) {
+        let rule = ArithmeticRuleFn::new(rule_fn);
+        self.add_arithmetic_rule(token, Box::new(rule));
+    }
+
+    /// Creates `ArithmeticRule` from `String` and adds it to `Assignment`.
+    pub fn add_arithmetic_rule_from_str(
+        &mut self,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = ArithmeticRuleStr::new_with_constants(rule_str, &self.constants)?;
+        self.add_arithmetic_rule(token, Box::new(rule));
+        Ok(())
+    }
+
+    /// Creates `ArithmeticRule` from `String` with constants of `Assignment` without adding it.
+    /// Returned rule can be added later with `add_arithmetic_rule`.
+    pub fn build_arithmetic_rule(
+        &self,
+        rule_str: String,
+    ) -> Result<Box<dyn ArithmeticRule>, Box<dyn Error + Send + Sync>> {
+        Ok(Box::new(ArithmeticRuleStr::new_with_constants(
+            rule_str,
+            &self.constants,
+        )?))
+    }
+
+    /// Returns named constants set with `add_constant`.
+    pub fn constants(&self) -> &HashMap<String, f64> {
+        &self.constants
+    }
+
+    /// Registers terminal `SubstitutionToken` that doesn't need arithmetic rule.
+    /// `eval_optional` returns no value for such token.
+    pub fn add_terminal_token(&mut self, token: SubstitutionToken) {
+        self.terminal_tokens.insert(token);
+        self.clear_cache();
+    }
+
+    /// Adds `Rule` to `Assignment` as logical or arithmetic rule according to its kind.
+    /// Returns error if logical rule exceeds the limit set by `set_max_logical_rules`.
+    pub fn add_rule(&mut self, rule: Rule) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match rule {
+            Rule::Logical(rule) => self.add_logical_rule(Box::new(rule)),
+            Rule::Arithmetic(token, rule) => {
+                self.add_arithmetic_rule(token, Box::new(rule));
+                Ok(())
+            }
+        }
+    }
+
+    /// Creates `Rule` from `String` and adds it to `Assignment`
+    /// as logical or arithmetic rule according to type of expression result.
+    pub fn add_rule_from_str(
+        &mut self,
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rule = Rule::new_with_constants(token, rule_str, &self.constants)?;
+        self.add_rule(rule)
+    }
+
+    /// Calculates result of substitution rules for given arguments.
+    ///
+    /// First, goes through all logical rules to get `SubstitutionToken` for arithmetical rules.
+    /// If there are several suitable logical rules, result of the rule with the highest priority will be taken.
+    /// If there are several of them, result of the last rule will be taken.
+    /// Returns `Error` if there is no suitable rule for given input.
+    ///
+    /// Then, calculates result of arithmetical rule for found `SubstitutionToken`.
+    /// Returns `Error` if there is no rule for `SubstitutionToken`.
+    ///
+    /// Returns tuple of `SubstitutionToken` and arithmetical rule result as `f64`.
+    ///
+    /// If cache is enabled, returns stored result for previously evaluated input.
+    pub fn eval(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>> {
+        let (token, value) = self.eval_raw(args)?;
+        Ok((token, self.round_output(value)))
+    }
+
+    /// Calculates result of substitution rules for given arguments without rounding.
+    ///
+    /// Works the same way as `eval`, but the value is not rounded to precision set by `set_output_precision`,
+    /// e.g. to keep precise value for auditing.
+    pub fn eval_raw(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        if let Some(cache) = &self.cache {
+            if let Some(res) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(args) {
+                return Ok(res);
+            }
+        }
+
+        let res = self.eval_rules(args)?;
+
+        if let Some(cache) = &self.cache {
+            cache
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(args.clone(), res.clone());
+        }
+
+        Ok(res)
+    }
+
+    /// Calculates result of substitution rules for given arguments and explains how it was obtained.
+    ///
+    /// Works the same way as `eval`, including default token and chained rules, but bypasses cache
+    /// and returns `EvalTrace` with sources of applied rules and input fields they don't use.
+    pub fn eval_explain(&self, args: &InputSet) -> Result<EvalTrace, Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        self.input_bounds.check(args)?;
+        let (matched, attempted) = self.scan_logical_rules(args)?;
+        let entry = matched.map(|(_, entry)| entry);
+        let token = self.resolve_token(args.a, args.b, args.c)?;
+        let rules = self.find_arithmetic_rules(&token)?;
+        let value = self.apply_arithmetic_rules(&rules, args)?;
+        let rule = self.select_arithmetic_rule(&rules);
+
+        let logical_source = entry.and_then(|e| e.rule.source()).map(str::to_owned);
+        let arithmetic_source = rule.and_then(|r| r.source()).map(str::to_owned);
+
+        let unused_fields = match (&logical_source, &arithmetic_source) {
+            (Some(l), Some(a)) => {
+                let used: HashSet<char> = referenced_vars(l)
+                    .union(&referenced_vars(a))
+                    .copied()
+                    .chain(self.chained_rules.iter().flat_map(|r| r.referenced_vars()))
+                    .map(|v| v.to_ascii_lowercase())
+                    .collect();
+                Some(
+                    INPUT_FIELDS
+                        .iter()
+                        .copied()
+                        .filter(|f| !used.contains(f))
+                        .collect(),
+                )
+            }
+            _ => None,
+        };
+
+        Ok(EvalTrace {
+            matched_token: token,
+            attempted,
+            logical_source,
+            arithmetic_source,
+            logical_metadata: RuleMetadata::from_parts(
+                entry.and_then(|e| e.rule.name()),
+                entry.and_then(|e| e.rule.description()),
+            ),
+            arithmetic_metadata: RuleMetadata::from_parts(
+                rule.and_then(|r| r.name()),
+                rule.and_then(|r| r.description()),
+            ),
+            value,
+            unused_fields,
+        })
+    }
+
+    /// Calculates results of substitution rules for each of given inputs sequentially.
+    ///
+    /// Returns results in the same order as inputs.
+    pub fn eval_batch(&self, inputs: &[InputSet]) -> Vec<EvalResult> {
+        inputs.iter().map(|args| self.eval(args)).collect()
+    }
+
+    /// Calculates results of substitution rules for each of given inputs sequentially
+    /// and formats them as CSV with header `a,b,c,d,e,f,token,value,error`, e.g. for spreadsheets.
+    ///
+    /// Each row corresponds to an input in the same order.
+    /// Failed rows have empty `token` and `value` and error message in `error`.
+    pub fn eval_batch_csv(&self, inputs: &[InputSet]) -> String {
+        let mut csv = String::from("a,b,c,d,e,f,token,value,error\n");
+        for (args, res) in inputs.iter().zip(self.eval_batch(inputs)) {
+            let (token, value, error) = match res {
+                Ok((token, value)) => (format!("{:?}", token), value.to_string(), String::new()),
+                Err(e) => (String::new(), String::new(), csv_field(&e.to_string())),
+            };
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{}\n",
+                args.a, args.b, args.c, args.d, args.e, args.f, token, value, error
+            ));
+        }
+        csv
+    }
+
+    /// Calculates results of substitution rules for each input of `EvalGrid`, `f` changes fastest and `d` slowest.
+    ///
+    /// Returns inputs with their results.
+    /// Returns error without evaluating anything if any range is invalid
+    /// or grid has more inputs than allowed by `set_max_grid_size`.
+    pub fn eval_grid(
+        &self,
+        grid: &EvalGrid,
+    ) -> Result<Vec<(InputSet, EvalResult)>, Box<dyn Error + Send + Sync>> {
+        let size = grid.size()?;
+        if size > self.max_grid_size {
+            Err(format!(
+                "Grid of {} inputs exceeds the limit of {}.",
+                size, self.max_grid_size
+            ))?
+        }
+
+        Ok(grid
+            .inputs()
+            .map(|args| {
+                let res = self.eval(&args);
+                (args, res)
+            })
+            .collect())
+    }
+
+    /// Calculates results of substitution rules for each of given inputs in parallel using `rayon`.
+    ///
+    /// Returns results in the same order as inputs.
+    #[cfg(feature = "rayon")]
+    pub fn eval_par(&self, inputs: &[InputSet]) -> Vec<EvalResult> {
+        use rayon::prelude::*;
+
+        inputs.par_iter().map(|args| self.eval(args)).collect()
+    }
+
+    /// Calculates result of substitution rules for given arguments and measures duration of each stage.
+    ///
+    /// Works the same way as `eval`, including output precision, but bypasses cache, so rules are always applied.
+    /// Timeout set by `set_eval_timeout` is not applied either: stages run on the calling thread
+    /// to be measured separately, so a slow rule is timed to the end instead of failing.
+    /// Returns `EvalTiming` along with result, even if calculation fails.
+    /// `eval` itself isn't instrumented, so timing has no cost unless this method is used.
+    pub fn eval_timed(&self, args: &InputSet) -> (EvalResult, EvalTiming) {
+        let args = &*self.transform_input(args);
+        let mut timing = EvalTiming::default();
+
+        let start = Instant::now();
+        let token = self.apply_logical_rules(args);
+        timing.logical = Some(start.elapsed());
+
+        let token = match token {
+            Ok(token) => token,
+            Err(e) => return (Err(e), timing),
+        };
+        let rules = match self.find_arithmetic_rules(&token) {
+            Ok(rules) => rules,
+            Err(e) => return (Err(e), timing),
+        };
+
+        let start = Instant::now();
+        let res = self.apply_arithmetic_rules(&rules, args);
+        timing.arithmetic = Some(start.elapsed());
+
+        (res.map(|res| (token, self.round_output(res))), timing)
+    }
+
+    /// Returns `SubstitutionToken` found by logical stage of `eval` for given arguments,
+    /// without looking for arithmetic rules, e.g. for pure classification.
+    ///
+    /// Applies default token and chained rules the same way as `eval`.
+    /// Returns `Error` if input is out of bounds or no logical rule matches without default token.
+    ///
+    /// Results are not cached.
+    pub fn classify(
+        &self,
+        args: &InputSet,
+    ) -> Result<SubstitutionToken, Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        self.apply_logical_rules(args)
+    }
+
+    /// Returns `true` if at least one logical rule matches given arguments.
+    /// Arithmetic rules aren't applied, logical rules failing to evaluate are treated as not matching.
+    pub fn matches_any(&self, args: &InputSet) -> bool {
+        let args = &*self.transform_input(args);
+        self.enabled_logical_rules().any(|entry| {
+            matches!(
+                entry
+                    .rule
+                    .apply_with_constants(args.a, args.b, args.c, &self.constants),
+                Ok(Some(_))
+            )
+        })
+    }
+
+    /// Calculates result of substitution rules of named profile for given arguments.
+    ///
+    /// Works the same way as `eval` of the profile.
+    /// Returns `Error` if there is no profile with given name.
+    pub fn eval_profile(
+        &self,
+        profile: &str,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>> {
+        self.profile(profile)
+            .ok_or_else(|| format!("Unknown profile: {}.", profile))?
+            .eval(args)
+    }
+
+    /// Calculates result of substitution rules for arguments with absent fields.
+    ///
+    /// Absent fields are filled with defaults set by `set_input_default`,
+    /// then works the same way as `eval`.
+    /// Returns `Error` if absent field has no default value.
+    pub fn eval_partial(
+        &self,
+        args: &PartialInputSet,
+    ) -> Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>> {
+        self.eval(&args.complete(&self.input_defaults)?)
+    }
+
+    /// Calculates result of substitution rules for given arguments allowing terminal tokens.
+    ///
+    /// Works the same way as `eval`, but if found `SubstitutionToken` is registered
+    /// with `add_terminal_token`, returns it with `None` without looking for arithmetic rule.
+    ///
+    /// Results are not cached.
+    pub fn eval_optional(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, Option<f64>), Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        let token = self.apply_logical_rules(args)?;
+        if self.terminal_tokens.contains(&token) {
+            return Ok((token, None));
+        }
+
+        let rules = self.find_arithmetic_rules(&token)?;
+        let res = self.apply_arithmetic_rules(&rules, args)?;
+
+        Ok((token, Some(res)))
+    }
+
+    /// Calculates result of substitution rules for given arguments as integer.
+    ///
+    /// Works the same way as `eval`, but arithmetical rule result is rounded
+    /// with `RoundingMode` set by `set_rounding_mode`.
+    /// Returns `Error` if result can't be represented as `i64`.
+    ///
+    /// Results are not cached.
+    pub fn eval_int(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, i64), Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        let token = self.apply_logical_rules(args)?;
+        let rules = self.find_arithmetic_rules(&token)?;
+        let res = self
+            .rounding_mode
+            .round(self.apply_arithmetic_rules(&rules, args)?)?;
+
+        Ok((token, res))
+    }
+
+    /// Calculates result of substitution rules for given arguments exactly.
+    ///
+    /// Works the same way as `eval`, but applies decimal rule added with `add_decimal_rule_from_str`
+    /// for found `SubstitutionToken` with `DivisionPolicy` and constants of `Assignment`,
+    /// so the result has no floating-point rounding artifacts.
+    /// Returns `Error` if there is no decimal rule for `SubstitutionToken`.
+    ///
+    /// Results are not cached.
+    #[cfg(feature = "decimal")]
+    pub fn eval_decimal(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, Decimal), Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        let token = self.apply_logical_rules(args)?;
+        let rule = self
+            .decimal_rules
+            .get(&token)
+            .ok_or("Failed to find decimal rule for token.")?;
+        let res = rule.apply_with_constants(
+            args.d,
+            args.e,
+            args.f,
+            self.division_policy,
+            &self.constants,
+        )?;
+
+        Ok((token, res))
+    }
+
+    /// Calculates named outputs of substitution rules for given arguments.
+    ///
+    /// Works the same way as `eval`, but applies multi-output rule added with `add_multi_rule`
+    /// for found `SubstitutionToken`, with `DivisionPolicy`, constants and output precision of `Assignment`.
+    /// Returns `Error` if there is no multi-output rule for `SubstitutionToken`.
+    ///
+    /// Results are not cached.
+    pub fn eval_multi(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, HashMap<String, f64>), Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        let token = self.apply_logical_rules(args)?;
+        let rule = self
+            .multi_rules
+            .get(&token)
+            .ok_or("Failed to find multi-output rule for token.")?;
+        let mut res = rule.apply_with_constants(
+            args.d,
+            args.e,
+            args.f,
+            self.division_policy,
+            &self.constants,
+        )?;
+        for value in res.values_mut() {
+            *value = self.round_output(*value);
+        }
+
+        Ok((token, res))
+    }
+
+    /// Calculates results of arithmetic rules for tokens of all matching logical rules.
+    ///
+    /// Returns map from each matched `SubstitutionToken` to its arithmetic rule result.
+    /// Returns `Error` if no logical rule matches or if there is no rule for matched `SubstitutionToken`.
+    ///
+    /// Results are not cached.
+    pub fn eval_map(
+        &self,
+        args: &InputSet,
+    ) -> Result<HashMap<SubstitutionToken, f64>, Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        self.input_bounds.check(args)?;
+
+        let mut res = HashMap::new();
+        for entry in self.enabled_logical_rules() {
+            if let Some(token) =
+                entry
+                    .rule
+                    .apply_with_constants(args.a, args.b, args.c, &self.constants)?
+            {
+                let rules = self.find_arithmetic_rules(&token)?;
+                let value = self.apply_arithmetic_rules(&rules, args)?;
+                res.insert(token, value);
+            }
+        }
+
+        if res.is_empty() {
+            Err("Failed to apply logical rule.")?
+        }
+        Ok(res)
+    }
+
+    /// Calculates results of arithmetic rules for tokens of matching logical rules, ranked like `eval` picks the winner.
+    ///
+    /// Index `0` is the winner of `eval`, including default token set by `set_default_token`
+    /// and chained rules. Other tokens of matching rules follow, ranked by priority,
+    /// rules with equal priority by registration order, the last one first,
+    /// so index `1` is the runner-up. Rules after a matched `stop_on_match` rule are not applied,
+    /// and each token appears once at its best rank.
+    /// Values are rounded to precision set by `set_output_precision`.
+    ///
+    /// Returns `Error` if no logical rule matches without default token or if there is no rule for ranked token.
+    ///
+    /// Results are not cached.
+    pub fn eval_ranked(
+        &self,
+        args: &InputSet,
+    ) -> Result<Vec<(SubstitutionToken, f64)>, Box<dyn Error + Send + Sync>> {
+        let args = &*self.transform_input(args);
+        self.input_bounds.check(args)?;
+
+        let mut matched = Vec::new();
+        for (index, entry) in self.enabled_logical_rules().enumerate() {
+            if let Some(token) =
+                entry
+                    .rule
+                    .apply_with_constants(args.a, args.b, args.c, &self.constants)?
+            {
+                matched.push((entry.priority, index, token));
+                if entry.stop_on_match {
+                    break;
+                }
+            }
+        }
+        matched.sort_by_key(|&(priority, index, _)| std::cmp::Reverse((priority, index)));
+
+        let mut tokens = vec![self.resolve_token(args.a, args.b, args.c)?];
+        for (_, _, token) in matched {
+            if !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+
+        tokens
+            .into_iter()
+            .map(|token| {
+                let rules = self.find_arithmetic_rules(&token)?;
+                let value = self.apply_arithmetic_rules(&rules, args)?;
+                Ok((token, self.round_output(value)))
+            })
+            .collect()
+    }
+
+    /// Calculates result of substitution rules for given arguments
+    /// and returns tokens of other matching logical rules.
+    ///
+    /// Winner is chosen the same way as in `eval`, including default token and chained rules.
+    /// Candidates are other tokens of enabled logical rules matching before the scan is stopped
+    /// by a rule with `stop_on_match`, each listed once in order of registration.
+    /// Their arithmetic rules are not applied.
+    ///
+    /// Results are not cached.
+    pub fn eval_with_candidates(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, f64, Vec<SubstitutionToken>), Box<dyn Error + Send + Sync>>
+    {
+        let args = &*self.transform_input(args);
+        let token = self.apply_logical_rules(args)?;
+        let rules = self.find_arithmetic_rules(&token)?;
+        let res = self.apply_arithmetic_rules(&rules, args)?;
+
+        let mut candidates = Vec::new();
+        for entry in self.enabled_logical_rules() {
+            if let Some(t) =
+                entry
+                    .rule
+                    .apply_with_constants(args.a, args.b, args.c, &self.constants)?
+            {
+                if t != token && !candidates.contains(&t) {
+                    candidates.push(t);
+                }
+                if entry.stop_on_match {
+                    break;
+                }
+            }
+        }
+
+        Ok((token, res, candidates))
+    }
+
+    /// Calculates result of substitution rules for given arguments as `f32`.
+    ///
+    /// Works the same way as `eval`, including timeout, cache and output precision,
+    /// and converts its result to `f32` once: rules are still calculated in `f64`,
+    /// see `ArithmeticRule::apply_f32` for precision tradeoffs.
+    pub fn eval_f32(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, f32), Box<dyn Error + Send + Sync>> {
+        let (token, value) = self.eval(args)?;
+        Ok((token, value as f32))
+    }
+
+    /// Calculates result of substitution rules for given arguments without using cache.
+    fn eval_rules(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(timeout) = self.eval_timeout {
+            return self.eval_rules_timed(args, timeout);
+        }
+        self.eval_rules_untimed(args)
+    }
+
+    /// Calculates result of substitution rules on a separate thread with snapshot of `Assignment`
+    /// and returns `AssignmentError::Timeout` if it takes longer than `timeout`. The thread is not stopped then.
+    /// Returns error if `max_eval_threads` threads are already running or thread can't be spawned.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn eval_rules_timed(
+        &self,
+        args: &InputSet,
+        timeout: Duration,
+    ) -> Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>> {
+        use std::sync::mpsc;
+
+        if self.eval_threads.fetch_add(1, Ordering::SeqCst) >= self.max_eval_threads {
+            self.eval_threads.fetch_sub(1, Ordering::SeqCst);
+            Err(format!(
+                "Number of running evaluation threads can't exceed {}.",
+                self.max_eval_threads
+            ))?;
+        }
+        let guard = EvalThreadGuard(Arc::clone(&self.eval_threads));
+
+        let assignment = self.eval_snapshot();
+        let (tx, rx) = mpsc::channel();
+        let args = args.clone();
+        std::thread::Builder::new()
+            .name("assignment-eval".to_owned())
+            .spawn(move || {
+                let _guard = guard;
+                let _ = tx.send(assignment.eval_rules_untimed(&args));
+            })
+            .map_err(|e| format!("Failed to spawn evaluation thread: {}.", e))?;
+
+        match rx.recv_timeout(timeout) {
+            Ok(res) => res,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(AssignmentError::Timeout(timeout))?,
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err("Evaluation thread panicked.")?,
+        }
+    }
+
+    /// Returns snapshot of `Assignment` shared through `Arc`,
+    /// which is taken once and reused until rules or settings change.
+    /// Used to evaluate on another thread without borrowing `Assignment`.
+    pub(crate) fn eval_snapshot(&self) -> Arc<Assignment> {
+        self.timed_snapshot
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(|| Arc::new(self.clone()))
+            .clone()
+    }
+
+    /// Calculates result of substitution rules on the current thread.
+    fn eval_rules_untimed(
+        &self,
+        args: &InputSet,
+    ) -> Result<(SubstitutionToken, f64), Box<dyn Error + Send + Sync>> {
+        let token = self.apply_logical_rules(args)?;
+        let rules = self.find_arithmetic_rules(&token)?;
+        let value = self.apply_arithmetic_rules(&rules, args)?;
+
+        Ok((token, value))
+    }
+
+    /// Rounds value to precision set by `set_output_precision`, half to even.
+    /// Returns value unchanged if precision is not set, exceeds `MAX_OUTPUT_PRECISION`
+    /// or value is too large to be scaled.
+    pub fn round_output(&self, value: f64) -> f64 {
+        match self.output_precision {
+            Some(precision) if precision <= MAX_OUTPUT_PRECISION => {
+                let factor = 10f64.powi(precision as i32);
+                let scaled = value * factor;
+                if scaled.is_finite() {
+                    scaled.round_ties_even() / factor
+                } else {
+                    value
+                }
+            }
+            _ => value,
+        }
+    }
+
+    /// Applies arithmetic rules of a token to given arguments with `DivisionPolicy` and constants of `Assignment`
+    /// and combines their results with `SelectionStrategy` of `Assignment`.
+    fn apply_arithmetic_rules(
+        &self,
+        rules: &[&dyn ArithmeticRule],
+        args: &InputSet,
+    ) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        if let Some(rule) = self.select_arithmetic_rule(rules) {
+            return rule.apply_with_constants(
+                args.d,
+                args.e,
+                args.f,
+                self.division_policy,
+                &self.constants,
+            );
+        }
+
+        let mut sum = 0.0;
+        for rule in rules {
+            sum += rule.apply_with_constants(
+                args.d,
+                args.e,
+                args.f,
+                self.division_policy,
+                &self.constants,
+            )?;
+        }
+
+        match self.selection_strategy {
+            SelectionStrategy::Average => Ok(sum / rules.len() as f64),
+            _ => Ok(sum),
+        }
+    }
+
+    /// Returns the only arithmetic rule which result is taken according to `SelectionStrategy`,
+    /// or `None` if results of several rules are combined.
+    fn select_arithmetic_rule<'a>(
+        &self,
+        rules: &[&'a dyn ArithmeticRule],
+    ) -> Option<&'a dyn ArithmeticRule> {
+        let rule = match (self.selection_strategy, rules) {
+            (_, [rule]) | (SelectionStrategy::First, [rule, ..]) => rule,
+            (SelectionStrategy::Last, [.., rule]) => rule,
+            _ => return None,
+        };
+
+        Some(*rule)
+    }
+
+    /// Returns `SubstitutionToken` of the winning logical rule for given arguments.
+    fn apply_logical_rules(
+        &self,
+        args: &InputSet,
+    ) -> Result<SubstitutionToken, Box<dyn Error + Send + Sync>> {
+        self.input_bounds.check(args)?;
+        self.resolve_token(args.a, args.b, args.c)
+    }
+
+    /// Returns `SubstitutionToken` of logical rules, or default token if none of them matches,
+    /// and chained rules for given logical arguments.
+    fn resolve_token(
+        &self,
+        a: bool,
+        b: bool,
+        c: bool,
+    ) -> Result<SubstitutionToken, Box<dyn Error + Send + Sync>> {
+        let token = match self
+            .scan_logical_rules(&InputSet::new(a, b, c, 0.0, 0, 0))?
+            .0
+        {
+            Some((token, _)) => token,
+            None => self
+                .default_token
+                .clone()
+                .ok_or("Failed to apply logical rule.")?,
+        };
+
+        for rule in &self.chained_rules {
+            if let Some(chained) = rule.apply(a, b, c, &token, &self.constants)? {
+                return Ok(chained);
+            }
+        }
+        Ok(token)
+    }
+
+    /// Returns `SubstitutionToken` and the winning logical rule for given arguments, if any,
+    /// and number of logical rules applied to find it.
+    #[allow(clippy::type_complexity)]
+    fn scan_logical_rules(
+        &self,
+        args: &InputSet,
+    ) -> Result<(Option<(SubstitutionToken, &LogicalRuleEntry)>, usize), Box<dyn Error + Send + Sync>>
+    {
+        let mut matched: Option<(SubstitutionToken, &LogicalRuleEntry)> = None;
+        let mut attempted = 0;
+        for entry in self.enabled_logical_rules() {
+            attempted += 1;
+            if let Some(t) =
+                entry
+                    .rule
+                    .apply_with_constants(args.a, args.b, args.c, &self.constants)?
+            {
+                if matched
+                    .as_ref()
+                    .is_none_or(|(_, m)| entry.priority >= m.priority)
+                {
+                    matched = Some((t, entry));
+                }
+                if entry.stop_on_match {
+                    break;
+                }
+            }
+        }
+
+        Ok((matched, attempted))
+    }
+
+    /// Returns logical rules not disabled with `set_logical_rule_enabled` in order of registration.
+    fn enabled_logical_rules(&self) -> impl Iterator<Item = &LogicalRuleEntry> {
+        self.logical_rules.iter().filter(|entry| entry.enabled)
+    }
+
+    /// Returns enabled arithmetic rules for given `SubstitutionToken` in order of registration,
+    /// or fallback arithmetic rule if the token has no rules.
+    /// Returns `MissingArithmeticRuleError` if there are none or all of them are disabled.
+    fn find_arithmetic_rules(
+        &self,
+        token: &SubstitutionToken,
+    ) -> Result<Vec<&dyn ArithmeticRule>, Box<dyn Error + Send + Sync>> {
+        let rules: Vec<&dyn ArithmeticRule> = match self.arithmetic_rules.get(token) {
+            Some(rules) => rules
+                .iter()
+                .filter(|entry| entry.enabled)
+                .map(|entry| entry.rule.as_ref())
+                .collect(),
+            None => self
+                .fallback_arithmetic_rule
+                .iter()
+                .map(|rule| rule.as_ref())
+                .collect(),
+        };
+        if rules.is_empty() {
+            Err(MissingArithmeticRuleError {
+                token: token.clone(),
+            })?
+        }
+
+        Ok(rules)
+    }
+
+    /// Returns `true` if given token has an enabled arithmetic rule,
+    /// or has no arithmetic rules and fallback arithmetic rule is set.
+    fn can_calculate(&self, token: &SubstitutionToken) -> bool {
+        match self.arithmetic_rules.get(token) {
+            Some(rules) => rules.iter().any(|entry| entry.enabled),
+            None => self.fallback_arithmetic_rule.is_some(),
+        }
+    }
+
+    /// Returns error if `count` logical rules exceed the limit set by `set_max_logical_rules`.
+    fn check_logical_rules_limit(&self, count: usize) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.max_logical_rules {
+            Some(max) if count > max => {
+                Err(format!("Number of logical rules can't exceed {}.", max))?
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns input transformed by transform set with `set_input_transform`, or input itself if there is none.
+    fn transform_input<'a>(&self, args: &'a InputSet) -> Cow<'a, InputSet> {
+        match &self.input_transform {
+            Some(transform) => {
+                let mut args = args.clone();
+                transform(&mut args);
+                Cow::Owned(args)
+            }
+            None => Cow::Borrowed(args),
+        }
+    }
+
+    /// Appends entry to audit log if it's enabled.
+    fn audit(
+        &mut self,
+        operation: AuditOperation,
+        token: Option<SubstitutionToken>,
+        source: Option<String>,
+    ) {
+        if let Some(log) = &mut self.audit_log {
+            log.push(AuditEntry::new(operation, token, source));
+        }
+    }
+
+    /// Removes all cached `eval` results and snapshot used for evaluation with timeout.
+    fn clear_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.get_mut().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+        *self
+            .timed_snapshot
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Adds set of predefined base rules to `Assignment`.
+    fn add_base_rules(obj: &mut Assignment) {
+        Self::add_rule_table(obj, BASE_LOGICAL_RULES, BASE_ARITHMETIC_RULES);
+    }
+
+    /// Adds set of predefined custom rules to `Assignment`.
+    fn add_custom_rules(obj: &mut Assignment) {
+        Self::add_rule_table(obj, CUSTOM_LOGICAL_RULES, CUSTOM_ARITHMETIC_RULES);
+    }
+
+    /// Adds predefined string rules from given tables to `Assignment`.
+    fn add_rule_table(
+        obj: &mut Assignment,
+        logical: &[(SubstitutionToken, &str)],
+        arithmetic: &[(SubstitutionToken, &str)],
+    ) {
+        for (token, rule_str) in logical {
+            obj.add_logical_rule_from_str(token.clone(), (*rule_str).to_owned())
+                .expect("Predefined logical rule should be valid.");
+        }
+        for (token, rule_str) in arithmetic {
+            obj.add_arithmetic_rule_from_str(token.clone(), (*rule_str).to_owned())
+                .expect("Predefined arithmetic rule should be valid.");
+        }
+    }
+}
+
+#[test]
+fn test_input_set_new() {
+    let literal = InputSet {
+        a: true,
+        b: false,
+        c: false,
+        d: 2.0,
+        e: 3,
+        f: 4,
+    };
+
+    assert!(InputSet::new(true, false, false, 2.0, 3, 4) == literal);
+    assert!(InputSet::from((true, false, false, 2.0, 3, 4)) == literal);
+    assert!(InputSet::from((true, false, false, 2.0, 3, 5)) != literal);
+    assert!(InputSet::new(false, false, false, 0.0, 0, 0) == InputSet::default());
+}
+
+#[test]
+fn test_new() {
+    let assignment = Assignment::new();
+
+    assert!(assignment.logical_rules.is_empty());
+    assert!(assignment.arithmetic_rules.is_empty());
+}
+
+#[test]
+fn test_with_rules() {
+    let assignment = Assignment::new().with_rules(false, false);
+    assert!(assignment.logical_rules.is_empty());
+    assert!(assignment.arithmetic_rules.is_empty());
+
+    let assignment = Assignment::new().with_rules(true, false);
+    assert!(!assignment.logical_rules.is_empty());
+    assert!(!assignment.arithmetic_rules.is_empty());
+
+    let assignment = Assignment::new().with_rules(false, true);
+    assert!(!assignment.logical_rules.is_empty());
+    assert!(!assignment.arithmetic_rules.is_empty());
+
+    let assignment = Assignment::new().with_rules(true, true);
+    assert!(!assignment.logical_rules.is_empty());
+    assert!(!assignment.arithmetic_rules.is_empty());
+}
+
+#[test]
+fn test_has_rules() {
+    let mut assignment = Assignment::new();
+    assert!(!assignment.has_rules());
+
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assert!(!assignment.has_rules());
+
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    assert!(assignment.has_rules());
+
+    assignment.remove_rules();
+    assert!(!assignment.has_rules());
+}
+
+#[test]
+fn test_remove_rules() {
+    let mut assignment = Assignment::new();
+
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
+        .unwrap();
+
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+
+    assignment.remove_rules();
+
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
+        .unwrap();
+
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+}
+
+#[test]
+fn test_replace_rules() {
+    let mut assignment = Assignment::new().with_rules(true, true);
+
+    let l_rule = LogicalRuleFn::new(SubstitutionToken::P, Box::new(|a, _, _| a));
+    let a_rule = ArithmeticRuleFn::new(Box::new(|d, _, _| d));
+    assignment
+        .replace_rules(
+            vec![Box::new(l_rule)],
+            vec![(SubstitutionToken::P, Box::new(a_rule))],
+        )
+        .unwrap();
+
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+    assert_eq!(
+        assignment
+            .eval(&InputSet {
+                a: true,
+                d: 2.0,
+                ..InputSet::default()
+            })
+            .unwrap(),
+        (SubstitutionToken::P, 2.0)
+    );
+
+    assignment.replace_rules(Vec::new(), Vec::new()).unwrap();
+    assert!(assignment.logical_rules.is_empty());
+    assert!(assignment.arithmetic_rules.is_empty());
+}
+
+#[test]
+fn test_add_logical_rule() {
+    let mut assignment = Assignment::new();
+
+    let rule0 = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
+    assignment.add_logical_rule(Box::new(rule0)).unwrap();
+
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+    assert_eq!(
+        assignment.logical_rules[0]
+            .rule
+            .apply(true, true, true)
+            .unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(
+        assignment.logical_rules[0]
+            .rule
+            .apply(false, true, true)
+            .unwrap(),
+        None
+    );
+
+    let rule1 = LogicalRuleStr::new(SubstitutionToken::T, "B".to_owned()).unwrap();
+    assignment.add_logical_rule(Box::new(rule1)).unwrap();
+
+    assert_eq!(assignment.logical_rules.len(), 2);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+    assert_eq!(
+        assignment.logical_rules[1]
+            .rule
+            .apply(true, true, true)
+            .unwrap(),
+        Some(SubstitutionToken::T)
+    );
+    assert_eq!(
+        assignment.logical_rules[1]
+            .rule
+            .apply(true, false, true)
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_add_logical_rule_from_fn() {
+    let mut assignment = Assignment::new();
+
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+    assert_eq!(
+        assignment.logical_rules[0]
+            .rule
+            .apply(true, true, true)
+            .unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(
+        assignment.logical_rules[0]
+            .rule
+            .apply(false, true, true)
+            .unwrap(),
+        None
+    );
+
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|_, b, _| b))
+        .unwrap();
+    assert_eq!(assignment.logical_rules.len(), 2);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+    assert_eq!(
+        assignment.logical_rules[1]
+            .rule
+            .apply(true, true, true)
+            .unwrap(),
+        Some(SubstitutionToken::P)
+    );
+    assert_eq!(
+        assignment.logical_rules[1]
+            .rule
+            .apply(true, false, true)
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_add_logical_rule_from_str() {
+    let mut assignment = Assignment::new();
+
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .expect("Should not fail.");
+
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+    assert_eq!(
+        assignment.logical_rules[0]
+            .rule
+            .apply(true, true, true)
+            .unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(
+        assignment.logical_rules[0]
+            .rule
+            .apply(false, true, true)
+            .unwrap(),
+        None
+    );
+
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::T, "B".to_owned())
+        .expect("Should not fail.");
+    assert_eq!(assignment.logical_rules.len(), 2);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+    assert_eq!(
+        assignment.logical_rules[1]
+            .rule
+            .apply(true, true, true)
+            .unwrap(),
+        Some(SubstitutionToken::T)
+    );
+    assert_eq!(
+        assignment.logical_rules[1]
+            .rule
+            .apply(true, false, true)
+            .unwrap(),
+        None
+    );
+
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "Z+X".to_owned())
+        .expect_err("Should fail.");
+    assert_eq!(assignment.logical_rules.len(), 2);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+}
+
+#[test]
+fn test_build_rules() {
+    let mut assignment = Assignment::new();
+
+    let l_rule = assignment
+        .build_logical_rule(SubstitutionToken::M, "A && B".to_owned())
+        .expect("Should not fail.");
+    let a_rule = assignment
+        .build_arithmetic_rule("D * E".to_owned())
+        .expect("Should not fail.");
+    assert!(assignment
+        .build_logical_rule(SubstitutionToken::M, "A + B".to_owned())
+        .is_err());
+    assert!(assignment
+        .build_arithmetic_rule("D && E".to_owned())
+        .is_err());
+
+    // Built rules are not added to `Assignment` until requested.
+    assert!(assignment.logical_rules.is_empty());
+    assert!(assignment.arithmetic_rules.is_empty());
+    assert_eq!(
+        l_rule.apply(true, true, false).unwrap(),
+        Some(SubstitutionToken::M)
+    );
+    assert_eq!(a_rule.apply(2.0, 3, 0).unwrap(), 6.0);
+
+    assignment.add_logical_rule(l_rule).unwrap();
+    assignment.add_arithmetic_rule(SubstitutionToken::M, a_rule);
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, true, false, 2.0, 3, 0))
+            .unwrap(),
+        (SubstitutionToken::M, 6.0)
+    );
+}
+
+#[test]
+fn test_add_arithmetic_rule() {
+    let mut assignment = Assignment::new();
+
+    let rule0 = ArithmeticRuleFn::new(Box::new(|d, _, _| d));
+    assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(rule0));
+
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::M]
+            .last()
+            .unwrap()
+            .rule
+            .apply(2.0, 0, 0)
+            .unwrap(),
+        2.0
+    );
+
+    let rule1 = ArithmeticRuleStr::new("E".to_owned()).unwrap();
+    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule1));
+
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 2);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::T]
+            .last()
+            .unwrap()
+            .rule
+            .apply(0.0, 2, 0)
+            .unwrap(),
+        2.0
+    );
+
+    let rule2 = ArithmeticRuleFn::new(Box::new(|_, _, f| f as f64));
+    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule2));
+
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 2);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::T]
+            .last()
+            .unwrap()
+            .rule
+            .apply(0.0, 0, 2)
+            .unwrap(),
+        2.0
+    );
+}
+
+#[test]
+fn test_add_arithmetic_rule_from_fn() {
+    let mut assignment = Assignment::new();
+
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::M]
+            .last()
+            .unwrap()
+            .rule
+            .apply(2.0, 0, 0)
+            .unwrap(),
+        2.0
+    );
+
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, e, _| e as f64));
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 2);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::T]
+            .last()
+            .unwrap()
+            .rule
+            .apply(0.0, 2, 0)
+            .unwrap(),
+        2.0
+    );
+
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, _, f| f as f64));
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 2);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::T]
+            .last()
+            .unwrap()
+            .rule
+            .apply(0.0, 0, 2)
+            .unwrap(),
+        2.0
+    );
+}
+
+#[test]
+fn test_add_arithmetic_rule_from_str() {
+    let mut assignment = Assignment::new();
+
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
+        .expect("Should not fail.");
+
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::M]
+            .last()
+            .unwrap()
+            .rule
+            .apply(2.0, 0, 0)
+            .unwrap(),
+        2.0
+    );
+
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::T, "E".to_owned())
+        .expect("Should not fail.");
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 2);
+    assert_eq!(
+        assignment.arithmetic_rules[&SubstitutionToken::T]
+            .last()
+            .unwrap()
+            .rule
+            .apply(0.0, 2, 0)
+            .unwrap(),
+        2.0
+    );
+
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::P, "Z&&X".to_owned())
+        .expect_err("Should fail.");
+    assert_eq!(assignment.logical_rules.len(), 0);
+    assert_eq!(assignment.arithmetic_rules.len(), 2);
+}
+
+#[test]
+fn test_add_rule_from_str() {
+    let mut assignment = Assignment::new();
+
+    assignment
+        .add_rule_from_str(SubstitutionToken::M, "A && B".to_owned())
+        .expect("Should not fail.");
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 0);
+
+    assignment
+        .add_rule_from_str(SubstitutionToken::M, "D + E".to_owned())
+        .expect("Should not fail.");
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+
+    assignment
+        .add_rule_from_str(SubstitutionToken::M, "A + D".to_owned())
+        .expect_err("Should fail.");
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.arithmetic_rules.len(), 1);
+
+    let res = assignment
+        .eval(&InputSet {
+            a: true,
+            b: true,
+            d: 1.0,
+            e: 2,
+            ..InputSet::default()
+        })
+        .unwrap();
+    assert_eq!(res, (SubstitutionToken::M, 3.0));
+}
+
+#[test]
+#[should_panic(expected = "Failed to apply logical rule.")]
+fn test_eval_empty_logical_rules() {
+    let mut assignment = Assignment::new();
+
+    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 0.0));
+    assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(rule));
+    assignment.eval(&InputSet::default()).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "MissingArithmeticRuleError { token: M }")]
+fn test_eval_empty_arithmetic_rules() {
+    let mut assignment = Assignment::new();
+
+    let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|_, _, _| true));
+    assignment.add_logical_rule(Box::new(rule)).unwrap();
+    assignment.eval(&InputSet::default()).unwrap();
+}
+
+#[test]
+fn test_eval() {
+    let mut assignment = Assignment::new();
+
+    let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
+    assignment.add_logical_rule(Box::new(rule)).unwrap();
+    let rule = LogicalRuleFn::new(SubstitutionToken::T, Box::new(|_, b, _| b));
+    assignment.add_logical_rule(Box::new(rule)).unwrap();
+    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 2.0));
+    assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(rule));
+    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 3.0));
+    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule));
+
+    let res = assignment
+        .eval(&InputSet {
+            a: true,
+            b: false,
+            c: false,
+            d: 0.0,
+            e: 0,
+            f: 0,
+        })
+        .expect("eval() should return Ok()");
+    assert_eq!(res, (SubstitutionToken::M, 2.0));
+
+    let res = assignment
+        .eval(&InputSet {
+            a: false,
+            b: true,
+            c: false,
+            d: 0.0,
+            e: 0,
+            f: 0,
+        })
+        .expect("eval() should return Ok()");
+    assert_eq!(res, (SubstitutionToken::T, 3.0));
+
+    // Override logical rule to substitute to another arithmetic rule.
+    let rule = LogicalRuleFn::new(SubstitutionToken::T, Box::new(|a, _, _| a));
+    assignment.add_logical_rule(Box::new(rule)).unwrap();
+
+    let res = assignment
+        .eval(&InputSet {
+            a: true,
+            b: false,
+            c: false,
+            d: 0.0,
+            e: 0,
+            f: 0,
+        })
+        .expect("eval() should return Ok()");
+    assert_eq!(res, (SubstitutionToken::T, 3.0));
+
+    // Override arithmetic rule.
+    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 4.0));
+    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule));
+
+    let res = assignment
+        .eval(&InputSet {
+            a: true,
+            b: false,
+            c: false,
+            d: 0.0,
+            e: 0,
+            f: 0,
+        })
+        .expect("eval() should return Ok()");
+    assert_eq!(res, (SubstitutionToken::T, 4.0));
+
+    // Override logical rule to no arithmetic rule.
+    let rule = LogicalRuleFn::new(SubstitutionToken::P, Box::new(|a, _, _| a));
+    assignment.add_logical_rule(Box::new(rule)).unwrap();
+
+    let res = assignment.eval(&InputSet {
+        a: true,
+        b: false,
+        c: false,
+        d: 0.0,
+        e: 0,
+        f: 0,
+    });
+
+    assert_eq!(
+        res.unwrap_err().to_string(),
+        "Failed to find arithmetic rule for token."
+    );
+}
+
+#[test]
+fn test_with_cache() {
+    let mut assignment = Assignment::new().with_rules(true, false).with_cache(4);
+    let input = InputSet {
+        a: true,
+        b: true,
+        c: false,
+        d: 2.0,
+        e: 3,
+        f: 4,
+    };
+
+    let res = assignment.eval(&input).unwrap();
+    assert_eq!(res.0, SubstitutionToken::M);
+    assert!(approx_eq(res.1, 2.6, 1e-9));
+    assert_eq!(assignment.cache.as_ref().unwrap().lock().unwrap().len(), 1);
+
+    // Cache hit returns the same result.
+    let cached = assignment.eval(&input).unwrap();
+    assert_eq!(cached, res);
+    assert_eq!(assignment.cache.as_ref().unwrap().lock().unwrap().len(), 1);
+
+    // Adding rule clears the cache and new rule is applied.
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|_, _, _| true))
+        .unwrap();
+    assert!(assignment
+        .cache
+        .as_ref()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .is_empty());
+
+    let res = assignment.eval(&input).unwrap();
+    assert_eq!(res.0, SubstitutionToken::P);
+
+    // Overriding arithmetic rule clears the cache as well.
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::P, Box::new(|_, _, _| 42.0));
+    assert!(assignment
+        .cache
+        .as_ref()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::P, 42.0)
+    );
+
+    assignment.remove_rules();
+    assert!(assignment
+        .cache
+        .as_ref()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .is_empty());
+    assert!(assignment.eval(&input).is_err());
+}
+
+#[test]
+fn test_clone() {
+    let assignment = Assignment::new().with_rules(true, false);
+    let input = InputSet {
+        a: true,
+        b: true,
+        c: false,
+        d: 2.0,
+        e: 3,
+        f: 4,
+    };
+
+    let mut cloned = assignment.clone();
+    assert_eq!(cloned.logical_rules.len(), assignment.logical_rules.len());
+    assert_eq!(
+        cloned.arithmetic_rules.len(),
+        assignment.arithmetic_rules.len()
+    );
+    assert_eq!(
+        cloned.eval(&input).unwrap(),
+        assignment.eval(&input).unwrap()
+    );
+
+    // Mutating clone doesn't affect the original.
+    cloned
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|_, _, _| true))
+        .unwrap();
+    cloned.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|_, _, _| 0.0));
+
+    assert_eq!(
+        cloned.logical_rules.len(),
+        assignment.logical_rules.len() + 1
+    );
+    assert_eq!(cloned.eval(&input).unwrap().0, SubstitutionToken::P);
+    let res = assignment.eval(&input).unwrap();
+    assert_eq!(res.0, SubstitutionToken::M);
+    assert!(approx_eq(res.1, 2.6, 1e-9));
+
+    cloned.remove_rules();
+    assert!(cloned.logical_rules.is_empty());
+    assert!(!assignment.logical_rules.is_empty());
+    let res = assignment.eval(&input).unwrap();
+    assert_eq!(res.0, SubstitutionToken::M);
+    assert!(approx_eq(res.1, 2.6, 1e-9));
+}
+
+#[test]
+fn test_add_logical_rule_with_priority() {
+    let mut assignment = Assignment::new();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|_, _, _| 1.0));
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::P, Box::new(|_, _, _| 2.0));
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, _, _| 3.0));
+
+    let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
+    assignment
+        .add_logical_rule_with_priority(Box::new(rule), 10)
+        .unwrap();
+    assert_eq!(assignment.logical_rules.len(), 1);
+    assert_eq!(assignment.logical_rules[0].priority, 10);
+
+    // High priority rule wins over later rules with lower priority.
+    let rule = LogicalRuleFn::new(SubstitutionToken::P, Box::new(|a, _, _| a));
+    assignment.add_logical_rule(Box::new(rule)).unwrap();
+    assert_eq!(assignment.logical_rules[1].priority, 0);
+
+    let input = InputSet {
+        a: true,
+        ..InputSet::default()
+    };
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 1.0)
+    );
+
+    // Equal priority is resolved by insertion order.
+    let rule = LogicalRuleFn::new(SubstitutionToken::T, Box::new(|a, _, _| a));
+    assignment
+        .add_logical_rule_with_priority(Box::new(rule), 10)
+        .unwrap();
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::T, 3.0)
+    );
+
+    // Lower priority rule is still applied if higher priority rules don't match.
+    let input = InputSet {
+        a: false,
+        ..InputSet::default()
+    };
+    let rule = LogicalRuleFn::new(SubstitutionToken::P, Box::new(|a, _, _| !a));
+    assignment
+        .add_logical_rule_with_priority(Box::new(rule), -5)
+        .unwrap();
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::P, 2.0)
+    );
+}
+
+#[test]
+fn test_eval_int() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D / E".to_owned())
+        .unwrap();
+
+    let input = InputSet {
+        a: true,
+        d: 5.0,
+        e: 2,
+        ..InputSet::default()
+    };
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 2.5)
+    );
+    assert_eq!(
+        assignment.eval_int(&input).unwrap(),
+        (SubstitutionToken::M, 3)
+    );
+
+    assignment.set_rounding_mode(RoundingMode::Floor);
+    assert_eq!(
+        assignment.eval_int(&input).unwrap(),
+        (SubstitutionToken::M, 2)
+    );
+
+    let input = InputSet {
+        a: true,
+        d: -5.0,
+        e: 2,
+        ..InputSet::default()
+    };
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, -2.5)
+    );
+    assert_eq!(
+        assignment.eval_int(&input).unwrap(),
+        (SubstitutionToken::M, -3)
+    );
+
+    assignment.set_rounding_mode(RoundingMode::Trunc);
+    assert_eq!(
+        assignment.eval_int(&input).unwrap(),
+        (SubstitutionToken::M, -2)
+    );
+
+    assert_eq!(
+        assignment
+            .eval_int(&InputSet {
+                a: true,
+                d: 1.0,
+                ..InputSet::default()
+            })
+            .unwrap_err()
+            .to_string(),
+        "Arithmetic rule result can't be represented as integer."
+    );
+    assert_eq!(
+        assignment
+            .eval_int(&InputSet::default())
+            .unwrap_err()
+            .to_string(),
+        "Failed to apply logical rule."
+    );
+}
+
+#[test]
+fn test_eval_timed() {
+    let assignment = Assignment::new().with_rules(true, false);
+
+    let (res, timing) = assignment.eval_timed(&InputSet {
+        a: true,
+        b: true,
+        c: false,
+        d: 2.0,
+        e: 3,
+        f: 4,
+    });
+    let res = res.unwrap();
+    assert_eq!(res.0, SubstitutionToken::M);
+    assert!(approx_eq(res.1, 2.6, 1e-9));
+    assert!(timing.logical.is_some());
+    assert!(timing.arithmetic.is_some());
+
+    let (res, timing) = assignment.eval_timed(&InputSet::default());
+    assert_eq!(
+        res.unwrap_err().to_string(),
+        "Failed to apply logical rule."
+    );
+    assert!(timing.logical.is_some());
+    assert!(timing.arithmetic.is_none());
+
+    let mut assignment = assignment;
+    assignment.set_output_precision(Some(0));
+    let input = InputSet::new(true, true, false, 2.0, 3, 4);
+    let (res, _) = assignment.eval_timed(&input);
+    assert_eq!(res.unwrap(), assignment.eval(&input).unwrap());
+}
+
+#[test]
+fn test_eval_batch() {
+    let assignment = Assignment::new().with_rules(true, false);
+
+    let res = assignment.eval_batch(&[
+        InputSet::new(true, true, false, 2.0, 3, 4),
+        InputSet::default(),
+        InputSet::new(true, true, true, 2.0, 3, 4),
+    ]);
+
+    assert_eq!(res.len(), 3);
+    let first = res[0].as_ref().unwrap();
+    assert_eq!(first.0, SubstitutionToken::M);
+    assert!(approx_eq(first.1, 2.6, 1e-9));
+    assert_eq!(
+        res[1].as_ref().unwrap_err().to_string(),
+        "Failed to apply logical rule."
+    );
+    assert_eq!(res[2].as_ref().unwrap().0, SubstitutionToken::P);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_eval_par() {
+    let assignment = Assignment::new().with_rules(true, true);
+
+    let inputs: Vec<InputSet> = (0..1000)
+        .map(|i| InputSet::new(i % 2 == 0, i % 3 == 0, i % 5 == 0, i as f64 / 7.0, i, -i))
+        .collect();
+
+    let sequential = assignment.eval_batch(&inputs);
+    let parallel = assignment.eval_par(&inputs);
+
+    assert_eq!(sequential.len(), parallel.len());
+    for (s, p) in sequential.iter().zip(parallel.iter()) {
+        match (s, p) {
+            (Ok(s), Ok(p)) => assert_eq!(s, p),
+            (Err(s), Err(p)) => assert_eq!(s.to_string(), p.to_string()),
+            _ => panic!("Sequential and parallel results differ."),
+        }
+    }
+}
+
+#[test]
+fn test_referenced_vars() {
+    assert_eq!(
+        referenced_vars("D + E"),
+        ['D', 'E'].iter().copied().collect()
+    );
+    assert_eq!(referenced_vars("A && A"), ['A'].iter().copied().collect());
+    assert!(referenced_vars("2 * 2").is_empty());
+}
+
+#[test]
+fn test_eval_explain() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A && B || C".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D + E".to_owned())
+        .unwrap();
+
+    let input = InputSet::new(true, true, false, 1.0, 2, 3);
+    let trace = assignment.eval_explain(&input).unwrap();
+    assert_eq!(trace.matched_token, SubstitutionToken::M);
+    assert_eq!(trace.attempted, 1);
+    assert_eq!(trace.logical_source.as_deref(), Some("A && B || C"));
+    assert_eq!(trace.arithmetic_source.as_deref(), Some("D + E"));
+    assert_eq!(trace.value, 3.0);
+    assert_eq!(trace.unused_fields, Some(vec!['f']));
+
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * E * F".to_owned())
+        .unwrap();
+    let trace = assignment.eval_explain(&input).unwrap();
+    assert_eq!(trace.value, 6.0);
+    assert_eq!(trace.unused_fields, Some(vec![]));
+
+    // Function-based rules have no source, so unused fields are unknown.
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    let trace = assignment.eval_explain(&input).unwrap();
+    assert_eq!(trace.arithmetic_source, None);
+    assert_eq!(trace.unused_fields, None);
+
+    assert_eq!(
+        assignment
+            .eval_explain(&InputSet::default())
+            .unwrap_err()
+            .to_string(),
+        "Failed to apply logical rule."
+    );
+
+    // Default token and chained rules are applied the same way as in `eval`.
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::T, "D - E".to_owned())
+        .unwrap();
+    assignment.set_default_token(Some(SubstitutionToken::T));
+    let trace = assignment.eval_explain(&InputSet::default()).unwrap();
+    assert_eq!(trace.matched_token, SubstitutionToken::T);
+    assert_eq!(trace.logical_source, None);
+    assert_eq!(trace.logical_metadata, RuleMetadata::default());
+    assert_eq!(trace.unused_fields, None);
+
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::P, "D * F".to_owned())
+        .unwrap();
+    assignment
+        .add_chained_rule_from_str(SubstitutionToken::P, "M && !C".to_owned())
+        .unwrap();
+    let input = InputSet::new(true, true, false, 2.0, 2, 3);
+    let trace = assignment.eval_explain(&input).unwrap();
+    assert_eq!(trace.matched_token, assignment.eval(&input).unwrap().0);
+    assert_eq!(trace.matched_token, SubstitutionToken::P);
+    assert_eq!(trace.logical_source.as_deref(), Some("A && B || C"));
+    assert_eq!(trace.arithmetic_source.as_deref(), Some("D * F"));
+    assert_eq!(trace.value, 6.0);
+    assert_eq!(trace.unused_fields, Some(vec!['e']));
+}
+
+#[test]
+fn test_eval_optional() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|_, b, _| b))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+
+    let input = InputSet::new(false, true, false, 2.0, 0, 0);
+    assert_eq!(
+        assignment.eval_optional(&input).unwrap_err().to_string(),
+        "Failed to find arithmetic rule for token."
+    );
+
+    assignment.add_terminal_token(SubstitutionToken::T);
+    assert_eq!(
+        assignment.eval_optional(&input).unwrap(),
+        (SubstitutionToken::T, None)
+    );
+    // `eval` still requires arithmetic rule.
+    assert!(assignment.eval(&input).is_err());
+
+    assert_eq!(
+        assignment
+            .eval_optional(&InputSet::new(true, false, false, 2.0, 0, 0))
+            .unwrap(),
+        (SubstitutionToken::M, Some(2.0))
+    );
+
+    assignment.remove_rules();
+    assert!(assignment.terminal_tokens.is_empty());
+}
+
+#[test]
+fn test_rule_limits() {
+    let limits = RuleLimits {
+        max_len: 10,
+        max_depth: 2,
+    };
+    assert!(limits.check("D + (E)").is_ok());
+    assert!(limits.check("((D))").is_ok());
+    assert!(limits.check("(D) + (E)").is_ok());
+    assert_eq!(
+        limits.check("D + E + F + D").unwrap_err().to_string(),
+        "Expression is too long: maximum length is 10."
+    );
+    assert_eq!(
+        limits.check("(((D)))").unwrap_err().to_string(),
+        "Expression is nested too deeply: maximum depth is 2."
+    );
+}
+
+#[test]
+fn test_input_set_eq() {
+    let input = InputSet::new(true, false, true, 1.5, 2, 3);
+    assert_eq!(input, InputSet::from((true, false, true, 1.5, 2, 3)));
+    assert_ne!(input, InputSet::new(true, false, true, 1.5, 2, 4));
+    assert_ne!(input, InputSet::new(false, false, true, 1.5, 2, 3));
+    assert_ne!(input, InputSet::new(true, false, true, -1.5, 2, 3));
+    assert_eq!(
+        format!("{:?}", input),
+        "InputSet { a: true, b: false, c: true, d: 1.5, e: 2, f: 3 }"
+    );
+}
+
+#[test]
+fn test_eval_explain_metadata() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule(Box::new(
+            LogicalRuleStr::new(SubstitutionToken::P, "A".to_owned())
+                .unwrap()
+                .with_name("weekend bonus")
+                .with_description("Applied on weekends."),
+        ))
+        .unwrap();
+    assignment.add_arithmetic_rule(
+        SubstitutionToken::P,
+        Box::new(ArithmeticRuleFn::identity_d().with_name("identity")),
+    );
+
+    let trace = assignment
+        .eval_explain(&InputSet::new(true, false, false, 1.0, 0, 0))
+        .unwrap();
+    assert_eq!(
+        trace.logical_metadata,
+        RuleMetadata {
+            name: Some("weekend bonus".to_owned()),
+            description: Some("Applied on weekends.".to_owned()),
+        }
+    );
+    assert_eq!(trace.arithmetic_metadata.name.as_deref(), Some("identity"));
+    assert_eq!(trace.arithmetic_metadata.description, None);
+}
+
+#[test]
+fn test_arithmetic_tokens() {
+    let mut assignment = Assignment::new();
+    assert_eq!(assignment.arithmetic_tokens().count(), 0);
+
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|d, _, _| d));
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, e, _| e as f64));
+
+    let mut tokens: Vec<_> = assignment.arithmetic_tokens().cloned().collect();
+    tokens.sort();
+    assert_eq!(tokens, vec![SubstitutionToken::M, SubstitutionToken::T]);
+
+    assert!(assignment.has_arithmetic_rule(&SubstitutionToken::M));
+    assert!(!assignment.has_arithmetic_rule(&SubstitutionToken::P));
+}
+
+#[test]
+fn test_validate_coverage() {
+    let assignment = Assignment::new().with_rules(true, true);
+    assert!(assignment.validate_coverage().is_empty());
+
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|_, b, _| b))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|_, _, c| c))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|_, _, c| c))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    assert_eq!(
+        assignment.validate_coverage(),
+        vec![SubstitutionToken::P, SubstitutionToken::T]
+    );
+
+    assignment.add_terminal_token(SubstitutionToken::T);
+    assert_eq!(assignment.validate_coverage(), vec![SubstitutionToken::P]);
+}
+
+#[test]
+fn test_eval_f32() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D / E".to_owned())
+        .unwrap();
+
+    let input = InputSet::new(true, false, false, 1.0, 3, 0);
+    let (token, res64) = assignment.eval(&input).unwrap();
+    let (token32, res32) = assignment.eval_f32(&input).unwrap();
+    assert_eq!(token, token32);
+    assert_eq!(res32, 0.333_333_34_f32);
+    assert_eq!(res32, res64 as f32);
+    assert_ne!(res32 as f64, res64);
+    assert!((res32 as f64 - res64).abs() < 1e-7);
+
+    let input = InputSet::new(true, false, false, 1e300, 1, 0);
+    assert_eq!(assignment.eval_f32(&input).unwrap().1, f32::INFINITY);
+
+    assert!(assignment.eval_f32(&InputSet::default()).is_err());
+
+    // Output precision is applied before conversion.
+    assignment.set_output_precision(Some(2));
+    let input = InputSet::new(true, false, false, 1.0, 3, 0);
+    assert_eq!(assignment.eval_f32(&input).unwrap().1, 0.33_f32);
+}
+
+#[test]
+fn test_eval_with_candidates() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|a, b, _| a && b))
+        .unwrap();
+    assignment
+        .add_logical_rule_with_priority(
+            Box::new(LogicalRuleFn::new(
+                SubstitutionToken::T,
+                Box::new(|_, _, c| c),
+            )),
+            1,
+        )
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|_, b, _| b))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, e, _| e as f64));
+
+    // Later rule wins the tie, other tokens of matching rules are candidates.
+    let input = InputSet::new(true, true, false, 1.5, 2, 0);
+    assert_eq!(
+        assignment.eval_with_candidates(&input).unwrap(),
+        (SubstitutionToken::M, 1.5, vec![SubstitutionToken::P])
+    );
+
+    // Higher priority rule wins over all others, each token is listed once.
+    let input = InputSet::new(true, true, true, 1.5, 2, 0);
+    assert_eq!(
+        assignment.eval_with_candidates(&input).unwrap(),
+        (
+            SubstitutionToken::T,
+            2.0,
+            vec![SubstitutionToken::M, SubstitutionToken::P]
+        )
+    );
+
+    // Single match has no candidates.
+    let input = InputSet::new(false, false, true, 1.5, 2, 0);
+    assert_eq!(
+        assignment.eval_with_candidates(&input).unwrap(),
+        (SubstitutionToken::T, 2.0, vec![])
+    );
+
+    assert!(assignment
+        .eval_with_candidates(&InputSet::default())
+        .is_err());
+}
+
+#[test]
+fn test_eval_with_candidates_stop_on_match() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|_, _, c| c))
+        .unwrap();
+    assignment
+        .add_logical_rule_with_options(
+            Box::new(LogicalRuleFn::new(
+                SubstitutionToken::M,
+                Box::new(|a, _, _| a),
+            )),
+            0,
+            true,
+        )
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|_, b, _| b))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, e, _| e as f64));
+
+    // `P` rule is skipped after `M` rule stops the scan.
+    let input = InputSet::new(true, true, true, 1.5, 2, 0);
+    assert_eq!(
+        assignment.eval_with_candidates(&input).unwrap(),
+        (SubstitutionToken::M, 1.5, vec![SubstitutionToken::T])
+    );
+
+    // Disabled rule is neither the winner nor a candidate.
+    assignment.set_logical_rule_enabled(0, false).unwrap();
+    assert_eq!(
+        assignment.eval_with_candidates(&input).unwrap(),
+        (SubstitutionToken::M, 1.5, vec![])
+    );
+
+    // Default token wins if no rule matches.
+    assignment.set_logical_rule_enabled(0, true).unwrap();
+    assignment.set_default_token(Some(SubstitutionToken::T));
+    assert_eq!(
+        assignment
+            .eval_with_candidates(&InputSet::new(false, false, false, 1.5, 2, 0))
+            .unwrap(),
+        (SubstitutionToken::T, 2.0, vec![])
+    );
+}
+
+#[test]
+fn test_predefined_rule_tables() {
+    // Predefined rules as they were defined with closures.
+    let mut base = Assignment::new();
+    base.add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, b, c| a && b && !c))
+        .unwrap();
+    base.add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|a, b, c| a && b && c))
+        .unwrap();
+    base.add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|a, b, c| !a && b && c))
+        .unwrap();
+    base.add_arithmetic_rule_from_fn(
+        SubstitutionToken::M,
+        Box::new(|d, e, _| d + (d * e as f64 / 10.0)),
+    );
+    base.add_arithmetic_rule_from_fn(
+        SubstitutionToken::P,
+        Box::new(|d, e, f| d + (d * (e - f) as f64 / 25.5)),
+    );
+    base.add_arithmetic_rule_from_fn(
+        SubstitutionToken::T,
+        Box::new(|d, _, f| d - (d * f as f64 / 30.0)),
+    );
+
+    let mut custom = base.clone();
+    custom
+        .add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|a, b, c| a && b && !c))
+        .unwrap();
+    custom
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, b, c| a && !b && c))
+        .unwrap();
+    custom.add_arithmetic_rule_from_fn(
+        SubstitutionToken::P,
+        Box::new(|d, e, _| 2.0 * d + (d * e as f64 / 100.0)),
+    );
+    custom.add_arithmetic_rule_from_fn(
+        SubstitutionToken::M,
+        Box::new(|d, e, f| f as f64 + d + (d * e as f64 / 100.0)),
+    );
+
+    let from_tables = [
+        (base, Assignment::new().with_rules(true, false)),
+        (custom, Assignment::new().with_rules(true, true)),
+    ];
+    for (expected, actual) in &from_tables {
+        for bits in 0..8 {
+            for &d in &[0.0, 1.5, -2.25, 100.0] {
+                for &(e, f) in &[(0, 0), (3, 4), (-7, 2), (1000, -1000)] {
+                    let input = InputSet::new(bits & 1 != 0, bits & 2 != 0, bits & 4 != 0, d, e, f);
+                    let expected = expected.eval(&input);
+                    let actual = actual.eval(&input);
+                    match (expected, actual) {
+                        (Ok(expected), Ok(actual)) => assert_eq!(expected, actual, "{:?}", input),
+                        (Err(_), Err(_)) => {}
+                        (expected, actual) => {
+                            panic!("{:?}: {:?} != {:?}", input, expected.ok(), actual.ok())
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_rules_iterators() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::P, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "B".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::T, "D".to_owned())
+        .unwrap();
+
+    let logical: Vec<_> = assignment
+        .logical_rules()
+        .map(|rule| (rule.token().clone(), rule.source()))
+        .collect();
+    assert_eq!(
+        logical,
+        vec![
+            (SubstitutionToken::P, None),
+            (SubstitutionToken::M, Some("B"))
+        ]
+    );
+
+    let arithmetic: Vec<_> = assignment
+        .arithmetic_rules()
+        .map(|(token, rule)| (token.clone(), rule.source()))
+        .collect();
+    assert_eq!(arithmetic, vec![(SubstitutionToken::T, Some("D"))]);
+}
+
+#[test]
+fn test_division_policy() {
+    let mut assignment = Assignment::new().with_cache(4);
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D / F".to_owned())
+        .unwrap();
+    let input = InputSet::new(true, false, false, 1.0, 0, 0);
+
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, f64::INFINITY)
+    );
+
+    assignment.set_division_policy(DivisionPolicy::SafeZero);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 0.0)
+    );
+    assert_eq!(
+        assignment.eval_int(&input).unwrap(),
+        (SubstitutionToken::M, 0)
+    );
+
+    assignment.set_division_policy(DivisionPolicy::Error);
+    assert_eq!(
+        assignment.eval(&input).err().unwrap().to_string(),
+        "Error dividing 1 / 0"
+    );
+    assert!(assignment.eval_explain(&input).is_err());
+    assert!(assignment.eval_timed(&input).0.is_err());
+}
+
+#[test]
+fn test_selection_strategy() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
+        .unwrap();
+    let rule = assignment
+        .build_arithmetic_rule("D * E".to_owned())
+        .unwrap();
+    assignment.push_arithmetic_rule(SubstitutionToken::M, rule);
+    let input = InputSet::new(true, false, false, 1.5, 3, 0);
+
+    // Last rule wins by default.
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 4.5)
+    );
+    assert_eq!(
+        assignment.eval_explain(&input).unwrap().arithmetic_source,
+        Some("D * E".to_owned())
+    );
+
+    assignment.set_selection_strategy(SelectionStrategy::First);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 1.5)
+    );
+
+    assignment.set_selection_strategy(SelectionStrategy::Sum);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 6.0)
+    );
+    assert_eq!(
+        assignment.eval_explain(&input).unwrap().arithmetic_source,
+        None
+    );
+
+    assignment.set_selection_strategy(SelectionStrategy::Average);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
+
+    assignment.set_selection_strategy(SelectionStrategy::Last);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 4.5)
+    );
+    assert_eq!(assignment.arithmetic_rules().count(), 2);
+
+    // Adding rule replaces all rules of the token.
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "E".to_owned())
+        .unwrap();
+    assert_eq!(assignment.arithmetic_rules().count(), 1);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
+}
+
+#[test]
+fn test_eval_rule_error() {
+    struct FailingRule;
+
+    impl ArithmeticRule for FailingRule {
+        fn apply(&self, _: f64, _: i32, _: i32) -> Result<f64, Box<dyn Error + Send + Sync>> {
+            Err("Rule failed.")?
+        }
+
+        fn clone_box(&self) -> Box<dyn ArithmeticRule> {
+            Box::new(FailingRule)
+        }
+    }
+
+    let mut assignment = Assignment::new().with_cache(4);
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(FailingRule));
+    let input = InputSet::new(true, false, false, 1.0, 2, 3);
+
+    assert_eq!(
+        assignment.eval(&input).err().unwrap().to_string(),
+        "Rule failed."
+    );
+    assert!(assignment.eval_int(&input).is_err());
+    assert!(assignment.eval_f32(&input).is_err());
+    assert!(assignment.eval_explain(&input).is_err());
+}
+
+#[test]
+fn test_constants() {
+    let mut assignment = Assignment::new().with_cache(4);
+    assignment.add_constant("rate", 0.5).unwrap();
+    assignment.add_constant("base", 1.0).unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A && rate != base".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * rate + base / E".to_owned())
+        .unwrap();
+    let input = InputSet::new(true, false, false, 3.0, 2, 0);
+
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 2.0)
+    );
+
+    // Changed constant is picked up by existing rules.
+    assignment.add_constant("rate", 1.0).unwrap();
+    assert_eq!(
+        assignment.eval(&input).err().unwrap().to_string(),
+        "Failed to apply logical rule."
+    );
+    assignment.add_constant("base", 2.0).unwrap();
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 4.0)
+    );
+
+    // Unknown names are still rejected.
+    assert!(assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * tax".to_owned())
+        .is_err());
+    assert!(assignment
+        .build_arithmetic_rule("D * rate".to_owned())
+        .is_ok());
+    assert!(Assignment::new()
+        .build_arithmetic_rule("D * rate".to_owned())
+        .is_err());
+
+    assert!(assignment.add_constant("D", 1.0).is_err());
+    assert!(assignment.add_constant("d", 1.0).is_err());
+    assert!(assignment.add_constant("if", 1.0).is_err());
+    assert!(assignment.add_constant("2x", 1.0).is_err());
+    assert!(assignment.add_constant("tax rate", 1.0).is_err());
+}
+
+#[test]
+fn test_eval_partial() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    let input = PartialInputSet {
+        a: Some(true),
+        b: Some(true),
+        c: Some(false),
+        d: Some(2.0),
+        e: None,
+        f: Some(4),
+    };
+
+    let err = assignment.eval_partial(&input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Field `e` is absent and has no default value."
+    );
+
+    assignment.set_input_default(PartialInputSet {
+        e: Some(3),
+        ..Default::default()
+    });
+    assert_eq!(
+        assignment.eval_partial(&input).unwrap(),
+        assignment
+            .eval(&InputSet::new(true, true, false, 2.0, 3, 4))
+            .unwrap()
+    );
+
+    let err = assignment
+        .eval_partial(&PartialInputSet::default())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Field `a` is absent and has no default value."
+    );
+
+    assignment.set_input_default(InputSet::default().into());
+    assert_eq!(
+        assignment.eval_partial(&input).unwrap(),
+        assignment
+            .eval(&InputSet::new(true, true, false, 2.0, 0, 4))
+            .unwrap()
+    );
+    assert_eq!(
+        assignment
+            .eval_partial(&PartialInputSet {
+                a: Some(true),
+                b: Some(true),
+                ..Default::default()
+            })
+            .unwrap(),
+        assignment
+            .eval(&InputSet::new(true, true, false, 0.0, 0, 0))
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_merge() {
+    let build = |token: SubstitutionToken, rule_str: &str| {
+        let mut assignment = Assignment::new();
+        assignment
+            .add_logical_rule_from_str(token.clone(), "A".to_owned())
+            .unwrap();
+        assignment
+            .add_arithmetic_rule_from_str(token, rule_str.to_owned())
+            .unwrap();
+        assignment
+    };
+    let input = InputSet::new(true, false, false, 2.0, 3, 4);
+
+    // Disjoint tokens: logical rule of `other` wins the tie, both arithmetic rules are kept.
+    for &policy in &[
+        ConflictPolicy::KeepSelf,
+        ConflictPolicy::TakeOther,
+        ConflictPolicy::Error,
+    ] {
+        let mut assignment = build(SubstitutionToken::M, "D");
+        assignment
+            .merge(build(SubstitutionToken::P, "E"), policy)
+            .unwrap();
+        assert_eq!(assignment.logical_rules().count(), 2);
+        assert!(assignment.has_arithmetic_rule(&SubstitutionToken::M));
+        assert_eq!(
+            assignment.eval(&input).unwrap(),
+            (SubstitutionToken::P, 3.0)
+        );
+    }
+
+    // Overlapping tokens.
+    let mut assignment = build(SubstitutionToken::M, "D");
+    assignment
+        .merge(build(SubstitutionToken::M, "E"), ConflictPolicy::KeepSelf)
+        .unwrap();
+    assert_eq!(assignment.logical_rules().count(), 2);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 2.0)
+    );
+
+    let mut assignment = build(SubstitutionToken::M, "D");
+    assignment
+        .merge(build(SubstitutionToken::M, "E"), ConflictPolicy::TakeOther)
+        .unwrap();
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
+
+    let mut assignment = build(SubstitutionToken::M, "D");
+    let err = assignment
+        .merge(build(SubstitutionToken::M, "E"), ConflictPolicy::Error)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Merge conflicts on: M.");
+    assert_eq!(assignment.logical_rules().count(), 1);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 2.0)
+    );
+
+    // Constants referenced by rules of `other` are moved along.
+    let mut other = Assignment::new();
+    other.add_constant("rate", 10.0).unwrap();
+    other
+        .add_logical_rule_from_str(SubstitutionToken::T, "A".to_owned())
+        .unwrap();
+    other
+        .add_arithmetic_rule_from_str(SubstitutionToken::T, "D * rate".to_owned())
+        .unwrap();
+    let mut assignment = build(SubstitutionToken::M, "D");
+    assignment.add_constant("rate", 1.0).unwrap();
+    assert!(assignment
+        .clone()
+        .merge(other.clone(), ConflictPolicy::Error)
+        .is_err());
+    assignment.merge(other, ConflictPolicy::TakeOther).unwrap();
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::T, 20.0)
+    );
+
+    // Multi-output rules, fallback rule, default token and enabled flags are moved too.
+    let mut other = build(SubstitutionToken::P, "E");
+    other
+        .add_multi_rule_from_str(SubstitutionToken::P, "x = D; y = E".to_owned())
+        .unwrap();
+    other.set_fallback_arithmetic_rule(Some(Box::new(
+        ArithmeticRuleStr::new("F".to_owned()).unwrap(),
+    )));
+    other.set_default_token(Some(SubstitutionToken::T));
+    other.set_logical_rule_enabled(0, false).unwrap();
+    let mut assignment = build(SubstitutionToken::M, "D");
+    assignment.set_default_token(Some(SubstitutionToken::M));
+    let err = assignment
+        .clone()
+        .merge(other.clone(), ConflictPolicy::Error)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Merge conflicts on: default token.");
+
+    let mut kept = assignment.clone();
+    kept.merge(other.clone(), ConflictPolicy::KeepSelf).unwrap();
+    assert!(!kept.logical_rule_infos()[1].enabled);
+    // `P` rule is disabled, so `M` rule matches.
+    assert_eq!(kept.eval(&input).unwrap(), (SubstitutionToken::M, 2.0));
+    // Default token `M` of `self` is kept.
+    let none = InputSet::new(false, false, false, 2.0, 3, 4);
+    assert_eq!(kept.eval(&none).unwrap(), (SubstitutionToken::M, 2.0));
+    assert!(kept.fallback_arithmetic_rule().is_some());
+
+    assignment.merge(other, ConflictPolicy::TakeOther).unwrap();
+    // Default token `T` of `other` uses its fallback rule.
+    assert_eq!(assignment.eval(&none).unwrap(), (SubstitutionToken::T, 4.0));
+    assignment.set_logical_rule_enabled(1, true).unwrap();
+    let (token, outputs) = assignment.eval_multi(&input).unwrap();
+    assert_eq!(token, SubstitutionToken::P);
+    assert_eq!(outputs["x"], 2.0);
+    assert_eq!(outputs["y"], 3.0);
+}
+
+#[test]
+fn test_eval_map() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "A && B".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "B".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::P, "D * E".to_owned())
+        .unwrap();
+
+    let res = assignment
+        .eval_map(&InputSet::new(true, true, false, 2.0, 3, 4))
+        .unwrap();
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[&SubstitutionToken::M], 2.0);
+    assert_eq!(res[&SubstitutionToken::P], 6.0);
+
+    let res = assignment
+        .eval_map(&InputSet::new(false, true, false, 2.0, 3, 4))
+        .unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[&SubstitutionToken::M], 2.0);
+
+    assert!(assignment.eval_map(&InputSet::default()).is_err());
+
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::T, "C".to_owned())
+        .unwrap();
+    let err = assignment
+        .eval_map(&InputSet::new(true, false, true, 2.0, 3, 4))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        assignment
+            .eval(&InputSet::new(false, false, true, 2.0, 3, 4))
+            .unwrap_err()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_eval_timeout() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(
+        SubstitutionToken::M,
+        Box::new(|d, e, _| {
+            std::thread::sleep(Duration::from_millis(e as u64));
+            d
+        }),
+    );
+    assignment.set_eval_timeout(Some(Duration::from_millis(200)));
+
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, false, false, 1.5, 0, 0))
+            .unwrap(),
+        (SubstitutionToken::M, 1.5)
+    );
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(false, false, false, 1.5, 0, 0))
+            .unwrap_err()
+            .to_string(),
+        "Failed to apply logical rule."
+    );
+
+    let err = assignment
+        .eval(&InputSet::new(true, false, false, 1.5, 2000, 0))
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AssignmentError>(),
+        Some(AssignmentError::Timeout(timeout)) if *timeout == Duration::from_millis(200)
+    ));
+
+    // Snapshot of rules is taken again after they change.
+    assignment.add_arithmetic_rule_from_fn(
+        SubstitutionToken::M,
+        Box::new(|d, e, _| {
+            std::thread::sleep(Duration::from_millis(e as u64));
+            d * 2.0
+        }),
+    );
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, false, false, 1.5, 0, 0))
+            .unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
+
+    assignment.set_eval_timeout(None);
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, false, false, 1.5, 300, 0))
+            .unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
+}
+
+#[test]
+fn test_max_eval_threads() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|a, _, _| a))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(
+        SubstitutionToken::M,
+        Box::new(|d, e, _| {
+            std::thread::sleep(Duration::from_millis(e as u64));
+            d
+        }),
+    );
+    assignment.set_eval_timeout(Some(Duration::from_millis(50)));
+    assignment.set_max_eval_threads(1);
+
+    // Timed out thread is still running, so the clone sharing the limit can't start another one.
+    let err = assignment
+        .eval(&InputSet::new(true, false, false, 1.5, 500, 0))
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AssignmentError>(),
+        Some(AssignmentError::Timeout(_))
+    ));
+    let clone = assignment.clone();
+    assert_eq!(
+        clone
+            .eval(&InputSet::new(true, false, false, 1.5, 0, 0))
+            .unwrap_err()
+            .to_string(),
+        "Number of running evaluation threads can't exceed 1."
+    );
+
+    std::thread::sleep(Duration::from_millis(700));
+    assert_eq!(
+        clone
+            .eval(&InputSet::new(true, false, false, 1.5, 0, 0))
+            .unwrap(),
+        (SubstitutionToken::M, 1.5)
+    );
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn test_merge_decimal_rules() {
+    let mut other = Assignment::new();
+    other
+        .add_decimal_rule_from_str(SubstitutionToken::M, "D * 3".to_owned())
+        .unwrap();
+    let mut assignment = Assignment::new().with_rules(true, false);
+    assignment
+        .add_decimal_rule_from_str(SubstitutionToken::M, "D * 2".to_owned())
+        .unwrap();
+    let input = InputSet::new(true, true, false, 0.1, 2, 0);
+
+    let err = assignment
+        .clone()
+        .merge(other.clone(), ConflictPolicy::Error)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Merge conflicts on: M decimal rule.");
+
+    assignment.merge(other, ConflictPolicy::TakeOther).unwrap();
+    assert_eq!(
+        assignment.eval_decimal(&input).unwrap(),
+        (SubstitutionToken::M, "0.3".parse().unwrap())
+    );
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn test_eval_decimal() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    let input = InputSet::new(true, true, false, 0.1, 2, 0);
+    assignment
+        .add_decimal_rule_from_str(SubstitutionToken::M, "D + D * E / 10".to_owned())
+        .unwrap();
+
+    let (_, value) = assignment.eval(&input).unwrap();
+    assert_ne!(value, 0.12);
+    assert_eq!(
+        assignment.eval_decimal(&input).unwrap(),
+        (SubstitutionToken::M, "0.12".parse().unwrap())
+    );
+
+    assert_eq!(
+        assignment
+            .eval_decimal(&InputSet::new(true, true, true, 0.1, 2, 0))
+            .unwrap_err()
+            .to_string(),
+        "Failed to find decimal rule for token."
+    );
+    assert!(assignment
+        .add_decimal_rule_from_str(SubstitutionToken::P, "D +".to_owned())
+        .is_err());
+
+    // Constants and division policy of the assignment are used, and the add is audited.
+    assignment.set_audit_log_enabled(true);
+    assignment.add_constant("rate", 0.1).unwrap();
+    assignment
+        .add_decimal_rule_from_str(SubstitutionToken::M, "D * rate / F".to_owned())
+        .unwrap();
+    assert_eq!(assignment.audit_log().len(), 1);
+    assert_eq!(
+        assignment.audit_log()[0].operation,
+        AuditOperation::AddDecimal
+    );
+    assert_eq!(
+        assignment.audit_log()[0].source.as_deref(),
+        Some("D * rate / F")
+    );
+    assert!(assignment.eval_decimal(&input).is_err());
+    assignment.set_division_policy(DivisionPolicy::SafeZero);
+    assert_eq!(
+        assignment.eval_decimal(&input).unwrap(),
+        (SubstitutionToken::M, Decimal::ZERO)
+    );
+    assert_eq!(
+        assignment
+            .eval_decimal(&InputSet::new(true, true, false, 0.2, 2, 4))
+            .unwrap(),
+        (SubstitutionToken::M, "0.005".parse().unwrap())
+    );
+
+    assignment.remove_rules();
+    assert!(assignment.eval_decimal(&input).is_err());
+}
+
+#[test]
+fn test_stop_on_match() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_with_options(
+            Box::new(LogicalRuleStr::new(SubstitutionToken::M, "A".to_owned()).unwrap()),
+            0,
+            true,
+        )
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "A || B".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::P, "E".to_owned())
+        .unwrap();
+
+    // Stop-on-match rule isn't overridden by the later matching rule.
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, true, false, 1.5, 2, 0))
+            .unwrap(),
+        (SubstitutionToken::M, 1.5)
+    );
+    // Scan continues if it doesn't match.
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(false, true, false, 1.5, 2, 0))
+            .unwrap(),
+        (SubstitutionToken::P, 2.0)
+    );
+
+    // Without the flag the last matching rule wins.
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "A || B".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::P, "E".to_owned())
+        .unwrap();
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, true, false, 1.5, 2, 0))
+            .unwrap(),
+        (SubstitutionToken::P, 2.0)
+    );
+}
+
+#[test]
+fn test_validation_error_from() {
+    let message = evalexpr::eval("1 +").unwrap_err().to_string();
+    let err = ValidationError::from(evalexpr::eval("1 +").unwrap_err());
+    assert_eq!(err.to_string(), message);
+    assert_eq!(err.evalexpr_error().unwrap().to_string(), message);
+    assert!(err.source().is_some());
+
+    let pattern = format!("({}", "A");
+    let e = Regex::new(&pattern).unwrap_err();
+    let err = ValidationError::from(e.clone());
+    assert_eq!(err.to_string(), e.to_string());
+    assert!(err.evalexpr_error().is_none());
+
+    let check = || -> Result<(), ValidationError> {
+        Regex::new(&pattern)?;
+        Ok(())
+    };
+    assert!(check().is_err());
+}
+
+#[test]
+fn test_assignment_error_from() {
+    let e = evalexpr::eval("1 +").unwrap_err();
+    let err = AssignmentError::from(e.clone());
+    assert_eq!(err.to_string(), e.to_string());
+    assert!(matches!(&err, AssignmentError::Evalexpr(inner) if *inner == e));
+    assert!(err.source().is_some());
+
+    let eval = || -> Result<f64, AssignmentError> { Ok(evalexpr::eval_number("D * 2")?) };
+    assert!(matches!(eval(), Err(AssignmentError::Evalexpr(_))));
+
+    let pattern = format!("({}", "A");
+    let regex = || -> Result<Regex, AssignmentError> { Ok(Regex::new(&pattern)?) };
+    assert!(matches!(regex(), Err(AssignmentError::Regex(_))));
+
+    let err = AssignmentError::Timeout(Duration::from_millis(100));
+    assert_eq!(err.to_string(), "Evaluation timed out after 100ms.");
+    assert!(err.source().is_none());
+}
+
+#[test]
+fn test_profiles() {
+    let mut pricing_2023 = Assignment::new().with_rules(true, false);
+    pricing_2023
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * 2".to_owned())
+        .unwrap();
+    let mut pricing_2024 = Assignment::new().with_rules(true, false);
+    pricing_2024
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * 3".to_owned())
+        .unwrap();
 
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 1);
+    let mut assignment = Assignment::new().with_rules(true, false);
+    assignment.add_profile("2024", pricing_2024);
+    assignment.add_profile("2023", pricing_2023);
+    assert_eq!(assignment.list_profiles(), vec!["2023", "2024"]);
+
+    let input = InputSet::new(true, true, false, 2.0, 3, 4);
+    let res = assignment.eval(&input).unwrap();
+    assert_eq!(res.0, SubstitutionToken::M);
+    assert!(approx_eq(res.1, 2.6, 1e-9));
+    assert_eq!(
+        assignment.eval_profile("2023", &input).unwrap(),
+        (SubstitutionToken::M, 4.0)
+    );
+    assert_eq!(
+        assignment.eval_profile("2024", &input).unwrap(),
+        (SubstitutionToken::M, 6.0)
+    );
+    assert_eq!(
+        assignment
+            .eval_profile("2025", &input)
+            .unwrap_err()
+            .to_string(),
+        "Unknown profile: 2025."
+    );
+
+    assignment.remove_rules();
+    assert!(assignment.eval(&input).is_err());
+    assert!(assignment.eval_profile("2023", &input).is_ok());
+
+    assert!(assignment.remove_profile("2023").is_some());
+    assert!(assignment.remove_profile("2023").is_none());
+    assert_eq!(assignment.list_profiles(), vec!["2024"]);
+    assert!(assignment.clone().eval_profile("2024", &input).is_ok());
+}
+
+#[test]
+fn test_max_logical_rules() {
+    let rule = || {
+        Box::new(LogicalRuleFn::new(
+            SubstitutionToken::M,
+            Box::new(|a, _, _| a),
+        ))
+    };
+    let mut assignment = Assignment::new();
+    assignment.set_max_logical_rules(Some(2));
+
+    assignment.add_logical_rule(rule()).unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "A && B".to_owned())
+        .unwrap();
+    let err = assignment.add_logical_rule(rule()).unwrap_err();
+    assert_eq!(err.to_string(), "Number of logical rules can't exceed 2.");
+    assert!(assignment
+        .add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|_, _, c| c))
+        .is_err());
+    assert!(assignment
+        .add_rule_from_str(SubstitutionToken::T, "C".to_owned())
+        .is_err());
+    assert_eq!(assignment.logical_rules().count(), 2);
+
+    // Arithmetic rules aren't limited.
+    assignment
+        .add_rule_from_str(SubstitutionToken::M, "D".to_owned())
+        .unwrap();
+
+    // Failed replace and merge keep existing rules.
+    assert!(assignment
+        .replace_rules(vec![rule(), rule(), rule()], Vec::new())
+        .is_err());
+    assert_eq!(assignment.logical_rules().count(), 2);
+    assert!(assignment
+        .merge(
+            Assignment::new().with_rules(true, false),
+            ConflictPolicy::TakeOther
+        )
+        .is_err());
+    assert_eq!(assignment.logical_rules().count(), 2);
+
+    // Removal frees slots.
+    assignment.remove_rules();
+    assignment.add_logical_rule(rule()).unwrap();
+    assignment.add_logical_rule(rule()).unwrap();
+    assert!(assignment.add_logical_rule(rule()).is_err());
+
+    // Lowering limit keeps existing rules.
+    assignment.set_max_logical_rules(Some(1));
+    assert_eq!(assignment.logical_rules().count(), 2);
+    assert!(assignment.add_logical_rule(rule()).is_err());
+
+    assignment.set_max_logical_rules(None);
+    assignment.add_logical_rule(rule()).unwrap();
+    assert_eq!(assignment.logical_rules().count(), 3);
+}
+
+#[test]
+fn test_eval_trace_serialize() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "B".to_owned())
+        .unwrap();
+    assignment.add_arithmetic_rule(
+        SubstitutionToken::M,
+        Box::new(
+            ArithmeticRuleStr::new("D + E".to_owned())
+                .unwrap()
+                .with_name("sum"),
+        ),
+    );
+
+    let trace = assignment
+        .eval_explain(&InputSet::new(true, false, false, 1.5, 2, 3))
+        .unwrap();
+    let json = serde_json::to_value(&trace).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "matched_token": "M",
+            "attempted": 2,
+            "logical_source": "A",
+            "arithmetic_source": "D + E",
+            "logical_metadata": { "name": null, "description": null },
+            "arithmetic_metadata": { "name": "sum", "description": null },
+            "value": 3.5,
+            "unused_fields": ["b", "c", "f"],
+        })
+    );
+
+    let round_trip: EvalTrace = serde_json::from_value(json).unwrap();
+    assert_eq!(round_trip, trace);
+    assert!(serde_json::from_str::<EvalTrace>(r#"{"matched_token": "M"}"#).is_err());
+}
+
+#[test]
+fn test_matches_any() {
+    let mut assignment = Assignment::new();
+    assert!(!assignment.matches_any(&InputSet::default()));
+
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A && B".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "C".to_owned())
+        .unwrap();
+    assert!(assignment.matches_any(&InputSet::new(true, true, false, 0.0, 0, 0)));
+    assert!(assignment.matches_any(&InputSet::new(false, false, true, 0.0, 0, 0)));
+    assert!(!assignment.matches_any(&InputSet::new(true, false, false, 0.0, 0, 0)));
+
+    // Missing arithmetic rule doesn't matter.
+    assert!(assignment
+        .eval(&InputSet::new(true, true, false, 0.0, 0, 0))
+        .is_err());
+}
+
+#[test]
+fn test_output_precision() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|_, _, _| true))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    let input = |d| InputSet::new(false, false, false, d, 0, 0);
+
+    assert_eq!(assignment.eval(&input(2.6666)).unwrap().1, 2.6666);
+
+    assignment.set_output_precision(Some(2));
+    assert_eq!(assignment.eval(&input(2.6666)).unwrap().1, 2.67);
+    assert_eq!(assignment.eval(&input(0.125)).unwrap().1, 0.12);
+    assert_eq!(assignment.eval(&input(-2.6666)).unwrap().1, -2.67);
+    let batch = assignment.eval_batch(&[input(2.6666), input(1.0 / 3.0)]);
+    assert_eq!(batch[0].as_ref().unwrap().1, 2.67);
+    assert_eq!(batch[1].as_ref().unwrap().1, 0.33);
+
+    assignment.set_output_precision(Some(0));
+    assert_eq!(assignment.eval(&input(2.6666)).unwrap().1, 3.0);
+    assert_eq!(assignment.eval(&input(2.5)).unwrap().1, 2.0);
+    assert_eq!(assignment.eval(&input(3.5)).unwrap().1, 4.0);
+
+    assignment.set_output_precision(None);
+    assert_eq!(assignment.eval(&input(2.6666)).unwrap().1, 2.6666);
+}
+
+#[test]
+fn test_output_precision_large() {
+    let mut assignment = Assignment::new();
+    for precision in [MAX_OUTPUT_PRECISION + 1, 400, u32::MAX].iter() {
+        assignment.set_output_precision(Some(*precision));
+        assert_eq!(assignment.round_output(2.6666), 2.6666);
+        assert_eq!(assignment.round_output(-1.0 / 3.0), -1.0 / 3.0);
+    }
+
+    assignment.set_output_precision(Some(MAX_OUTPUT_PRECISION));
+    assert_eq!(assignment.round_output(f64::MAX), f64::MAX);
+    assert_eq!(assignment.round_output(0.5), 0.5);
+}
+
+#[test]
+fn test_eval_raw() {
+    let mut assignment = Assignment::new().with_cache(4);
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::M, Box::new(|_, _, _| true))
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    let input = InputSet::new(false, false, false, 2.6666, 0, 0);
+
+    assert_eq!(assignment.eval_raw(&input).unwrap().1, 2.6666);
+    assignment.set_output_precision(Some(2));
+    for _ in 0..2 {
+        assert_eq!(assignment.eval(&input).unwrap().1, 2.67);
+        assert_eq!(assignment.eval_raw(&input).unwrap().1, 2.6666);
+    }
+    assert_eq!(assignment.round_output(0.125), 0.12);
+}
+
+#[test]
+fn test_missing_arithmetic_rule_token() {
+    let mut assignment = Assignment::new();
+    let rule = LogicalRuleFn::new(SubstitutionToken::P, Box::new(|a, _, _| a));
+    assignment.add_logical_rule(Box::new(rule)).unwrap();
+
+    let args = InputSet::new(true, false, false, 1.0, 2, 3);
+    let err = assignment.eval(&args).unwrap_err();
+    assert_eq!(err.to_string(), "Failed to find arithmetic rule for token.");
+    let err = err.downcast_ref::<MissingArithmeticRuleError>().unwrap();
+    assert_eq!(err.token(), &SubstitutionToken::P);
+
+    // Token is kept when evaluation runs on another thread.
+    assignment.set_eval_timeout(Some(Duration::from_secs(10)));
+    let err = assignment.eval(&args).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<MissingArithmeticRuleError>()
+            .unwrap()
+            .token(),
+        &SubstitutionToken::P
+    );
+}
+
+#[test]
+fn test_eval_grid() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    let mut grid = EvalGrid {
+        a: true,
+        b: true,
+        c: false,
+        d_range: [0.0, 0.3, 0.1],
+        e_range: [1, 5, 2],
+        f_range: [10, 10, 1],
+    };
+    assert_eq!(grid.size().unwrap(), 12);
+
+    let res = assignment.eval_grid(&grid).unwrap();
+    assert_eq!(res.len(), 12);
+    assert_eq!(res[0].0, InputSet::new(true, true, false, 0.0, 1, 10));
+    assert_eq!(res[1].0, InputSet::new(true, true, false, 0.0, 3, 10));
+    assert_eq!(res[11].0.e, 5);
+    assert!((res[11].0.d - 0.3).abs() < 1e-12);
+    for (args, res) in &res {
+        assert_eq!(res.as_ref().unwrap(), &assignment.eval(args).unwrap());
+    }
+
+    // Empty range gives empty grid.
+    grid.e_range = [5, 1, 1];
+    assert!(assignment.eval_grid(&grid).unwrap().is_empty());
+
+    grid.e_range = [1, 5, 0];
+    assert!(assignment.eval_grid(&grid).is_err());
+    grid.e_range = [1, 5, 2];
+    grid.d_range = [0.0, 1.0, -0.1];
+    assert!(assignment.eval_grid(&grid).is_err());
+
+    grid.d_range = [0.0, 1e9, 1.0];
+    assert_eq!(
+        assignment.eval_grid(&grid).unwrap_err().to_string(),
+        format!(
+            "Grid of 3000000003 inputs exceeds the limit of {}.",
+            DEFAULT_MAX_GRID_SIZE
+        )
+    );
+    grid.d_range = [0.0, 0.3, 0.1];
+    assignment.set_max_grid_size(11);
+    assert!(assignment.eval_grid(&grid).is_err());
+}
+
+#[test]
+fn test_approx_eq() {
+    assert!(approx_eq(2.6, 2.6, 0.0));
+    assert!(approx_eq(0.1 + 0.2, 0.3, 1e-9));
+    assert!(!approx_eq(0.1 + 0.2, 0.3, 0.0));
+    assert!(approx_eq(-1.0, -1.0 + 1e-12, 1e-9));
+    assert!(!approx_eq(1.0, 1.1, 1e-9));
+    assert!(!approx_eq(1.0, -1.0, 1.0));
+    assert!(approx_eq(f64::INFINITY, f64::INFINITY, 1e-9));
+    assert!(!approx_eq(f64::INFINITY, f64::NEG_INFINITY, 1e-9));
+    assert!(!approx_eq(f64::NAN, f64::NAN, 1e-9));
+}
+
+#[test]
+fn test_chained_rules() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    let input = InputSet::new(true, true, false, 2.0, 3, 4);
+    assert_eq!(assignment.eval(&input).unwrap().0, SubstitutionToken::M);
+
+    // Route M to T when A holds, and T to P.
+    assignment
+        .add_chained_rule_from_str(SubstitutionToken::T, "M && A".to_owned())
+        .unwrap();
+    assignment
+        .add_chained_rule_from_str(SubstitutionToken::P, "T".to_owned())
+        .unwrap();
+    assert_eq!(assignment.chained_rules().len(), 2);
+
+    // Chained rules see only the first pass token, so `T` rule doesn't fire after `M && A`.
+    let res = assignment.eval(&input).unwrap();
+    assert_eq!(res.0, SubstitutionToken::T);
+    assert!(approx_eq(res.1, 2.0 - 2.0 * 4.0 / 30.0, 1e-9));
+
+    // No chained rule matches, first pass token is kept.
+    let input = InputSet::new(true, true, true, 2.0, 3, 4);
+    assert_eq!(assignment.eval(&input).unwrap().0, SubstitutionToken::P);
+
+    let input = InputSet::new(false, true, true, 2.0, 3, 4);
+    let res = assignment.eval(&input).unwrap();
+    assert_eq!(res.0, SubstitutionToken::P);
+    assert!(approx_eq(res.1, 2.0 + 2.0 * (3.0 - 4.0) / 25.5, 1e-9));
+
+    // Chained rules don't help when the first pass finds nothing.
+    assert!(assignment.eval(&InputSet::default()).is_err());
+
+    assert!(assignment
+        .add_chained_rule_from_str(SubstitutionToken::M, "M && D".to_owned())
+        .is_err());
+    assert!(assignment.add_constant("M".to_owned(), 1.0).is_err());
+
+    assignment.remove_rules();
+    assert!(assignment.chained_rules().is_empty());
+}
+
+#[test]
+fn test_boxed_rules_source() {
+    let mut assignment = Assignment::new();
+    let logical = LogicalRuleStr::new(SubstitutionToken::P, "a && !b".to_owned()).unwrap();
+    assignment.add_logical_rule(Box::new(logical)).unwrap();
+    let arithmetic = ArithmeticRuleStr::new("d * (e - f)".to_owned()).unwrap();
+    assignment.add_arithmetic_rule(SubstitutionToken::P, Box::new(arithmetic));
+
+    let logical: Vec<_> = assignment.logical_rules().map(|r| r.source()).collect();
+    assert_eq!(logical, vec![Some("A && !B")]);
+    let arithmetic: Vec<_> = assignment
+        .arithmetic_rules()
+        .map(|(_, r)| r.source())
+        .collect();
+    assert_eq!(arithmetic, vec![Some("D * (E - F)")]);
+
+    let trace = assignment
+        .eval_explain(&InputSet::new(true, false, false, 2.0, 3, 1))
+        .unwrap();
+    assert_eq!(trace.logical_source.as_deref(), Some("A && !B"));
+    assert_eq!(trace.arithmetic_source.as_deref(), Some("D * (E - F)"));
+}
+
+#[test]
+fn test_fingerprint() {
+    let mut first = Assignment::new();
+    first
+        .add_logical_rule_from_str(SubstitutionToken::M, "A && B".to_owned())
+        .unwrap();
+    first
+        .add_logical_rule_from_str(SubstitutionToken::P, "C".to_owned())
+        .unwrap();
+    first
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D + E".to_owned())
+        .unwrap();
+
+    let mut second = Assignment::new();
+    second
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "d + e".to_owned())
+        .unwrap();
+    second
+        .add_logical_rule_from_str(SubstitutionToken::P, "C".to_owned())
+        .unwrap();
+    second
+        .add_logical_rule_with_priority(
+            Box::new(LogicalRuleStr::new(SubstitutionToken::M, "A && B".to_owned()).unwrap()),
+            5,
+        )
+        .unwrap();
+    assert_eq!(first.fingerprint(), second.fingerprint());
+    assert_ne!(first.fingerprint(), Assignment::new().fingerprint());
+
+    // Known value keeps the fingerprint stable across releases.
+    assert_eq!(Assignment::new().fingerprint(), 0xcbf2_9ce4_8422_2325);
+
+    second
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * E".to_owned())
+        .unwrap();
+    assert_ne!(first.fingerprint(), second.fingerprint());
+
+    // Kind is part of the fingerprint.
+    let mut logical = Assignment::new();
+    logical
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    let mut function = logical.clone();
+    function.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
+    assert_ne!(logical.fingerprint(), function.fingerprint());
+    let mut other_function = logical.clone();
+    other_function.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|_, _, _| 0.0));
+    assert_eq!(function.fingerprint(), other_function.fingerprint());
+}
+
+#[test]
+fn test_rule_builder_chain() {
+    let assignment = Assignment::new()
+        .with_cache(4)
+        .with_logical_rule_str(SubstitutionToken::M, "A && B")
+        .unwrap()
+        .with_logical_rule_fn(SubstitutionToken::P, Box::new(|_, _, c| c))
+        .unwrap()
+        .with_arithmetic_rule_str(SubstitutionToken::M, "D + E".to_owned())
+        .unwrap()
+        .with_arithmetic_rule_fn(SubstitutionToken::P, Box::new(|d, _, f| d * f as f64));
+
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, true, false, 1.0, 2, 3))
+            .unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(false, false, true, 2.0, 2, 3))
+            .unwrap(),
+        (SubstitutionToken::P, 6.0)
+    );
+
+    // Validation errors surface in the chain.
+    assert!(Assignment::new()
+        .with_logical_rule_str(SubstitutionToken::M, "A + B")
+        .is_err());
+    assert!(Assignment::new()
+        .with_logical_rule_str(SubstitutionToken::M, "A")
+        .and_then(|a| a.with_arithmetic_rule_str(SubstitutionToken::M, "D ** E"))
+        .is_err());
+}
+
+#[test]
+fn test_input_bounds() {
+    let mut assignment = Assignment::new().with_rules(true, false).with_cache(4);
+    let input = InputSet::new(true, true, false, 2.0, -1, 4);
+    assert!(assignment.eval(&input).is_ok());
+
+    assignment.set_input_bounds(InputBounds {
+        d: Some((0.0, 100.0)),
+        e: Some((0, i32::MAX)),
+        f: None,
+    });
+    let err = assignment.eval(&input).unwrap_err();
+    assert_eq!(err.to_string(), "Field `e` is out of range: -1.");
+    assert!(matches!(
+        err.downcast_ref::<AssignmentError>(),
+        Some(AssignmentError::InputOutOfRange { field: 'e', value }) if *value == -1.0
+    ));
+
+    assert!(assignment
+        .eval(&InputSet::new(true, true, false, 100.0, 0, -4))
+        .is_ok());
+    for d in [-0.5, 100.5, f64::NAN, f64::INFINITY] {
+        let err = assignment
+            .eval(&InputSet::new(true, true, false, d, 1, 4))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AssignmentError>(),
+            Some(AssignmentError::InputOutOfRange { field: 'd', .. })
+        ));
+    }
+    assert!(assignment.eval_map(&input).is_err());
+    assert!(assignment.eval_explain(&input).is_err());
+
+    assignment.set_input_bounds(InputBounds::default());
+    assert!(assignment.eval(&input).is_ok());
+}
+
+#[test]
+fn test_eval_batch_csv() {
+    let mut assignment = Assignment::new()
+        .with_logical_rule_str(SubstitutionToken::M, "A")
+        .unwrap()
+        .with_arithmetic_rule_str(SubstitutionToken::M, "D + E")
+        .unwrap();
+    assignment.set_input_bounds(InputBounds {
+        d: Some((0.0, 10.0)),
+        ..InputBounds::default()
+    });
+
+    let csv = assignment.eval_batch_csv(&[
+        InputSet::new(true, false, true, 1.5, 2, 0),
+        InputSet::new(false, false, false, 1.0, 0, 0),
+        InputSet::new(true, false, false, -1.0, 0, 0),
+    ]);
+    assert_eq!(
+        csv,
+        "a,b,c,d,e,f,token,value,error\n\
+         true,false,true,1.5,2,0,M,3.5,\n\
+         false,false,false,1,0,0,,,Failed to apply logical rule.\n\
+         true,false,false,-1,0,0,,,Field `d` is out of range: -1.\n"
+    );
+
+    assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+}
+
+#[test]
+fn test_reachable_tokens() {
+    let all: HashSet<_> = [
+        SubstitutionToken::M,
+        SubstitutionToken::P,
+        SubstitutionToken::T,
+    ]
+    .iter()
+    .cloned()
+    .collect();
+    let assignment = Assignment::new().with_rules(true, false);
+    assert_eq!(assignment.reachable_tokens(), all);
+    assert!(Assignment::new().reachable_tokens().is_empty());
+
+    let mut assignment = Assignment::new()
+        .with_logical_rule_str(SubstitutionToken::M, "A")
+        .unwrap()
+        .with_logical_rule_str(SubstitutionToken::P, "A && B")
+        .unwrap();
+    let reachable = |assignment: &Assignment| {
+        let mut tokens: Vec<_> = assignment.reachable_tokens().into_iter().collect();
+        tokens.sort();
+        tokens
+    };
+    assert_eq!(
+        reachable(&assignment),
+        vec![SubstitutionToken::M, SubstitutionToken::P]
+    );
+
+    // `P` is shadowed when `M` stops matching.
+    let mut shadowing = Assignment::new();
+    shadowing
+        .add_logical_rule_with_options(
+            Box::new(LogicalRuleStr::new(SubstitutionToken::M, "A".to_owned()).unwrap()),
+            0,
+            true,
+        )
+        .unwrap();
+    shadowing
+        .add_logical_rule_from_str(SubstitutionToken::P, "A && B".to_owned())
+        .unwrap();
+    assert_eq!(reachable(&shadowing), vec![SubstitutionToken::M]);
+    assert_eq!(
+        shadowing.validate_coverage(),
+        vec![SubstitutionToken::M, SubstitutionToken::P]
+    );
+
+    // Chained rules and input bounds are taken into account and ignored respectively.
+    assignment
+        .add_chained_rule_from_str(SubstitutionToken::T, "P".to_owned())
+        .unwrap();
+    assignment.set_input_bounds(InputBounds {
+        e: Some((1, 2)),
+        ..InputBounds::default()
+    });
+    assert_eq!(
+        reachable(&assignment),
+        vec![SubstitutionToken::M, SubstitutionToken::T]
+    );
+}
+
+#[test]
+fn test_default_token() {
+    let input = InputSet::new(false, false, false, 2.0, 4, 4);
+    let mut assignment = Assignment::new().with_rules(true, false);
+    assert_eq!(
+        assignment.eval(&input).unwrap_err().to_string(),
+        "Failed to apply logical rule."
+    );
+    assert_eq!(assignment.default_token(), None);
+
+    assignment.set_default_token(Some(SubstitutionToken::P));
+    assert_eq!(assignment.default_token(), Some(&SubstitutionToken::P));
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::P, 2.0)
+    );
+    // Matching rules still win over default token.
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, true, false, 2.0, 5, 4))
+            .unwrap()
+            .0,
+        SubstitutionToken::M
+    );
+    assert!(assignment.validate_coverage().is_empty());
+    // Explanation uses default token the same way.
+    assert_eq!(
+        assignment.eval_explain(&input).unwrap().matched_token,
+        SubstitutionToken::P
+    );
+
+    let mut assignment = Assignment::new()
+        .with_logical_rule_str(SubstitutionToken::M, "A")
+        .unwrap()
+        .with_arithmetic_rule_str(SubstitutionToken::M, "D")
+        .unwrap();
+    assignment.set_default_token(Some(SubstitutionToken::T));
+    assert!(assignment
+        .eval(&input)
+        .unwrap_err()
+        .is::<MissingArithmeticRuleError>());
+    assert_eq!(assignment.validate_coverage(), vec![SubstitutionToken::T]);
+
+    assignment.set_default_token(None);
+    assert_eq!(
+        assignment.eval(&input).unwrap_err().to_string(),
+        "Failed to apply logical rule."
+    );
+}
+
+#[test]
+fn test_audit_log() {
+    let mut assignment = Assignment::new();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A".to_owned())
+        .unwrap();
+    assert!(assignment.audit_log().is_empty());
+
+    assignment.set_audit_log_enabled(true);
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "a && b # note".to_owned())
+        .unwrap();
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::P, Box::new(|d, _, _| d));
+    assignment
+        .add_chained_rule_from_str(SubstitutionToken::T, "P && C".to_owned())
+        .unwrap();
+    assignment.remove_rules();
+    assignment
+        .replace_rules(
+            vec![assignment
+                .build_logical_rule(SubstitutionToken::M, "B".to_owned())
+                .unwrap()],
+            vec![(
+                SubstitutionToken::M,
+                assignment
+                    .build_arithmetic_rule("D * 2".to_owned())
+                    .unwrap(),
+            )],
+        )
+        .unwrap();
+    assignment
+        .merge(Assignment::new(), ConflictPolicy::Error)
+        .unwrap();
+    // Rejected rules are not recorded.
+    assert!(assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D +".to_owned())
+        .is_err());
+
+    let entries: Vec<_> = assignment
+        .audit_log()
+        .iter()
+        .map(|e| (e.operation, e.token.clone(), e.source.as_deref()))
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            (
+                AuditOperation::AddLogical,
+                Some(SubstitutionToken::P),
+                Some("A && B")
+            ),
+            (
+                AuditOperation::AddArithmetic,
+                Some(SubstitutionToken::P),
+                None
+            ),
+            (
+                AuditOperation::AddChained,
+                Some(SubstitutionToken::T),
+                Some("P && C")
+            ),
+            (AuditOperation::Remove, None, None),
+            (AuditOperation::Replace, None, None),
+            (
+                AuditOperation::AddLogical,
+                Some(SubstitutionToken::M),
+                Some("B")
+            ),
+            (
+                AuditOperation::AddArithmetic,
+                Some(SubstitutionToken::M),
+                Some("D * 2")
+            ),
+            (AuditOperation::Merge, None, None),
+        ]
+    );
+    let log = assignment.audit_log();
+    assert!(log
+        .windows(2)
+        .all(|w| w[0].timestamp_ms <= w[1].timestamp_ms));
+    assert!(log[0].timestamp_ms > 0);
+
+    assignment.set_audit_log_enabled(false);
+    assert!(assignment.audit_log().is_empty());
+}
+
+#[test]
+fn test_eval_ranked() {
+    let input = InputSet::new(true, true, false, 2.0, 4, 4);
+    let mut assignment = Assignment::new()
+        .with_arithmetic_rule_str(SubstitutionToken::M, "D")
+        .unwrap()
+        .with_arithmetic_rule_str(SubstitutionToken::P, "D * 2")
+        .unwrap()
+        .with_arithmetic_rule_str(SubstitutionToken::T, "D * 3")
+        .unwrap();
+    assert_eq!(
+        assignment.eval_ranked(&input).unwrap_err().to_string(),
+        "Failed to apply logical rule."
+    );
+
+    // Three overlapping rules: `M` has the highest priority, `T` wins the tie with `P` as the last one.
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "A".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_with_priority(
+            assignment
+                .build_logical_rule(SubstitutionToken::M, "A && B".to_owned())
+                .unwrap(),
+            1,
+        )
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::T, "B".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "C".to_owned())
+        .unwrap();
+    let ranked = assignment.eval_ranked(&input).unwrap();
+    assert_eq!(
+        ranked,
+        vec![
+            (SubstitutionToken::M, 2.0),
+            (SubstitutionToken::T, 6.0),
+            (SubstitutionToken::P, 4.0),
+        ]
+    );
+    assert_eq!(ranked[0], assignment.eval(&input).unwrap());
+
+    // Repeated token keeps its best rank.
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "A || C".to_owned())
+        .unwrap();
+    let tokens: Vec<_> = assignment
+        .eval_ranked(&input)
+        .unwrap()
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            SubstitutionToken::M,
+            SubstitutionToken::P,
+            SubstitutionToken::T
+        ]
+    );
+
+    assignment.set_default_token(Some(SubstitutionToken::T));
+    assert_eq!(
+        assignment
+            .eval_ranked(&InputSet::new(false, false, false, 2.0, 4, 4))
+            .unwrap(),
+        vec![(SubstitutionToken::T, 6.0)]
+    );
+
+    // Chained rule replaces the winner, matched token of logical rules becomes the runner-up.
+    assignment
+        .add_chained_rule_from_str(SubstitutionToken::P, "M && !C".to_owned())
+        .unwrap();
+    let ranked = assignment.eval_ranked(&input).unwrap();
+    assert_eq!(ranked[0], assignment.eval(&input).unwrap());
     assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::M].apply(2.0, 0, 0),
-        2.0
+        ranked,
+        vec![
+            (SubstitutionToken::P, 4.0),
+            (SubstitutionToken::M, 2.0),
+            (SubstitutionToken::T, 6.0),
+        ]
     );
+}
 
-    let rule1 = ArithmeticRuleStr::new("E".to_owned()).unwrap();
-    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule1));
-
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 2);
+#[test]
+fn test_eval_multi() {
+    let input = InputSet::new(true, true, false, 2.0, 5, 4);
+    let mut assignment = Assignment::new().with_rules(true, false);
     assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::T].apply(0.0, 2, 0),
-        2.0
+        assignment.eval_multi(&input).unwrap_err().to_string(),
+        "Failed to find multi-output rule for token."
     );
 
-    let rule2 = ArithmeticRuleFn::new(Box::new(|_, _, f| f as f64));
-    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule2));
+    assignment.add_constant("rate", 0.25).unwrap();
+    assignment
+        .add_multi_rule_from_str(
+            SubstitutionToken::M,
+            "base = D * E; tax = base * rate # two outputs".to_owned(),
+        )
+        .unwrap();
+    let (token, res) = assignment.eval_multi(&input).unwrap();
+    assert_eq!(token, SubstitutionToken::M);
+    assert_eq!(res.len(), 2);
+    assert_eq!(res["base"], 10.0);
+    assert_eq!(res["tax"], 2.5);
 
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 2);
+    assignment.set_output_precision(Some(0));
+    assert_eq!(assignment.eval_multi(&input).unwrap().1["tax"], 2.0);
+
+    assert!(assignment
+        .add_multi_rule_from_str(SubstitutionToken::P, "rate = D".to_owned())
+        .is_err());
+    assignment.remove_rules();
+    assert!(assignment.eval_multi(&input).is_err());
+}
+
+#[test]
+fn test_input_transform() {
+    let mut assignment = Assignment::new().with_rules(true, false).with_cache(4);
+    assignment.set_input_bounds(InputBounds {
+        d: Some((0.0, 100.0)),
+        ..InputBounds::default()
+    });
+    let input = InputSet::new(true, true, false, -2.0, 5, 4);
+    assert!(matches!(
+        assignment.eval(&input).unwrap_err().downcast_ref(),
+        Some(AssignmentError::InputOutOfRange { .. })
+    ));
+
+    // Transform runs before bounds checks and both stages: `d` is clamped and `c` forced.
+    assignment.set_input_transform(Some(Box::new(|args| {
+        args.d = args.d.max(0.0) + 2.0;
+        args.c = false;
+    })));
+    let expected = Assignment::new()
+        .with_rules(true, false)
+        .eval(&InputSet::new(true, true, false, 2.0, 5, 4))
+        .unwrap();
+    assert_eq!(assignment.eval(&input).unwrap(), expected);
     assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::T].apply(0.0, 0, 2),
-        2.0
+        assignment
+            .eval(&InputSet::new(true, true, true, -2.0, 5, 4))
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        assignment.eval_map(&input).unwrap()[&expected.0],
+        expected.1
     );
+    assert_eq!(assignment.clone().eval(&input).unwrap(), expected);
+
+    assignment.set_input_transform(None);
+    assert!(assignment.eval(&input).is_err());
 }
 
 #[test]
-fn test_add_arithmetic_rule_from_fn() {
+fn test_logical_rule_infos() {
     let mut assignment = Assignment::new();
+    assert!(assignment.logical_rule_infos().is_empty());
 
-    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::M, Box::new(|d, _, _| d));
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 1);
-    assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::M].apply(2.0, 0, 0),
-        2.0
-    );
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "a && !b".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_with_options(
+            assignment
+                .build_logical_rule(SubstitutionToken::P, "C".to_owned())
+                .unwrap(),
+            2,
+            true,
+        )
+        .unwrap();
+    assignment
+        .add_logical_rule_from_fn(SubstitutionToken::T, Box::new(|a, _, _| a))
+        .unwrap();
 
-    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, e, _| e as f64));
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 2);
+    let info = |token, source: Option<&str>, priority, stop_on_match| LogicalRuleInfo {
+        token,
+        source: source.map(str::to_owned),
+        priority,
+        stop_on_match,
+        enabled: true,
+    };
     assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::T].apply(0.0, 2, 0),
-        2.0
+        assignment.logical_rule_infos(),
+        vec![
+            info(SubstitutionToken::M, Some("A && !B"), 0, false),
+            info(SubstitutionToken::P, Some("C"), 2, true),
+            info(SubstitutionToken::T, None, 0, false),
+        ]
     );
+}
+
+#[test]
+fn test_add_logical_rule_all() {
+    let mut assignment = Assignment::new()
+        .with_arithmetic_rule_str(SubstitutionToken::T, "D")
+        .unwrap();
+    assert!(assignment
+        .add_logical_rule_all(SubstitutionToken::T, Vec::new())
+        .is_err());
+    assignment
+        .add_logical_rule_all(
+            SubstitutionToken::T,
+            vec![
+                assignment
+                    .build_logical_rule(SubstitutionToken::M, "A".to_owned())
+                    .unwrap(),
+                assignment
+                    .build_logical_rule(SubstitutionToken::M, "B".to_owned())
+                    .unwrap(),
+                Box::new(LogicalRuleFn::new(
+                    SubstitutionToken::P,
+                    Box::new(|_, _, c| !c),
+                )),
+            ],
+        )
+        .unwrap();
+    assert_eq!(assignment.logical_rules().count(), 1);
 
-    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|_, _, f| f as f64));
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 2);
     assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::T].apply(0.0, 0, 2),
-        2.0
+        assignment
+            .eval(&InputSet::new(true, true, false, 2.0, 0, 0))
+            .unwrap(),
+        (SubstitutionToken::T, 2.0)
     );
+    for (a, b, c) in [
+        (false, true, false),
+        (true, false, false),
+        (true, true, true),
+    ] {
+        assert!(assignment.eval(&InputSet::new(a, b, c, 2.0, 0, 0)).is_err());
+    }
 }
 
 #[test]
-fn test_add_arithmetic_rule_from_str() {
-    let mut assignment = Assignment::new();
+fn test_input_set_lenient_deserialize() {
+    let input: InputSet = serde_json::from_str(
+        r#"{"a": "true", "b": true, "c": " false ", "d": "2.5", "e": 5, "f": "-4"}"#,
+    )
+    .unwrap();
+    assert_eq!(input, InputSet::new(true, true, false, 2.5, 5, -4));
 
-    assignment
-        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D".to_owned())
-        .expect("Should not fail.");
+    let input: InputSet =
+        serde_json::from_str(r#"{"a": false, "b": "false", "c": true, "d": 3, "e": "0", "f": 1}"#)
+            .unwrap();
+    assert_eq!(input, InputSet::new(false, false, true, 3.0, 0, 1));
 
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 1);
+    for json in [
+        r#"{"a": "yes", "b": true, "c": true, "d": 1.0, "e": 0, "f": 0}"#,
+        r#"{"a": 1, "b": true, "c": true, "d": 1.0, "e": 0, "f": 0}"#,
+        r#"{"a": true, "b": true, "c": true, "d": "abc", "e": 0, "f": 0}"#,
+        r#"{"a": true, "b": true, "c": true, "d": true, "e": 0, "f": 0}"#,
+        r#"{"a": true, "b": true, "c": true, "d": 1.0, "e": "1.5", "f": 0}"#,
+        r#"{"a": true, "b": true, "c": true, "d": 1.0, "e": 1.5, "f": 0}"#,
+        r#"{"a": true, "b": true, "c": true, "d": 1.0, "e": 0, "f": 3000000000}"#,
+    ] {
+        assert!(serde_json::from_str::<InputSet>(json).is_err(), "{}", json);
+    }
+
+    let input: PartialInputSet =
+        serde_json::from_str(r#"{"a": "true", "d": "2", "e": null, "f": 7}"#).unwrap();
+    assert_eq!(input.a, Some(true));
+    assert_eq!(input.b, None);
+    assert_eq!(input.d, Some(2.0));
+    assert_eq!(input.e, None);
+    assert_eq!(input.f, Some(7));
+    assert!(serde_json::from_str::<PartialInputSet>(r#"{"f": "x"}"#).is_err());
+}
+
+#[test]
+fn test_set_rule_enabled() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    let input = InputSet::new(true, true, true, 2.0, 5, 4);
+    assert_eq!(assignment.eval(&input).unwrap().0, SubstitutionToken::P);
+
+    // `A && B && C` is disabled, so `T` rule `!A && B && C` doesn't match either.
+    assignment.set_logical_rule_enabled(1, false).unwrap();
+    assert_eq!(assignment.is_logical_rule_enabled(1), Some(false));
+    assert_eq!(assignment.is_logical_rule_enabled(0), Some(true));
+    assert_eq!(assignment.is_logical_rule_enabled(3), None);
+    assert!(assignment.set_logical_rule_enabled(3, false).is_err());
+    assert!(assignment.eval(&input).is_err());
+    assert!(!assignment.matches_any(&input));
     assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::M].apply(2.0, 0, 0),
-        2.0
+        assignment
+            .logical_rule_infos()
+            .iter()
+            .map(|info| (info.token.clone(), info.enabled))
+            .collect::<Vec<_>>(),
+        vec![
+            (SubstitutionToken::M, true),
+            (SubstitutionToken::P, false),
+            (SubstitutionToken::T, true),
+        ]
     );
 
+    assignment.set_logical_rule_enabled(1, true).unwrap();
+    assert_eq!(assignment.eval(&input).unwrap().0, SubstitutionToken::P);
+
+    let input = InputSet::new(true, true, false, 2.0, 5, 4);
     assignment
-        .add_arithmetic_rule_from_str(SubstitutionToken::T, "E".to_owned())
-        .expect("Should not fail.");
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 2);
+        .set_arithmetic_rule_enabled(&SubstitutionToken::M, 0, false)
+        .unwrap();
     assert_eq!(
-        assignment.arithmetic_rules[&SubstitutionToken::T].apply(0.0, 2, 0),
-        2.0
+        assignment.is_arithmetic_rule_enabled(&SubstitutionToken::M, 0),
+        Some(false)
+    );
+    assert!(assignment
+        .set_arithmetic_rule_enabled(&SubstitutionToken::M, 1, false)
+        .is_err());
+    assert!(assignment
+        .eval(&input)
+        .unwrap_err()
+        .is::<MissingArithmeticRuleError>());
+    assert_eq!(assignment.validate_coverage(), vec![SubstitutionToken::M]);
+    assert_eq!(
+        assignment.arithmetic_rule_infos()[0],
+        ArithmeticRuleInfo {
+            token: SubstitutionToken::M,
+            source: Some("D + (D * E / 10)".to_owned()),
+            enabled: false,
+        }
     );
 
+    // Only the disabled rule of the token is skipped.
+    let rule = assignment
+        .build_arithmetic_rule("D * 10".to_owned())
+        .unwrap();
+    assignment.push_arithmetic_rule(SubstitutionToken::M, rule);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 20.0)
+    );
     assignment
-        .add_arithmetic_rule_from_str(SubstitutionToken::P, "Z&&X".to_owned())
-        .expect_err("Should fail.");
-    assert_eq!(assignment.logical_rules.len(), 0);
-    assert_eq!(assignment.arithmetic_rules.len(), 2);
+        .set_arithmetic_rule_enabled(&SubstitutionToken::M, 0, true)
+        .unwrap();
+    assignment
+        .set_arithmetic_rule_enabled(&SubstitutionToken::M, 1, false)
+        .unwrap();
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
+
+    // Flags belong to rules, so replaced rules are enabled.
+    assignment.set_logical_rule_enabled(0, false).unwrap();
+    assignment.remove_rules();
+    let assignment = assignment.with_rules(true, false);
+    assert!(assignment
+        .logical_rule_infos()
+        .iter()
+        .all(|info| info.enabled));
+    assert!(assignment
+        .arithmetic_rule_infos()
+        .iter()
+        .all(|info| info.enabled));
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 3.0)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Failed to apply logical rule.")]
-fn test_eval_empty_logical_rules() {
-    let mut assignment = Assignment::new();
+fn test_fallback_arithmetic_rule() {
+    let mut assignment = Assignment::new()
+        .with_logical_rule_str(SubstitutionToken::M, "A")
+        .unwrap()
+        .with_logical_rule_str(SubstitutionToken::P, "B")
+        .unwrap()
+        .with_arithmetic_rule_str(SubstitutionToken::M, "D * 2")
+        .unwrap();
+    let only_b = InputSet::new(false, true, false, 3.0, 0, 0);
+    assert!(assignment
+        .eval(&only_b)
+        .unwrap_err()
+        .is::<MissingArithmeticRuleError>());
+    assert_eq!(assignment.validate_coverage(), vec![SubstitutionToken::P]);
 
-    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 0.0));
-    assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(rule));
-    assignment.eval(InputSet::default()).unwrap();
+    assignment.set_fallback_arithmetic_rule(Some(Box::new(
+        ArithmeticRuleStr::new("D + 1".to_owned()).unwrap(),
+    )));
+    assert_eq!(
+        assignment.fallback_arithmetic_rule().unwrap().source(),
+        Some("D + 1")
+    );
+    assert!(assignment.validate_coverage().is_empty());
+    assert!(assignment.prepare().is_ok());
+    // `P` has no rule of its own and uses the fallback, `M` ignores it.
+    assert_eq!(
+        assignment.eval(&only_b).unwrap(),
+        (SubstitutionToken::P, 4.0)
+    );
+    assert_eq!(
+        assignment
+            .eval(&InputSet::new(true, false, false, 3.0, 0, 0))
+            .unwrap(),
+        (SubstitutionToken::M, 6.0)
+    );
+    assert_eq!(
+        assignment.clone().eval(&only_b).unwrap(),
+        (SubstitutionToken::P, 4.0)
+    );
+
+    // Token with its rule disabled doesn't use the fallback and is still reported.
+    assignment
+        .set_arithmetic_rule_enabled(&SubstitutionToken::M, 0, false)
+        .unwrap();
+    assert!(assignment
+        .eval(&InputSet::new(true, false, false, 3.0, 0, 0))
+        .is_err());
+    assert_eq!(assignment.validate_coverage(), vec![SubstitutionToken::M]);
+    assignment
+        .set_arithmetic_rule_enabled(&SubstitutionToken::M, 0, true)
+        .unwrap();
+
+    // Fallback is part of the fingerprint.
+    let fingerprint = assignment.fingerprint();
+    let mut without = assignment.clone();
+    without.set_fallback_arithmetic_rule(None);
+    assert_ne!(without.fingerprint(), fingerprint);
+    assert!(without.eval(&only_b).is_err());
+
+    // Fallback is removed with the rest of rules.
+    assignment.remove_rules();
+    assert!(assignment.fallback_arithmetic_rule().is_none());
 }
 
 #[test]
-#[should_panic(expected = "Failed to find arithmetic rule for token.")]
-fn test_eval_empty_arithmetic_rules() {
+fn test_serializable_assignment() {
     let mut assignment = Assignment::new();
+    assignment.add_constant("rate", 2.0).unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::M, "A && !B".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_with_options(
+            assignment
+                .build_logical_rule(SubstitutionToken::P, "C".to_owned())
+                .unwrap(),
+            1,
+            true,
+        )
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::M, "D * rate".to_owned())
+        .unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::P, "D + E - F".to_owned())
+        .unwrap();
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::T, "A && B".to_owned())
+        .unwrap();
+    assignment.set_fallback_arithmetic_rule(Some(
+        assignment
+            .build_arithmetic_rule("F * rate".to_owned())
+            .unwrap(),
+    ));
 
-    let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|_, _, _| true));
-    assignment.add_logical_rule(Box::new(rule));
-    assignment.eval(InputSet::default()).unwrap();
+    let rules = assignment.to_serializable().unwrap();
+    assert_eq!(rules.fallback_arithmetic.as_deref(), Some("F * rate"));
+    assert_eq!(rules.logical[1].priority, 1);
+    assert!(rules.logical[1].stop_on_match);
+    let json = serde_json::to_string(&rules).unwrap();
+    let loaded = Assignment::from_serializable(
+        &serde_json::from_str::<SerializableAssignment>(&json).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(loaded.to_serializable().unwrap(), rules);
+    assert_eq!(loaded.fingerprint(), assignment.fingerprint());
+    for a in [false, true] {
+        for b in [false, true] {
+            for c in [false, true] {
+                let input = InputSet::new(a, b, c, 1.5, 3, 2);
+                assert_eq!(
+                    loaded.eval(&input).ok(),
+                    assignment.eval(&input).ok(),
+                    "{:?}",
+                    input
+                );
+            }
+        }
+    }
+
+    // Rules are validated on load.
+    let mut broken = rules.clone();
+    broken.arithmetic[0].rule_str = "D +".to_owned();
+    assert!(Assignment::from_serializable(&broken).is_err());
+    let mut broken = rules;
+    broken.constants.clear();
+    assert!(Assignment::from_serializable(&broken).is_err());
+
+    assignment
+        .set_fallback_arithmetic_rule(Some(Box::new(ArithmeticRuleFn::new(Box::new(|d, _, _| d)))));
+    assert!(assignment.to_serializable().is_err());
+    let (lossy, skipped) = assignment.to_serializable_lossy();
+    assert_eq!(skipped, vec![SkippedRule::FallbackArithmetic]);
+    assert_eq!(lossy.fallback_arithmetic, None);
+    assignment.set_fallback_arithmetic_rule(None);
+    assignment.add_arithmetic_rule_from_fn(SubstitutionToken::T, Box::new(|d, _, _| d));
+    assert_eq!(
+        assignment.to_serializable().unwrap_err().to_string(),
+        "Function-based arithmetic rule of token T can't be serialized."
+    );
+    let (lossy, skipped) = assignment.to_serializable_lossy();
+    assert_eq!(skipped, vec![SkippedRule::Arithmetic(SubstitutionToken::T)]);
+    assert_eq!(lossy.logical.len(), 3);
+    assert_eq!(lossy.arithmetic.len(), 2);
 }
 
 #[test]
-fn test_eval() {
+fn test_serializable_assignment_keeps_rules_of_token() {
     let mut assignment = Assignment::new();
+    assignment.set_selection_strategy(SelectionStrategy::Sum);
+    assignment
+        .add_logical_rule_from_str(SubstitutionToken::P, "A".to_owned())
+        .unwrap();
+    for rule_str in ["D", "E * 2", "F"] {
+        let rule = assignment
+            .build_arithmetic_rule(rule_str.to_owned())
+            .unwrap();
+        assignment.push_arithmetic_rule(SubstitutionToken::P, rule);
+    }
+    let input = InputSet::new(true, true, false, 1.5, 3, 2);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::P, 9.5)
+    );
 
-    let rule = LogicalRuleFn::new(SubstitutionToken::M, Box::new(|a, _, _| a));
-    assignment.add_logical_rule(Box::new(rule));
-    let rule = LogicalRuleFn::new(SubstitutionToken::T, Box::new(|_, b, _| b));
-    assignment.add_logical_rule(Box::new(rule));
-    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 2.0));
-    assignment.add_arithmetic_rule(SubstitutionToken::M, Box::new(rule));
-    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 3.0));
-    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule));
+    let rules = assignment.to_serializable().unwrap();
+    let mut loaded = Assignment::from_serializable(&rules).unwrap();
+    loaded.set_selection_strategy(SelectionStrategy::Sum);
+    assert_eq!(loaded.to_serializable().unwrap(), rules);
+    assert_eq!(loaded.eval(&input).unwrap(), (SubstitutionToken::P, 9.5));
 
-    let res = assignment
-        .eval(InputSet {
-            a: true,
-            b: false,
-            c: false,
-            d: 0.0,
-            e: 0,
-            f: 0,
-        })
-        .expect("eval() should return Ok()");
-    assert_eq!(res, (SubstitutionToken::M, 2.0));
+    let mut replaced = Assignment::new();
+    replaced.set_selection_strategy(SelectionStrategy::Sum);
+    replaced.replace_from_serializable(&rules).unwrap();
+    assert_eq!(replaced.eval(&input).unwrap(), (SubstitutionToken::P, 9.5));
+}
 
-    let res = assignment
-        .eval(InputSet {
-            a: false,
-            b: true,
-            c: false,
-            d: 0.0,
-            e: 0,
-            f: 0,
-        })
-        .expect("eval() should return Ok()");
-    assert_eq!(res, (SubstitutionToken::T, 3.0));
+#[test]
+fn test_serializable_assignment_keeps_enabled_flags() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    assignment.set_logical_rule_enabled(1, false).unwrap();
+    assignment
+        .set_arithmetic_rule_enabled(&SubstitutionToken::M, 0, false)
+        .unwrap();
 
-    // Override logical rule to substitute to another arithmetic rule.
-    let rule = LogicalRuleFn::new(SubstitutionToken::T, Box::new(|a, _, _| a));
-    assignment.add_logical_rule(Box::new(rule));
+    let rules = assignment.to_serializable().unwrap();
+    assert!(!rules.logical[1].enabled);
+    assert!(!rules.arithmetic[0].enabled);
+    let json = serde_json::to_string(&rules).unwrap();
+    let loaded = Assignment::from_serializable(
+        &serde_json::from_str::<SerializableAssignment>(&json).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(loaded.logical_rule_infos(), assignment.logical_rule_infos());
+    assert_eq!(
+        loaded.arithmetic_rule_infos(),
+        assignment.arithmetic_rule_infos()
+    );
+    let input = InputSet::new(true, true, true, 1.5, 3, 2);
+    assert!(loaded.eval(&input).is_err());
 
-    let res = assignment
-        .eval(InputSet {
-            a: true,
-            b: false,
-            c: false,
-            d: 0.0,
-            e: 0,
-            f: 0,
-        })
-        .expect("eval() should return Ok()");
-    assert_eq!(res, (SubstitutionToken::T, 3.0));
+    let mut replaced = Assignment::new();
+    replaced.replace_from_serializable(&rules).unwrap();
+    assert_eq!(replaced.is_logical_rule_enabled(1), Some(false));
+    assert_eq!(
+        replaced.is_arithmetic_rule_enabled(&SubstitutionToken::M, 0),
+        Some(false)
+    );
 
-    // Override arithmetic rule.
-    let rule = ArithmeticRuleFn::new(Box::new(|_, _, _| 4.0));
-    assignment.add_arithmetic_rule(SubstitutionToken::T, Box::new(rule));
+    // Absent flags mean enabled rules.
+    let rules: SerializableAssignment = serde_json::from_str(
+        r#"{"logical": [{"token": "M", "rule_str": "A"}], "arithmetic": [{"token": "M", "rule_str": "D"}]}"#,
+    )
+    .unwrap();
+    assert!(rules.logical[0].enabled);
+    assert!(rules.arithmetic[0].enabled);
+}
 
-    let res = assignment
-        .eval(InputSet {
-            a: true,
-            b: false,
-            c: false,
-            d: 0.0,
-            e: 0,
-            f: 0,
-        })
-        .expect("eval() should return Ok()");
-    assert_eq!(res, (SubstitutionToken::T, 4.0));
+#[cfg(feature = "bincode")]
+#[test]
+fn test_bincode() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    assignment.add_constant("rate", 2.0).unwrap();
+    assignment
+        .add_arithmetic_rule_from_str(SubstitutionToken::T, "D * rate - F".to_owned())
+        .unwrap();
+    assignment.set_fallback_arithmetic_rule(Some(
+        assignment
+            .build_arithmetic_rule("E + rate".to_owned())
+            .unwrap(),
+    ));
 
-    // Override logical rule to no arithmetic rule.
-    let rule = LogicalRuleFn::new(SubstitutionToken::P, Box::new(|a, _, _| a));
-    assignment.add_logical_rule(Box::new(rule));
+    let bytes = assignment.to_bincode().unwrap();
+    let loaded = Assignment::from_bincode(&bytes).unwrap();
+    assert_eq!(
+        loaded.to_serializable().unwrap(),
+        assignment.to_serializable().unwrap()
+    );
+    for a in [false, true] {
+        for b in [false, true] {
+            for c in [false, true] {
+                let input = InputSet::new(a, b, c, 1.5, 3, 2);
+                assert_eq!(
+                    loaded.eval(&input).ok(),
+                    assignment.eval(&input).ok(),
+                    "{:?}",
+                    input
+                );
+            }
+        }
+    }
 
-    let res = assignment.eval(InputSet {
-        a: true,
-        b: false,
-        c: false,
-        d: 0.0,
-        e: 0,
-        f: 0,
+    // Rules are validated on load.
+    let mut rules = assignment.to_serializable().unwrap();
+    rules.arithmetic[0].rule_str = "D * tax".to_owned();
+    let bytes = bincode::serialize(&rules).unwrap();
+    assert!(Assignment::from_bincode(&bytes).is_err());
+    assert!(Assignment::from_bincode(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn test_replace_from_serializable() {
+    let mut assignment = Assignment::new().with_rules(true, false);
+    assignment.add_constant("rate", 2.0).unwrap();
+    let input = InputSet::new(true, false, false, 1.5, 3, 2);
+
+    let mut rules = SerializableAssignment::default();
+    rules.constants.insert("bonus".to_owned(), 1.0);
+    rules.logical.push(SerializableLogicalRule {
+        token: SubstitutionToken::M,
+        rule_str: "A".to_owned(),
+        priority: 0,
+        stop_on_match: false,
+        enabled: true,
     });
+    rules.arithmetic.push(SerializableArithmeticRule {
+        token: SubstitutionToken::M,
+        rule_str: "D * rate + bonus".to_owned(),
+        enabled: true,
+    });
+    assignment.replace_from_serializable(&rules).unwrap();
+    assert_eq!(assignment.logical_rules().count(), 1);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 4.0)
+    );
 
+    // Invalid rule set keeps existing rules.
+    rules.arithmetic[0].rule_str = "D * tax".to_owned();
+    let e = assignment.replace_from_serializable(&rules).unwrap_err();
     assert_eq!(
-        res.unwrap_err().to_string(),
-        "Failed to find arithmetic rule for token."
+        e.element(),
+        &RuleSetElement::Arithmetic {
+            index: 0,
+            token: SubstitutionToken::M,
+            rule_str: "D * tax".to_owned(),
+        }
+    );
+    assert!(e
+        .to_string()
+        .starts_with("Invalid arithmetic rule \"D * tax\": "));
+    let mut broken = rules.clone();
+    broken.constants.insert("if".to_owned(), 1.0);
+    let e = assignment.replace_from_serializable(&broken).unwrap_err();
+    assert_eq!(
+        e.element(),
+        &RuleSetElement::Constant {
+            name: "if".to_owned()
+        }
+    );
+    assignment.set_max_logical_rules(Some(0));
+    let e = assignment.replace_from_serializable(&rules).unwrap_err();
+    assert_eq!(e.element(), &RuleSetElement::LogicalRulesLimit);
+    assignment.set_max_logical_rules(None);
+    assert_eq!(
+        assignment.eval(&input).unwrap(),
+        (SubstitutionToken::M, 4.0)
+    );
+}
+
+#[test]
+fn test_classify() {
+    let mut assignment = Assignment::new();
+    for (token, rule_str) in [
+        (SubstitutionToken::M, "A && B && !C"),
+        (SubstitutionToken::P, "A && B && C"),
+        (SubstitutionToken::T, "!A && B && C"),
+    ] {
+        assignment
+            .add_logical_rule_from_str(token, rule_str.to_owned())
+            .unwrap();
+    }
+    assert!(!assignment.has_rules());
+
+    let input = |a, b, c| InputSet::new(a, b, c, 1.0, 0, 0);
+    assert_eq!(
+        assignment.classify(&input(true, true, false)).unwrap(),
+        SubstitutionToken::M
+    );
+    assert_eq!(
+        assignment.classify(&input(false, true, true)).unwrap(),
+        SubstitutionToken::T
+    );
+    assert!(assignment
+        .eval(&input(true, true, false))
+        .unwrap_err()
+        .is::<MissingArithmeticRuleError>());
+    assert!(assignment.classify(&input(false, false, false)).is_err());
+
+    assignment.set_default_token(Some(SubstitutionToken::P));
+    assert_eq!(
+        assignment.classify(&input(false, false, false)).unwrap(),
+        SubstitutionToken::P
     );
 }
+
+#[test]
+fn test_cache_poisoned_lock() {
+    let assignment = Arc::new(Assignment::new().with_rules(true, false).with_cache(4));
+    let poisoner = assignment.clone();
+    std::thread::spawn(move || {
+        let _guard = poisoner.cache.as_ref().unwrap().lock().unwrap();
+        panic!("Evaluation panicked while holding the cache lock.");
+    })
+    .join()
+    .unwrap_err();
+
+    let input = InputSet::new(true, true, false, 2.0, 5, 4);
+    for _ in 0..2 {
+        assert_eq!(
+            assignment.eval(&input).unwrap(),
+            (SubstitutionToken::M, 3.0)
+        );
+    }
+    let mut assignment = Arc::try_unwrap(assignment).ok().unwrap();
+    assert_eq!(assignment.clone().eval(&input).unwrap().1, 3.0);
+    assignment.set_output_precision(Some(0));
+    assert_eq!(assignment.eval(&input).unwrap().1, 3.0);
+}
@@ -0,0 +1,261 @@
+use regex::Regex;
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::{Arc, OnceLock},
+};
+
+use crate::assignment::{
+    arithmetic_rule::{ArithmeticRule, ArithmeticRuleStr, DivisionPolicy},
+    normalize_rule_str, RESERVED_NAMES,
+};
+
+/// Arithmetic rule calculating several named outputs at once, e.g. `base`, `tax` and `total`,
+/// so shared subexpressions are calculated once.
+pub trait MultiArithmeticRule: Send + Sync {
+    /// Returns named outputs of rule calculation.
+    /// Returns error if rule can't be evaluated.
+    fn apply(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+    ) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>>;
+
+    /// Returns named outputs the same way as `apply`, with division treated according to `DivisionPolicy`
+    /// and given named constants available to the rule.
+    ///
+    /// By default policy and constants are ignored and result of `apply` is returned.
+    fn apply_with_constants(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        _policy: DivisionPolicy,
+        _constants: &HashMap<String, f64>,
+    ) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>> {
+        self.apply(d, e, f)
+    }
+
+    /// Returns boxed copy of the rule.
+    fn clone_box(&self) -> Box<dyn MultiArithmeticRule>;
+
+    /// Returns source string of the rule if it has one.
+    fn source(&self) -> Option<&str> {
+        None
+    }
+}
+
+pub type MultiRuleFn = Box<dyn Fn(f64, i32, i32) -> HashMap<String, f64> + Send + Sync>;
+
+/// Stores `MultiRuleFn` function that used for calculation.
+/// Function is stored in `Arc`, so cloned rules share it.
+#[derive(Clone)]
+pub struct MultiArithmeticRuleFn {
+    rule_fn: Arc<dyn Fn(f64, i32, i32) -> HashMap<String, f64> + Send + Sync>,
+}
+
+impl MultiArithmeticRuleFn {
+    /// Builds `MultiArithmeticRuleFn`.
+    ///
+    /// # Arguments
+    /// * `rule_fn` `MultiRuleFn` function.
+    pub fn new(rule_fn: MultiRuleFn) -> Self {
+        Self {
+            rule_fn: Arc::from(rule_fn),
+        }
+    }
+}
+
+impl MultiArithmeticRule for MultiArithmeticRuleFn {
+    fn apply(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+    ) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>> {
+        Ok((self.rule_fn)(d, e, f))
+    }
+
+    fn clone_box(&self) -> Box<dyn MultiArithmeticRule> {
+        Box::new(self.clone())
+    }
+}
+
+/// Multi-output rule built from `name = expression` definitions separated by `;`,
+/// e.g. `base = D * E; tax = base * 0.2; total = base + tax`.
+///
+/// Each expression is an arithmetic rule string which may reference outputs defined before it,
+/// and definitions are calculated in order.
+#[derive(Clone)]
+pub struct MultiArithmeticRuleStr {
+    rule_str: String,
+    outputs: Vec<(String, ArithmeticRuleStr)>,
+}
+
+impl MultiArithmeticRuleStr {
+    /// Normalizes and validates provided rule string and builds `MultiArithmeticRuleStr`.
+    /// Returns error with description if validation fails.
+    pub fn new(rule_str: String) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::new_with_constants(rule_str, &HashMap::new())
+    }
+
+    /// Normalizes and validates provided rule string with given named constants and builds `MultiArithmeticRuleStr`.
+    ///
+    /// Returns error if a definition is not `name = expression`, output name is not an identifier,
+    /// repeats or clashes with rule variables, functions or constants, or an expression is invalid.
+    pub fn new_with_constants(
+        rule_str: String,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| Regex::new(r"^([A-Za-z_]\w*) ?=([^=].*)$").unwrap());
+
+        let rule_str = normalize_rule_str(&rule_str);
+        let mut known = constants.clone();
+        let mut outputs: Vec<(String, ArithmeticRuleStr)> = Vec::new();
+        for definition in rule_str.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let caps = re.captures(definition).ok_or_else(|| {
+                format!(
+                    "Output definition `{}` is not `name = expression`.",
+                    definition
+                )
+            })?;
+            let name = &caps[1];
+            if RESERVED_NAMES.contains(&name) || known.contains_key(name) {
+                Err(format!(
+                    "Output `{}` clashes with rule variables, functions, constants or other outputs.",
+                    name
+                ))?
+            }
+
+            let rule = ArithmeticRuleStr::new_with_constants(caps[2].to_owned(), &known)
+                .map_err(|e| format!("Output `{}`: {}", name, e))?;
+            known.insert(name.to_owned(), 0.0);
+            outputs.push((name.to_owned(), rule));
+        }
+
+        if outputs.is_empty() {
+            Err("Multi-output rule must define at least one output.")?
+        }
+        Ok(Self { rule_str, outputs })
+    }
+
+    /// Returns names of outputs in order of definition.
+    pub fn output_names(&self) -> impl Iterator<Item = &str> {
+        self.outputs.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns input variables `D`, `E` and `F` referenced by any output definition.
+    pub fn referenced_vars(&self) -> HashSet<char> {
+        self.outputs
+            .iter()
+            .flat_map(|(_, rule)| rule.referenced_vars())
+            .collect()
+    }
+}
+
+impl MultiArithmeticRule for MultiArithmeticRuleStr {
+    fn apply(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+    ) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>> {
+        self.apply_with_constants(d, e, f, DivisionPolicy::default(), &HashMap::new())
+    }
+
+    fn apply_with_constants(
+        &self,
+        d: f64,
+        e: i32,
+        f: i32,
+        policy: DivisionPolicy,
+        constants: &HashMap<String, f64>,
+    ) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>> {
+        let mut scope = constants.clone();
+        let mut res = HashMap::with_capacity(self.outputs.len());
+        for (name, rule) in &self.outputs {
+            let value = rule.apply_with_constants(d, e, f, policy, &scope)?;
+            scope.insert(name.clone(), value);
+            res.insert(name.clone(), value);
+        }
+        Ok(res)
+    }
+
+    fn clone_box(&self) -> Box<dyn MultiArithmeticRule> {
+        Box::new(self.clone())
+    }
+
+    fn source(&self) -> Option<&str> {
+        Some(&self.rule_str)
+    }
+}
+
+#[test]
+fn test_multi_rule_str() {
+    let rule =
+        MultiArithmeticRuleStr::new("base = D * E; tax = base / 4; total = base + tax".to_owned())
+            .unwrap();
+    assert_eq!(
+        rule.output_names().collect::<Vec<_>>(),
+        vec!["base", "tax", "total"]
+    );
+    let res = rule.apply(2.0, 10, 0).unwrap();
+    assert_eq!(res.len(), 3);
+    assert_eq!(res["base"], 20.0);
+    assert_eq!(res["tax"], 5.0);
+    assert_eq!(res["total"], 25.0);
+
+    let constants: HashMap<_, _> = vec![("rate".to_owned(), 0.5)].into_iter().collect();
+    let rule = MultiArithmeticRuleStr::new_with_constants(
+        "base = d + 1; tax = base * rate".to_owned(),
+        &constants,
+    )
+    .unwrap();
+    let res = rule
+        .apply_with_constants(3.0, 0, 0, DivisionPolicy::default(), &constants)
+        .unwrap();
+    assert_eq!(res["tax"], 2.0);
+
+    for rule_str in [
+        "",
+        "D + E",
+        "base = D; base = E",
+        "D = E",
+        "rate = D",
+        "tax = base * 2; base = D",
+        "base = D +",
+        "base == D",
+    ] {
+        assert!(
+            MultiArithmeticRuleStr::new_with_constants(rule_str.to_owned(), &constants).is_err(),
+            "{}",
+            rule_str
+        );
+    }
+}
+
+#[test]
+fn test_multi_rule_fn() {
+    let rule = MultiArithmeticRuleFn::new(Box::new(|d, e, _| {
+        let base = d * e as f64;
+        vec![("base".to_owned(), base), ("total".to_owned(), base * 1.5)]
+            .into_iter()
+            .collect()
+    }));
+    let res = rule.clone_box().apply(2.0, 2, 0).unwrap();
+    assert_eq!(res["base"], 4.0);
+    assert_eq!(res["total"], 6.0);
+    assert!(rule.source().is_none());
+}
+
+#[test]
+fn test_multi_rule_referenced_vars() {
+    let rule = MultiArithmeticRuleStr::new("base = D * 2; total = base + F".to_owned()).unwrap();
+    assert_eq!(
+        rule.referenced_vars(),
+        ['D', 'F'].iter().copied().collect::<HashSet<char>>()
+    );
+}
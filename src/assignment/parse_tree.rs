@@ -0,0 +1,212 @@
+use evalexpr::build_operator_tree;
+use serde::{Deserialize, Serialize};
+
+use std::error::Error;
+
+/// Node of operator tree built by `evalexpr` from rule string, e.g. to debug operator precedence.
+///
+/// Parentheses and the whole expression are `RootNode` operators, so `A || B && C` is
+/// `RootNode(Or(A, And(B, C)))` and `(A || B) && C` is `RootNode(And(RootNode(Or(A, B)), C))`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParseTreeNode {
+    /// Name of `evalexpr::Operator`, e.g. `Or`, `Not`, `Add`, `VariableIdentifier` or `Const`.
+    pub operator: String,
+    /// Name of variable or function, or value of constant, `None` for other operators.
+    pub value: Option<String>,
+    pub children: Vec<ParseTreeNode>,
+}
+
+impl ParseTreeNode {
+    /// Builds operator tree of rule string with `evalexpr`.
+    /// Rule string is parsed as is, without validation or normalization.
+    /// Returns error if `evalexpr` can't parse it.
+    pub fn build(rule_str: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        // `evalexpr::Node` doesn't expose its operator and children, so they are read from its `Debug` output.
+        let debug = format!("{:?}", build_operator_tree(rule_str)?);
+        let mut parser = DebugParser { rest: &debug };
+        let node = parser.node()?;
+        if !parser.rest.is_empty() {
+            Err(parser.error())?
+        }
+        Ok(node)
+    }
+}
+
+/// Parser of `Debug` output of `evalexpr::Node`,
+/// e.g. `Node { operator: Not, children: [Node { operator: VariableIdentifier { identifier: "A" }, children: [] }] }`.
+struct DebugParser<'a> {
+    rest: &'a str,
+}
+
+impl DebugParser<'_> {
+    fn error(&self) -> String {
+        let at: String = self.rest.chars().take(20).collect();
+        format!("Unexpected format of operator tree at `{}`.", at)
+    }
+
+    fn expect(&mut self, prefix: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.rest.strip_prefix(prefix) {
+            Some(rest) => {
+                self.rest = rest;
+                Ok(())
+            }
+            None => Err(self.error())?,
+        }
+    }
+
+    fn node(&mut self) -> Result<ParseTreeNode, Box<dyn Error + Send + Sync>> {
+        self.expect("Node { operator: ")?;
+        let name_len = self
+            .rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(self.rest.len());
+        let operator = self.rest[..name_len].to_owned();
+        self.rest = &self.rest[name_len..];
+
+        let value = if self.rest.starts_with(" { ") {
+            self.expect(" { ")?;
+            let field_len = self.rest.find(": ").ok_or_else(|| self.error())?;
+            self.rest = &self.rest[field_len + 2..];
+            let value = self.field_value()?;
+            self.expect(" }")?;
+            Some(value)
+        } else {
+            None
+        };
+
+        self.expect(", children: [")?;
+        let mut children = Vec::new();
+        if !self.rest.starts_with(']') {
+            loop {
+                children.push(self.node()?);
+                if self.rest.starts_with(", ") {
+                    self.expect(", ")?;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect("] }")?;
+
+        Ok(ParseTreeNode {
+            operator,
+            value,
+            children,
+        })
+    }
+
+    /// Reads value of operator field up to closing ` }`, e.g. `"A"` or `Float(1.5)`,
+    /// and returns identifier without quotes or constant without its type, e.g. `A` or `1.5`.
+    fn field_value(&mut self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut depth = 0;
+        let mut in_str = false;
+        let mut escaped = false;
+        let mut end = None;
+        for (i, c) in self.rest.char_indices() {
+            if in_str {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_str = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                ' ' if depth == 0 && self.rest[i..].starts_with(" }") => {
+                    end = Some(i);
+                    break;
+                }
+                '"' => in_str = true,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        let end = end.ok_or_else(|| self.error())?;
+        let raw = &self.rest[..end];
+        self.rest = &self.rest[end..];
+
+        Ok(if let Some(quoted) = raw.strip_prefix('"') {
+            unescape(quoted.strip_suffix('"').unwrap_or(quoted))
+        } else {
+            match (raw.find('('), raw.strip_suffix(')')) {
+                (Some(start), Some(inner)) => inner[start + 1..].to_owned(),
+                _ => raw.to_owned(),
+            }
+        })
+    }
+}
+
+/// Removes backslash escapes of `Debug` string.
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => res.extend(chars.next()),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+#[test]
+fn test_parse_tree() {
+    fn leaf(operator: &str, value: &str) -> ParseTreeNode {
+        ParseTreeNode {
+            operator: operator.to_owned(),
+            value: Some(value.to_owned()),
+            children: Vec::new(),
+        }
+    }
+    fn node(operator: &str, children: Vec<ParseTreeNode>) -> ParseTreeNode {
+        ParseTreeNode {
+            operator: operator.to_owned(),
+            value: None,
+            children,
+        }
+    }
+    let var = |name| leaf("VariableIdentifier", name);
+
+    assert_eq!(
+        ParseTreeNode::build("A || B && !C").unwrap(),
+        node(
+            "RootNode",
+            vec![node(
+                "Or",
+                vec![
+                    var("A"),
+                    node("And", vec![var("B"), node("Not", vec![var("C")])]),
+                ]
+            )]
+        )
+    );
+    assert_eq!(
+        ParseTreeNode::build("(D + 1.5) * E").unwrap(),
+        node(
+            "RootNode",
+            vec![node(
+                "Mul",
+                vec![
+                    node(
+                        "RootNode",
+                        vec![node("Add", vec![var("D"), leaf("Const", "1.5")])]
+                    ),
+                    var("E"),
+                ]
+            )]
+        )
+    );
+
+    let tree = ParseTreeNode::build(r#"if(D > 2, "a \" }", 0)"#).unwrap();
+    let function = &tree.children[0];
+    assert_eq!(function.operator, "FunctionIdentifier");
+    assert_eq!(function.value.as_deref(), Some("if"));
+    let args = &function.children[0].children[0];
+    assert_eq!(args.operator, "Tuple");
+    assert_eq!(args.children.len(), 3);
+    assert_eq!(args.children[1].children[0], leaf("Const", r#""a \" }""#));
+
+    assert!(ParseTreeNode::build("(A || B").is_err());
+}
@@ -0,0 +1,169 @@
+use evalexpr::*;
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashMap, error::Error};
+
+use crate::assignment::{
+    arithmetic_rule::{if_function, ArithmeticRule, ArithmeticRuleStr, SubstitutionToken},
+    logical_rule::{LogicalRule, LogicalRuleStr},
+    normalize_rule_str, set_constants, InputSet,
+};
+
+/// String rule which kind is detected from its expression.
+///
+/// Expression is evaluated over all six variables and classified by type of the result:
+/// boolean result builds `LogicalRuleStr`, numeric result builds `ArithmeticRuleStr`.
+/// Built rule is then validated as usual, so expression can't mix logical and arithmetic variables.
+///
+/// # Examples
+///
+/// ```
+/// use st_test::assignment::arithmetic_rule::SubstitutionToken;
+/// use st_test::assignment::rule::Rule;
+///
+/// let rule = Rule::new(SubstitutionToken::M, "A && !B".to_owned()).unwrap();
+/// assert!(matches!(rule, Rule::Logical(_)));
+///
+/// let rule = Rule::new(SubstitutionToken::M, "D * E".to_owned()).unwrap();
+/// assert!(matches!(rule, Rule::Arithmetic(_, _)));
+/// ```
+pub enum Rule {
+    Logical(LogicalRuleStr),
+    Arithmetic(SubstitutionToken, ArithmeticRuleStr),
+}
+
+/// Result of applying single `Rule` to `InputSet`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleOutput {
+    /// Token of logical rule, `None` if rule doesn't match.
+    Logical(Option<SubstitutionToken>),
+    /// Result of arithmetic rule.
+    Arithmetic(f64),
+}
+
+impl Rule {
+    /// Classifies and validates provided rule string and builds `Rule`.
+    /// Returns `Ok(Rule)` if validation is successful,
+    /// otherwise returns error with description.
+    pub fn new(
+        token: SubstitutionToken,
+        rule_str: String,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Rule::new_with_constants(token, rule_str, &HashMap::new())
+    }
+
+    /// Builds `Rule` the same way as `new`, but allows names of given constants in rule string.
+    pub fn new_with_constants(
+        token: SubstitutionToken,
+        rule_str: String,
+        constants: &HashMap<String, f64>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let rule_str = normalize_rule_str(&rule_str);
+
+        // Ordering comparisons of booleans can't be evaluated by `evalexpr` directly.
+        if let Ok(rule) =
+            LogicalRuleStr::new_with_constants(token.clone(), rule_str.clone(), constants)
+        {
+            return Ok(Rule::Logical(rule));
+        }
+
+        // Evaluate expression with some input to check type of its result.
+        let mut context = context_map! {
+            "A" => true,
+            "B" => true,
+            "C" => true,
+            "D" => 0.0,
+            "E" => 0 as f64,
+            "F" => 0 as f64,
+            "if" => Function::new(Box::new(if_function)),
+        }
+        .unwrap();
+        set_constants(&mut context, constants);
+
+        match eval_with_context(&rule_str, &context)? {
+            Value::Boolean(_) => Ok(Rule::Logical(LogicalRuleStr::new_with_constants(
+                token, rule_str, constants,
+            )?)),
+            Value::Float(_) | Value::Int(_) => Ok(Rule::Arithmetic(
+                token,
+                ArithmeticRuleStr::new_with_constants(rule_str, constants)?,
+            )),
+            _ => Err("Expression result is neither boolean nor numeric.")?,
+        }
+    }
+
+    /// Applies the rule alone to given arguments.
+    /// Returns error if rule can't be evaluated.
+    pub fn apply(&self, args: &InputSet) -> Result<RuleOutput, Box<dyn Error + Send + Sync>> {
+        Ok(match self {
+            Rule::Logical(rule) => RuleOutput::Logical(rule.apply(args.a, args.b, args.c)?),
+            Rule::Arithmetic(_, rule) => {
+                RuleOutput::Arithmetic(rule.apply(args.d, args.e, args.f)?)
+            }
+        })
+    }
+}
+
+#[test]
+fn test_new_logical() {
+    let rule = Rule::new(SubstitutionToken::M, "A && B || !C".to_owned()).unwrap();
+    match rule {
+        Rule::Logical(_) => {}
+        _ => panic!("Rule should be logical."),
+    }
+
+    let rule = Rule::new(SubstitutionToken::M, "A > B".to_owned()).unwrap();
+    assert!(matches!(rule, Rule::Logical(_)));
+}
+
+#[test]
+fn test_new_arithmetic() {
+    let rule = Rule::new(SubstitutionToken::P, "D + E * F".to_owned()).unwrap();
+    match rule {
+        Rule::Arithmetic(token, _) => assert_eq!(token, SubstitutionToken::P),
+        _ => panic!("Rule should be arithmetic."),
+    }
+
+    let rule = Rule::new(SubstitutionToken::T, "2 * D".to_owned()).unwrap();
+    assert!(matches!(rule, Rule::Arithmetic(_, _)));
+
+    let rule = Rule::new(SubstitutionToken::T, "if(E > F, D, 0)".to_owned()).unwrap();
+    assert!(matches!(rule, Rule::Arithmetic(_, _)));
+}
+
+#[test]
+fn test_new_invalid() {
+    // Boolean result, but arithmetic variable in logical rule.
+    assert_eq!(
+        Rule::new(SubstitutionToken::M, "A && D == 0".to_owned())
+            .err()
+            .unwrap()
+            .to_string(),
+        "Expression contains invalid variables or operators."
+    );
+    // Numeric result, but logical variable in arithmetic rule.
+    assert!(Rule::new(SubstitutionToken::M, "if(A, D, E)".to_owned()).is_err());
+    // Mixed types can't be evaluated.
+    assert!(Rule::new(SubstitutionToken::M, "A + D".to_owned()).is_err());
+    // Unknown variable.
+    assert!(Rule::new(SubstitutionToken::M, "X".to_owned()).is_err());
+    // Empty tuple result.
+    assert!(Rule::new(SubstitutionToken::M, "()".to_owned()).is_err());
+}
+
+#[test]
+fn test_apply() {
+    let input = InputSet::new(true, false, false, 1.5, 2, 3);
+
+    let rule = Rule::new(SubstitutionToken::P, "A && !B".to_owned()).unwrap();
+    assert_eq!(
+        rule.apply(&input).unwrap(),
+        RuleOutput::Logical(Some(SubstitutionToken::P))
+    );
+    let rule = Rule::new(SubstitutionToken::P, "B".to_owned()).unwrap();
+    assert_eq!(rule.apply(&input).unwrap(), RuleOutput::Logical(None));
+
+    let rule = Rule::new(SubstitutionToken::P, "D * E + F".to_owned()).unwrap();
+    assert_eq!(rule.apply(&input).unwrap(), RuleOutput::Arithmetic(6.0));
+}
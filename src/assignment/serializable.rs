@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use crate::assignment::arithmetic_rule::SubstitutionToken;
+
+/// Logical rule of `SerializableAssignment` with its options.
+/// Absent options default to priority `0` without `stop_on_match`, enabled.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableLogicalRule {
+    pub token: SubstitutionToken,
+    pub rule_str: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub stop_on_match: bool,
+    /// `false` if the rule is disabled with `Assignment::set_logical_rule_enabled`.
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+/// Arithmetic rule of `SerializableAssignment`, enabled if the flag is absent.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableArithmeticRule {
+    pub token: SubstitutionToken,
+    pub rule_str: String,
+    /// `false` if the rule is disabled with `Assignment::set_arithmetic_rule_enabled`.
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+/// Rule set of `Assignment` in a form independent of serialization format,
+/// built with `Assignment::to_serializable` and loaded with `Assignment::from_serializable`.
+///
+/// Holds constants, string logical rules in order of registration,
+/// string arithmetic rules sorted by token and fallback arithmetic rule.
+/// Chained, multi-output and decimal rules and settings of `Assignment` are not included.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SerializableAssignment {
+    #[serde(default)]
+    pub constants: BTreeMap<String, f64>,
+    pub logical: Vec<SerializableLogicalRule>,
+    pub arithmetic: Vec<SerializableArithmeticRule>,
+    /// Source of fallback arithmetic rule, if any.
+    #[serde(default)]
+    pub fallback_arithmetic: Option<String>,
+}
+
+/// Function-based rule left out by `Assignment::to_serializable_lossy`, since it has no source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkippedRule {
+    Logical(SubstitutionToken),
+    Arithmetic(SubstitutionToken),
+    /// Fallback arithmetic rule set with `Assignment::set_fallback_arithmetic_rule`.
+    FallbackArithmetic,
+}
+
+impl fmt::Display for SkippedRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkippedRule::Logical(token) => {
+                write!(f, "Function-based logical rule of token {:?}", token)
+            }
+            SkippedRule::Arithmetic(token) => {
+                write!(f, "Function-based arithmetic rule of token {:?}", token)
+            }
+            SkippedRule::FallbackArithmetic => {
+                f.write_str("Function-based fallback arithmetic rule")
+            }
+        }
+    }
+}
+
+/// Element of `SerializableAssignment` that failed to load, reported by `InvalidRuleSetError`.
+/// Rules are addressed by their index in `logical` or `arithmetic` list.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RuleSetElement {
+    Constant {
+        name: String,
+    },
+    Logical {
+        index: usize,
+        token: SubstitutionToken,
+        rule_str: String,
+    },
+    Arithmetic {
+        index: usize,
+        token: SubstitutionToken,
+        rule_str: String,
+    },
+    FallbackArithmetic {
+        rule_str: String,
+    },
+    /// Number of logical rules exceeds the limit set by `Assignment::set_max_logical_rules`.
+    LogicalRulesLimit,
+}
+
+/// Error of loading `SerializableAssignment` with the element that failed validation.
+#[derive(Debug)]
+pub struct InvalidRuleSetError {
+    element: RuleSetElement,
+    error: Box<dyn Error + Send + Sync>,
+}
+
+impl InvalidRuleSetError {
+    pub(crate) fn new(element: RuleSetElement, error: Box<dyn Error + Send + Sync>) -> Self {
+        Self { element, error }
+    }
+
+    /// Returns element of the rule set that failed validation.
+    pub fn element(&self) -> &RuleSetElement {
+        &self.element
+    }
+
+    /// Returns validation error of the element, e.g. `ValidationError` of a rule.
+    pub fn error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self.error.as_ref()
+    }
+}
+
+impl fmt::Display for InvalidRuleSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.element {
+            RuleSetElement::Constant { name } => write!(f, "Invalid constant `{}`: ", name)?,
+            RuleSetElement::Logical { rule_str, .. } => {
+                write!(f, "Invalid logical rule \"{}\": ", rule_str)?
+            }
+            RuleSetElement::Arithmetic { rule_str, .. } => {
+                write!(f, "Invalid arithmetic rule \"{}\": ", rule_str)?
+            }
+            RuleSetElement::FallbackArithmetic { rule_str } => {
+                write!(f, "Invalid fallback arithmetic rule \"{}\": ", rule_str)?
+            }
+            RuleSetElement::LogicalRulesLimit => {}
+        }
+        write!(f, "{}", self.error)
+    }
+}
+
+impl Error for InvalidRuleSetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
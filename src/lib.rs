@@ -0,0 +1,3 @@
+#[cfg(feature = "server")]
+pub mod actix_app;
+pub mod assignment;
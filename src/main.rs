@@ -1,5 +1,4 @@
-mod actix_app;
-mod assignment;
+use st_test::actix_app;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {